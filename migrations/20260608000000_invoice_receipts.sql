@@ -0,0 +1,10 @@
+CREATE TABLE invoice_receipts (
+    invoice_uuid UUID PRIMARY KEY REFERENCES invoices(uuid),
+    device_id UUID NOT NULL REFERENCES devices(device_uuid),
+    icv INTEGER NOT NULL,
+    invoice_hash BYTEA NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ NOT NULL,
+    signed_receipt TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,16 @@
+ALTER TABLE invoices
+    ADD COLUMN invoice_number TEXT,
+    ADD COLUMN issue_date DATE,
+    ADD COLUMN issue_time TIME,
+    ADD COLUMN invoice_type_code TEXT,
+    ADD COLUMN invoice_type_name TEXT,
+    ADD COLUMN currency TEXT,
+    ADD COLUMN buyer_tin TEXT,
+    ADD COLUMN buyer_name TEXT,
+    ADD COLUMN net_amount NUMERIC,
+    ADD COLUMN tax_amount NUMERIC,
+    ADD COLUMN payable_amount NUMERIC,
+    ADD COLUMN metadata_extracted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_invoices_issue_date ON invoices (device_id, issue_date);
+CREATE INDEX idx_invoices_metadata_pending ON invoices (uuid) WHERE metadata_extracted_at IS NULL;
@@ -0,0 +1,15 @@
+CREATE TABLE invoice_lines (
+    invoice_uuid UUID NOT NULL REFERENCES invoices(uuid),
+    line_number INTEGER NOT NULL,
+    line_id TEXT NOT NULL,
+    item_name TEXT,
+    classification_code TEXT,
+    quantity NUMERIC,
+    unit_code TEXT,
+    unit_price NUMERIC,
+    net_amount NUMERIC,
+    tax_amount NUMERIC,
+    tax_category TEXT,
+    tax_percent NUMERIC,
+    PRIMARY KEY (invoice_uuid, line_number)
+);
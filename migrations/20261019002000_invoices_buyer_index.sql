@@ -0,0 +1 @@
+CREATE INDEX idx_invoices_buyer_issue_date ON invoices (buyer_tin, issue_date);
@@ -0,0 +1,16 @@
+CREATE TABLE tax_periods (
+    id UUID PRIMARY KEY,
+    tin VARCHAR(10) NOT NULL REFERENCES taxpayers(tin),
+    period_from DATE NOT NULL,
+    period_to DATE NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'filed', 'locked')),
+    filed_at TIMESTAMPTZ,
+    locked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (period_from <= period_to)
+);
+
+CREATE INDEX idx_tax_periods_tin_dates ON tax_periods (tin, period_from, period_to);
+
+ALTER TABLE invoices
+    ADD COLUMN flags TEXT[] NOT NULL DEFAULT '{}';
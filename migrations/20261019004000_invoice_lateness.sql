@@ -0,0 +1,4 @@
+ALTER TABLE invoices
+    ADD COLUMN late_by_seconds BIGINT;
+
+CREATE INDEX idx_invoices_late ON invoices (device_id, issue_date) WHERE late_by_seconds IS NOT NULL;
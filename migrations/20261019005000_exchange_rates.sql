@@ -0,0 +1,17 @@
+CREATE TABLE exchange_rates (
+    currency TEXT NOT NULL,
+    rate_date DATE NOT NULL,
+    -- SDG per unit of `currency`.
+    rate NUMERIC NOT NULL CHECK (rate > 0),
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (currency, rate_date)
+);
+
+ALTER TABLE invoices
+    ADD COLUMN tax_currency TEXT,
+    ADD COLUMN tax_currency_amount NUMERIC;
+
+-- Re-extract foreign-currency invoices so the backfill fills their SDG tax total.
+UPDATE invoices
+SET metadata_extracted_at = NULL
+WHERE currency IS NOT NULL AND currency <> 'SDG';
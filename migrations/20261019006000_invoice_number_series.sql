@@ -0,0 +1,10 @@
+CREATE INDEX idx_invoices_number ON invoices (invoice_number, invoice_type_code);
+
+-- Optional gapless numbering for one device: its next invoice must be
+-- numbered `prefix` followed by `next_number`.
+CREATE TABLE invoice_number_series (
+    device_uuid UUID PRIMARY KEY REFERENCES devices(device_uuid),
+    prefix TEXT NOT NULL DEFAULT '',
+    next_number BIGINT NOT NULL CHECK (next_number > 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
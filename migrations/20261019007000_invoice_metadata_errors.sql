@@ -0,0 +1,3 @@
+-- Invoices the metadata backfill could not parse are marked as extracted with
+-- the reason, so later startups do not parse them again.
+ALTER TABLE invoices ADD COLUMN metadata_error TEXT;
@@ -0,0 +1,28 @@
+ALTER TABLE invoices
+    ADD COLUMN supplier_tin TEXT;
+
+UPDATE invoices i
+SET supplier_tin = d.tin
+FROM devices d
+WHERE d.device_uuid = i.device_id;
+
+-- Numbers reused before the check ran under a lock are flagged, keeping the
+-- earliest invoice, so the unique index can be built.
+UPDATE invoices i
+SET flags = array_append(i.flags, 'duplicate_invoice_number')
+WHERE NOT ('duplicate_invoice_number' = ANY (i.flags))
+  AND EXISTS (
+      SELECT 1
+      FROM invoices earlier
+      WHERE earlier.supplier_tin = i.supplier_tin
+        AND earlier.invoice_number = i.invoice_number
+        AND earlier.invoice_type_code = i.invoice_type_code
+        AND NOT ('duplicate_invoice_number' = ANY (earlier.flags))
+        AND (earlier.created_at, earlier.uuid) < (i.created_at, i.uuid)
+  );
+
+DROP INDEX idx_invoices_number;
+
+CREATE UNIQUE INDEX idx_invoices_supplier_number
+    ON invoices (supplier_tin, invoice_number, invoice_type_code)
+    WHERE NOT ('duplicate_invoice_number' = ANY (flags));
@@ -0,0 +1,46 @@
+use openssl::memcmp;
+
+/// Bearer token for the `/admin` routes. Without `ADMIN_API_TOKEN` every
+/// admin request is refused.
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    token: Option<String>,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        Self {
+            token: std::env::var("ADMIN_API_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }
+    }
+
+    /// Whether `authorization` is `Bearer <ADMIN_API_TOKEN>`.
+    pub fn accepts(&self, authorization: Option<&str>) -> bool {
+        let (Some(token), Some(presented)) = (
+            self.token.as_deref(),
+            authorization.and_then(|value| value.strip_prefix("Bearer ")),
+        ) else {
+            return false;
+        };
+        presented.len() == token.len() && memcmp::eq(presented.as_bytes(), token.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_the_configured_bearer_token() {
+        let config = AdminConfig {
+            token: Some("s3cret".to_owned()),
+        };
+        assert!(config.accepts(Some("Bearer s3cret")));
+        assert!(!config.accepts(Some("Bearer s3cre")));
+        assert!(!config.accepts(Some("s3cret")));
+        assert!(!config.accepts(None));
+        assert!(!AdminConfig::default().accepts(Some("Bearer ")));
+    }
+}
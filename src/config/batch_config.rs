@@ -0,0 +1,22 @@
+use crate::config::db_config::env_u64;
+
+/// Size limits applied to batch reporting submissions.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchLimits {
+    /// Maximum number of invoices accepted in one batch.
+    pub max_items: usize,
+    /// Maximum request body size, and maximum total uncompressed size of a ZIP batch.
+    pub max_bytes: usize,
+    /// Maximum uncompressed size of a single XML file inside a ZIP batch.
+    pub max_entry_bytes: usize,
+}
+
+impl BatchLimits {
+    pub fn from_env() -> Self {
+        Self {
+            max_items: env_u64("BATCH_MAX_ITEMS", 500) as usize,
+            max_bytes: env_u64("BATCH_MAX_BYTES", 32 * 1024 * 1024) as usize,
+            max_entry_bytes: env_u64("BATCH_MAX_ENTRY_BYTES", 256 * 1024) as usize,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+use crate::services::{pipeline::business_rule_service::compile_rules, xml::xpath::prefixes};
+
+/// Rule set shipped with the server, used unless `BUSINESS_RULES_PATH` names
+/// another file in the same format.
+const EMBEDDED_RULES: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/registry/business_rules.json"
+));
+
+/// Empty invoice the rules are compiled against when loaded, so a bad XPath
+/// stops the server instead of failing invoices.
+const SKELETON_INVOICE: &str = r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2"/>"#;
+
+/// Whether a failed assertion rejects the invoice or only flags it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A Schematron-style assertion.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BusinessRule {
+    /// Returned as the issue code, e.g. `SD-BR-06`.
+    pub code: String,
+    pub severity: Severity,
+    /// XPath selecting the nodes the assertion applies to.
+    pub context: String,
+    /// XPath expression that must hold for every context node.
+    pub assert: String,
+    pub message: String,
+}
+
+/// A versioned set of business rules.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleSet {
+    /// Returned with rejections and accepted invoices so devices know which
+    /// rules they met.
+    pub version: String,
+    /// Namespace URI of each prefix the XPaths use.
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, String>,
+    pub rules: Vec<BusinessRule>,
+}
+
+impl Default for RuleSet {
+    /// The embedded rule set.
+    fn default() -> Self {
+        Self::from_json(EMBEDDED_RULES).expect("embedded business rules are valid")
+    }
+}
+
+impl RuleSet {
+    /// Reads the file named by `var`, if it is set.
+    pub fn from_env(var: &str) -> anyhow::Result<Option<Self>> {
+        match std::env::var(var) {
+            Ok(path) if !path.trim().is_empty() => {
+                let json = std::fs::read_to_string(path.trim())
+                    .with_context(|| format!("failed to read business rules {path}"))?;
+                Self::from_json(&json)
+                    .map(Some)
+                    .with_context(|| format!("invalid business rules {path}"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let rules: Self = serde_json::from_str(json)?;
+        if rules.version.trim().is_empty() {
+            bail!("business rules have no version");
+        }
+        let mut codes = HashSet::new();
+        for rule in &rules.rules {
+            if rule.code.trim().is_empty() || rule.message.trim().is_empty() {
+                bail!("every business rule needs a code and a message");
+            }
+            if !codes.insert(rule.code.as_str()) {
+                bail!("business rule {} is defined twice", rule.code);
+            }
+            for xpath in [&rule.context, &rule.assert] {
+                if let Some(prefix) = prefixes(xpath)
+                    .into_iter()
+                    .find(|prefix| !rules.namespaces.contains_key(*prefix))
+                {
+                    bail!(
+                        "business rule {} uses undeclared prefix {prefix}",
+                        rule.code
+                    );
+                }
+            }
+        }
+        let skeleton = fastxml::parse(SKELETON_INVOICE)?;
+        compile_rules(&rules, &skeleton)?;
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(context: &str, assert: &str) -> String {
+        format!(
+            r#"{{"version": "test", "namespaces": {{"inv": "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2", "cbc": "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2"}},
+                "rules": [{{"code": "T-01", "severity": "error", "context": "{context}", "assert": "{assert}", "message": "m"}}]}}"#
+        )
+    }
+
+    #[test]
+    fn embedded_rules_load() {
+        let rules = RuleSet::default();
+        assert!(!rules.version.is_empty());
+        assert!(
+            rules
+                .rules
+                .iter()
+                .any(|rule| rule.severity == Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn rejects_rules_that_do_not_compile() {
+        RuleSet::from_json(&rules("/inv:Invoice", "cbc:ID = '1'")).unwrap();
+        for (context, assert) in [
+            ("/inv:Invoice", "cbc:ID = "),
+            ("/inv:Invoice", "x:ID"),
+            ("/inv:Invoice[", "cbc:ID"),
+            ("/inv:Invoice/cac:InvoiceLine", "cbc:ID = "),
+            ("/inv:Invoice", "1] | //cbc:ID[1"),
+            ("/inv:Invoice", "cbc:ID = ']"),
+        ] {
+            assert!(
+                RuleSet::from_json(&rules(context, assert)).is_err(),
+                "{assert}"
+            );
+        }
+        let twice = r#"{"version": "test", "rules": [
+            {"code": "T-01", "severity": "error", "context": "/Invoice", "assert": "cbc:ID", "message": "m"},
+            {"code": "T-01", "severity": "warning", "context": "/Invoice", "assert": "cbc:ID", "message": "m"}]}"#;
+        assert!(RuleSet::from_json(twice).is_err());
+    }
+}
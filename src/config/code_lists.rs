@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use anyhow::bail;
+use serde::Deserialize;
+
+/// Code lists shipped with the server.
+const EMBEDDED_CODE_LISTS: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/registry/code_lists.json"
+));
+
+/// A code list invoice values are checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeList {
+    /// ISO 4217.
+    Currency,
+    /// ISO 3166-1 alpha-2.
+    Country,
+    /// UN/ECE Recommendation 20, with the Recommendation 21 package codes
+    /// prefixed `X`.
+    Unit,
+    /// UNCL1001, restricted to invoice documents.
+    DocumentType,
+    /// UNCL4461.
+    PaymentMeans,
+}
+
+impl CodeList {
+    /// Code of the validation issue raised for a value outside the list.
+    pub const fn issue_code(self) -> &'static str {
+        match self {
+            Self::Currency => "unknown_currency_code",
+            Self::Country => "unknown_country_code",
+            Self::Unit => "unknown_unit_code",
+            Self::DocumentType => "unknown_document_type_code",
+            Self::PaymentMeans => "unknown_payment_means_code",
+        }
+    }
+}
+
+/// The embedded currency, country, unit, document type and payment means
+/// code lists.
+#[derive(Debug, Clone)]
+pub struct CodeLists {
+    currencies: Entry,
+    countries: Entry,
+    units: Entry,
+    document_types: Entry,
+    payment_means: Entry,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Entry {
+    title: String,
+    codes: HashSet<String>,
+}
+
+#[derive(Deserialize)]
+struct CodeListsFile {
+    currencies: Entry,
+    countries: Entry,
+    units: Entry,
+    document_types: Entry,
+    payment_means: Entry,
+}
+
+impl Default for CodeLists {
+    fn default() -> Self {
+        Self::from_json(EMBEDDED_CODE_LISTS).expect("embedded code lists are valid")
+    }
+}
+
+impl CodeLists {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let file: CodeListsFile = serde_json::from_str(json)?;
+        let lists = Self {
+            currencies: file.currencies,
+            countries: file.countries,
+            units: file.units,
+            document_types: file.document_types,
+            payment_means: file.payment_means,
+        };
+        for list in [
+            CodeList::Currency,
+            CodeList::Country,
+            CodeList::Unit,
+            CodeList::DocumentType,
+            CodeList::PaymentMeans,
+        ] {
+            if lists.entry(list).codes.is_empty() {
+                bail!("code list {} is empty", lists.title(list));
+            }
+        }
+        Ok(lists)
+    }
+
+    pub fn contains(&self, list: CodeList, code: &str) -> bool {
+        self.entry(list).codes.contains(code)
+    }
+
+    /// Human-readable name of the list, such as "ISO 4217 currency codes".
+    pub fn title(&self, list: CodeList) -> &str {
+        &self.entry(list).title
+    }
+
+    fn entry(&self, list: CodeList) -> &Entry {
+        match list {
+            CodeList::Currency => &self.currencies,
+            CodeList::Country => &self.countries,
+            CodeList::Unit => &self.units,
+            CodeList::DocumentType => &self.document_types,
+            CodeList::PaymentMeans => &self.payment_means,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_code_lists_load() {
+        let lists = CodeLists::default();
+        assert!(lists.contains(CodeList::Currency, "SDG"));
+        assert!(lists.contains(CodeList::Country, "SD"));
+        assert!(lists.contains(CodeList::Unit, "PCE"));
+        assert!(lists.contains(CodeList::DocumentType, "388"));
+        assert!(lists.contains(CodeList::PaymentMeans, "30"));
+        assert!(!lists.contains(CodeList::Currency, "sdg"));
+        assert!(!lists.contains(CodeList::DocumentType, "999"));
+    }
+
+    #[test]
+    fn units_cover_recommendations_20_and_21() {
+        let lists = CodeLists::default();
+        for code in [
+            "C62", "H87", "EA", "KGM", "GRM", "TNE", "LTR", "MLT", "MTR", "MTK", "MTQ", "KMT",
+            "HUR", "DAY", "MON", "ANN", "KWH", "SET", "PR", "DZN", "LS", "E48", "HAR", "ZZ",
+        ] {
+            assert!(lists.contains(CodeList::Unit, code), "missing unit {code}");
+        }
+        for code in ["BX", "BG", "PK", "CT", "RO", "BO", "PA", "ZZ"] {
+            let code = format!("X{code}");
+            assert!(lists.contains(CodeList::Unit, &code), "missing unit {code}");
+        }
+        assert!(!lists.contains(CodeList::Unit, "BOX"));
+    }
+}
@@ -0,0 +1,21 @@
+use crate::config::db_config::env_u64;
+
+/// Sizing for the bounded pool that runs CPU-bound pipeline stages.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuPoolConfig {
+    /// Jobs allowed to run at once.
+    pub workers: usize,
+    /// Jobs allowed to wait for a worker before new work is shed.
+    pub max_queue: usize,
+}
+
+impl CpuPoolConfig {
+    pub fn from_env() -> Self {
+        let default_workers = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(4);
+        let workers = (env_u64("CPU_POOL_WORKERS", default_workers as u64) as usize).max(1);
+        let max_queue = env_u64("CPU_POOL_MAX_QUEUE", (workers * 16) as u64) as usize;
+        Self { workers, max_queue }
+    }
+}
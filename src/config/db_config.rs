@@ -32,14 +32,14 @@ pub async fn db_from_env() -> anyhow::Result<PgPool> {
         .await?)
 }
 
-fn env_u32(key: &str, default: u32) -> u32 {
+pub(crate) fn env_u32(key: &str, default: u32) -> u32 {
     std::env::var(key)
         .ok()
         .and_then(|value| value.parse().ok())
         .unwrap_or(default)
 }
 
-fn env_u64(key: &str, default: u64) -> u64 {
+pub(crate) fn env_u64(key: &str, default: u64) -> u64 {
     std::env::var(key)
         .ok()
         .and_then(|value| value.parse().ok())
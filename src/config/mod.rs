@@ -1,3 +1,13 @@
+pub mod admin_config;
+pub mod batch_config;
+pub mod business_rules;
+pub mod code_lists;
+pub mod cpu_pool_config;
 pub mod crypto_config;
 pub mod db_config;
+pub mod signature_config;
+pub mod tax_registry;
+pub mod tsa_config;
+pub mod validation_config;
+pub mod xml_config;
 pub mod xsd_config;
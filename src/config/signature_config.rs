@@ -0,0 +1,100 @@
+use std::env;
+
+use anyhow::{Context, bail};
+
+use crate::services::crypto::xmldsig_algorithms::{
+    Canonicalization, DigestMethod, SUPPORTED_ALGORITHMS,
+};
+
+/// Canonicalization and digest algorithms accepted in submitted signatures.
+#[derive(Debug, Clone)]
+pub struct SignatureAlgorithms {
+    allowed: Vec<String>,
+}
+
+impl Default for SignatureAlgorithms {
+    /// Every supported algorithm.
+    fn default() -> Self {
+        Self {
+            allowed: SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|uri| uri.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl SignatureAlgorithms {
+    /// Reads `XMLDSIG_ALLOWED_ALGORITHMS`, a comma-separated list of algorithm
+    /// URIs. Without it every supported algorithm is allowed; a list that
+    /// names none is rejected rather than refusing every signature.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env::var("XMLDSIG_ALLOWED_ALGORITHMS") {
+            Ok(list) => Self::new(list.split(',').map(str::trim).filter(|uri| !uri.is_empty()))
+                .context("invalid XMLDSIG_ALLOWED_ALGORITHMS"),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn new<'a>(uris: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Self> {
+        let mut allowed = Vec::new();
+        for uri in uris {
+            if !SUPPORTED_ALGORITHMS.contains(&uri) {
+                bail!("unsupported signature algorithm: {uri}");
+            }
+            allowed.push(uri.to_owned());
+        }
+        if allowed.is_empty() {
+            bail!("the signature algorithm allow-list is empty");
+        }
+        Ok(Self { allowed })
+    }
+
+    /// The canonicalization named by `uri`, if it is allowed.
+    pub fn canonicalization(
+        &self,
+        uri: &str,
+        inclusive_prefixes: &[String],
+    ) -> anyhow::Result<Canonicalization> {
+        let canonicalization = Canonicalization::from_uri(uri, inclusive_prefixes)?;
+        self.check(uri, "canonicalization")?;
+        Ok(canonicalization)
+    }
+
+    /// The digest method named by `uri`, if it is allowed.
+    pub fn digest(&self, uri: &str) -> anyhow::Result<DigestMethod> {
+        let digest = DigestMethod::from_uri(uri)?;
+        self.check(uri, "digest")?;
+        Ok(digest)
+    }
+
+    fn check(&self, uri: &str, kind: &str) -> anyhow::Result<()> {
+        if !self.allowed.iter().any(|allowed| allowed == uri) {
+            bail!("{kind} algorithm not allowed: {uri}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::crypto::xmldsig_algorithms::{C14N_11, EXC_C14N, SHA256, SHA512};
+
+    #[test]
+    fn only_listed_algorithms_are_allowed() {
+        let algorithms = SignatureAlgorithms::new([C14N_11, SHA256]).unwrap();
+        assert!(algorithms.canonicalization(C14N_11, &[]).is_ok());
+        assert!(algorithms.digest(SHA256).is_ok());
+        let err = algorithms.digest(SHA512).unwrap_err().to_string();
+        assert_eq!(err, format!("digest algorithm not allowed: {SHA512}"));
+        let err = algorithms.canonicalization(EXC_C14N, &[]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("canonicalization algorithm not allowed")
+        );
+        assert!(SignatureAlgorithms::new(["http://example.com/sha1"]).is_err());
+        let err = SignatureAlgorithms::new([]).unwrap_err().to_string();
+        assert!(err.contains("allow-list is empty"));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, bail};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::services::xml::decimal::decimal;
+
+/// Registry shipped with the server, used unless `TAX_REGISTRY_PATH` names
+/// another file in the same format.
+const EMBEDDED_REGISTRY: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/registry/tax_registry.json"
+));
+
+/// Tax categories an invoice may use, with their rates over time and the
+/// exemption reason codes zero-rated and exempt supplies must carry.
+#[derive(Debug, Clone)]
+pub struct TaxRegistry {
+    categories: Vec<TaxCategoryRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaxCategoryRule {
+    pub id: String,
+    pub name: String,
+    pub rates: Vec<TaxRate>,
+    /// When non-empty, subtotals of this category must carry one of these
+    /// `TaxExemptionReasonCode`s.
+    pub exemption_reason_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaxRate {
+    /// Normalized by [`normalize_percent`].
+    pub percent: String,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_to: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    categories: Vec<CategoryEntry>,
+}
+
+#[derive(Deserialize)]
+struct CategoryEntry {
+    id: String,
+    name: String,
+    rates: Vec<RateEntry>,
+    #[serde(default)]
+    exemption_reason_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RateEntry {
+    percent: String,
+    valid_from: Option<String>,
+    valid_to: Option<String>,
+}
+
+impl Default for TaxRegistry {
+    /// The embedded registry.
+    fn default() -> Self {
+        Self::from_json(EMBEDDED_REGISTRY).expect("embedded tax registry is valid")
+    }
+}
+
+impl TaxRegistry {
+    /// Reads the file named by `TAX_REGISTRY_PATH`, or the embedded registry
+    /// without it.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("TAX_REGISTRY_PATH") {
+            Ok(path) if !path.trim().is_empty() => {
+                let json = std::fs::read_to_string(path.trim())
+                    .with_context(|| format!("failed to read tax registry {path}"))?;
+                Self::from_json(&json).with_context(|| format!("invalid tax registry {path}"))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let file: RegistryFile = serde_json::from_str(json)?;
+        let mut ids = HashSet::new();
+        let mut categories = Vec::with_capacity(file.categories.len());
+        for entry in file.categories {
+            let id = entry.id.trim().to_owned();
+            if id.is_empty() || !ids.insert(id.clone()) {
+                bail!("tax category ids must be unique and non-empty: {id:?}");
+            }
+            if entry.rates.is_empty() {
+                bail!("tax category {id} has no rates");
+            }
+            let rates = entry
+                .rates
+                .into_iter()
+                .map(|rate| parse_rate(&id, rate))
+                .collect::<anyhow::Result<_>>()?;
+            categories.push(TaxCategoryRule {
+                id,
+                name: entry.name,
+                rates,
+                exemption_reason_codes: entry.exemption_reason_codes,
+            });
+        }
+        Ok(Self { categories })
+    }
+
+    pub fn category(&self, id: &str) -> Option<&TaxCategoryRule> {
+        self.categories.iter().find(|category| category.id == id)
+    }
+}
+
+impl TaxCategoryRule {
+    /// Rates in force on `date`; every rate when the date is unknown.
+    pub fn rates_on(&self, date: Option<NaiveDate>) -> impl Iterator<Item = &TaxRate> {
+        self.rates.iter().filter(move |rate| {
+            date.is_none_or(|date| {
+                rate.valid_from.is_none_or(|from| from <= date)
+                    && rate.valid_to.is_none_or(|to| date <= to)
+            })
+        })
+    }
+
+    pub fn requires_exemption_reason(&self) -> bool {
+        !self.exemption_reason_codes.is_empty()
+    }
+}
+
+fn parse_rate(category: &str, rate: RateEntry) -> anyhow::Result<TaxRate> {
+    let date = |value: Option<String>| -> anyhow::Result<Option<NaiveDate>> {
+        value
+            .map(|value| {
+                NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                    .with_context(|| format!("tax category {category}: invalid date {value}"))
+            })
+            .transpose()
+    };
+    let percent = normalize_percent(&rate.percent)
+        .with_context(|| format!("tax category {category}: invalid percent {}", rate.percent))?;
+    let valid_from = date(rate.valid_from)?;
+    let valid_to = date(rate.valid_to)?;
+    if let (Some(from), Some(to)) = (valid_from, valid_to)
+        && from > to
+    {
+        bail!("tax category {category}: rate {percent} ends before it starts");
+    }
+    Ok(TaxRate {
+        percent,
+        valid_from,
+        valid_to,
+    })
+}
+
+/// A non-negative decimal without insignificant zeros, so `15.00` and `15`
+/// compare equal.
+pub fn normalize_percent(value: &str) -> Option<String> {
+    let value = decimal(value)?;
+    if value.starts_with('-') {
+        return None;
+    }
+    let value = match value.split_once('.') {
+        Some((whole, fraction)) => match fraction.trim_end_matches('0') {
+            "" => whole.to_owned(),
+            fraction => format!("{whole}.{fraction}"),
+        },
+        None => value,
+    };
+    let trimmed = value.trim_start_matches('0');
+    Some(match trimmed {
+        "" => "0".to_owned(),
+        rest if rest.starts_with('.') => format!("0{rest}"),
+        rest => rest.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_registry_loads() {
+        let registry = TaxRegistry::default();
+        let standard = registry.category("S").unwrap();
+        let today = NaiveDate::from_ymd_opt(2026, 3, 1);
+        assert_eq!(
+            standard
+                .rates_on(today)
+                .map(|rate| rate.percent.as_str())
+                .collect::<Vec<_>>(),
+            ["17"]
+        );
+        assert!(!standard.requires_exemption_reason());
+        assert!(registry.category("E").unwrap().requires_exemption_reason());
+    }
+
+    #[test]
+    fn rejects_malformed_registries() {
+        let duplicate = r#"{"categories": [
+            {"id": "S", "name": "a", "rates": [{"percent": "17"}]},
+            {"id": "S", "name": "b", "rates": [{"percent": "15"}]}
+        ]}"#;
+        assert!(TaxRegistry::from_json(duplicate).is_err());
+        let reversed = r#"{"categories": [{"id": "S", "name": "a", "rates": [
+            {"percent": "17", "valid_from": "2026-02-01", "valid_to": "2026-01-01"}
+        ]}]}"#;
+        assert!(TaxRegistry::from_json(reversed).is_err());
+        let percent =
+            r#"{"categories": [{"id": "S", "name": "a", "rates": [{"percent": "17%"}]}]}"#;
+        assert!(TaxRegistry::from_json(percent).is_err());
+    }
+
+    #[test]
+    fn normalizes_percents() {
+        assert_eq!(normalize_percent("15.00").as_deref(), Some("15"));
+        assert_eq!(normalize_percent("017.50").as_deref(), Some("17.5"));
+        assert_eq!(normalize_percent("0.000").as_deref(), Some("0"));
+        assert_eq!(normalize_percent("00.25").as_deref(), Some("0.25"));
+        assert_eq!(normalize_percent("-5"), None);
+    }
+}
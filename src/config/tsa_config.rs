@@ -0,0 +1,79 @@
+use std::env;
+
+use base64::{Engine, engine::general_purpose};
+use openssl::{pkey::PKey, rsa::Rsa};
+
+use crate::{config::crypto_config::Crypto, services::crypto::pki_service::issue_tsa_certificate};
+
+/// Policy OID written into every `TSTInfo` when `TSA_POLICY_OID` is not set.
+pub const DEFAULT_TSA_POLICY_OID: &str = "1.2.3.4.1";
+
+/// The built-in RFC 3161 time-stamping authority.
+pub struct Tsa {
+    /// TSA signing key and its certificate, issued by the STC CA at startup.
+    pub signer: Crypto,
+    /// Policy under which time-stamps are issued, as OID arcs.
+    pub policy: Vec<u64>,
+}
+
+impl Tsa {
+    /// Loads the TSA key from `TSA_PRIVATE_KEY` (base64 PEM, like
+    /// `SEC_PRIVATE_KEY`) and issues its certificate from the STC CA. Without
+    /// a configured key an ephemeral one is generated.
+    pub fn from_env(ca: &Crypto) -> Result<Self, String> {
+        let private_key = match env::var("TSA_PRIVATE_KEY") {
+            Ok(private_key_base64) => {
+                let private_key_binary = general_purpose::STANDARD
+                    .decode(&private_key_base64)
+                    .map_err(|e| format!("failed to parse the TSA private key base64 : {}", e))?;
+                PKey::private_key_from_pem(&private_key_binary)
+                    .map_err(|e| format!("failed to convert the binary into a TSA key : {}", e))?
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "TSA_PRIVATE_KEY not set; using an ephemeral TSA key. The TSA certificate changes on every restart."
+                );
+                Rsa::generate(2048)
+                    .and_then(PKey::from_rsa)
+                    .map_err(|e| format!("failed to generate the TSA key : {}", e))?
+            }
+        };
+        let policy = env::var("TSA_POLICY_OID").unwrap_or_else(|_| DEFAULT_TSA_POLICY_OID.into());
+        let policy = parse_oid(&policy)?;
+        let certificate = issue_tsa_certificate(&private_key, ca)
+            .map_err(|e| format!("failed to issue the TSA certificate : {}", e))?;
+        Ok(Self {
+            signer: Crypto {
+                private_key,
+                certificate,
+            },
+            policy,
+        })
+    }
+}
+
+fn parse_oid(oid: &str) -> Result<Vec<u64>, String> {
+    let arcs = oid
+        .trim()
+        .split('.')
+        .map(|arc| arc.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("TSA_POLICY_OID is not a dotted OID : {}", oid))?;
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] > 39) {
+        return Err(format!("TSA_POLICY_OID is not a valid OID : {}", oid));
+    }
+    Ok(arcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dotted_policy_oid() {
+        assert_eq!(parse_oid("1.2.3.4.1").unwrap(), vec![1, 2, 3, 4, 1]);
+        assert!(parse_oid("1").is_err());
+        assert!(parse_oid("3.1").is_err());
+        assert!(parse_oid("1.2.x").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::bail;
+use chrono::{Duration, FixedOffset};
+
+use crate::config::{
+    business_rules::RuleSet, code_lists::CodeLists, db_config::env_u64, tax_registry::TaxRegistry,
+};
+
+/// What happens to an invoice whose number and document type the supplier
+/// has already used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateInvoiceNumbers {
+    Reject,
+    /// Accept and flag `duplicate_invoice_number`.
+    Warn,
+}
+
+impl FromStr for DuplicateInvoiceNumbers {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "warn" => Ok(Self::Warn),
+            other => bail!("DUPLICATE_INVOICE_NUMBERS must be reject or warn, not {other:?}"),
+        }
+    }
+}
+
+/// Business-rule settings applied by the validation pipeline.
+#[derive(Debug, Clone)]
+pub struct ValidationPolicy {
+    /// How long after its `IssueDate`/`IssueTime` a clearance invoice may
+    /// still be submitted.
+    pub clearance_max_age: Duration,
+    /// How far in the future an invoice may be dated, to absorb device clock
+    /// drift.
+    pub max_clock_skew: Duration,
+    /// Statutory window for reporting invoices. Later reports are accepted
+    /// and flagged.
+    pub reporting_window: Duration,
+    /// Offset assumed for an `IssueTime` without `Z` or an explicit offset,
+    /// from `ISSUE_TIME_UTC_OFFSET_MINUTES` (minutes east of UTC, Sudan time
+    /// by default). The clock skew and clearance age checks read such a time
+    /// in this offset, so a device writing local time of another zone is off
+    /// by the difference; each assumed offset is logged.
+    pub default_issue_offset: FixedOffset,
+    /// Tax categories, rates and exemption reason codes lines and subtotals
+    /// are checked against.
+    pub tax_registry: TaxRegistry,
+    /// Currency, country, unit, document type and payment means codes.
+    /// Shared with the blocking pool, where it is checked after the schema.
+    pub code_lists: Arc<CodeLists>,
+    /// How far the SDG tax total of a foreign-currency invoice may stray from
+    /// its tax total converted at the published rate, in percent.
+    pub exchange_rate_tolerance_percent: f64,
+    /// Oldest published rate still used for an issue date, to bridge days
+    /// without a publication.
+    pub exchange_rate_max_age: Duration,
+    /// Business rules production invoices are checked against.
+    pub business_rules: Arc<RuleSet>,
+    /// Business rules for sandbox submissions, so devices can try an upcoming
+    /// rule set before production enables it. The production set by default.
+    pub sandbox_business_rules: Arc<RuleSet>,
+    /// Whether a supplier may reuse an invoice number for the same document
+    /// type.
+    pub duplicate_invoice_numbers: DuplicateInvoiceNumbers,
+}
+
+impl ValidationPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        let offset = std::env::var("ISSUE_TIME_UTC_OFFSET_MINUTES")
+            .ok()
+            .and_then(|value| value.parse::<i32>().ok())
+            .and_then(|minutes| FixedOffset::east_opt(minutes * 60))
+            .unwrap_or(defaults.default_issue_offset);
+        let business_rules =
+            Arc::new(RuleSet::from_env("BUSINESS_RULES_PATH")?.unwrap_or_default());
+        let sandbox_business_rules = match RuleSet::from_env("SANDBOX_BUSINESS_RULES_PATH")? {
+            Some(upcoming) => Arc::new(upcoming),
+            None => Arc::clone(&business_rules),
+        };
+        Ok(Self {
+            clearance_max_age: Duration::minutes(env_u64(
+                "CLEARANCE_MAX_AGE_MINUTES",
+                defaults.clearance_max_age.num_minutes() as u64,
+            ) as i64),
+            max_clock_skew: Duration::seconds(env_u64(
+                "ISSUE_TIME_MAX_SKEW_SECONDS",
+                defaults.max_clock_skew.num_seconds() as u64,
+            ) as i64),
+            reporting_window: Duration::hours(env_u64(
+                "REPORTING_WINDOW_HOURS",
+                defaults.reporting_window.num_hours() as u64,
+            ) as i64),
+            default_issue_offset: offset,
+            tax_registry: TaxRegistry::from_env()?,
+            code_lists: Arc::default(),
+            exchange_rate_tolerance_percent: std::env::var("EXCHANGE_RATE_TOLERANCE_PERCENT")
+                .ok()
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .filter(|percent| percent.is_finite() && *percent >= 0.0)
+                .unwrap_or(defaults.exchange_rate_tolerance_percent),
+            exchange_rate_max_age: Duration::days(env_u64(
+                "EXCHANGE_RATE_MAX_AGE_DAYS",
+                defaults.exchange_rate_max_age.num_days() as u64,
+            ) as i64),
+            business_rules,
+            sandbox_business_rules,
+            duplicate_invoice_numbers: match std::env::var("DUPLICATE_INVOICE_NUMBERS") {
+                Ok(value) if !value.trim().is_empty() => value.parse()?,
+                _ => defaults.duplicate_invoice_numbers,
+            },
+        })
+    }
+
+    /// The rule set submissions to the sandbox or production are checked
+    /// against.
+    pub fn business_rules(&self, sandbox: bool) -> &Arc<RuleSet> {
+        if sandbox {
+            &self.sandbox_business_rules
+        } else {
+            &self.business_rules
+        }
+    }
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        let business_rules = Arc::<RuleSet>::default();
+        Self {
+            clearance_max_age: Duration::minutes(15),
+            max_clock_skew: Duration::minutes(5),
+            reporting_window: Duration::hours(24),
+            // Sudan time, UTC+02:00.
+            default_issue_offset: FixedOffset::east_opt(2 * 3600).unwrap(),
+            tax_registry: TaxRegistry::default(),
+            code_lists: Arc::default(),
+            exchange_rate_tolerance_percent: 1.0,
+            exchange_rate_max_age: Duration::days(7),
+            business_rules: Arc::clone(&business_rules),
+            sandbox_business_rules: business_rules,
+            duplicate_invoice_numbers: DuplicateInvoiceNumbers::Reject,
+        }
+    }
+}
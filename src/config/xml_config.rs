@@ -0,0 +1,29 @@
+use crate::config::db_config::env_u64;
+
+/// Limits enforced on untrusted XML before it reaches the XML parsers and
+/// canonicalization.
+#[derive(Debug, Clone, Copy)]
+pub struct XmlLimits {
+    /// Maximum size of a document, in bytes.
+    pub max_bytes: usize,
+    /// Maximum element nesting depth.
+    pub max_depth: usize,
+    /// Maximum number of elements in a document.
+    pub max_elements: usize,
+    /// Maximum number of attributes on a single element.
+    pub max_attributes: usize,
+    /// Maximum size of a single text node, CDATA section or attribute value.
+    pub max_text_bytes: usize,
+}
+
+impl XmlLimits {
+    pub fn from_env() -> Self {
+        Self {
+            max_bytes: env_u64("XML_MAX_BYTES", 256 * 1024) as usize,
+            max_depth: env_u64("XML_MAX_DEPTH", 64) as usize,
+            max_elements: env_u64("XML_MAX_ELEMENTS", 20_000) as usize,
+            max_attributes: env_u64("XML_MAX_ATTRIBUTES", 32) as usize,
+            max_text_bytes: env_u64("XML_MAX_TEXT_BYTES", 128 * 1024) as usize,
+        }
+    }
+}
@@ -2,11 +2,21 @@ use utoipa::OpenApi;
 
 use crate::{
     models::{
+        batch_report::{
+            BatchItemError, BatchItemResultDto, BatchItemStatus, BatchReportDto, BatchResumeDto,
+        },
         enrollment::{EnrollDTO, EnrollmentCertificateDto},
-        responses::{ApiResponse, EmptyApiResponse, ErrorData, ErrorInfo},
+        metrics::CpuPoolStats,
+        qr_verification::{
+            QrFactsDto, QrSignerDto, QrSignerKind, QrVerificationDto, QrVerificationResultDto,
+        },
+        receipt::{InvoiceReceipt, InvoiceReceiptDto},
+        responses::{ApiResponse, EmptyApiResponse, ErrorData, ErrorInfo, ValidationIssue},
         submit_invoice::{ClearedInvoiceDto, SubmitInvoiceDto},
     },
-    routes::{enroll, health_check, invoice_controller},
+    routes::{
+        enroll, health_check, invoice_controller, invoice_pdf, metrics, qr_image, tsa, verify_qr,
+    },
 };
 
 #[derive(OpenApi)]
@@ -19,11 +29,20 @@ use crate::{
     servers((url = "https://stc-server.onrender.com", description = "Render production server"), (url = "http://localhost:8080", description = "Local development server")),
     paths(
         health_check::health_check,
+        metrics::cpu_pool_metrics,
         enroll::enroll,
         invoice_controller::clearance_prod,
         invoice_controller::clearance_sandbox,
         invoice_controller::reporting_prod,
-        invoice_controller::reporting_sandbox
+        invoice_controller::reporting_sandbox,
+        invoice_controller::reporting_batch_prod,
+        invoice_controller::reporting_batch_sandbox,
+        invoice_controller::invoice_receipt,
+        invoice_pdf::invoice_pdf,
+        tsa::timestamp,
+        verify_qr::verify_qr,
+        qr_image::invoice_qr_image,
+        qr_image::uploaded_invoice_qr_image
     ),
     components(schemas(
         EnrollDTO,
@@ -31,12 +50,30 @@ use crate::{
         SubmitInvoiceDto,
         ClearedInvoiceDto,
         ApiResponse<EnrollmentCertificateDto>,
+        InvoiceReceipt,
+        InvoiceReceiptDto,
         ApiResponse<ClearedInvoiceDto>,
+        ApiResponse<InvoiceReceiptDto>,
+        BatchItemStatus,
+        BatchItemError,
+        BatchItemResultDto,
+        BatchResumeDto,
+        BatchReportDto,
+        ApiResponse<BatchReportDto>,
+        CpuPoolStats,
+        ApiResponse<CpuPoolStats>,
+        QrVerificationDto,
+        QrSignerKind,
+        QrSignerDto,
+        QrFactsDto,
+        QrVerificationResultDto,
+        ApiResponse<QrVerificationResultDto>,
         EmptyApiResponse,
         ApiResponse<ErrorData>,
         ErrorData,
-        ErrorInfo
+        ErrorInfo,
+        ValidationIssue
     )),
-    tags((name = "Public API", description = "Public integration endpoints for enrollment, invoice processing, and health checks."))
+    tags((name = "Public API", description = "Public integration endpoints for enrollment, invoice processing, time-stamping, QR verification and rendering, and health checks."))
 )]
 pub struct ApiDoc;
@@ -4,19 +4,37 @@ use actix_web::{
     HttpRequest, HttpResponse, ResponseError,
     body::BoxBody,
     error::{InternalError, JsonPayloadError},
-    http::StatusCode,
+    http::{StatusCode, header},
 };
 
-use crate::models::responses::{ApiResponse, ErrorData, ErrorInfo};
+use crate::{
+    models::responses::{ApiResponse, ErrorData, ErrorInfo, ValidationIssue},
+    services::{cpu_pool::CpuPoolSaturated, pipeline::validation_service::ValidationIssues},
+};
 
-#[derive(Debug, Clone, Copy)]
+/// Seconds clients are asked to wait before retrying a shed request.
+const RETRY_AFTER_SECS: u32 = 1;
+
+#[derive(Debug, Clone)]
 pub struct ApiError {
     code: ErrorCode,
+    issues: Vec<ValidationIssue>,
+    rules_version: Option<String>,
 }
 
 impl ApiError {
     pub const fn new(code: ErrorCode) -> Self {
-        Self { code }
+        Self {
+            code,
+            issues: Vec::new(),
+            rules_version: None,
+        }
+    }
+
+    fn with_issues(mut self, failed: &ValidationIssues) -> Self {
+        self.issues = failed.issues.clone();
+        self.rules_version = failed.rules_version.clone();
+        self
     }
 
     pub const fn internal() -> Self {
@@ -35,6 +53,21 @@ impl ApiError {
         self.code.status()
     }
 
+    /// Failed checks behind the error, each with the XPath it applies to.
+    pub fn issues(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+
+    /// Business rule set version behind a rule violation.
+    pub fn rules_version(&self) -> Option<&str> {
+        self.rules_version.as_deref()
+    }
+
+    /// Whether the request was shed under load and should simply be retried.
+    pub const fn is_overloaded(&self) -> bool {
+        matches!(self.code, ErrorCode::ServiceOverloaded)
+    }
+
     pub fn from_json_payload(error: &JsonPayloadError) -> Self {
         match error {
             JsonPayloadError::ContentType => Self::new(ErrorCode::UnsupportedContentType),
@@ -98,6 +131,10 @@ impl ApiError {
     }
 
     pub fn from_invoice_parse(error: &anyhow::Error) -> Self {
+        if is_cpu_pool_saturated(error) {
+            return Self::new(ErrorCode::ServiceOverloaded);
+        }
+
         if matches!(sqlx_error(error), Some(error) if matches!(error, sqlx::Error::RowNotFound)) {
             return Self::new(ErrorCode::DeviceNotFound);
         }
@@ -107,7 +144,9 @@ impl ApiError {
         }
 
         let error_text = error_chain_text(error);
-        if error_text.contains("failed to decode the the invoice") {
+        if error_text.contains("xml guard") {
+            Self::new(ErrorCode::UnsafeXml)
+        } else if error_text.contains("failed to decode the the invoice") {
             Self::new(ErrorCode::InvalidInvoiceEncoding)
         } else if error_text.contains("failed to decode the invoice hash") {
             Self::new(ErrorCode::InvalidInvoiceHashEncoding)
@@ -130,6 +169,15 @@ impl ApiError {
             Self::new(ErrorCode::InvalidInvoiceCertificate)
         } else if error_text.contains("failed to extract the company id") {
             Self::new(ErrorCode::InvalidSupplierTin)
+        } else if contains_any(
+            &error_text,
+            &[
+                "invoice reference",
+                "unsupported reference",
+                "unsupported xpath filter",
+            ],
+        ) {
+            Self::new(ErrorCode::InvoiceSignatureInvalid)
         } else if contains_any(
             &error_text,
             &[
@@ -145,6 +193,10 @@ impl ApiError {
     }
 
     pub fn from_invoice_pipeline(error: &anyhow::Error) -> Self {
+        if is_cpu_pool_saturated(error) {
+            return Self::new(ErrorCode::ServiceOverloaded);
+        }
+
         let error_text = error_chain_text(error);
 
         if error_text.contains("invoice uuid already exists")
@@ -158,6 +210,10 @@ impl ApiError {
             return Self::new(ErrorCode::DuplicateInvoiceHash);
         }
 
+        if has_database_constraint(error, "idx_invoices_supplier_number") {
+            return Self::new(ErrorCode::DuplicateInvoiceNumber);
+        }
+
         if matches!(sqlx_error(error), Some(error) if matches!(error, sqlx::Error::RowNotFound)) {
             return Self::new(ErrorCode::DeviceNotFound);
         }
@@ -166,7 +222,53 @@ impl ApiError {
             return Self::internal();
         }
 
-        if error_text.contains("invoice hash mismatch") {
+        if let Some(failed) = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ValidationIssues>())
+        {
+            let code = if error_text.contains("code list violation") {
+                ErrorCode::InvalidCodeListValue
+            } else if error_text.contains("business rule violation") {
+                ErrorCode::BusinessRuleViolation
+            } else {
+                ErrorCode::InvoiceValidationFailed
+            };
+            return Self::new(code).with_issues(failed);
+        }
+
+        if error_text.contains("batch device mismatch") {
+            Self::new(ErrorCode::BatchDeviceMismatch)
+        } else if error_text.contains("duplicate invoice number") {
+            Self::new(ErrorCode::DuplicateInvoiceNumber)
+        } else if error_text.contains("invoice number out of sequence") {
+            Self::new(ErrorCode::InvoiceNumberOutOfSequence)
+        } else if error_text.contains("tax period locked") {
+            Self::new(ErrorCode::TaxPeriodLocked)
+        } else if error_text.contains("issue date or time unreadable") {
+            Self::new(ErrorCode::InvalidIssueDateTime)
+        } else if error_text.contains("issued in the future") {
+            Self::new(ErrorCode::IssueDateInFuture)
+        } else if error_text.contains("clearance submission late") {
+            Self::new(ErrorCode::ClearanceSubmissionLate)
+        } else if error_text.contains("unknown tax category") {
+            Self::new(ErrorCode::UnknownTaxCategory)
+        } else if error_text.contains("tax rate not allowed") {
+            Self::new(ErrorCode::TaxRateNotAllowed)
+        } else if error_text.contains("tax exemption reason code") {
+            Self::new(ErrorCode::TaxExemptionReasonInvalid)
+        } else if error_text.contains("tax amount does not match the rate") {
+            Self::new(ErrorCode::TaxAmountMismatch)
+        } else if error_text.contains("sdg tax total missing") {
+            Self::new(ErrorCode::TaxCurrencyTotalMissing)
+        } else if error_text.contains("exchange rate missing") {
+            Self::new(ErrorCode::ExchangeRateMissing)
+        } else if error_text.contains("sdg tax amount does not match the exchange rate") {
+            Self::new(ErrorCode::TaxCurrencyAmountMismatch)
+        } else if contains_any(&error_text, &["qr mismatch", "qr not found in invoice"]) {
+            Self::new(ErrorCode::QrInvoiceMismatch)
+        } else if error_text.contains("invoice qr is malformed") {
+            Self::new(ErrorCode::InvalidQrTlv)
+        } else if error_text.contains("invoice hash mismatch") {
             Self::new(ErrorCode::InvoiceHashMismatch)
         } else if error_text.contains("invoice type mismatch") {
             Self::new(ErrorCode::InvoiceTypeMismatch)
@@ -196,10 +298,61 @@ impl ApiError {
         }
     }
 
+    pub fn from_tax_period(error: &anyhow::Error) -> Self {
+        if has_database_constraint(error, "tax_periods_tin_fkey") {
+            return Self::new(ErrorCode::CompanyIdNotRegistered);
+        }
+
+        let error_text = error_chain_text(error);
+        if error_text.contains("tax period not found") {
+            Self::new(ErrorCode::TaxPeriodNotFound)
+        } else if error_text.contains("overlaps an existing period") {
+            Self::new(ErrorCode::TaxPeriodOverlap)
+        } else if error_text.contains("tax period cannot become") {
+            Self::new(ErrorCode::InvalidTaxPeriodTransition)
+        } else {
+            Self::internal()
+        }
+    }
+
+    pub fn from_number_series(error: &anyhow::Error) -> Self {
+        if has_database_constraint(error, "invoice_number_series_device_uuid_fkey") {
+            return Self::new(ErrorCode::DeviceNotFound);
+        }
+
+        if error_chain_text(error).contains("invalid number series") {
+            Self::new(ErrorCode::InvalidNumberSeries)
+        } else {
+            Self::internal()
+        }
+    }
+
+    pub fn from_batch_archive(error: &anyhow::Error) -> Self {
+        let error_text = error_chain_text(error);
+
+        if error_text.contains("too many invoices") {
+            Self::new(ErrorCode::BatchTooManyInvoices)
+        } else if error_text.contains("too large") {
+            Self::new(ErrorCode::RequestBodyTooLarge)
+        } else {
+            Self::new(ErrorCode::InvalidBatchArchive)
+        }
+    }
+
+    pub fn from_timestamp(error: &anyhow::Error) -> Self {
+        if is_cpu_pool_saturated(error) {
+            Self::new(ErrorCode::ServiceOverloaded)
+        } else {
+            Self::internal()
+        }
+    }
+
     pub fn from_qr(error: &anyhow::Error) -> Self {
         let error_text = error_chain_text(error);
 
-        if contains_any(
+        if error_text.contains("xml guard") {
+            Self::new(ErrorCode::UnsafeXml)
+        } else if contains_any(
             &error_text,
             &["invalid byte", "invalid padding", "encoded text"],
         ) {
@@ -210,12 +363,28 @@ impl ApiError {
             Self::new(ErrorCode::QrSignatureMissing)
         } else if error_text.contains("missing certificate tag") {
             Self::new(ErrorCode::QrCertificateMissing)
+        } else if error_text.contains("missing seller tin tag") {
+            Self::new(ErrorCode::QrSellerTinMissing)
         } else if contains_any(
             &error_text,
-            &["truncated tlv", "unsupported tlv", "tlv length overflow"],
+            &[
+                "truncated tlv",
+                "unsupported tlv",
+                "tlv length overflow",
+                "not valid utf-8",
+                "failed to parse the qr certificate",
+            ],
         ) {
             Self::new(ErrorCode::InvalidQrTlv)
-        } else if error_text.contains("certificate does not match") {
+        } else if contains_any(
+            &error_text,
+            &[
+                "certificate does not match",
+                "not issued by the stc ca",
+                "does not match the device certificate",
+                "expired or yet to be used",
+            ],
+        ) {
             Self::new(ErrorCode::QrCertificateMismatch)
         } else if error_text.contains("invalid qr signature") {
             Self::new(ErrorCode::QrSignatureInvalid)
@@ -223,6 +392,35 @@ impl ApiError {
             Self::new(ErrorCode::QrVerificationFailed)
         }
     }
+
+    /// For work on the CPU pool whose only failure a client can act on is
+    /// the pool being full.
+    pub fn from_cpu_job(error: &anyhow::Error) -> Self {
+        if is_cpu_pool_saturated(error) {
+            Self::new(ErrorCode::ServiceOverloaded)
+        } else {
+            Self::internal()
+        }
+    }
+
+    pub fn from_qr_image(error: &anyhow::Error) -> Self {
+        if is_cpu_pool_saturated(error) {
+            return Self::new(ErrorCode::ServiceOverloaded);
+        }
+
+        let error_text = error_chain_text(error);
+        if error_text.contains("xml guard") {
+            Self::new(ErrorCode::UnsafeXml)
+        } else if error_text.contains("qr not found in invoice") {
+            Self::new(ErrorCode::QrInvoiceMismatch)
+        } else if error_text.contains("too long for the requested error correction") {
+            Self::new(ErrorCode::InvalidQrImageOptions)
+        } else if contains_any(&error_text, &["invalid xml", "xml error"]) {
+            Self::new(ErrorCode::InvalidInvoiceXml)
+        } else {
+            Self::from_qr(error)
+        }
+    }
 }
 
 impl fmt::Display for ApiError {
@@ -237,12 +435,18 @@ impl ResponseError for ApiError {
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        HttpResponse::build(self.status_code()).json(ApiResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.is_overloaded() {
+            response.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS));
+        }
+        response.json(ApiResponse {
             success: false,
             message: self.code.message().to_string(),
             data: Some(ErrorData {
                 error: ErrorInfo {
                     code: self.code.as_str(),
+                    errors: self.issues.clone(),
+                    rules_version: self.rules_version.clone(),
                 },
             }),
         })
@@ -278,6 +482,7 @@ pub enum ErrorCode {
     InvalidInvoiceCertificate,
     InvalidSupplierTin,
     InvalidInvoiceXml,
+    UnsafeXml,
     InvalidInvoiceData,
     DuplicateInvoiceUuid,
     DuplicateInvoiceHash,
@@ -295,10 +500,46 @@ pub enum ErrorCode {
     QrHashMissing,
     QrSignatureMissing,
     QrCertificateMissing,
+    QrSellerTinMissing,
     InvalidQrTlv,
+    QrInvoiceMismatch,
+    InvalidQrImageOptions,
+    InvoiceNotFound,
+    UnsupportedXmlContentType,
     QrCertificateMismatch,
     QrSignatureInvalid,
     QrVerificationFailed,
+    ReceiptNotFound,
+    UnsupportedBatchContentType,
+    UnsupportedInvoiceContentType,
+    UnsupportedTimestampContentType,
+    BatchEmpty,
+    BatchTooManyInvoices,
+    InvalidBatchArchive,
+    BatchDeviceMismatch,
+    ServiceOverloaded,
+    TaxPeriodLocked,
+    TaxPeriodNotFound,
+    TaxPeriodOverlap,
+    InvalidTaxPeriodTransition,
+    AdminUnauthorized,
+    InvalidIssueDateTime,
+    IssueDateInFuture,
+    ClearanceSubmissionLate,
+    UnknownTaxCategory,
+    TaxRateNotAllowed,
+    TaxExemptionReasonInvalid,
+    TaxAmountMismatch,
+    InvalidCodeListValue,
+    TaxCurrencyTotalMissing,
+    ExchangeRateMissing,
+    TaxCurrencyAmountMismatch,
+    InvalidExchangeRateFile,
+    BusinessRuleViolation,
+    DuplicateInvoiceNumber,
+    InvoiceNumberOutOfSequence,
+    InvalidNumberSeries,
+    NumberSeriesNotFound,
 }
 
 impl ErrorCode {
@@ -331,6 +572,7 @@ impl ErrorCode {
             Self::InvalidInvoiceCertificate => "invalid_invoice_certificate",
             Self::InvalidSupplierTin => "invalid_supplier_tin",
             Self::InvalidInvoiceXml => "invalid_invoice_xml",
+            Self::UnsafeXml => "unsafe_xml",
             Self::InvalidInvoiceData => "invalid_invoice_data",
             Self::DuplicateInvoiceUuid => "duplicate_invoice_uuid",
             Self::DuplicateInvoiceHash => "duplicate_invoice_hash",
@@ -348,10 +590,46 @@ impl ErrorCode {
             Self::QrHashMissing => "qr_hash_missing",
             Self::QrSignatureMissing => "qr_signature_missing",
             Self::QrCertificateMissing => "qr_certificate_missing",
+            Self::QrSellerTinMissing => "qr_seller_tin_missing",
             Self::InvalidQrTlv => "invalid_qr_tlv",
+            Self::QrInvoiceMismatch => "qr_invoice_mismatch",
+            Self::InvalidQrImageOptions => "invalid_qr_image_options",
+            Self::InvoiceNotFound => "invoice_not_found",
+            Self::UnsupportedXmlContentType => "unsupported_xml_content_type",
             Self::QrCertificateMismatch => "qr_certificate_mismatch",
             Self::QrSignatureInvalid => "qr_signature_invalid",
             Self::QrVerificationFailed => "qr_verification_failed",
+            Self::ReceiptNotFound => "receipt_not_found",
+            Self::UnsupportedBatchContentType => "unsupported_batch_content_type",
+            Self::UnsupportedInvoiceContentType => "unsupported_invoice_content_type",
+            Self::UnsupportedTimestampContentType => "unsupported_timestamp_content_type",
+            Self::BatchEmpty => "batch_empty",
+            Self::BatchTooManyInvoices => "batch_too_many_invoices",
+            Self::InvalidBatchArchive => "invalid_batch_archive",
+            Self::BatchDeviceMismatch => "batch_device_mismatch",
+            Self::ServiceOverloaded => "service_overloaded",
+            Self::TaxPeriodLocked => "tax_period_locked",
+            Self::TaxPeriodNotFound => "tax_period_not_found",
+            Self::TaxPeriodOverlap => "tax_period_overlap",
+            Self::InvalidTaxPeriodTransition => "invalid_tax_period_transition",
+            Self::AdminUnauthorized => "admin_unauthorized",
+            Self::InvalidIssueDateTime => "invalid_issue_date_time",
+            Self::IssueDateInFuture => "issue_date_in_future",
+            Self::ClearanceSubmissionLate => "clearance_submission_late",
+            Self::UnknownTaxCategory => "unknown_tax_category",
+            Self::TaxRateNotAllowed => "tax_rate_not_allowed",
+            Self::TaxExemptionReasonInvalid => "tax_exemption_reason_invalid",
+            Self::TaxAmountMismatch => "tax_amount_mismatch",
+            Self::InvalidCodeListValue => "invalid_code_list_value",
+            Self::TaxCurrencyTotalMissing => "tax_currency_total_missing",
+            Self::ExchangeRateMissing => "exchange_rate_missing",
+            Self::TaxCurrencyAmountMismatch => "tax_currency_amount_mismatch",
+            Self::InvalidExchangeRateFile => "invalid_exchange_rate_file",
+            Self::BusinessRuleViolation => "business_rule_violation",
+            Self::DuplicateInvoiceNumber => "duplicate_invoice_number",
+            Self::InvoiceNumberOutOfSequence => "invoice_number_out_of_sequence",
+            Self::InvalidNumberSeries => "invalid_number_series",
+            Self::NumberSeriesNotFound => "number_series_not_found",
         }
     }
 
@@ -384,6 +662,7 @@ impl ErrorCode {
             Self::InvalidInvoiceCertificate => "Invoice certificate is invalid",
             Self::InvalidSupplierTin => "Invoice supplier TIN is missing or invalid",
             Self::InvalidInvoiceXml => "Invoice XML is invalid",
+            Self::UnsafeXml => "Document contains a DTD or exceeds the configured parser limits",
             Self::InvalidInvoiceData => "Invalid invoice data",
             Self::DuplicateInvoiceUuid => "Invoice UUID already exists",
             Self::DuplicateInvoiceHash => "Invoice was already submitted",
@@ -403,29 +682,117 @@ impl ErrorCode {
             Self::QrHashMissing => "QR payload is missing the invoice hash",
             Self::QrSignatureMissing => "QR payload is missing the signature",
             Self::QrCertificateMissing => "QR payload is missing the certificate",
+            Self::QrSellerTinMissing => "Device-signed QR payload is missing the seller TIN",
             Self::InvalidQrTlv => "QR payload is malformed",
-            Self::QrCertificateMismatch => "QR certificate does not match this server",
+            Self::QrInvoiceMismatch => "Invoice QR is missing or does not match the invoice",
+            Self::InvalidQrImageOptions => {
+                "QR image format must be png or svg, size 64-2048 pixels, and error correction L, M, Q or H, with room for the QR payload"
+            }
+            Self::InvoiceNotFound => "No invoice exists with this UUID",
+            Self::UnsupportedXmlContentType => "Content-Type must be application/xml",
+            Self::QrCertificateMismatch => {
+                "QR certificate is neither the STC certificate nor a valid device certificate for the seller"
+            }
             Self::QrSignatureInvalid => "QR signature is invalid",
             Self::QrVerificationFailed => "QR verification failed",
+            Self::ReceiptNotFound => "No receipt exists for this invoice",
+            Self::UnsupportedBatchContentType => {
+                "Content-Type must be application/json or application/zip"
+            }
+            Self::UnsupportedInvoiceContentType => {
+                "Content-Type must be application/json or application/xml"
+            }
+            Self::UnsupportedTimestampContentType => {
+                "Content-Type must be application/timestamp-query"
+            }
+            Self::BatchEmpty => "Batch contains no invoices",
+            Self::BatchTooManyInvoices => "Batch contains too many invoices",
+            Self::InvalidBatchArchive => "Batch archive is invalid",
+            Self::BatchDeviceMismatch => "All invoices in a batch must come from the same device",
+            Self::ServiceOverloaded => "Server is busy. Retry shortly.",
+            Self::TaxPeriodLocked => {
+                "Invoice IssueDate falls in a locked tax period; only credit or debit notes referencing that period are accepted"
+            }
+            Self::TaxPeriodNotFound => "No tax period exists with this ID",
+            Self::TaxPeriodOverlap => "Tax period overlaps an existing period",
+            Self::InvalidTaxPeriodTransition => {
+                "Tax periods move from open to filed to locked, one step at a time"
+            }
+            Self::AdminUnauthorized => "A valid admin token is required",
+            Self::InvalidIssueDateTime => "Invoice IssueDate or IssueTime is missing or unreadable",
+            Self::IssueDateInFuture => "Invoice IssueDate and IssueTime are in the future",
+            Self::ClearanceSubmissionLate => {
+                "Clearance invoice was submitted too long after its IssueDate and IssueTime"
+            }
+            Self::UnknownTaxCategory => "Invoice uses a tax category that is not registered",
+            Self::TaxRateNotAllowed => {
+                "Invoice tax percent is not a rate of its category on the issue date"
+            }
+            Self::TaxExemptionReasonInvalid => {
+                "Tax exemption reason code is missing or not allowed for its category"
+            }
+            Self::TaxAmountMismatch => {
+                "Invoice tax amount does not match its taxable amount and rate"
+            }
+            Self::InvalidCodeListValue => "Invoice uses codes that are not in their code lists",
+            Self::TaxCurrencyTotalMissing => {
+                "Foreign-currency invoice must declare TaxCurrencyCode SDG and an SDG tax total"
+            }
+            Self::ExchangeRateMissing => {
+                "No exchange rate is published for the invoice currency and issue date"
+            }
+            Self::TaxCurrencyAmountMismatch => {
+                "Invoice SDG tax total does not match the published exchange rate"
+            }
+            Self::InvalidExchangeRateFile => {
+                "Exchange rate file must be a currency,date,rate CSV of valid rates"
+            }
+            Self::BusinessRuleViolation => "Invoice breaks one or more business rules",
+            Self::DuplicateInvoiceNumber => {
+                "Supplier already used this invoice number for the same document type"
+            }
+            Self::InvoiceNumberOutOfSequence => {
+                "Invoice number is not the next number of the device's number series"
+            }
+            Self::InvalidNumberSeries => {
+                "Number series needs a next_number of at least 1 and a prefix without whitespace"
+            }
+            Self::NumberSeriesNotFound => "No number series is enforced for this device",
         }
     }
 
     const fn status(self) -> StatusCode {
         match self {
-            Self::UnsupportedContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
-            Self::RequestBodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedContentType
+            | Self::UnsupportedBatchContentType
+            | Self::UnsupportedInvoiceContentType
+            | Self::UnsupportedTimestampContentType
+            | Self::UnsupportedXmlContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::RequestBodyTooLarge | Self::BatchTooManyInvoices => StatusCode::PAYLOAD_TOO_LARGE,
             Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::InvalidCredentials | Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Self::InvalidCredentials | Self::Unauthenticated | Self::AdminUnauthorized => {
+                StatusCode::UNAUTHORIZED
+            }
             Self::CompanyIdNotRegistered
             | Self::DeviceNotFound
             | Self::SupplierTinNotRegistered
-            | Self::CustomerTinNotRegistered => StatusCode::NOT_FOUND,
+            | Self::CustomerTinNotRegistered
+            | Self::ReceiptNotFound
+            | Self::InvoiceNotFound
+            | Self::TaxPeriodNotFound
+            | Self::NumberSeriesNotFound => StatusCode::NOT_FOUND,
             Self::DeviceAlreadyEnrolled
             | Self::DuplicateInvoiceUuid
             | Self::DuplicateInvoiceHash
             | Self::InvoiceSequenceMismatch
-            | Self::InvoiceChainMismatch => StatusCode::CONFLICT,
+            | Self::InvoiceChainMismatch
+            | Self::TaxPeriodLocked
+            | Self::TaxPeriodOverlap
+            | Self::InvalidTaxPeriodTransition
+            | Self::DuplicateInvoiceNumber
+            | Self::InvoiceNumberOutOfSequence => StatusCode::CONFLICT,
             Self::DeviceInactive => StatusCode::FORBIDDEN,
+            Self::ServiceOverloaded => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::BAD_REQUEST,
         }
     }
@@ -445,6 +812,10 @@ fn error_chain_text(error: &anyhow::Error) -> String {
         .to_ascii_lowercase()
 }
 
+fn is_cpu_pool_saturated(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<CpuPoolSaturated>())
+}
+
 fn sqlx_error(error: &anyhow::Error) -> Option<&sqlx::Error> {
     error
         .chain()
@@ -3,23 +3,44 @@ use actix_web::cookie::Key;
 use actix_web::{App, HttpMessage, HttpResponse, HttpServer, dev::Service, http::header, web};
 use stc_server::{
     config::crypto_config::Crypto,
-    config::{db_config, xsd_config::schema_validator_from_temp},
+    config::{
+        admin_config::AdminConfig, batch_config::BatchLimits, cpu_pool_config::CpuPoolConfig,
+        db_config, signature_config::SignatureAlgorithms, tsa_config::Tsa,
+        validation_config::ValidationPolicy, xml_config::XmlLimits,
+        xsd_config::schema_validator_from_temp,
+    },
     docs::ApiDoc,
     errors::json_error_handler,
     routes::{
+        admin::{
+            admin_add_tax_period, admin_delete_number_series, admin_exchange_rates,
+            admin_file_tax_period, admin_import_exchange_rates, admin_lock_tax_period,
+            admin_number_series, admin_set_number_series, admin_tax_category_breakdown,
+            admin_tax_periods, admin_top_items,
+        },
         enroll::enroll,
         health_check::health_check,
         invoice_controller::{
-            clearance_prod, clearance_sandbox, reporting_prod, reporting_sandbox,
+            clearance_prod, clearance_sandbox, invoice_receipt, reporting_batch_prod,
+            reporting_batch_sandbox, reporting_prod, reporting_sandbox,
         },
+        invoice_pdf::invoice_pdf,
+        metrics::cpu_pool_metrics,
         pages::{e_invoicing_page, home, login_page, sandbox_page},
+        qr_image::{invoice_qr_image, uploaded_invoice_qr_image},
         taxpayer_portal::{
-            generate_enrollment_token, invoice_report, prepare_invoice_payload, sign_in, sign_out,
-            taxpayer_me,
+            add_tax_period, export_vat_return, file_tax_period, generate_enrollment_token,
+            invoice_report, lock_tax_period, prepare_invoice_payload, sign_in, sign_out,
+            tax_category_breakdown, tax_periods, taxpayer_invoice_pdf, taxpayer_me, top_items,
+            vat_return,
         },
+        tsa::timestamp,
         verify_qr::verify_qr,
     },
-    services::db::token_checking::token_cleanup_loop,
+    services::{
+        cpu_pool::CpuPool,
+        db::{invoice_metadata::invoice_metadata_backfill, token_checking::token_cleanup_loop},
+    },
 };
 use tracing_actix_web::{RequestId, TracingLogger};
 use tracing_subscriber::fmt::format::FmtSpan;
@@ -67,16 +88,40 @@ async fn main() -> std::io::Result<()> {
     migrator.run(&pool).await.expect("Failed to run migrations");
 
     tokio::spawn(token_cleanup_loop(pool.clone()));
+    tokio::spawn(invoice_metadata_backfill(pool.clone()));
 
     let crypto_config = match Crypto::from_env().await {
         Ok(crypto_config) => crypto_config,
         Err(e) => panic!("Error in the reading of the crypto_config from env :{}", e),
     };
+    let tsa = Tsa::from_env(&crypto_config)
+        .unwrap_or_else(|e| panic!("Error in setting up the time-stamping authority :{}", e));
     let xsd_schema = schema_validator_from_temp()
         .unwrap_or_else(|e| panic!("failed to obtain the XSD schema : {}", e));
     let crypto_data = web::Data::new(crypto_config);
+    let tsa_data = web::Data::new(tsa);
     let pool_data = web::Data::new(pool);
     let xsd_schema = web::Data::new(xsd_schema);
+    let batch_limits = web::Data::new(BatchLimits::from_env());
+    let xml_limits = web::Data::new(XmlLimits::from_env());
+    let admin_config = web::Data::new(AdminConfig::from_env());
+    let validation_policy = ValidationPolicy::from_env()
+        .unwrap_or_else(|e| panic!("Error in the validation policy configuration :{:#}", e));
+    let validation_policy = web::Data::new(validation_policy);
+    let signature_algorithms = SignatureAlgorithms::from_env().unwrap_or_else(|e| {
+        panic!(
+            "Error in the reading of the signature algorithm allow-list :{:#}",
+            e
+        )
+    });
+    let signature_algorithms = web::Data::new(signature_algorithms);
+    let cpu_pool_config = CpuPoolConfig::from_env();
+    tracing::info!(
+        workers = cpu_pool_config.workers,
+        max_queue = cpu_pool_config.max_queue,
+        "CPU pool configured"
+    );
+    let cpu_pool = web::Data::new(CpuPool::new(cpu_pool_config));
     let session_key = match std::env::var("SESSION_SECRET") {
         Ok(val) => Key::from(val.as_bytes()),
         Err(_) => {
@@ -115,6 +160,13 @@ async fn main() -> std::io::Result<()> {
             .app_data(xsd_schema.clone())
             .app_data(pool_data.clone())
             .app_data(crypto_data.clone())
+            .app_data(tsa_data.clone())
+            .app_data(batch_limits.clone())
+            .app_data(xml_limits.clone())
+            .app_data(signature_algorithms.clone())
+            .app_data(cpu_pool.clone())
+            .app_data(admin_config.clone())
+            .app_data(validation_policy.clone())
             .app_data(
                 web::JsonConfig::default()
                     .limit(256 * 1024)
@@ -131,6 +183,74 @@ async fn main() -> std::io::Result<()> {
                 web::post().to(generate_enrollment_token),
             )
             .route("/e-invoicing/invoices", web::post().to(invoice_report))
+            .route(
+                "/e-invoicing/invoices/{uuid}/pdf",
+                web::get().to(taxpayer_invoice_pdf),
+            )
+            .route("/e-invoicing/analytics/top-items", web::get().to(top_items))
+            .route(
+                "/e-invoicing/analytics/tax-categories",
+                web::get().to(tax_category_breakdown),
+            )
+            .route("/e-invoicing/vat-return", web::get().to(vat_return))
+            .route(
+                "/e-invoicing/vat-return/export",
+                web::get().to(export_vat_return),
+            )
+            .route("/e-invoicing/tax-periods", web::get().to(tax_periods))
+            .route("/e-invoicing/tax-periods", web::post().to(add_tax_period))
+            .route(
+                "/e-invoicing/tax-periods/{id}/file",
+                web::post().to(file_tax_period),
+            )
+            .route(
+                "/e-invoicing/tax-periods/{id}/lock",
+                web::post().to(lock_tax_period),
+            )
+            .service(
+                web::scope("/admin")
+                    .route(
+                        "/taxpayers/{tin}/tax-periods",
+                        web::get().to(admin_tax_periods),
+                    )
+                    .route(
+                        "/taxpayers/{tin}/tax-periods",
+                        web::post().to(admin_add_tax_period),
+                    )
+                    .route(
+                        "/tax-periods/{id}/file",
+                        web::post().to(admin_file_tax_period),
+                    )
+                    .route(
+                        "/tax-periods/{id}/lock",
+                        web::post().to(admin_lock_tax_period),
+                    )
+                    .route(
+                        "/taxpayers/{tin}/analytics/top-items",
+                        web::get().to(admin_top_items),
+                    )
+                    .route(
+                        "/taxpayers/{tin}/analytics/tax-categories",
+                        web::get().to(admin_tax_category_breakdown),
+                    )
+                    .route("/exchange-rates", web::get().to(admin_exchange_rates))
+                    .route(
+                        "/exchange-rates",
+                        web::post().to(admin_import_exchange_rates),
+                    )
+                    .route(
+                        "/devices/{device_uuid}/number-series",
+                        web::get().to(admin_number_series),
+                    )
+                    .route(
+                        "/devices/{device_uuid}/number-series",
+                        web::put().to(admin_set_number_series),
+                    )
+                    .route(
+                        "/devices/{device_uuid}/number-series",
+                        web::delete().to(admin_delete_number_series),
+                    ),
+            )
             .route("/sandbox", web::get().to(sandbox_page))
             .route(
                 "/sandbox/invoice-payload",
@@ -146,12 +266,17 @@ async fn main() -> std::io::Result<()> {
             )
             .service(SwaggerUi::new("/api/{_:.*}").url("/api/openapi.json", ApiDoc::openapi()))
             .route("/health_check", web::get().to(health_check))
+            .route("/metrics/cpu_pool", web::get().to(cpu_pool_metrics))
             .service(
                 web::scope("/prod")
                     .service(
                         web::scope("/invoices")
                             .route("/clear", web::post().to(clearance_prod))
-                            .route("/report", web::post().to(reporting_prod)),
+                            .route("/report", web::post().to(reporting_prod))
+                            .route("/report/batch", web::post().to(reporting_batch_prod))
+                            .route("/{uuid}/receipt", web::get().to(invoice_receipt))
+                            .route("/{uuid}/qr", web::get().to(invoice_qr_image))
+                            .route("/{uuid}/pdf", web::get().to(invoice_pdf)),
                     )
                     .route("/enrollment/enroll", web::post().to(enroll)),
             )
@@ -159,10 +284,13 @@ async fn main() -> std::io::Result<()> {
                 web::scope("/sandbox").service(
                     web::scope("/invoices")
                         .route("/clear", web::post().to(clearance_sandbox))
-                        .route("/report", web::post().to(reporting_sandbox)),
+                        .route("/report", web::post().to(reporting_sandbox))
+                        .route("/report/batch", web::post().to(reporting_batch_sandbox)),
                 ),
             )
             .route("/verify_qr", web::post().to(verify_qr))
+            .route("/invoices/qr", web::post().to(uploaded_invoice_qr_image))
+            .route("/tsa", web::post().to(timestamp))
     })
     .bind(("0.0.0.0", port))?
     .run()
@@ -0,0 +1,64 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::models::{receipt::InvoiceReceiptDto, responses::ValidationIssue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Accepted,
+    Rejected,
+    /// Not attempted because an earlier invoice in the batch was rejected.
+    NotProcessed,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchItemError {
+    #[schema(example = "invoice_sequence_mismatch")]
+    pub code: &'static str,
+    #[schema(example = "Invoice sequence is out of order")]
+    pub message: &'static str,
+    /// Failed checks with their XPath, as in single-invoice error responses.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationIssue>,
+    /// Business rule set version, as in single-invoice error responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rules_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchItemResultDto {
+    /// Zero-based position of the invoice in the submitted batch.
+    #[schema(example = 0)]
+    pub index: usize,
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub uuid: String,
+    pub status: BatchItemStatus,
+    pub receipt: Option<InvoiceReceiptDto>,
+    pub error: Option<BatchItemError>,
+}
+
+/// Where the device should restart after a batch stopped at a rejected invoice.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResumeDto {
+    /// Index of the first invoice that was not accepted.
+    #[schema(example = 3)]
+    pub index: usize,
+    /// Next ICV the chain expects; null when the device could not be determined.
+    #[schema(example = 46)]
+    pub icv: Option<i32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchReportDto {
+    #[schema(example = 10)]
+    pub total: usize,
+    #[schema(example = 3)]
+    pub accepted: usize,
+    pub items: Vec<BatchItemResultDto>,
+    /// Version of the business rules the batch was validated against.
+    #[schema(example = "2026.1")]
+    pub rules_version: String,
+    /// Present only when the batch stopped before the last invoice.
+    pub resume_from: Option<BatchResumeDto>,
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExchangeRateDto {
+    pub currency: String,
+    pub rate_date: String,
+    /// SDG per unit of `currency`.
+    pub rate: String,
+    pub imported_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeRateImportDto {
+    /// Rows inserted or updated.
+    pub imported: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExchangeRateQuery {
+    #[serde(default)]
+    pub currency: Option<String>,
+}
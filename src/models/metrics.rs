@@ -0,0 +1,25 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Point-in-time view of the CPU pool used by the invoice pipeline.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CpuPoolStats {
+    #[schema(example = 8)]
+    pub workers: usize,
+    /// Jobs currently running.
+    #[schema(example = 8)]
+    pub busy: usize,
+    /// Jobs waiting for a worker.
+    #[schema(example = 12)]
+    pub queued: usize,
+    #[schema(example = 128)]
+    pub max_queue: usize,
+    /// `(busy + queued) / (workers + max_queue)`; new work is shed at `1.0`.
+    #[schema(example = 0.15)]
+    pub saturation: f64,
+    #[schema(example = 10452)]
+    pub completed_total: u64,
+    /// Jobs rejected with `503 Service Unavailable` since startup.
+    #[schema(example = 3)]
+    pub rejected_total: u64,
+}
@@ -1,6 +1,14 @@
+pub mod batch_report;
 pub mod device;
 pub mod enrollment;
+pub mod exchange_rate;
+pub mod metrics;
+pub mod number_series;
+pub mod qr_image;
 pub mod qr_verification;
+pub mod receipt;
 pub mod responses;
 pub mod submit_invoice;
+pub mod tax_period;
 pub mod taxpayer_portal;
+pub mod vat_return;
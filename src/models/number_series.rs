@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NumberSeriesDto {
+    pub device_uuid: String,
+    pub prefix: String,
+    /// Number the device's next invoice must carry after `prefix`.
+    pub next_number: i64,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SetNumberSeriesDto {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub next_number: Option<i64>,
+}
@@ -0,0 +1,25 @@
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::services::xml::qr_image::QrImageOptions;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QrImageQuery {
+    /// `png` (default) or `svg`.
+    pub format: Option<String>,
+    /// Width and height in pixels, 64 to 2048 (default 256).
+    pub size: Option<u32>,
+    /// Error-correction level: `L`, `M` (default), `Q` or `H`.
+    pub ecc: Option<String>,
+}
+
+impl QrImageQuery {
+    pub fn options(&self) -> anyhow::Result<QrImageOptions> {
+        QrImageOptions::new(
+            self.format.as_deref().unwrap_or("png").parse()?,
+            self.size.unwrap_or(256),
+            self.ecc.as_deref().unwrap_or("M").parse()?,
+        )
+    }
+}
@@ -1,6 +1,63 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct QrVerificationDto {
+    #[schema(example = "BASE64_QR_TLV")]
     pub qr_b64: String,
 }
+
+/// Who signed the QR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QrSignerKind {
+    /// The STC, on a cleared invoice.
+    Stc,
+    /// An enrolled device, on a reported invoice.
+    Device,
+}
+
+/// Identity taken from the certificate in QR tag 8.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QrSignerDto {
+    pub kind: QrSignerKind,
+    #[schema(example = "EGS1-886431145")]
+    pub common_name: Option<String>,
+    /// Subject `organizationName`; the supplier TIN on device certificates.
+    #[schema(example = "399999999900003")]
+    pub organization: Option<String>,
+    /// Subject `serialNumber`; the device UUID on device certificates.
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub device_uuid: Option<String>,
+    pub issuer: Option<String>,
+    /// Certificate serial number, hex.
+    pub serial_number: String,
+    #[schema(example = "Jun  8 10:15:30 2027 GMT")]
+    pub not_after: String,
+}
+
+/// Invoice facts carried in QR tags 1-6, as encoded.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct QrFactsDto {
+    #[schema(example = "Smith & Sons")]
+    pub seller_name: Option<String>,
+    #[schema(example = "399999999900003")]
+    pub seller_tin: Option<String>,
+    #[schema(example = "2026-06-08T10:15:30Z")]
+    pub timestamp: Option<String>,
+    #[schema(example = "1150.00")]
+    pub invoice_total: Option<String>,
+    #[schema(example = "150.00")]
+    pub vat_total: Option<String>,
+    /// Base64 invoice hash.
+    #[schema(example = "BASE64_SHA256_HASH_OF_CANONICAL_INVOICE")]
+    pub invoice_hash: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QrVerificationResultDto {
+    pub facts: QrFactsDto,
+    pub signer: QrSignerDto,
+    /// Whether an invoice with this hash is stored in `invoices`.
+    pub invoice_registered: bool,
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Facts STC attests to when it accepts a reported invoice.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct InvoiceReceipt {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub invoice_uuid: String,
+    #[schema(example = "BASE64_SHA256_HASH_OF_CANONICAL_INVOICE")]
+    pub invoice_hash: String,
+    #[schema(example = "6f1c2a4e-8d3b-4f0a-9c1e-2b7d5e9a0f13")]
+    pub device_uuid: String,
+    #[schema(example = 42)]
+    pub icv: i32,
+    #[schema(example = "2026-06-08T10:15:30Z")]
+    pub received_at: String,
+    #[schema(example = "2026-06-08T10:15:31Z")]
+    pub accepted_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InvoiceReceiptDto {
+    pub receipt: InvoiceReceipt,
+    /// Compact JWS (RS256) over the receipt, signed with the STC key.
+    #[schema(example = "eyJhbGciOiJSUzI1NiJ9.eyJpbnZvaWNlX3V1aWQiOiIuLi4ifQ.SIGNATURE")]
+    pub signed_receipt: String,
+    /// Version of the business rules the invoice was validated against.
+    /// Omitted when a stored receipt is looked up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2026.1")]
+    pub rules_version: Option<String>,
+}
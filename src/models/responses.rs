@@ -25,4 +25,24 @@ pub struct ErrorData {
 pub struct ErrorInfo {
     #[schema(value_type = String, example = "invalid_invoice_data")]
     pub code: &'static str,
+    /// Each failed check with the XPath it applies to, for errors that list
+    /// them. Omitted otherwise.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationIssue>,
+    /// Business rule set version, for `business_rule_violation`. Omitted
+    /// otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2026.1")]
+    pub rules_version: Option<String>,
+}
+
+/// One failed check, located by the XPath of the offending node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ValidationIssue {
+    #[schema(example = "unknown_currency_code")]
+    pub code: String,
+    #[schema(example = "\"XYZ\" is not in the ISO 4217 currency codes")]
+    pub message: String,
+    #[schema(example = "/Invoice/cbc:DocumentCurrencyCode")]
+    pub xpath: String,
 }
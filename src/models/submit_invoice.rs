@@ -6,15 +6,21 @@ use base64::engine::general_purpose;
 
 use openssl::x509::X509;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgPool, types::time::OffsetDateTime};
 use tracing::instrument;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::config::signature_config::SignatureAlgorithms;
+use crate::config::xml_config::XmlLimits;
 use crate::models::device::Device;
+use crate::services::cpu_pool::CpuPool;
+use crate::services::crypto::pki_service::compute_hash;
+use crate::services::crypto::xades_bes::signed_invoice_content;
 use crate::services::db::device_service::get_device;
-use crate::services::xml::c14n11::canonicalize_c14n11;
-use crate::services::xml::extractors::{extract_crt, extract_invoice, extract_supplier_id};
+use crate::services::pipeline::receipt_service::receipt_now;
+use crate::services::xml::guard::check_xml;
+use crate::services::xml::invoice_document::InvoiceDocument;
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow, ToSchema)]
 pub struct SubmitInvoiceDto {
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
@@ -29,9 +35,12 @@ pub struct SubmitInvoiceDto {
 pub struct ClearedInvoiceDto {
     #[schema(example = "BASE64_CLEARED_INVOICE_XML")]
     pub cleared_invoice: String,
+    /// Version of the business rules the invoice was validated against.
+    #[schema(example = "2026.1")]
+    pub rules_version: String,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InvoiceType {
     Reporting,
     Clearance,
@@ -46,26 +55,126 @@ impl InvoiceType {
     }
 }
 
+/// Why an accepted invoice is stored for follow-up. Flags never reject an
+/// invoice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvoiceFlag {
+    /// Issued inside a tax period the taxpayer has already filed.
+    FiledPeriod,
+    /// Credit or debit note correcting an invoice of a locked tax period.
+    LockedPeriodCorrection,
+    /// Reporting invoice received after the statutory window.
+    LateReport { late_by_seconds: i64 },
+    /// Failed one or more `warning` business rules, by rule code.
+    RuleWarning { codes: Vec<String> },
+    /// Reuses an invoice number and document type of the same supplier.
+    DuplicateInvoiceNumber,
+}
+
+impl InvoiceFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceFlag::FiledPeriod => "filed_period",
+            InvoiceFlag::LockedPeriodCorrection => "locked_period_correction",
+            InvoiceFlag::LateReport { .. } => "late_report",
+            InvoiceFlag::RuleWarning { .. } => "rule_warning",
+            InvoiceFlag::DuplicateInvoiceNumber => "duplicate_invoice_number",
+        }
+    }
+}
+
 pub struct IntermediateInvoiceDto {
     pub uuid: Uuid,
     pub invoice_bytes: Vec<u8>,
+    pub document: InvoiceDocument,
     pub canonicalized_invoice_bytes: Vec<u8>,
     pub invoice_hash: Vec<u8>,
     pub certificate: X509,
     pub supplier: String,
     pub device: Device,
+    pub received_at: OffsetDateTime,
 }
 
 impl SubmitInvoiceDto {
-    #[instrument(skip(self, pool), fields(uuid = %self.uuid, invoice_b64_len = self.invoice.len()))]
-    pub async fn parse(self, pool: &PgPool) -> anyhow::Result<IntermediateInvoiceDto> {
+    /// Builds a submission from raw invoice XML, deriving the UUID from
+    /// `cbc:UUID` and the hash from the content the signature covers. Values
+    /// that cannot be derived are left empty so `parse` reports the precise
+    /// error; documents the XML guard rejects are not parsed at all.
+    pub fn from_xml(
+        invoice_bytes: &[u8],
+        xml_limits: &XmlLimits,
+        algorithms: &SignatureAlgorithms,
+    ) -> Self {
+        let document = check_xml(invoice_bytes, xml_limits)
+            .and_then(|()| InvoiceDocument::parse(invoice_bytes))
+            .ok();
+        let uuid = document
+            .as_ref()
+            .map(|document| document.uuid.clone())
+            .unwrap_or_default();
+        let invoice_hash = document
+            .map(|document| {
+                signed_invoice_content(&document, invoice_bytes, algorithms)
+                    .and_then(|content| compute_hash(&content))
+                    .map(|hash| general_purpose::STANDARD.encode(hash))
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+
+        Self {
+            uuid,
+            invoice_hash,
+            invoice: general_purpose::STANDARD.encode(invoice_bytes),
+        }
+    }
+
+    #[instrument(skip(self, pool, cpu, xml_limits, algorithms), fields(uuid = %self.uuid, invoice_b64_len = self.invoice.len()))]
+    pub async fn parse(
+        self,
+        pool: &PgPool,
+        cpu: &CpuPool,
+        xml_limits: &XmlLimits,
+        algorithms: &SignatureAlgorithms,
+    ) -> anyhow::Result<IntermediateInvoiceDto> {
+        let received_at = receipt_now();
+        let xml_limits = *xml_limits;
+        let algorithms = algorithms.clone();
+        let decoded = cpu
+            .run(move || self.decode(&xml_limits, &algorithms))
+            .await?;
+        let device = get_device(&decoded.certificate, pool).await?;
+        Ok(IntermediateInvoiceDto {
+            uuid: decoded.uuid,
+            invoice_bytes: decoded.invoice_bytes,
+            document: decoded.document,
+            canonicalized_invoice_bytes: decoded.canonicalized_invoice_bytes,
+            invoice_hash: decoded.invoice_hash,
+            certificate: decoded.certificate,
+            supplier: decoded.supplier,
+            device,
+            received_at,
+        })
+    }
+
+    /// CPU-bound part of `parse`: decoding, the XML guard, the single XML
+    /// pass, certificate extraction and the signed content behind the hash.
+    fn decode(
+        self,
+        xml_limits: &XmlLimits,
+        algorithms: &SignatureAlgorithms,
+    ) -> anyhow::Result<DecodedSubmission> {
         let invoice_bytes = general_purpose::STANDARD
             .decode(self.invoice)
             .context("failed to decode the the invoice")?;
-        let certificate =
-            extract_crt(&invoice_bytes).context("failed to extract the certificate")?;
-        let canonicalized_invoice_bytes = canonicalize_c14n11(extract_invoice(&invoice_bytes)?)
-            .context("failed to canonicalize the invoice")?;
+        check_xml(&invoice_bytes, xml_limits)?;
+        let document = InvoiceDocument::parse(&invoice_bytes)?;
+        let certificate = document
+            .signature()
+            .and_then(|signature| signature.certificate())
+            .context("failed to extract the certificate")?;
+        let canonicalized_invoice_bytes =
+            signed_invoice_content(&document, &invoice_bytes, algorithms)
+                .context("failed to canonicalize the invoice")?;
 
         let invoice_hash = general_purpose::STANDARD
             .decode(self.invoice_hash)
@@ -80,17 +189,25 @@ impl SubmitInvoiceDto {
 
         let uuid = Uuid::from_str(&self.uuid)
             .context("failed to optain a valid uuid from the provided uuid")?;
-        let supplier = extract_supplier_id(&invoice_bytes)
-            .context("failed to extract the company id from the invoice")?;
-        let device = get_device(&certificate, pool).await?;
-        Ok(IntermediateInvoiceDto {
+        let supplier = document.supplier.tin.clone();
+        Ok(DecodedSubmission {
             uuid,
             invoice_bytes,
+            document,
             canonicalized_invoice_bytes,
             invoice_hash,
             certificate,
             supplier,
-            device,
         })
     }
 }
+
+struct DecodedSubmission {
+    uuid: Uuid,
+    invoice_bytes: Vec<u8>,
+    document: InvoiceDocument,
+    canonicalized_invoice_bytes: Vec<u8>,
+    invoice_hash: Vec<u8>,
+    certificate: X509,
+    supplier: String,
+}
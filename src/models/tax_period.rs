@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TaxPeriodDto {
+    pub id: String,
+    pub tin: String,
+    /// First and last day of the period, both inclusive.
+    pub period_from: String,
+    pub period_to: String,
+    /// `open`, `filed` or `locked`.
+    pub status: String,
+    pub filed_at: Option<String>,
+    pub locked_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateTaxPeriodDto {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+}
@@ -87,6 +87,42 @@ pub struct InvoiceReportSummaryDto {
     pub latest_invoice_at: Option<String>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct InvoiceAnalyticsQueryDto {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// Amounts are decimal strings; credit notes are subtracted.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TopItemDto {
+    pub item_name: Option<String>,
+    pub classification_code: Option<String>,
+    pub unit_code: Option<String>,
+    pub currency: Option<String>,
+    pub quantity: Option<String>,
+    pub net_amount: Option<String>,
+    pub tax_amount: Option<String>,
+    pub lines: i64,
+    pub invoices: i64,
+}
+
+/// Amounts are decimal strings; credit notes are subtracted.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TaxCategoryBreakdownDto {
+    pub currency: Option<String>,
+    pub tax_category: Option<String>,
+    pub tax_percent: Option<String>,
+    pub taxable_amount: Option<String>,
+    pub tax_amount: Option<String>,
+    pub lines: i64,
+    pub invoices: i64,
+}
+
 #[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct InvoiceReportRowDto {
     pub uuid: String,
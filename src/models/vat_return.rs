@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// VAT return computed from the invoices a taxpayer issued (output VAT) and
+/// received as the buyer (input VAT) in a period. Amounts are decimal
+/// strings in the invoice currency; credit notes are subtracted and debit
+/// notes added.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VatReturn {
+    pub tin: String,
+    /// First and last issue date covered, both inclusive.
+    pub period_from: String,
+    pub period_to: String,
+    /// Always `draft`: the return is a pre-fill the taxpayer still files.
+    pub status: String,
+    pub generated_at: String,
+    pub output: Vec<VatReturnLine>,
+    pub input: Vec<VatReturnLine>,
+    pub totals: Vec<VatReturnTotals>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, sqlx::FromRow)]
+pub struct VatReturnLine {
+    pub currency: Option<String>,
+    pub tax_category: Option<String>,
+    pub tax_percent: Option<String>,
+    pub taxable_amount: String,
+    pub tax_amount: String,
+    pub invoices: i64,
+    pub credit_notes: i64,
+    pub debit_notes: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, sqlx::FromRow)]
+pub struct VatReturnTotals {
+    pub currency: Option<String>,
+    pub output_taxable_amount: String,
+    pub output_tax_amount: String,
+    pub input_taxable_amount: String,
+    pub input_tax_amount: String,
+    /// Output minus input VAT; negative when VAT is refundable.
+    pub net_vat: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VatReturnQueryDto {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedVatReturnDto {
+    pub draft: VatReturn,
+    /// Compact JWS (RS256) over the draft, signed with the STC key.
+    pub signed_draft: String,
+}
@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use actix_web::{HttpRequest, HttpResponse, http::header, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::{admin_config::AdminConfig, validation_config::ValidationPolicy},
+    errors::{ApiError, ErrorCode},
+    models::{
+        exchange_rate::{ExchangeRateImportDto, ExchangeRateQuery},
+        number_series::SetNumberSeriesDto,
+        responses::ApiResponse,
+        tax_period::CreateTaxPeriodDto,
+        taxpayer_portal::InvoiceAnalyticsQueryDto,
+    },
+    routes::taxpayer_portal::{
+        parse_required_period, tax_category_breakdown_response, top_items_response,
+    },
+    services::db::{
+        exchange_rate_service::{import_exchange_rates, list_exchange_rates, parse_exchange_rates},
+        number_series_service::{
+            NumberSeries, delete_number_series, get_number_series, set_number_series,
+        },
+        tax_period_service::{
+            TaxPeriodAction, create_tax_period, list_tax_periods, transition_tax_period,
+        },
+    },
+};
+
+/// Admin routes are off unless `ADMIN_API_TOKEN` is set, and then need it
+/// as a bearer token.
+fn require_admin(request: &HttpRequest, admin: &AdminConfig) -> Result<(), ApiError> {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if admin.accepts(authorization) {
+        Ok(())
+    } else {
+        Err(ApiError::new(ErrorCode::AdminUnauthorized))
+    }
+}
+
+pub async fn admin_tax_periods(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let tin = path.into_inner();
+    let periods = list_tax_periods(&tin, &pool).await.map_err(|error| {
+        tracing::error!(tin = %tin, error = %error, "Failed to list tax periods");
+        ApiError::internal()
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Tax periods fetched".to_string(),
+        data: Some(periods),
+    }))
+}
+
+pub async fn admin_add_tax_period(
+    request: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Json<CreateTaxPeriodDto>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let tin = path.into_inner();
+    let (from, to) = parse_required_period(payload.from.as_deref(), payload.to.as_deref())?;
+
+    let period = create_tax_period(&tin, from, to, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to create tax period");
+            ApiError::from_tax_period(&error)
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse {
+        success: true,
+        message: "Tax period created".to_string(),
+        data: Some(period),
+    }))
+}
+
+pub async fn admin_file_tax_period(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    update_tax_period(path, pool, TaxPeriodAction::File).await
+}
+
+pub async fn admin_lock_tax_period(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    update_tax_period(path, pool, TaxPeriodAction::Lock).await
+}
+
+async fn update_tax_period(
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    action: TaxPeriodAction,
+) -> Result<HttpResponse, ApiError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::TaxPeriodNotFound))?;
+
+    let period = transition_tax_period(&id, None, action, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(id = %id, action = ?action, error = %error, "Failed to update tax period");
+            ApiError::from_tax_period(&error)
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Tax period {}", period.status),
+        data: Some(period),
+    }))
+}
+
+/// Top items of any taxpayer; see `top_items`.
+pub async fn admin_top_items(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<InvoiceAnalyticsQueryDto>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    top_items_response(&path.into_inner(), &query, &pool).await
+}
+
+/// Tax category breakdown of any taxpayer; see `tax_category_breakdown`.
+pub async fn admin_tax_category_breakdown(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<InvoiceAnalyticsQueryDto>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    tax_category_breakdown_response(&path.into_inner(), &query, &pool).await
+}
+
+pub async fn admin_exchange_rates(
+    request: HttpRequest,
+    query: web::Query<ExchangeRateQuery>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let currency = query
+        .currency
+        .as_deref()
+        .map(str::trim)
+        .filter(|currency| !currency.is_empty());
+    let rates = list_exchange_rates(currency, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(error = %error, "Failed to list exchange rates");
+            ApiError::internal()
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Exchange rates fetched".to_string(),
+        data: Some(rates),
+    }))
+}
+
+/// Imports a `currency,date,rate` CSV file sent as the request body.
+pub async fn admin_import_exchange_rates(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+    policy: web::Data<ValidationPolicy>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let rates = std::str::from_utf8(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(|csv| parse_exchange_rates(csv, &policy.code_lists))
+        .map_err(|error| {
+            tracing::warn!(error = %error, "Rejected exchange rate file");
+            ApiError::new(ErrorCode::InvalidExchangeRateFile)
+        })?;
+
+    let imported = import_exchange_rates(&rates, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(error = %error, "Failed to import exchange rates");
+            ApiError::internal()
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Exchange rates imported".to_string(),
+        data: Some(ExchangeRateImportDto { imported }),
+    }))
+}
+
+pub async fn admin_number_series(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let device_uuid = parse_device_uuid(path)?;
+    let series = get_number_series(&device_uuid, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(device_uuid = %device_uuid, error = %error, "Failed to fetch number series");
+            ApiError::internal()
+        })?
+        .ok_or_else(|| ApiError::new(ErrorCode::NumberSeriesNotFound))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Number series fetched".to_string(),
+        data: Some(series),
+    }))
+}
+
+pub async fn admin_set_number_series(
+    request: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Json<SetNumberSeriesDto>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let device_uuid = parse_device_uuid(path)?;
+    let series = NumberSeries::new(
+        payload.prefix.as_deref().unwrap_or_default(),
+        payload.next_number.unwrap_or_default(),
+    )
+    .map_err(|error| ApiError::from_number_series(&error))?;
+
+    let series = set_number_series(&device_uuid, &series, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(device_uuid = %device_uuid, error = %error, "Failed to set number series");
+            ApiError::from_number_series(&error)
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Number series set".to_string(),
+        data: Some(series),
+    }))
+}
+
+pub async fn admin_delete_number_series(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    admin: web::Data<AdminConfig>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&request, &admin)?;
+    let device_uuid = parse_device_uuid(path)?;
+    let deleted = delete_number_series(&device_uuid, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(device_uuid = %device_uuid, error = %error, "Failed to delete number series");
+            ApiError::internal()
+        })?;
+    if !deleted {
+        return Err(ApiError::new(ErrorCode::NumberSeriesNotFound));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::<()> {
+        success: true,
+        message: "Number series removed".to_string(),
+        data: None,
+    }))
+}
+
+fn parse_device_uuid(path: web::Path<String>) -> Result<Uuid, ApiError> {
+    Uuid::from_str(&path.into_inner()).map_err(|_| ApiError::new(ErrorCode::DeviceNotFound))
+}
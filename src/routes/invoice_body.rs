@@ -0,0 +1,283 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    FromRequest, HttpMessage, HttpRequest,
+    body::{self, BodyStream},
+    dev::{self, Decompress},
+    http::header::{self, Header},
+    mime,
+    web::{self, Bytes},
+};
+
+use crate::{
+    config::{signature_config::SignatureAlgorithms, xml_config::XmlLimits},
+    errors::{ApiError, ErrorCode},
+    models::submit_invoice::SubmitInvoiceDto,
+};
+
+/// Upper bound for raw XML invoice bodies, after request decompression.
+pub const MAX_XML_BODY_BYTES: usize = 256 * 1024;
+
+/// Optional header carrying the invoice UUID for raw XML submissions.
+pub const INVOICE_UUID_HEADER: &str = "X-Invoice-UUID";
+/// Optional header carrying the base64 invoice hash for raw XML submissions.
+pub const INVOICE_HASH_HEADER: &str = "X-Invoice-Hash";
+/// Business rule set version of a cleared invoice returned as raw XML.
+pub const RULES_VERSION_HEADER: &str = "X-Business-Rules-Version";
+
+/// Invoice submission read from either a `SubmitInvoiceDto` JSON body or a raw
+/// UBL XML body (`application/xml` or `text/xml`).
+///
+/// For XML bodies the UUID and hash come from the `X-Invoice-UUID` and
+/// `X-Invoice-Hash` headers when present and are otherwise derived from the
+/// document. Both forms honour gzip/deflate `Content-Encoding`.
+pub struct InvoiceSubmission(pub SubmitInvoiceDto);
+
+impl InvoiceSubmission {
+    pub fn into_inner(self) -> SubmitInvoiceDto {
+        self.0
+    }
+}
+
+impl FromRequest for InvoiceSubmission {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let req = req.clone();
+        let mut payload = payload.take();
+
+        Box::pin(async move {
+            match body_kind(&req) {
+                Some(BodyKind::Xml) => {
+                    let body = read_body(&req, payload, MAX_XML_BODY_BYTES).await?;
+                    Ok(Self(submission_from_xml_request(&req, &body)?))
+                }
+                Some(BodyKind::Json) => {
+                    web::Json::<SubmitInvoiceDto>::from_request(&req, &mut payload)
+                        .await
+                        .map(|json| Self(json.into_inner()))
+                }
+                None => Err(ApiError::new(ErrorCode::UnsupportedInvoiceContentType).into()),
+            }
+        })
+    }
+}
+
+enum BodyKind {
+    Json,
+    Xml,
+}
+
+/// Whether the request body is XML (`application/xml`, `text/xml` or a
+/// `+xml` type).
+pub fn is_xml_body(req: &HttpRequest) -> bool {
+    matches!(body_kind(req), Some(BodyKind::Xml))
+}
+
+fn body_kind(req: &HttpRequest) -> Option<BodyKind> {
+    let mime = req.mime_type().ok()??;
+    match (
+        mime.type_().as_str(),
+        mime.subtype().as_str(),
+        mime.suffix(),
+    ) {
+        ("application", "json", _) | ("application", _, Some(mime::JSON)) => Some(BodyKind::Json),
+        ("application" | "text", "xml", _) | ("application", _, Some(mime::XML)) => {
+            Some(BodyKind::Xml)
+        }
+        _ => None,
+    }
+}
+
+fn submission_from_xml_request(
+    req: &HttpRequest,
+    invoice_bytes: &[u8],
+) -> Result<SubmitInvoiceDto, ApiError> {
+    let xml_limits = req
+        .app_data::<web::Data<XmlLimits>>()
+        .map(|limits| *limits.get_ref())
+        .unwrap_or_else(XmlLimits::from_env);
+    let algorithms = req
+        .app_data::<web::Data<SignatureAlgorithms>>()
+        .map(|algorithms| algorithms.get_ref().clone())
+        .unwrap_or_default();
+    let mut submission = SubmitInvoiceDto::from_xml(invoice_bytes, &xml_limits, &algorithms);
+    if let Some(uuid) = header_text(req, INVOICE_UUID_HEADER, ErrorCode::InvalidInvoiceUuid)? {
+        submission.uuid = uuid;
+    }
+    if let Some(hash) = header_text(
+        req,
+        INVOICE_HASH_HEADER,
+        ErrorCode::InvalidInvoiceHashEncoding,
+    )? {
+        submission.invoice_hash = hash;
+    }
+    Ok(submission)
+}
+
+fn header_text(
+    req: &HttpRequest,
+    name: &str,
+    invalid: ErrorCode,
+) -> Result<Option<String>, ApiError> {
+    req.headers()
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map(|value| value.trim().to_owned())
+                .map_err(|_| ApiError::new(invalid))
+        })
+        .transpose()
+}
+
+/// Reads a request body, undoing any gzip/deflate `Content-Encoding`, and
+/// enforces `limit` on the decoded size.
+pub async fn read_body(
+    req: &HttpRequest,
+    payload: dev::Payload,
+    limit: usize,
+) -> Result<Bytes, ApiError> {
+    let decoded = Decompress::from_headers(payload, req.headers());
+    match body::to_bytes_limited(BodyStream::new(decoded), limit).await {
+        Ok(Ok(bytes)) => Ok(bytes),
+        Ok(Err(_)) => Err(ApiError::new(ErrorCode::RequestBodyReadError)),
+        Err(_) => Err(ApiError::new(ErrorCode::RequestBodyTooLarge)),
+    }
+}
+
+/// Whether the client asked for raw XML rather than the JSON envelope.
+pub fn prefers_xml(req: &HttpRequest) -> bool {
+    header::Accept::parse(req).is_ok_and(|accept| {
+        let preferred = accept.preference();
+        matches!(
+            (preferred.type_().as_str(), preferred.subtype().as_str()),
+            ("application" | "text", "xml")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header::ContentType, test::TestRequest};
+    use base64::{Engine, engine::general_purpose};
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    const INVOICE: &str = r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2" xmlns:ext="urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2"><ext:UBLExtensions><ext:UBLExtension><ext:ExtensionContent><ds:Signature xmlns:ds="http://www.w3.org/2000/09/xmldsig#" Id="signature"><ds:SignedInfo><ds:Reference URI=""><ds:Transforms><ds:Transform Algorithm="http://www.w3.org/TR/1999/REC-xpath-19991116"><ds:XPath>not(//ancestor-or-self::ext:UBLExtensions)</ds:XPath></ds:Transform><ds:Transform Algorithm="http://www.w3.org/2006/12/xml-c14n11#"/></ds:Transforms><ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/></ds:Reference></ds:SignedInfo></ds:Signature></ext:ExtensionContent></ext:UBLExtension></ext:UBLExtensions><cbc:UUID>550e8400-e29b-41d4-a716-446655440000</cbc:UUID></Invoice>"#;
+
+    async fn extract(request: TestRequest) -> Result<SubmitInvoiceDto, actix_web::Error> {
+        let (req, mut payload) = request.to_http_parts();
+        InvoiceSubmission::from_request(&req, &mut payload)
+            .await
+            .map(InvoiceSubmission::into_inner)
+    }
+
+    #[actix_web::test]
+    async fn xml_body_derives_uuid_and_hash() {
+        let submission = extract(
+            TestRequest::post()
+                .insert_header(ContentType::xml())
+                .set_payload(INVOICE),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(submission.uuid, "550e8400-e29b-41d4-a716-446655440000");
+        assert!(!submission.invoice_hash.is_empty());
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(submission.invoice)
+                .unwrap(),
+            INVOICE.as_bytes()
+        );
+    }
+
+    #[actix_web::test]
+    async fn headers_override_derived_values() {
+        let submission = extract(
+            TestRequest::post()
+                .insert_header(("Content-Type", "text/xml; charset=utf-8"))
+                .insert_header((INVOICE_UUID_HEADER, "6f1c2a4e-8d3b-4f0a-9c1e-2b7d5e9a0f13"))
+                .insert_header((INVOICE_HASH_HEADER, "AAAA"))
+                .set_payload(INVOICE),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(submission.uuid, "6f1c2a4e-8d3b-4f0a-9c1e-2b7d5e9a0f13");
+        assert_eq!(submission.invoice_hash, "AAAA");
+    }
+
+    #[actix_web::test]
+    async fn gzip_xml_body_is_decompressed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(INVOICE.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let submission = extract(
+            TestRequest::post()
+                .insert_header(ContentType::xml())
+                .insert_header(("Content-Encoding", "gzip"))
+                .set_payload(compressed),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(submission.invoice)
+                .unwrap(),
+            INVOICE.as_bytes()
+        );
+    }
+
+    #[actix_web::test]
+    async fn oversized_xml_body_is_rejected() {
+        let err = extract(
+            TestRequest::post()
+                .insert_header(ContentType::xml())
+                .set_payload(vec![b' '; MAX_XML_BODY_BYTES + 1]),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[actix_web::test]
+    async fn unsupported_content_type_is_rejected() {
+        let err = extract(
+            TestRequest::post()
+                .insert_header(ContentType::plaintext())
+                .set_payload(INVOICE),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[test]
+    fn accept_header_selects_xml() {
+        let xml = TestRequest::default()
+            .insert_header(("Accept", "application/xml, application/json;q=0.5"))
+            .to_http_request();
+        let json = TestRequest::default()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let none = TestRequest::default().to_http_request();
+
+        assert!(prefers_xml(&xml));
+        assert!(!prefers_xml(&json));
+        assert!(!prefers_xml(&none));
+    }
+}
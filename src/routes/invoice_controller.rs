@@ -1,21 +1,35 @@
-use actix_web::{HttpResponse, web};
+use std::str::FromStr;
+
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
 use base64::{Engine, engine::general_purpose};
 use fastxml::schema::CompiledSchema;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
-    config::crypto_config::Crypto,
+    config::{
+        batch_config::BatchLimits, crypto_config::Crypto, signature_config::SignatureAlgorithms,
+        tsa_config::Tsa, validation_config::ValidationPolicy, xml_config::XmlLimits,
+    },
     errors::{ApiError, ErrorCode},
     models::{
-        responses::{ApiResponse, EmptyApiResponse, ErrorData},
+        batch_report::{
+            BatchItemError, BatchItemResultDto, BatchItemStatus, BatchReportDto, BatchResumeDto,
+        },
+        receipt::{InvoiceReceipt, InvoiceReceiptDto},
+        responses::{ApiResponse, ErrorData},
         submit_invoice::{ClearedInvoiceDto, InvoiceType, SubmitInvoiceDto},
     },
+    routes::invoice_body::{
+        INVOICE_UUID_HEADER, InvoiceSubmission, RULES_VERSION_HEADER, prefers_xml, read_body,
+    },
     services::{
+        cpu_pool::CpuPool,
+        db::receipt_service::fetch_receipt,
         db::rejected_invoice_service::{RejectedInvoiceRecord, save_rejected_invoice},
         pipeline::clearance_service::process_clearance,
-        pipeline::reporting_service::process_reporting,
-        xml::extractors::extract_supplier_id,
+        pipeline::reporting_service::{process_reporting, process_reporting_batch},
+        xml::{batch_archive::read_invoice_archive, extractors::extract_supplier_id},
     },
 };
 
@@ -23,64 +37,134 @@ use crate::{
     post,
     path = "/prod/invoices/clear",
     tag = "Public API",
-    request_body = SubmitInvoiceDto,
+    request_body(
+        description = "Invoice as `SubmitInvoiceDto` JSON, or raw UBL XML with optional `X-Invoice-UUID` and `X-Invoice-Hash` headers; gzip/deflate `Content-Encoding` is accepted",
+        content(
+            (SubmitInvoiceDto = "application/json"),
+            (String = "application/xml")
+        )
+    ),
     responses(
-        (status = 200, description = "Invoice cleared", body = ApiResponse<ClearedInvoiceDto>),
+        (status = 200, description = "Invoice cleared; raw cleared XML when `Accept: application/xml` is sent", content(
+            (ApiResponse<ClearedInvoiceDto> = "application/json"),
+            (String = "application/xml")
+        )),
         (status = 400, description = "Invalid invoice request or validation failure", body = ApiResponse<ErrorData>),
         (status = 403, description = "Device is inactive", body = ApiResponse<ErrorData>),
         (status = 404, description = "Device or taxpayer was not found", body = ApiResponse<ErrorData>),
-        (status = 409, description = "Duplicate invoice or chain conflict", body = ApiResponse<ErrorData>),
+        (status = 409, description = "Duplicate invoice, chain conflict or locked tax period", body = ApiResponse<ErrorData>),
         (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
-        (status = 415, description = "Content-Type must be application/json", body = ApiResponse<ErrorData>),
-        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+        (status = 415, description = "Content-Type must be application/json or application/xml", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn clearance_prod(
+    req: HttpRequest,
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
+    tsa: web::Data<Tsa>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
-    handle_clearance(db_pool, invoice_dto, crypto, schema_validator, false).await
+    handle_clearance(
+        req,
+        db_pool,
+        invoice_dto,
+        crypto,
+        tsa,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        false,
+    )
+    .await
 }
 
 #[utoipa::path(
     post,
     path = "/sandbox/invoices/clear",
     tag = "Public API",
-    request_body = SubmitInvoiceDto,
+    request_body(
+        description = "Invoice as `SubmitInvoiceDto` JSON, or raw UBL XML with optional `X-Invoice-UUID` and `X-Invoice-Hash` headers; gzip/deflate `Content-Encoding` is accepted",
+        content(
+            (SubmitInvoiceDto = "application/json"),
+            (String = "application/xml")
+        )
+    ),
     responses(
-        (status = 200, description = "Invoice cleared without persistence", body = ApiResponse<ClearedInvoiceDto>),
+        (status = 200, description = "Invoice cleared without persistence; raw cleared XML when `Accept: application/xml` is sent", content(
+            (ApiResponse<ClearedInvoiceDto> = "application/json"),
+            (String = "application/xml")
+        )),
         (status = 400, description = "Invalid invoice request or validation failure", body = ApiResponse<ErrorData>),
         (status = 403, description = "Device is inactive", body = ApiResponse<ErrorData>),
         (status = 404, description = "Device or taxpayer was not found", body = ApiResponse<ErrorData>),
-        (status = 409, description = "Invoice chain conflict", body = ApiResponse<ErrorData>),
+        (status = 409, description = "Invoice chain conflict or locked tax period", body = ApiResponse<ErrorData>),
         (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
-        (status = 415, description = "Content-Type must be application/json", body = ApiResponse<ErrorData>),
-        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+        (status = 415, description = "Content-Type must be application/json or application/xml", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn clearance_sandbox(
+    req: HttpRequest,
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
+    tsa: web::Data<Tsa>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
-    handle_clearance(db_pool, invoice_dto, crypto, schema_validator, true).await
+    handle_clearance(
+        req,
+        db_pool,
+        invoice_dto,
+        crypto,
+        tsa,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        true,
+    )
+    .await
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_clearance(
+    req: HttpRequest,
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
+    tsa: web::Data<Tsa>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
     sandbox: bool,
 ) -> Result<HttpResponse, ApiError> {
     let dto = invoice_dto.into_inner();
     let submitted = dto.clone();
     let raw_uuid = dto.uuid.clone();
 
-    let intermediate_dto = match dto.parse(&db_pool).await {
+    let intermediate_dto = match dto
+        .parse(&db_pool, &cpu_pool, &xml_limits, &algorithms)
+        .await
+    {
         Ok(intermediate_dto) => intermediate_dto,
         Err(e) => {
             tracing::error!(uuid = %raw_uuid, error = %e, "Failed to parse clearance invoice");
@@ -132,9 +216,13 @@ async fn handle_clearance(
         intermediate_dto,
         &db_pool,
         &crypto,
+        &tsa,
         sandbox,
         schema_validator,
+        &algorithms,
+        &policy,
         InvoiceType::Clearance,
+        &cpu_pool,
     )
     .await
     {
@@ -158,10 +246,28 @@ async fn handle_clearance(
         }
     };
 
+    let rules_version = policy.business_rules(sandbox).version.clone();
+    if prefers_xml(&req) {
+        let cleared_xml = general_purpose::STANDARD
+            .decode(&cleared_invoice)
+            .map_err(|e| {
+                tracing::error!(uuid = %uuid, error = %e, "Failed to decode cleared invoice");
+                ApiError::internal()
+            })?;
+        return Ok(HttpResponse::Ok()
+            .content_type("application/xml")
+            .insert_header((INVOICE_UUID_HEADER, uuid.to_string()))
+            .insert_header((RULES_VERSION_HEADER, rules_version))
+            .body(cleared_xml));
+    }
+
     Ok(HttpResponse::Ok().json(ApiResponse {
         success: true,
         message: "Invoice cleared".into(),
-        data: Some(ClearedInvoiceDto { cleared_invoice }),
+        data: Some(ClearedInvoiceDto {
+            cleared_invoice,
+            rules_version,
+        }),
     }))
 }
 
@@ -169,64 +275,118 @@ async fn handle_clearance(
     post,
     path = "/prod/invoices/report",
     tag = "Public API",
-    request_body = SubmitInvoiceDto,
+    request_body(
+        description = "Invoice as `SubmitInvoiceDto` JSON, or raw UBL XML with optional `X-Invoice-UUID` and `X-Invoice-Hash` headers; gzip/deflate `Content-Encoding` is accepted",
+        content(
+            (SubmitInvoiceDto = "application/json"),
+            (String = "application/xml")
+        )
+    ),
     responses(
-        (status = 202, description = "Invoice reported", body = EmptyApiResponse),
+        (status = 202, description = "Invoice reported; returns the STC-signed acknowledgment receipt", body = ApiResponse<InvoiceReceiptDto>),
         (status = 400, description = "Invalid invoice request or validation failure", body = ApiResponse<ErrorData>),
         (status = 403, description = "Device is inactive", body = ApiResponse<ErrorData>),
         (status = 404, description = "Device or taxpayer was not found", body = ApiResponse<ErrorData>),
-        (status = 409, description = "Duplicate invoice or chain conflict", body = ApiResponse<ErrorData>),
+        (status = 409, description = "Duplicate invoice, chain conflict or locked tax period", body = ApiResponse<ErrorData>),
         (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
-        (status = 415, description = "Content-Type must be application/json", body = ApiResponse<ErrorData>),
-        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+        (status = 415, description = "Content-Type must be application/json or application/xml", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn reporting_prod(
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
-    handle_reporting(db_pool, invoice_dto, crypto, schema_validator, false).await
+    handle_reporting(
+        db_pool,
+        invoice_dto,
+        crypto,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        false,
+    )
+    .await
 }
 
 #[utoipa::path(
     post,
     path = "/sandbox/invoices/report",
     tag = "Public API",
-    request_body = SubmitInvoiceDto,
+    request_body(
+        description = "Invoice as `SubmitInvoiceDto` JSON, or raw UBL XML with optional `X-Invoice-UUID` and `X-Invoice-Hash` headers; gzip/deflate `Content-Encoding` is accepted",
+        content(
+            (SubmitInvoiceDto = "application/json"),
+            (String = "application/xml")
+        )
+    ),
     responses(
-        (status = 202, description = "Invoice reported without persistence", body = EmptyApiResponse),
+        (status = 202, description = "Invoice reported without persistence; the receipt is signed but not stored", body = ApiResponse<InvoiceReceiptDto>),
         (status = 400, description = "Invalid invoice request or validation failure", body = ApiResponse<ErrorData>),
         (status = 403, description = "Device is inactive", body = ApiResponse<ErrorData>),
         (status = 404, description = "Device or taxpayer was not found", body = ApiResponse<ErrorData>),
-        (status = 409, description = "Invoice chain conflict", body = ApiResponse<ErrorData>),
+        (status = 409, description = "Invoice chain conflict or locked tax period", body = ApiResponse<ErrorData>),
         (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
-        (status = 415, description = "Content-Type must be application/json", body = ApiResponse<ErrorData>),
-        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+        (status = 415, description = "Content-Type must be application/json or application/xml", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn reporting_sandbox(
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
-    handle_reporting(db_pool, invoice_dto, crypto, schema_validator, true).await
+    handle_reporting(
+        db_pool,
+        invoice_dto,
+        crypto,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        true,
+    )
+    .await
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_reporting(
     db_pool: web::Data<PgPool>,
-    invoice_dto: web::Json<SubmitInvoiceDto>,
+    invoice_dto: InvoiceSubmission,
     crypto: web::Data<Crypto>,
     schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
     sandbox: bool,
 ) -> Result<HttpResponse, ApiError> {
     let dto = invoice_dto.into_inner();
     let submitted = dto.clone();
     let raw_uuid = dto.uuid.clone();
 
-    let intermediate_dto = match dto.parse(&db_pool).await {
+    let intermediate_dto = match dto
+        .parse(&db_pool, &cpu_pool, &xml_limits, &algorithms)
+        .await
+    {
         Ok(intermediate_dto) => intermediate_dto,
         Err(e) => {
             tracing::error!(uuid = %raw_uuid, error = %e, "Failed to parse reporting invoice");
@@ -274,37 +434,400 @@ async fn handle_reporting(
     let device_uuid = intermediate_dto.device.device_uuid;
     let supplier_tin = intermediate_dto.supplier.clone();
 
-    if let Err(e) = process_reporting(
+    let mut receipt = match process_reporting(
         intermediate_dto,
         &db_pool,
         &crypto,
         sandbox,
         schema_validator,
+        &algorithms,
+        &policy,
         InvoiceType::Reporting,
+        &cpu_pool,
     )
     .await
     {
-        tracing::error!(uuid = %uuid, device_uuid = %device_uuid, error = %e, "Reporting pipeline failed");
-        let api_error = ApiError::from_invoice_pipeline(&e);
-        if !sandbox {
-            return Err(persist_rejection_or_internal(
-                db_pool.get_ref(),
-                &submitted,
-                "report",
-                InvoiceType::Reporting.as_str(),
-                api_error,
-                Some(&supplier_tin),
-                Some(device_uuid),
-            )
-            .await);
+        Ok(receipt) => receipt,
+        Err(e) => {
+            tracing::error!(uuid = %uuid, device_uuid = %device_uuid, error = %e, "Reporting pipeline failed");
+            let api_error = ApiError::from_invoice_pipeline(&e);
+            if !sandbox {
+                return Err(persist_rejection_or_internal(
+                    db_pool.get_ref(),
+                    &submitted,
+                    "report",
+                    InvoiceType::Reporting.as_str(),
+                    api_error,
+                    Some(&supplier_tin),
+                    Some(device_uuid),
+                )
+                .await);
+            }
+            return Err(api_error);
         }
-        return Err(api_error);
-    }
+    };
 
-    Ok(HttpResponse::Accepted().json(ApiResponse::<()> {
+    receipt.rules_version = Some(policy.business_rules(sandbox).version.clone());
+    Ok(HttpResponse::Accepted().json(ApiResponse {
         success: true,
         message: "Invoice reported".into(),
-        data: None,
+        data: Some(receipt),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/prod/invoices/report/batch",
+    tag = "Public API",
+    request_body(
+        description = "Ordered invoices from one device, as a JSON array or a ZIP of signed XML files",
+        content(
+            (Vec<SubmitInvoiceDto> = "application/json"),
+            (Vec<u8> = "application/zip")
+        )
+    ),
+    responses(
+        (status = 202, description = "Batch processed; `success` is false and `resume_from` is set when it stopped at a rejected invoice", body = ApiResponse<BatchReportDto>),
+        (status = 400, description = "Batch is empty or the archive is invalid", body = ApiResponse<ErrorData>),
+        (status = 413, description = "Request body or batch is too large", body = ApiResponse<ErrorData>),
+        (status = 415, description = "Content-Type must be application/json or application/zip", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn reporting_batch_prod(
+    req: HttpRequest,
+    payload: web::Payload,
+    db_pool: web::Data<PgPool>,
+    crypto: web::Data<Crypto>,
+    schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
+    limits: web::Data<BatchLimits>,
+) -> Result<HttpResponse, ApiError> {
+    let submitted = read_batch(&req, payload, &limits, &xml_limits, &algorithms).await?;
+    handle_reporting_batch(
+        submitted,
+        db_pool,
+        crypto,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        false,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/sandbox/invoices/report/batch",
+    tag = "Public API",
+    request_body(
+        description = "Ordered invoices from one device, as a JSON array or a ZIP of signed XML files",
+        content(
+            (Vec<SubmitInvoiceDto> = "application/json"),
+            (Vec<u8> = "application/zip")
+        )
+    ),
+    responses(
+        (status = 202, description = "Batch checked against the device chain without persistence", body = ApiResponse<BatchReportDto>),
+        (status = 400, description = "Batch is empty or the archive is invalid", body = ApiResponse<ErrorData>),
+        (status = 413, description = "Request body or batch is too large", body = ApiResponse<ErrorData>),
+        (status = 415, description = "Content-Type must be application/json or application/zip", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn reporting_batch_sandbox(
+    req: HttpRequest,
+    payload: web::Payload,
+    db_pool: web::Data<PgPool>,
+    crypto: web::Data<Crypto>,
+    schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
+    limits: web::Data<BatchLimits>,
+) -> Result<HttpResponse, ApiError> {
+    let submitted = read_batch(&req, payload, &limits, &xml_limits, &algorithms).await?;
+    handle_reporting_batch(
+        submitted,
+        db_pool,
+        crypto,
+        schema_validator,
+        algorithms,
+        policy,
+        cpu_pool,
+        xml_limits,
+        true,
+    )
+    .await
+}
+
+struct BatchRejection {
+    index: usize,
+    api_error: ApiError,
+    supplier_tin: Option<String>,
+    device_id: Option<Uuid>,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_reporting_batch(
+    submitted: Vec<SubmitInvoiceDto>,
+    db_pool: web::Data<PgPool>,
+    crypto: web::Data<Crypto>,
+    schema_validator: web::Data<CompiledSchema>,
+    algorithms: web::Data<SignatureAlgorithms>,
+    policy: web::Data<ValidationPolicy>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
+    sandbox: bool,
+) -> Result<HttpResponse, ApiError> {
+    let total = submitted.len();
+
+    // Parse up front; a parse failure ends the batch at that invoice.
+    let mut parsed = Vec::with_capacity(total);
+    let mut rejection = None;
+    for (index, dto) in submitted.iter().enumerate() {
+        match dto
+            .clone()
+            .parse(&db_pool, &cpu_pool, &xml_limits, &algorithms)
+            .await
+        {
+            Ok(intermediate) if !intermediate.device.is_active => {
+                tracing::warn!(
+                    index,
+                    uuid = %intermediate.uuid,
+                    device_uuid = %intermediate.device.device_uuid,
+                    "Batch invoice rejected because device is inactive"
+                );
+                rejection = Some(BatchRejection {
+                    index,
+                    api_error: ApiError::new(ErrorCode::DeviceInactive),
+                    supplier_tin: Some(intermediate.supplier),
+                    device_id: Some(intermediate.device.device_uuid),
+                });
+                break;
+            }
+            Ok(intermediate) => parsed.push(intermediate),
+            Err(e) => {
+                tracing::error!(index, uuid = %dto.uuid, error = %e, "Failed to parse batch invoice");
+                rejection = Some(BatchRejection {
+                    index,
+                    api_error: ApiError::from_invoice_parse(&e),
+                    supplier_tin: best_effort_supplier_tin(dto),
+                    device_id: None,
+                });
+                break;
+            }
+        }
+    }
+
+    let mut receipts = Vec::new();
+    let mut next_icv = None;
+    if !parsed.is_empty() {
+        let parties: Vec<_> = parsed
+            .iter()
+            .map(|intermediate| {
+                (
+                    intermediate.supplier.clone(),
+                    intermediate.device.device_uuid,
+                )
+            })
+            .collect();
+
+        let outcome = process_reporting_batch(
+            parsed,
+            &db_pool,
+            &crypto,
+            sandbox,
+            schema_validator,
+            &algorithms,
+            &policy,
+            &cpu_pool,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Batch reporting pass failed");
+            ApiError::from_invoice_pipeline(&e)
+        })?;
+
+        receipts = outcome.receipts;
+        next_icv = Some(outcome.next_icv);
+        if let Some(failure) = outcome.failure {
+            let (supplier_tin, device_id) = parties[failure.index].clone();
+            tracing::error!(
+                index = failure.index,
+                uuid = %submitted[failure.index].uuid,
+                device_uuid = %device_id,
+                error = %failure.error,
+                "Batch reporting stopped at rejected invoice"
+            );
+            rejection = Some(BatchRejection {
+                index: failure.index,
+                api_error: ApiError::from_invoice_pipeline(&failure.error),
+                supplier_tin: Some(supplier_tin),
+                device_id: Some(device_id),
+            });
+        }
+    }
+
+    if let Some(rejected) = rejection.as_mut()
+        && !sandbox
+    {
+        rejected.api_error = persist_rejection_or_internal(
+            db_pool.get_ref(),
+            &submitted[rejected.index],
+            "report",
+            InvoiceType::Reporting.as_str(),
+            rejected.api_error.clone(),
+            rejected.supplier_tin.as_deref(),
+            rejected.device_id,
+        )
+        .await;
+    }
+
+    let accepted = receipts.len();
+    let mut receipts = receipts.into_iter();
+    let items = submitted
+        .into_iter()
+        .enumerate()
+        .map(|(index, dto)| {
+            let (status, receipt, error) = match &rejection {
+                Some(rejected) if index == rejected.index => (
+                    BatchItemStatus::Rejected,
+                    None,
+                    Some(BatchItemError {
+                        code: rejected.api_error.public_code(),
+                        message: rejected.api_error.public_message(),
+                        errors: rejected.api_error.issues().to_vec(),
+                        rules_version: rejected.api_error.rules_version().map(str::to_owned),
+                    }),
+                ),
+                Some(rejected) if index > rejected.index => {
+                    (BatchItemStatus::NotProcessed, None, None)
+                }
+                _ => (BatchItemStatus::Accepted, receipts.next(), None),
+            };
+            BatchItemResultDto {
+                index,
+                uuid: dto.uuid,
+                status,
+                receipt,
+                error,
+            }
+        })
+        .collect();
+
+    let resume_from = rejection.as_ref().map(|rejected| BatchResumeDto {
+        index: rejected.index,
+        icv: next_icv,
+    });
+    let message = match &rejection {
+        Some(rejected) => format!(
+            "Batch stopped at invoice {}; {} of {} reported",
+            rejected.index, accepted, total
+        ),
+        None => "Batch reported".into(),
+    };
+
+    Ok(HttpResponse::Accepted().json(ApiResponse {
+        success: rejection.is_none(),
+        message,
+        data: Some(BatchReportDto {
+            total,
+            accepted,
+            items,
+            rules_version: policy.business_rules(sandbox).version.clone(),
+            resume_from,
+        }),
+    }))
+}
+
+async fn read_batch(
+    req: &HttpRequest,
+    payload: web::Payload,
+    limits: &BatchLimits,
+    xml_limits: &XmlLimits,
+    algorithms: &SignatureAlgorithms,
+) -> Result<Vec<SubmitInvoiceDto>, ApiError> {
+    let is_zip = match req.content_type() {
+        "application/json" => false,
+        "application/zip" | "application/x-zip-compressed" => true,
+        _ => return Err(ApiError::new(ErrorCode::UnsupportedBatchContentType)),
+    };
+
+    let body = read_body(req, payload.into_inner(), limits.max_bytes).await?;
+
+    let invoices = if is_zip {
+        read_invoice_archive(&body, limits, xml_limits, algorithms).map_err(|e| {
+            tracing::error!(error = %e, "Failed to read batch archive");
+            ApiError::from_batch_archive(&e)
+        })?
+    } else {
+        serde_json::from_slice::<Vec<SubmitInvoiceDto>>(&body)
+            .map_err(|_| ApiError::new(ErrorCode::InvalidJson))?
+    };
+
+    if invoices.is_empty() {
+        return Err(ApiError::new(ErrorCode::BatchEmpty));
+    }
+    if invoices.len() > limits.max_items {
+        return Err(ApiError::new(ErrorCode::BatchTooManyInvoices));
+    }
+
+    Ok(invoices)
+}
+
+#[utoipa::path(
+    get,
+    path = "/prod/invoices/{uuid}/receipt",
+    tag = "Public API",
+    params(("uuid" = String, Path, description = "UUID of the reported invoice")),
+    responses(
+        (status = 200, description = "Signed acknowledgment receipt", body = ApiResponse<InvoiceReceiptDto>),
+        (status = 400, description = "Invoice UUID is invalid", body = ApiResponse<ErrorData>),
+        (status = 404, description = "No receipt exists for this invoice", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+    )
+)]
+pub async fn invoice_receipt(
+    db_pool: web::Data<PgPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let uuid = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::InvalidInvoiceUuid))?;
+
+    let stored = fetch_receipt(&uuid, &db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(uuid = %uuid, error = %e, "Failed to fetch invoice receipt");
+            ApiError::internal()
+        })?
+        .ok_or_else(|| ApiError::new(ErrorCode::ReceiptNotFound))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Receipt loaded".into(),
+        data: Some(InvoiceReceiptDto {
+            receipt: InvoiceReceipt {
+                invoice_uuid: stored.invoice_uuid.to_string(),
+                invoice_hash: general_purpose::STANDARD.encode(&stored.invoice_hash),
+                device_uuid: stored.device_id.to_string(),
+                icv: stored.icv,
+                received_at: stored.received_at,
+                accepted_at: stored.accepted_at,
+            },
+            signed_receipt: stored.signed_receipt,
+            rules_version: None,
+        }),
     }))
 }
 
@@ -317,13 +840,18 @@ async fn persist_rejection_or_internal(
     supplier_tin: Option<&str>,
     device_id: Option<Uuid>,
 ) -> ApiError {
+    // Shed requests were never judged; the client is told to retry instead.
+    if api_error.is_overloaded() {
+        return api_error;
+    }
+
     let result = save_rejected_invoice(
         db_pool,
         RejectedInvoiceRecord {
             submitted,
             endpoint,
             invoice_type,
-            api_error,
+            api_error: &api_error,
             supplier_tin,
             device_id,
         },
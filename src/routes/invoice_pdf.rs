@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use actix_web::{HttpResponse, http::header, web};
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    errors::{ApiError, ErrorCode},
+    models::responses::{ApiResponse, ErrorData},
+    services::{
+        cpu_pool::CpuPool, db::invoice_lookup::fetch_invoice_bytes,
+        pdf::invoice_pdf::render_invoice_pdf,
+    },
+};
+
+#[utoipa::path(
+    get,
+    path = "/prod/invoices/{uuid}/pdf",
+    tag = "Public API",
+    params(("uuid" = String, Path, description = "UUID of a cleared or reported invoice")),
+    responses(
+        (status = 200, description = "PDF/A-3b rendering of the stored invoice, with the UBL XML embedded as `invoice.xml`", content_type = "application/pdf", body = Vec<u8>),
+        (status = 400, description = "Invoice UUID is invalid", body = ApiResponse<ErrorData>),
+        (status = 404, description = "No invoice with this UUID is stored", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+pub async fn invoice_pdf(
+    db_pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    cpu_pool: web::Data<CpuPool>,
+) -> Result<HttpResponse, ApiError> {
+    let uuid = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::InvalidInvoiceUuid))?;
+
+    let invoice_bytes = fetch_invoice_bytes(&uuid, &db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(uuid = %uuid, error = %e, "Failed to fetch invoice");
+            ApiError::internal()
+        })?
+        .ok_or_else(|| ApiError::new(ErrorCode::InvoiceNotFound))?;
+
+    pdf_response(uuid, invoice_bytes, &cpu_pool).await
+}
+
+/// Renders a stored invoice on the CPU pool and returns it as an inline PDF.
+pub async fn pdf_response(
+    uuid: Uuid,
+    invoice_bytes: Vec<u8>,
+    cpu_pool: &CpuPool,
+) -> Result<HttpResponse, ApiError> {
+    let generated_at = Utc::now();
+    let pdf = cpu_pool
+        .run(move || render_invoice_pdf(&invoice_bytes, generated_at))
+        .await
+        .map_err(|e| {
+            tracing::error!(uuid = %uuid, error = %e, "Invoice PDF rendering failed");
+            ApiError::from_cpu_job(&e)
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"invoice-{uuid}.pdf\""),
+        ))
+        .body(pdf))
+}
@@ -0,0 +1,22 @@
+use actix_web::{HttpResponse, web};
+
+use crate::{
+    models::{metrics::CpuPoolStats, responses::ApiResponse},
+    services::cpu_pool::CpuPool,
+};
+
+#[utoipa::path(
+    get,
+    path = "/metrics/cpu_pool",
+    tag = "Public API",
+    responses(
+        (status = 200, description = "Current load of the CPU pool used by the invoice pipeline", body = ApiResponse<CpuPoolStats>)
+    )
+)]
+pub async fn cpu_pool_metrics(cpu_pool: web::Data<CpuPool>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "CPU pool stats".into(),
+        data: Some(cpu_pool.stats()),
+    })
+}
@@ -1,6 +1,12 @@
+pub mod admin;
 pub mod enroll;
 pub mod health_check;
+pub mod invoice_body;
 pub mod invoice_controller;
+pub mod invoice_pdf;
+pub mod metrics;
 pub mod pages;
+pub mod qr_image;
 pub mod taxpayer_portal;
+pub mod tsa;
 pub mod verify_qr;
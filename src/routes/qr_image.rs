@@ -0,0 +1,133 @@
+use std::str::FromStr;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::xml_config::XmlLimits,
+    errors::{ApiError, ErrorCode},
+    models::{
+        qr_image::QrImageQuery,
+        responses::{ApiResponse, ErrorData},
+    },
+    routes::invoice_body::{MAX_XML_BODY_BYTES, is_xml_body, read_body},
+    services::{
+        cpu_pool::CpuPool,
+        db::invoice_lookup::fetch_invoice_bytes,
+        xml::{
+            guard::check_xml,
+            invoice_document::InvoiceDocument,
+            qr_image::{QrImageOptions, render_qr},
+        },
+    },
+};
+
+#[utoipa::path(
+    get,
+    path = "/prod/invoices/{uuid}/qr",
+    tag = "Public API",
+    params(
+        ("uuid" = String, Path, description = "UUID of a cleared or reported invoice"),
+        QrImageQuery
+    ),
+    responses(
+        (status = 200, description = "QR code of the stored invoice", content(
+            (Vec<u8> = "image/png"),
+            (String = "image/svg+xml")
+        )),
+        (status = 400, description = "Invoice UUID, image options or the invoice QR are invalid", body = ApiResponse<ErrorData>),
+        (status = 404, description = "No invoice with this UUID is stored", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+pub async fn invoice_qr_image(
+    db_pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    query: web::Query<QrImageQuery>,
+    cpu_pool: web::Data<CpuPool>,
+) -> Result<HttpResponse, ApiError> {
+    let options = image_options(&query)?;
+    let uuid = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::InvalidInvoiceUuid))?;
+
+    let invoice_bytes = fetch_invoice_bytes(&uuid, &db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(uuid = %uuid, error = %e, "Failed to fetch invoice");
+            ApiError::internal()
+        })?
+        .ok_or_else(|| ApiError::new(ErrorCode::InvoiceNotFound))?;
+
+    render(invoice_bytes, options, &cpu_pool).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/invoices/qr",
+    tag = "Public API",
+    params(QrImageQuery),
+    request_body(
+        description = "Invoice XML carrying a QR `cac:AdditionalDocumentReference`; gzip/deflate `Content-Encoding` is accepted",
+        content((String = "application/xml"))
+    ),
+    responses(
+        (status = 200, description = "QR code of the uploaded invoice", content(
+            (Vec<u8> = "image/png"),
+            (String = "image/svg+xml")
+        )),
+        (status = 400, description = "Invoice XML, image options or the invoice QR are invalid", body = ApiResponse<ErrorData>),
+        (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
+        (status = 415, description = "Content-Type must be application/xml", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+pub async fn uploaded_invoice_qr_image(
+    req: HttpRequest,
+    payload: web::Payload,
+    query: web::Query<QrImageQuery>,
+    cpu_pool: web::Data<CpuPool>,
+    xml_limits: web::Data<XmlLimits>,
+) -> Result<HttpResponse, ApiError> {
+    if !is_xml_body(&req) {
+        return Err(ApiError::new(ErrorCode::UnsupportedXmlContentType));
+    }
+    let options = image_options(&query)?;
+    let invoice_bytes = read_body(&req, payload.into_inner(), MAX_XML_BODY_BYTES).await?;
+    check_xml(&invoice_bytes, &xml_limits).map_err(|e| {
+        tracing::error!(error = %e, "Uploaded invoice rejected by the XML guard");
+        ApiError::from_qr_image(&e)
+    })?;
+
+    render(invoice_bytes.to_vec(), options, &cpu_pool).await
+}
+
+fn image_options(query: &QrImageQuery) -> Result<QrImageOptions, ApiError> {
+    query.options().map_err(|e| {
+        tracing::error!(error = %e, "Invalid QR image options");
+        ApiError::new(ErrorCode::InvalidQrImageOptions)
+    })
+}
+
+async fn render(
+    invoice_bytes: Vec<u8>,
+    options: QrImageOptions,
+    cpu_pool: &CpuPool,
+) -> Result<HttpResponse, ApiError> {
+    let image = cpu_pool
+        .run(move || {
+            let document = InvoiceDocument::parse(&invoice_bytes)?;
+            render_qr(document.qr()?, &options)
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "QR image rendering failed");
+            ApiError::from_qr_image(&e)
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(options.format.content_type())
+        .body(image))
+}
@@ -1,30 +1,50 @@
+use std::str::FromStr;
+
 use actix_session::Session;
 use actix_web::{HttpResponse, web};
 use base64::{Engine, engine::general_purpose};
 use chrono::NaiveDate;
 use serde_json::json;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
+    config::{crypto_config::Crypto, xml_config::XmlLimits},
     errors::{ApiError, ErrorCode},
     models::{
         responses::ApiResponse,
+        tax_period::CreateTaxPeriodDto,
         taxpayer_portal::{
-            EnrollmentTokenDto, InvoicePayloadDto, InvoiceReportDto, InvoiceReportRequestDto,
-            InvoiceReportRowDto, InvoiceReportSummaryDto, PreparedInvoicePayloadDto,
-            TaxpayerCredentialsDto, TaxpayerDto, TaxpayerProfileDto,
+            EnrollmentTokenDto, InvoiceAnalyticsQueryDto, InvoicePayloadDto, InvoiceReportDto,
+            InvoiceReportRequestDto, InvoiceReportRowDto, InvoiceReportSummaryDto,
+            PreparedInvoicePayloadDto, TaxpayerCredentialsDto, TaxpayerDto, TaxpayerProfileDto,
         },
+        vat_return::VatReturnQueryDto,
     },
+    routes::invoice_pdf::pdf_response,
     services::{
+        cpu_pool::CpuPool,
         crypto::pki_service::compute_hash,
-        db::taxpayer_auth::{authenticate_taxpayer, fetch_taxpayer_profile},
-        pipeline::onboarding_service,
-        xml::{c14n11::canonicalize_c14n11, extractors::extract_invoice},
+        db::{
+            invoice_lines::{fetch_tax_category_breakdown, fetch_top_items},
+            invoice_lookup::fetch_taxpayer_invoice_bytes,
+            tax_period_service::{
+                TaxPeriodAction, create_tax_period, list_tax_periods, transition_tax_period,
+            },
+            taxpayer_auth::{authenticate_taxpayer, fetch_taxpayer_profile},
+        },
+        pipeline::{
+            onboarding_service,
+            vat_return_service::{compute_vat_return, sign_vat_return},
+        },
+        xml::{c14n11::canonicalize_c14n11, extractors::extract_invoice, guard::check_xml},
     },
 };
 
 const DEFAULT_INVOICE_REPORT_LIMIT: i64 = 25;
 const MAX_INVOICE_REPORT_LIMIT: i64 = 100;
+const DEFAULT_TOP_ITEMS_LIMIT: i64 = 10;
+const MAX_TOP_ITEMS_LIMIT: i64 = 100;
 
 #[derive(sqlx::FromRow)]
 struct InvoiceReportSummaryRow {
@@ -92,6 +112,35 @@ fn parse_report_filters(
     })
 }
 
+/// Issue-date period of an analytics query, both ends inclusive.
+fn parse_analytics_period(
+    query: &InvoiceAnalyticsQueryDto,
+) -> Result<(Option<NaiveDate>, Option<NaiveDate>), ApiError> {
+    let from = parse_report_date(query.from.as_deref())?;
+    let to = parse_report_date(query.to.as_deref())?;
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(ApiError::new(ErrorCode::InvalidRequestBody));
+    }
+    Ok((from, to))
+}
+
+/// VAT return period; both ends are required and inclusive.
+/// A period whose bounds are both given, used by VAT returns and tax
+/// periods.
+pub(crate) fn parse_required_period(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(NaiveDate, NaiveDate), ApiError> {
+    let from = parse_report_date(from)?;
+    let to = parse_report_date(to)?;
+    match (from, to) {
+        (Some(from), Some(to)) if from <= to => Ok((from, to)),
+        _ => Err(ApiError::new(ErrorCode::InvalidRequestBody)),
+    }
+}
+
 fn normalize_report_choice(value: Option<&str>, allowed: &[&str]) -> Result<String, ApiError> {
     let value = value
         .map(str::trim)
@@ -482,11 +531,244 @@ pub async fn invoice_report(
     }))
 }
 
+/// Best-selling items of the signed-in taxpayer by net amount, for invoices
+/// issued in the `from`/`to` period.
+pub async fn top_items(
+    session: Session,
+    query: web::Query<InvoiceAnalyticsQueryDto>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    top_items_response(&tin, &query, &pool).await
+}
+
+/// Top items of `tin`, shared with the admin route.
+pub(crate) async fn top_items_response(
+    tin: &str,
+    query: &InvoiceAnalyticsQueryDto,
+    pool: &PgPool,
+) -> Result<HttpResponse, ApiError> {
+    let (from, to) = parse_analytics_period(query)?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TOP_ITEMS_LIMIT)
+        .clamp(1, MAX_TOP_ITEMS_LIMIT);
+
+    let items = fetch_top_items(tin, from, to, limit, pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to fetch taxpayer top items");
+            ApiError::internal()
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Top items loaded".to_string(),
+        data: Some(items),
+    }))
+}
+
+/// Net and tax amounts of the signed-in taxpayer's invoice lines per tax
+/// category and rate, for invoices issued in the `from`/`to` period.
+pub async fn tax_category_breakdown(
+    session: Session,
+    query: web::Query<InvoiceAnalyticsQueryDto>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    tax_category_breakdown_response(&tin, &query, &pool).await
+}
+
+/// Tax category breakdown of `tin`, shared with the admin route.
+pub(crate) async fn tax_category_breakdown_response(
+    tin: &str,
+    query: &InvoiceAnalyticsQueryDto,
+    pool: &PgPool,
+) -> Result<HttpResponse, ApiError> {
+    let (from, to) = parse_analytics_period(query)?;
+
+    let categories = fetch_tax_category_breakdown(tin, from, to, pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to fetch taxpayer tax categories");
+            ApiError::internal()
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Tax category breakdown loaded".to_string(),
+        data: Some(categories),
+    }))
+}
+
+/// Draft VAT return of the signed-in taxpayer for invoices issued in the
+/// `from`/`to` period.
+pub async fn vat_return(
+    session: Session,
+    query: web::Query<VatReturnQueryDto>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let (from, to) = parse_required_period(query.from.as_deref(), query.to.as_deref())?;
+
+    let draft = compute_vat_return(&tin, from, to, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to compute VAT return");
+            ApiError::internal()
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "VAT return computed".to_string(),
+        data: Some(draft),
+    }))
+}
+
+/// The draft VAT return with an STC-signed JWS over it, as a JSON download.
+pub async fn export_vat_return(
+    session: Session,
+    query: web::Query<VatReturnQueryDto>,
+    pool: web::Data<PgPool>,
+    crypto: web::Data<Crypto>,
+    cpu_pool: web::Data<CpuPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let (from, to) = parse_required_period(query.from.as_deref(), query.to.as_deref())?;
+
+    let draft = compute_vat_return(&tin, from, to, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to compute VAT return");
+            ApiError::internal()
+        })?;
+    let signed = sign_vat_return(draft, &crypto, &cpu_pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to sign VAT return");
+            ApiError::from_cpu_job(&error)
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!(r#"attachment; filename="vat-return-{tin}-{from}-{to}.json""#),
+        ))
+        .json(signed))
+}
+
+/// PDF of one of the signed-in taxpayer's invoices. Invoices of other
+/// taxpayers are reported as not found.
+pub async fn taxpayer_invoice_pdf(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    cpu_pool: web::Data<CpuPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let uuid = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::InvalidInvoiceUuid))?;
+
+    let invoice_bytes = fetch_taxpayer_invoice_bytes(&uuid, &tin, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, uuid = %uuid, error = %error, "Failed to fetch taxpayer invoice");
+            ApiError::internal()
+        })?
+        .ok_or_else(|| ApiError::new(ErrorCode::InvoiceNotFound))?;
+
+    pdf_response(uuid, invoice_bytes, &cpu_pool).await
+}
+
+pub async fn tax_periods(
+    session: Session,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let periods = list_tax_periods(&tin, &pool).await.map_err(|error| {
+        tracing::error!(tin = %tin, error = %error, "Failed to list tax periods");
+        ApiError::internal()
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: "Tax periods fetched".to_string(),
+        data: Some(periods),
+    }))
+}
+
+pub async fn add_tax_period(
+    session: Session,
+    payload: web::Json<CreateTaxPeriodDto>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let (from, to) = parse_required_period(payload.from.as_deref(), payload.to.as_deref())?;
+
+    let period = create_tax_period(&tin, from, to, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, error = %error, "Failed to create tax period");
+            ApiError::from_tax_period(&error)
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse {
+        success: true,
+        message: "Tax period created".to_string(),
+        data: Some(period),
+    }))
+}
+
+pub async fn file_tax_period(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    update_tax_period(session, path, pool, TaxPeriodAction::File).await
+}
+
+pub async fn lock_tax_period(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    update_tax_period(session, path, pool, TaxPeriodAction::Lock).await
+}
+
+async fn update_tax_period(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    action: TaxPeriodAction,
+) -> Result<HttpResponse, ApiError> {
+    let tin = require_tin(&session)?;
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| ApiError::new(ErrorCode::TaxPeriodNotFound))?;
+
+    let period = transition_tax_period(&id, Some(&tin), action, &pool)
+        .await
+        .map_err(|error| {
+            tracing::error!(tin = %tin, id = %id, action = ?action, error = %error, "Failed to update tax period");
+            ApiError::from_tax_period(&error)
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Tax period {}", period.status),
+        data: Some(period),
+    }))
+}
+
 pub async fn prepare_invoice_payload(
     payload: web::Json<InvoicePayloadDto>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
     let invoice_xml = payload.into_inner().invoice_xml;
     let invoice_bytes = invoice_xml.into_bytes();
+    check_xml(&invoice_bytes, &xml_limits).map_err(|error| {
+        tracing::error!(error = %error, "Sandbox invoice rejected by the XML guard");
+        ApiError::from_invoice_parse(&error)
+    })?;
     let canonical_invoice =
         canonicalize_c14n11(extract_invoice(&invoice_bytes).map_err(|error| {
             tracing::error!(error = %error, "Sandbox invoice extraction failed");
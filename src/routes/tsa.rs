@@ -0,0 +1,55 @@
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
+
+use crate::{
+    config::tsa_config::Tsa,
+    errors::{ApiError, ErrorCode},
+    models::responses::{ApiResponse, ErrorData},
+    routes::invoice_body::read_body,
+    services::{
+        cpu_pool::CpuPool,
+        crypto::tsa_service::{TIMESTAMP_QUERY, TIMESTAMP_REPLY, respond},
+    },
+};
+
+/// Upper bound for a `TimeStampReq` body; real requests are a few hundred bytes.
+const MAX_TIMESTAMP_QUERY_BYTES: usize = 16 * 1024;
+
+#[utoipa::path(
+    post,
+    path = "/tsa",
+    tag = "Public API",
+    request_body(
+        description = "DER-encoded RFC 3161 `TimeStampReq` (SHA-256, SHA-384, or SHA-512 message imprint)",
+        content((Vec<u8> = "application/timestamp-query"))
+    ),
+    responses(
+        (status = 200, description = "DER-encoded `TimeStampResp`; malformed or unsupported requests carry a rejection status and `PKIFailureInfo`", content((Vec<u8> = "application/timestamp-reply"))),
+        (status = 413, description = "Request body is too large", body = ApiResponse<ErrorData>),
+        (status = 415, description = "Content-Type must be application/timestamp-query", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>),
+        (status = 503, description = "Server is busy; retry after the `Retry-After` delay", body = ApiResponse<ErrorData>)
+    )
+)]
+pub async fn timestamp(
+    req: HttpRequest,
+    payload: web::Payload,
+    tsa: web::Data<Tsa>,
+    cpu_pool: web::Data<CpuPool>,
+) -> Result<HttpResponse, ApiError> {
+    if req.content_type() != TIMESTAMP_QUERY {
+        return Err(ApiError::new(ErrorCode::UnsupportedTimestampContentType));
+    }
+    let request = read_body(&req, payload.into_inner(), MAX_TIMESTAMP_QUERY_BYTES).await?;
+
+    let response = cpu_pool
+        .run(move || Ok(respond(&tsa, &request)))
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Time-stamp request failed");
+            ApiError::from_timestamp(&e)
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(TIMESTAMP_REPLY)
+        .body(response))
+}
@@ -1,24 +1,57 @@
 use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
 
 use crate::{
-    config::crypto_config::Crypto,
+    config::{crypto_config::Crypto, xml_config::XmlLimits},
     errors::ApiError,
-    models::{qr_verification::QrVerificationDto, responses::ApiResponse},
-    services::crypto::verify_qr::verify_qr_signature,
+    models::{
+        qr_verification::{QrVerificationDto, QrVerificationResultDto},
+        responses::{ApiResponse, ErrorData},
+    },
+    services::{
+        crypto::verify_qr::verify_qr_signature, db::invoice_lookup::invoice_exists_by_hash,
+        xml::guard::check_size,
+    },
 };
 
+#[utoipa::path(
+    post,
+    path = "/verify_qr",
+    tag = "Public API",
+    request_body = QrVerificationDto,
+    responses(
+        (status = 200, description = "QR signature verified; decoded invoice facts, signer identity and whether the invoice is registered", body = ApiResponse<QrVerificationResultDto>),
+        (status = 400, description = "QR payload is malformed, incomplete, or its signature or certificate is invalid", body = ApiResponse<ErrorData>),
+        (status = 500, description = "Internal server error", body = ApiResponse<ErrorData>)
+    )
+)]
 pub async fn verify_qr(
     qr_dto: web::Json<QrVerificationDto>,
+    db_pool: web::Data<PgPool>,
     crypto: web::Data<Crypto>,
+    xml_limits: web::Data<XmlLimits>,
 ) -> Result<HttpResponse, ApiError> {
-    verify_qr_signature(&qr_dto.into_inner().qr_b64, crypto.get_ref()).map_err(|e| {
-        tracing::error!(error = %e, "QR verification failed");
-        ApiError::from_qr(&e)
-    })?;
+    let qr_b64 = qr_dto.into_inner().qr_b64;
+    let verified = check_size(qr_b64.len(), &xml_limits)
+        .and_then(|()| verify_qr_signature(&qr_b64, crypto.get_ref()))
+        .map_err(|e| {
+            tracing::error!(error = %e, "QR verification failed");
+            ApiError::from_qr(&e)
+        })?;
+    let invoice_registered = invoice_exists_by_hash(&verified.invoice_hash, &db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "QR invoice lookup failed");
+            ApiError::internal()
+        })?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::<()> {
+    Ok(HttpResponse::Ok().json(ApiResponse {
         success: true,
         message: "verified".into(),
-        data: None,
+        data: Some(QrVerificationResultDto {
+            facts: verified.facts,
+            signer: verified.signer,
+            invoice_registered,
+        }),
     }))
 }
@@ -0,0 +1,185 @@
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::Context;
+use tokio::sync::Semaphore;
+
+use crate::{config::cpu_pool_config::CpuPoolConfig, models::metrics::CpuPoolStats};
+
+/// Returned when the pool's wait queue is full and the job was shed.
+#[derive(Debug)]
+pub struct CpuPoolSaturated;
+
+impl fmt::Display for CpuPoolSaturated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cpu pool saturated")
+    }
+}
+
+impl std::error::Error for CpuPoolSaturated {}
+
+/// Bounded pool for CPU-bound pipeline stages (schema validation, C14N,
+/// XAdES verification, RSA signing). Jobs run on Tokio's blocking threads so
+/// Actix workers stay free; at most `workers` run at once and at most
+/// `max_queue` wait, beyond which new jobs fail with [`CpuPoolSaturated`].
+pub struct CpuPool {
+    workers: usize,
+    max_queue: usize,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl CpuPool {
+    pub fn new(config: CpuPoolConfig) -> Self {
+        Self {
+            workers: config.workers,
+            max_queue: config.max_queue,
+            permits: Arc::new(Semaphore::new(config.workers)),
+            queued: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Runs `job` on the pool, waiting for a worker if all are busy.
+    pub async fn run<F, T>(&self, job: F) -> anyhow::Result<T>
+    where
+        F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queue {
+                    self.queued.fetch_sub(1, Ordering::SeqCst);
+                    self.rejected.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(
+                        workers = self.workers,
+                        max_queue = self.max_queue,
+                        "CPU pool saturated; shedding work"
+                    );
+                    return Err(CpuPoolSaturated.into());
+                }
+                let _slot = QueueSlot(&self.queued);
+                self.permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .context("cpu pool is closed")?
+            }
+        };
+
+        // The permit moves into the job so it is held until the work finishes,
+        // even if the caller stops waiting.
+        let span = tracing::Span::current();
+        let result = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _entered = span.enter();
+            job()
+        })
+        .await
+        .context("cpu pool job panicked")?;
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    pub fn stats(&self) -> CpuPoolStats {
+        let busy = self.workers - self.permits.available_permits().min(self.workers);
+        let queued = self.queued.load(Ordering::SeqCst);
+        let capacity = self.workers + self.max_queue;
+        CpuPoolStats {
+            workers: self.workers,
+            busy,
+            queued,
+            max_queue: self.max_queue,
+            saturation: (busy + queued) as f64 / capacity as f64,
+            completed_total: self.completed.load(Ordering::Relaxed),
+            rejected_total: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Releases a wait-queue slot when the waiting future completes or is dropped.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, time::Duration};
+
+    fn pool(workers: usize, max_queue: usize) -> Arc<CpuPool> {
+        Arc::new(CpuPool::new(CpuPoolConfig { workers, max_queue }))
+    }
+
+    #[tokio::test]
+    async fn runs_jobs_and_counts_them() {
+        let pool = pool(2, 2);
+        let value = pool.run(|| Ok(21 * 2)).await.unwrap();
+        assert_eq!(value, 42);
+        assert_eq!(pool.stats().completed_total, 1);
+        assert_eq!(pool.stats().busy, 0);
+    }
+
+    #[tokio::test]
+    async fn job_errors_are_returned() {
+        let pool = pool(1, 0);
+        let err = pool
+            .run(|| -> anyhow::Result<()> { anyhow::bail!("bad invoice") })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "bad invoice");
+    }
+
+    #[tokio::test]
+    async fn sheds_work_when_queue_is_full() {
+        let pool = pool(1, 1);
+        let (release, wait) = mpsc::channel::<()>();
+
+        let busy = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pool.run(move || {
+                    wait.recv().ok();
+                    Ok(())
+                })
+                .await
+            }
+        });
+        while pool.stats().busy == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| Ok(())).await }
+        });
+        while pool.stats().queued == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let err = pool.run(|| Ok(())).await.unwrap_err();
+        assert!(err.is::<CpuPoolSaturated>());
+        let stats = pool.stats();
+        assert_eq!(stats.rejected_total, 1);
+        assert_eq!(stats.saturation, 1.0);
+
+        release.send(()).unwrap();
+        busy.await.unwrap().unwrap();
+        queued.await.unwrap().unwrap();
+        assert_eq!(pool.stats().queued, 0);
+        assert_eq!(pool.stats().completed_total, 2);
+    }
+}
@@ -1,3 +1,44 @@
 pub mod pki_service;
+pub mod receipt_signing;
+pub mod tsa_service;
 pub mod verify_qr;
 pub mod xades_bes;
+pub mod xades_signer;
+pub mod xmldsig_algorithms;
+
+/// A self-signed RSA certificate and key for tests, valid from today for one
+/// day with serial number 42.
+#[cfg(test)]
+pub(crate) fn test_crypto(common_name: &str) -> crate::config::crypto_config::Crypto {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{X509Builder, X509NameBuilder},
+    };
+
+    let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", common_name).unwrap();
+    let name = name.build();
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(42).unwrap().to_asn1_integer().unwrap();
+    builder.set_serial_number(&serial).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&private_key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder.sign(&private_key, MessageDigest::sha256()).unwrap();
+    crate::config::crypto_config::Crypto {
+        private_key,
+        certificate: builder.build(),
+    }
+}
@@ -7,9 +7,14 @@ use openssl::hash::hash;
 use openssl::nid::Nid;
 use openssl::{
     asn1::Asn1Time,
+    error::ErrorStack,
     hash::MessageDigest,
+    pkey::{PKeyRef, Private},
     sign::{Signer, Verifier},
-    x509::{X509, X509Builder, X509Req},
+    x509::{
+        X509, X509Builder, X509NameBuilder, X509Req,
+        extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage},
+    },
 };
 use tracing::instrument;
 use uuid::Uuid;
@@ -46,7 +51,7 @@ pub async fn handle_enrollment(
         .map_err(|e| anyhow!("failed to convert the certificate to a String : {}", e))
 }
 
-pub async fn verify_cert_with_ca(ca_crt: &X509, client_crt: &X509) -> anyhow::Result<bool> {
+pub fn verify_cert_with_ca(ca_crt: &X509, client_crt: &X509) -> anyhow::Result<bool> {
     let now = Asn1Time::days_from_now(0).context("failed to generate the time in the server")?;
     if client_crt.not_before() > now || client_crt.not_after() < now {
         return Err(anyhow!("certficate is expired or yet to be used"));
@@ -148,6 +153,43 @@ pub async fn sign_csr(req: &X509Req, crypto: &Crypto) -> Result<X509, openssl::e
     Ok(builder.build())
 }
 
+/// Issues the certificate of the built-in time-stamping authority from the STC
+/// CA. It is restricted to the critical `timeStamping` extended key usage, as
+/// RFC 3161 requires.
+pub fn issue_tsa_certificate(
+    private_key: &PKeyRef<Private>,
+    crypto: &Crypto,
+) -> Result<X509, ErrorStack> {
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    let mut serial = BigNum::new()?;
+    serial.rand(128, openssl::bn::MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    builder.set_serial_number(&serial)?;
+    let mut subject = X509NameBuilder::new()?;
+    subject.append_entry_by_text("CN", "STC Time-Stamping Authority")?;
+    builder.set_subject_name(&subject.build())?;
+    builder.set_issuer_name(crypto.certificate.subject_name())?;
+    builder.set_pubkey(private_key)?;
+    let validity_in = Asn1Time::days_from_now(0)?;
+    let validity_expr = Asn1Time::days_from_now(365)?;
+    builder.set_not_before(&validity_in)?;
+    builder.set_not_after(&validity_expr)?;
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .non_repudiation()
+            .build()?,
+    )?;
+    builder.append_extension(ExtendedKeyUsage::new().critical().time_stamping().build()?)?;
+
+    builder.sign(&crypto.private_key, MessageDigest::sha256())?;
+
+    Ok(builder.build())
+}
+
 pub fn sign(hash: &[u8], crypto: &Crypto) -> anyhow::Result<Vec<u8>> {
     let mut signer = Signer::new(MessageDigest::sha256(), &crypto.private_key)?;
     signer.update(hash)?;
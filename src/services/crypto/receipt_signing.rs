@@ -0,0 +1,130 @@
+use anyhow::{Context, bail};
+use base64::{
+    Engine,
+    engine::general_purpose::{self, URL_SAFE_NO_PAD},
+};
+use openssl::{pkey::Id, x509::X509};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    config::crypto_config::Crypto,
+    models::receipt::InvoiceReceipt,
+    services::crypto::pki_service::{sign, verify_signature_with_cert},
+};
+
+/// JWS `typ` of acknowledgment receipts.
+pub const RECEIPT_JWS_TYPE: &str = "receipt+jws";
+
+/// Signs an acknowledgment receipt as a compact JWS (RS256) carrying the STC
+/// certificate in the `x5c` header.
+pub fn sign_receipt(receipt: &InvoiceReceipt, crypto: &Crypto) -> anyhow::Result<String> {
+    sign_jws(receipt, RECEIPT_JWS_TYPE, crypto).context("failed to sign receipt")
+}
+
+/// Signs `payload` as JSON in a compact JWS (RS256) carrying the STC
+/// certificate in the `x5c` header. `typ` names the payload; it is not a JWT.
+pub fn sign_jws(payload: &impl Serialize, typ: &str, crypto: &Crypto) -> anyhow::Result<String> {
+    if crypto.private_key.id() != Id::RSA {
+        bail!("JWS signing requires an RSA key");
+    }
+    let certificate = general_purpose::STANDARD.encode(crypto.certificate.to_der()?);
+    let header = json!({ "alg": "RS256", "typ": typ, "x5c": [certificate] });
+    let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?);
+    let signing_input = format!("{header}.{payload}");
+    let signature = sign(signing_input.as_bytes(), crypto)?;
+    Ok(format!(
+        "{signing_input}.{}",
+        URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
+
+/// Verifies a compact JWS receipt against `certificate` and returns its payload.
+pub fn verify_receipt(signed_receipt: &str, certificate: &X509) -> anyhow::Result<InvoiceReceipt> {
+    let mut parts = signed_receipt.split('.');
+    let (Some(header_b64), Some(payload_b64), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("receipt is not a compact JWS");
+    };
+
+    let header: serde_json::Value = serde_json::from_slice(
+        &URL_SAFE_NO_PAD
+            .decode(header_b64)
+            .context("invalid receipt header encoding")?,
+    )
+    .context("invalid receipt header")?;
+    if header["alg"] != "RS256" {
+        bail!("unsupported receipt algorithm");
+    }
+    if header["typ"] != RECEIPT_JWS_TYPE {
+        bail!("not a receipt JWS");
+    }
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .context("invalid receipt signature encoding")?;
+    let signing_input = format!("{header_b64}.{payload_b64}");
+    if !verify_signature_with_cert(signing_input.as_bytes(), &signature, certificate)? {
+        bail!("invalid receipt signature");
+    }
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .context("invalid receipt payload encoding")?;
+    serde_json::from_slice(&payload).context("invalid receipt payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::crypto::test_crypto;
+
+    fn receipt() -> InvoiceReceipt {
+        InvoiceReceipt {
+            invoice_uuid: "550e8400-e29b-41d4-a716-446655440000".to_owned(),
+            invoice_hash: "AAAA".to_owned(),
+            device_uuid: "6f1c2a4e-8d3b-4f0a-9c1e-2b7d5e9a0f13".to_owned(),
+            icv: 7,
+            received_at: "2026-06-08T10:15:30Z".to_owned(),
+            accepted_at: "2026-06-08T10:15:31Z".to_owned(),
+        }
+    }
+
+    #[test]
+    fn signed_receipt_round_trips() {
+        let crypto = test_crypto("STC Test CA");
+        let jws = sign_receipt(&receipt(), &crypto).unwrap();
+        let verified = verify_receipt(&jws, &crypto.certificate).unwrap();
+        assert_eq!(verified.icv, 7);
+        assert_eq!(verified.invoice_uuid, receipt().invoice_uuid);
+
+        let header = URL_SAFE_NO_PAD
+            .decode(jws.split('.').next().unwrap())
+            .unwrap();
+        let header: serde_json::Value = serde_json::from_slice(&header).unwrap();
+        assert_eq!(header["typ"], "receipt+jws");
+    }
+
+    #[test]
+    fn tampered_receipt_is_rejected() {
+        let crypto = test_crypto("STC Test CA");
+        let jws = sign_receipt(&receipt(), &crypto).unwrap();
+        let mut tampered = receipt();
+        tampered.icv = 8;
+        let tampered_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&tampered).unwrap());
+        let parts: Vec<_> = jws.split('.').collect();
+        let forged = format!("{}.{}.{}", parts[0], tampered_payload, parts[2]);
+        let err = verify_receipt(&forged, &crypto.certificate)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("invalid receipt signature"));
+    }
+
+    #[test]
+    fn malformed_receipt_is_rejected() {
+        let crypto = test_crypto("STC Test CA");
+        assert!(verify_receipt("a.b", &crypto.certificate).is_err());
+    }
+}
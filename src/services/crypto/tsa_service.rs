@@ -0,0 +1,876 @@
+use anyhow::{Context, bail};
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, BigNumRef, MsbOption},
+    hash::{MessageDigest, hash},
+    memcmp,
+    x509::X509,
+};
+use time::OffsetDateTime;
+use yasna::{
+    ASN1Error, ASN1ErrorKind, ASN1Result, BERReader, DERWriter, Tag,
+    models::{GeneralizedTime, ObjectIdentifier},
+};
+
+use crate::{
+    config::tsa_config::Tsa,
+    services::crypto::pki_service::{compute_hash, sign, verify_signature_with_cert},
+};
+
+/// Media type of a DER `TimeStampReq`.
+pub const TIMESTAMP_QUERY: &str = "application/timestamp-query";
+/// Media type of a DER `TimeStampResp`.
+pub const TIMESTAMP_REPLY: &str = "application/timestamp-reply";
+
+const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+const OID_SHA384: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 2];
+const OID_SHA512: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 3];
+const OID_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+const OID_SHA256_WITH_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 11];
+const OID_SIGNED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 2];
+const OID_CONTENT_TYPE: &[u64] = &[1, 2, 840, 113549, 1, 9, 3];
+const OID_MESSAGE_DIGEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 4];
+const OID_TST_INFO: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 1, 4];
+const OID_SIGNING_CERTIFICATE_V2: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 2, 47];
+const OID_EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+const OID_TIME_STAMPING: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 3, 8];
+
+const STATUS_GRANTED: u8 = 0;
+const STATUS_REJECTION: u8 = 2;
+
+/// Hash algorithms accepted in a `MessageImprint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImprintAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl ImprintAlgorithm {
+    const ALL: [Self; 3] = [Self::Sha256, Self::Sha384, Self::Sha512];
+
+    fn oid(self) -> &'static [u64] {
+        match self {
+            Self::Sha256 => OID_SHA256,
+            Self::Sha384 => OID_SHA384,
+            Self::Sha512 => OID_SHA512,
+        }
+    }
+
+    fn from_oid(oid: &ObjectIdentifier) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| oid.components().as_slice() == algorithm.oid())
+    }
+
+    pub fn digest(self) -> MessageDigest {
+        match self {
+            Self::Sha256 => MessageDigest::sha256(),
+            Self::Sha384 => MessageDigest::sha384(),
+            Self::Sha512 => MessageDigest::sha512(),
+        }
+    }
+}
+
+/// The hash a time-stamp is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageImprint {
+    pub algorithm: ImprintAlgorithm,
+    pub hashed_message: Vec<u8>,
+}
+
+impl MessageImprint {
+    pub fn sha256(hashed_message: Vec<u8>) -> Self {
+        Self {
+            algorithm: ImprintAlgorithm::Sha256,
+            hashed_message,
+        }
+    }
+
+    /// Hashes `data` with `algorithm`.
+    pub fn of(algorithm: ImprintAlgorithm, data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            algorithm,
+            hashed_message: hash(algorithm.digest(), data)?.to_vec(),
+        })
+    }
+}
+
+/// A parsed `TimeStampReq`.
+#[derive(Debug)]
+pub struct TimeStampRequest {
+    pub imprint: MessageImprint,
+    pub policy: Option<Vec<u64>>,
+    /// Big-endian magnitude of the positive nonce.
+    pub nonce: Option<Vec<u8>>,
+    pub cert_req: bool,
+}
+
+/// `TSTInfo` fields of a verified time-stamp token.
+#[derive(Debug)]
+pub struct TimeStampInfo {
+    pub policy: Vec<u64>,
+    pub serial_number: Vec<u8>,
+    pub gen_time: OffsetDateTime,
+    pub nonce: Option<Vec<u8>>,
+}
+
+/// `PKIFailureInfo` bits reported in a rejected `TimeStampResp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureInfo {
+    BadAlg = 0,
+    BadRequest = 2,
+    BadDataFormat = 5,
+    UnacceptedPolicy = 15,
+    UnacceptedExtension = 16,
+    SystemFailure = 25,
+}
+
+impl FailureInfo {
+    fn status_string(self) -> &'static str {
+        match self {
+            Self::BadAlg => "unsupported hash algorithm",
+            Self::BadRequest => "unsupported request version",
+            Self::BadDataFormat => "malformed time-stamp request",
+            Self::UnacceptedPolicy => "requested policy is not supported",
+            Self::UnacceptedExtension => "request extensions are not supported",
+            Self::SystemFailure => "time-stamp could not be issued",
+        }
+    }
+}
+
+/// Answers a DER `TimeStampReq` with a DER `TimeStampResp`. Malformed or
+/// unsupported requests are answered with a rejection status rather than an
+/// error, as RFC 3161 expects.
+pub fn respond(tsa: &Tsa, request_der: &[u8]) -> Vec<u8> {
+    let request = match parse_request(request_der) {
+        Ok(request) => request,
+        Err(failure) => return rejection(failure),
+    };
+    if let Some(policy) = &request.policy
+        && *policy != tsa.policy
+    {
+        return rejection(FailureInfo::UnacceptedPolicy);
+    }
+
+    match timestamp(
+        tsa,
+        &request.imprint,
+        request.nonce.as_deref(),
+        request.cert_req,
+    ) {
+        Ok(token) => yasna::construct_der(|writer| {
+            writer.write_sequence(|writer| {
+                write_status(writer.next(), STATUS_GRANTED, None);
+                writer.next().write_der(&token);
+            })
+        }),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to issue time-stamp token");
+            rejection(FailureInfo::SystemFailure)
+        }
+    }
+}
+
+pub fn parse_request(request_der: &[u8]) -> Result<TimeStampRequest, FailureInfo> {
+    let (version, (algorithm, hashed_message), policy, nonce, cert_req, has_extensions) =
+        yasna::parse_ber(request_der, |reader| {
+            reader.read_sequence(|reader| {
+                let version = reader.next().read_u8()?;
+                let imprint = read_imprint(reader.next())?;
+                let policy = reader.read_optional(|reader| reader.read_oid())?;
+                let nonce = reader.read_optional(|reader| reader.read_bigint_bytes())?;
+                let cert_req = reader.read_default(false, |reader| reader.read_bool())?;
+                let extensions = reader.read_optional(|reader| reader.read_der())?;
+                Ok((
+                    version,
+                    imprint,
+                    policy,
+                    nonce,
+                    cert_req,
+                    extensions.is_some(),
+                ))
+            })
+        })
+        .map_err(|_| FailureInfo::BadDataFormat)?;
+
+    if version != 1 {
+        return Err(FailureInfo::BadRequest);
+    }
+    let algorithm = ImprintAlgorithm::from_oid(&algorithm).ok_or(FailureInfo::BadAlg)?;
+    if hashed_message.len() != algorithm.digest().size() {
+        return Err(FailureInfo::BadDataFormat);
+    }
+    let nonce = match nonce {
+        Some((_, false)) => return Err(FailureInfo::BadDataFormat),
+        Some((nonce, true)) => Some(nonce),
+        None => None,
+    };
+    if has_extensions {
+        return Err(FailureInfo::UnacceptedExtension);
+    }
+
+    Ok(TimeStampRequest {
+        imprint: MessageImprint {
+            algorithm,
+            hashed_message,
+        },
+        policy: policy.map(|policy| policy.components().clone()),
+        nonce,
+        cert_req,
+    })
+}
+
+/// Issues a `TimeStampToken`: a CMS `SignedData` over `TSTInfo`, signed by
+/// the TSA key with RSA-SHA256.
+pub fn timestamp(
+    tsa: &Tsa,
+    imprint: &MessageImprint,
+    nonce: Option<&[u8]>,
+    include_certificate: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let signer = &tsa.signer;
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::ONE, false)?;
+    let serial = serial.to_vec();
+    let gen_time = OffsetDateTime::now_utc().replace_nanosecond(0)?;
+
+    let tst_info = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer.next().write_u8(1);
+            writer
+                .next()
+                .write_oid(&ObjectIdentifier::from_slice(&tsa.policy));
+            write_imprint(writer.next(), imprint);
+            writer.next().write_bigint_bytes(&serial, true);
+            writer
+                .next()
+                .write_generalized_time(&GeneralizedTime::from_datetime(gen_time));
+            if let Some(nonce) = nonce {
+                writer.next().write_bigint_bytes(nonce, true);
+            }
+        })
+    });
+
+    let certificate = signer.certificate.to_der()?;
+    let tst_info_digest = compute_hash(&tst_info)?;
+    let certificate_digest = compute_hash(&certificate)?;
+    let signed_attributes = yasna::construct_der(|writer| {
+        writer.write_set_of(|writer| {
+            write_attribute(writer.next(), OID_CONTENT_TYPE, |writer| {
+                writer.write_oid(&oid(OID_TST_INFO))
+            });
+            write_attribute(writer.next(), OID_MESSAGE_DIGEST, |writer| {
+                writer.write_bytes(&tst_info_digest)
+            });
+            // SigningCertificateV2 ::= SEQUENCE { certs SEQUENCE OF ESSCertIDv2 }
+            write_attribute(writer.next(), OID_SIGNING_CERTIFICATE_V2, |writer| {
+                writer.write_sequence(|writer| {
+                    writer.next().write_sequence(|writer| {
+                        writer
+                            .next()
+                            .write_sequence(|writer| writer.next().write_bytes(&certificate_digest))
+                    })
+                })
+            });
+        })
+    });
+    let signature = sign(&signed_attributes, signer).context("failed to sign TSTInfo")?;
+
+    let issuer = signer.certificate.issuer_name().to_der()?;
+    let certificate_serial = signer.certificate.serial_number().to_bn()?;
+    let certificate_serial = integer_bytes(&certificate_serial);
+    let certificates = include_certificate.then(|| {
+        context_zero(yasna::construct_der(|writer| {
+            writer.write_set_of(|writer| writer.next().write_der(&certificate))
+        }))
+    });
+
+    Ok(yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer.next().write_oid(&oid(OID_SIGNED_DATA));
+            writer.next().write_tagged(Tag::context(0), |writer| {
+                writer.write_sequence(|writer| {
+                    writer.next().write_u8(3);
+                    writer
+                        .next()
+                        .write_set_of(|writer| write_algorithm(writer.next(), OID_SHA256));
+                    writer.next().write_sequence(|writer| {
+                        writer.next().write_oid(&oid(OID_TST_INFO));
+                        writer
+                            .next()
+                            .write_tagged(Tag::context(0), |writer| writer.write_bytes(&tst_info));
+                    });
+                    if let Some(certificates) = &certificates {
+                        writer.next().write_der(certificates);
+                    }
+                    writer.next().write_set_of(|writer| {
+                        writer.next().write_sequence(|writer| {
+                            writer.next().write_u8(1);
+                            writer.next().write_sequence(|writer| {
+                                writer.next().write_der(&issuer);
+                                writer.next().write_bigint_bytes(&certificate_serial, true);
+                            });
+                            write_algorithm(writer.next(), OID_SHA256);
+                            writer
+                                .next()
+                                .write_der(&context_zero(signed_attributes.clone()));
+                            writer.next().write_sequence(|writer| {
+                                writer.next().write_oid(&oid(OID_SHA256_WITH_RSA));
+                                writer.next().write_null();
+                            });
+                            writer.next().write_bytes(&signature);
+                        })
+                    });
+                })
+            });
+        })
+    }))
+}
+
+/// Verifies a `TimeStampToken` signed by a TSA certificate that
+/// `ca_certificate` issued for time-stamping only, and checks that it covers
+/// `expected` and was generated while that certificate was valid.
+pub fn verify_token(
+    token: &[u8],
+    expected: &MessageImprint,
+    ca_certificate: &X509,
+) -> anyhow::Result<TimeStampInfo> {
+    let signed = parse_token(token)
+        .map_err(|e| anyhow::anyhow!("time-stamp token is not a valid SignedData: {e}"))?;
+    if signed.digest_algorithm.components().as_slice() != OID_SHA256 {
+        bail!("unsupported time-stamp token digest algorithm");
+    }
+    let signature_algorithm = signed.signature_algorithm.components().as_slice();
+    if signature_algorithm != OID_SHA256_WITH_RSA && signature_algorithm != OID_RSA_ENCRYPTION {
+        bail!("unsupported time-stamp token signature algorithm");
+    }
+
+    let attributes = parse_attributes(&signed.signed_attributes)
+        .map_err(|e| anyhow::anyhow!("invalid time-stamp token signed attributes: {e}"))?;
+    let content_type = attributes
+        .content_type
+        .context("time-stamp token is missing the content-type attribute")?;
+    if content_type.components().as_slice() != OID_TST_INFO {
+        bail!("time-stamp token content-type attribute is not TSTInfo");
+    }
+    let message_digest = attributes
+        .message_digest
+        .context("time-stamp token is missing the message-digest attribute")?;
+    if !memcmp::eq(&message_digest, &compute_hash(&signed.tst_info)?) {
+        bail!("time-stamp token TSTInfo digest mismatch");
+    }
+    let certificate_digest = attributes
+        .signing_certificate_digest
+        .context("time-stamp token is missing the signing-certificate attribute")?;
+
+    let certificate = signed
+        .certificates
+        .iter()
+        .find(|certificate| {
+            compute_hash(certificate)
+                .map(|digest| digest == certificate_digest)
+                .unwrap_or(false)
+        })
+        .context("time-stamp token does not carry the TSA certificate")?;
+    let extended_key_usage = parse_extended_key_usage(certificate)
+        .map_err(|e| anyhow::anyhow!("invalid time-stamp TSA certificate extensions: {e}"))?;
+    if extended_key_usage != Some((true, vec![oid(OID_TIME_STAMPING)])) {
+        bail!(
+            "time-stamp TSA certificate is not restricted to the critical timeStamping extended key usage"
+        );
+    }
+    let certificate = X509::from_der(certificate).context("invalid time-stamp TSA certificate")?;
+    let ca_public_key = ca_certificate.public_key()?;
+    if !certificate
+        .verify(&ca_public_key)
+        .context("failed to verify the time-stamp TSA certificate")?
+    {
+        bail!("time-stamp TSA certificate is not issued by the STC CA");
+    }
+    if !verify_signature_with_cert(&signed.signed_attributes, &signed.signature, &certificate)? {
+        bail!("invalid time-stamp token signature");
+    }
+
+    let info = parse_tst_info(&signed.tst_info)
+        .map_err(|e| anyhow::anyhow!("invalid time-stamp TSTInfo: {e}"))?;
+    if info.imprint != *expected {
+        bail!("time-stamp token does not cover the expected data");
+    }
+    let gen_time = Asn1Time::from_unix(info.info.gen_time.unix_timestamp())?;
+    if certificate.not_before() > gen_time || certificate.not_after() < gen_time {
+        bail!("time-stamp token genTime is outside the TSA certificate validity");
+    }
+    Ok(info.info)
+}
+
+struct SignedToken {
+    tst_info: Vec<u8>,
+    certificates: Vec<Vec<u8>>,
+    digest_algorithm: ObjectIdentifier,
+    /// `signedAttrs` re-tagged as a SET, which is what the signature covers.
+    signed_attributes: Vec<u8>,
+    signature_algorithm: ObjectIdentifier,
+    signature: Vec<u8>,
+}
+
+struct SignedAttributes {
+    content_type: Option<ObjectIdentifier>,
+    message_digest: Option<Vec<u8>>,
+    signing_certificate_digest: Option<Vec<u8>>,
+}
+
+struct ParsedTstInfo {
+    imprint: MessageImprint,
+    info: TimeStampInfo,
+}
+
+fn parse_token(token: &[u8]) -> ASN1Result<SignedToken> {
+    yasna::parse_der(token, |reader| {
+        reader.read_sequence(|reader| {
+            expect_oid(&reader.next().read_oid()?, OID_SIGNED_DATA)?;
+            reader.next().read_tagged(Tag::context(0), |reader| {
+                reader.read_sequence(|reader| {
+                    reader.next().read_u8()?;
+                    reader.next().read_der()?;
+                    let tst_info = reader.next().read_sequence(|reader| {
+                        expect_oid(&reader.next().read_oid()?, OID_TST_INFO)?;
+                        reader
+                            .next()
+                            .read_tagged(Tag::context(0), |reader| reader.read_bytes())
+                    })?;
+                    let certificates = reader
+                        .read_optional(|reader| {
+                            reader.read_tagged_implicit(Tag::context(0), |reader| {
+                                reader.collect_set_of(|reader| reader.read_der())
+                            })
+                        })?
+                        .unwrap_or_default();
+                    let mut signers = reader.next().collect_set_of(|reader| {
+                        reader.read_sequence(|reader| {
+                            reader.next().read_u8()?;
+                            reader.next().read_der()?;
+                            let digest_algorithm = read_algorithm(reader.next())?;
+                            let mut signed_attributes = reader.next().read_der()?;
+                            if signed_attributes.first() != Some(&0xa0) {
+                                return Err(ASN1Error::new(ASN1ErrorKind::Invalid));
+                            }
+                            signed_attributes[0] = 0x31;
+                            let signature_algorithm = read_algorithm(reader.next())?;
+                            let signature = reader.next().read_bytes()?;
+                            reader.read_optional(|reader| reader.read_der())?;
+                            Ok((
+                                digest_algorithm,
+                                signed_attributes,
+                                signature_algorithm,
+                                signature,
+                            ))
+                        })
+                    })?;
+                    if signers.len() != 1 {
+                        return Err(ASN1Error::new(ASN1ErrorKind::Invalid));
+                    }
+                    let (digest_algorithm, signed_attributes, signature_algorithm, signature) =
+                        signers.remove(0);
+                    Ok(SignedToken {
+                        tst_info,
+                        certificates,
+                        digest_algorithm,
+                        signed_attributes,
+                        signature_algorithm,
+                        signature,
+                    })
+                })
+            })
+        })
+    })
+}
+
+fn parse_attributes(signed_attributes: &[u8]) -> ASN1Result<SignedAttributes> {
+    let attributes = yasna::parse_der(signed_attributes, |reader| {
+        reader.collect_set_of(|reader| {
+            reader.read_sequence(|reader| {
+                let attribute_type = reader.next().read_oid()?;
+                let values = reader.next().collect_set_of(|reader| reader.read_der())?;
+                Ok((attribute_type, values))
+            })
+        })
+    })?;
+
+    let mut parsed = SignedAttributes {
+        content_type: None,
+        message_digest: None,
+        signing_certificate_digest: None,
+    };
+    for (attribute_type, values) in attributes {
+        let [value] = values.as_slice() else {
+            return Err(ASN1Error::new(ASN1ErrorKind::Invalid));
+        };
+        match attribute_type.components().as_slice() {
+            OID_CONTENT_TYPE => {
+                parsed.content_type = Some(yasna::parse_der(value, |reader| reader.read_oid())?)
+            }
+            OID_MESSAGE_DIGEST => {
+                parsed.message_digest = Some(yasna::parse_der(value, |reader| reader.read_bytes())?)
+            }
+            OID_SIGNING_CERTIFICATE_V2 => {
+                parsed.signing_certificate_digest =
+                    Some(yasna::parse_der(value, read_first_cert_id)?)
+            }
+            _ => {}
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reads the SHA-256 `certHash` of the first `ESSCertIDv2`.
+fn read_first_cert_id(reader: BERReader<'_, '_>) -> ASN1Result<Vec<u8>> {
+    reader.read_sequence(|reader| {
+        let mut cert_ids = reader.next().collect_sequence_of(|reader| {
+            reader.read_sequence(|reader| {
+                let algorithm = reader.read_optional(read_algorithm)?;
+                let cert_hash = reader.next().read_bytes()?;
+                reader.read_optional(|reader| reader.read_der())?;
+                Ok((algorithm, cert_hash))
+            })
+        })?;
+        reader.read_optional(|reader| reader.read_der())?;
+        if cert_ids.is_empty() {
+            return Err(ASN1Error::new(ASN1ErrorKind::Invalid));
+        }
+        let (algorithm, cert_hash) = cert_ids.remove(0);
+        if let Some(algorithm) = algorithm {
+            expect_oid(&algorithm, OID_SHA256)?;
+        }
+        Ok(cert_hash)
+    })
+}
+
+/// Reads the `extKeyUsage` extension of a DER certificate: whether it is
+/// critical, and its key purposes.
+fn parse_extended_key_usage(
+    certificate: &[u8],
+) -> ASN1Result<Option<(bool, Vec<ObjectIdentifier>)>> {
+    let extensions = yasna::parse_der(certificate, |reader| {
+        reader.read_sequence(|reader| {
+            let extensions = reader.next().read_sequence(|reader| {
+                let mut extensions = None;
+                while let Some(field) = reader.read_optional(|reader| reader.read_tagged_der())? {
+                    if field.tag() == Tag::context(3) {
+                        extensions = Some(field.value().to_vec());
+                    }
+                }
+                Ok(extensions)
+            })?;
+            // signatureAlgorithm and signatureValue
+            reader.next().read_der()?;
+            reader.next().read_der()?;
+            Ok(extensions)
+        })
+    })?;
+    let Some(extensions) = extensions else {
+        return Ok(None);
+    };
+    let extensions = yasna::parse_der(&extensions, |reader| {
+        reader.collect_sequence_of(|reader| {
+            reader.read_sequence(|reader| {
+                let extension_id = reader.next().read_oid()?;
+                let critical = reader.read_default(false, |reader| reader.read_bool())?;
+                let value = reader.next().read_bytes()?;
+                Ok((extension_id, critical, value))
+            })
+        })
+    })?;
+    extensions
+        .into_iter()
+        .find(|(extension_id, _, _)| extension_id.components().as_slice() == OID_EXTENDED_KEY_USAGE)
+        .map(|(_, critical, value)| {
+            let purposes = yasna::parse_der(&value, |reader| {
+                reader.collect_sequence_of(|reader| reader.read_oid())
+            })?;
+            Ok((critical, purposes))
+        })
+        .transpose()
+}
+
+fn parse_tst_info(tst_info: &[u8]) -> ASN1Result<ParsedTstInfo> {
+    yasna::parse_der(tst_info, |reader| {
+        reader.read_sequence(|reader| {
+            if reader.next().read_u8()? != 1 {
+                return Err(ASN1Error::new(ASN1ErrorKind::Invalid));
+            }
+            let policy = reader.next().read_oid()?;
+            let (algorithm, hashed_message) = read_imprint(reader.next())?;
+            let algorithm = ImprintAlgorithm::from_oid(&algorithm)
+                .ok_or(ASN1Error::new(ASN1ErrorKind::Invalid))?;
+            let (serial_number, _) = reader.next().read_bigint_bytes()?;
+            let gen_time = reader.next().read_generalized_time()?;
+            // accuracy
+            reader.read_optional(|reader| {
+                reader.read_sequence(|reader| {
+                    for _ in 0..3 {
+                        reader.read_optional(|reader| reader.read_der())?;
+                    }
+                    Ok(())
+                })
+            })?;
+            reader.read_default(false, |reader| reader.read_bool())?;
+            let nonce = reader.read_optional(|reader| reader.read_bigint_bytes())?;
+            // tsa and extensions
+            reader.read_optional(|reader| reader.read_der())?;
+            reader.read_optional(|reader| reader.read_der())?;
+            Ok(ParsedTstInfo {
+                imprint: MessageImprint {
+                    algorithm,
+                    hashed_message,
+                },
+                info: TimeStampInfo {
+                    policy: policy.components().clone(),
+                    serial_number,
+                    gen_time: *gen_time.datetime(),
+                    nonce: nonce.map(|(nonce, _)| nonce),
+                },
+            })
+        })
+    })
+}
+
+fn rejection(failure: FailureInfo) -> Vec<u8> {
+    yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| write_status(writer.next(), STATUS_REJECTION, Some(failure)))
+    })
+}
+
+fn write_status(writer: DERWriter, status: u8, failure: Option<FailureInfo>) {
+    writer.write_sequence(|writer| {
+        writer.next().write_u8(status);
+        if let Some(failure) = failure {
+            writer
+                .next()
+                .write_sequence(|writer| writer.next().write_utf8_string(failure.status_string()));
+            let bit = failure as usize;
+            let mut bits = vec![0u8; bit / 8 + 1];
+            bits[bit / 8] = 0x80 >> (bit % 8);
+            writer.next().write_bitvec_bytes(&bits, bit + 1);
+        }
+    })
+}
+
+fn write_imprint(writer: DERWriter, imprint: &MessageImprint) {
+    writer.write_sequence(|writer| {
+        write_algorithm(writer.next(), imprint.algorithm.oid());
+        writer.next().write_bytes(&imprint.hashed_message);
+    })
+}
+
+fn write_algorithm(writer: DERWriter, algorithm: &[u64]) {
+    writer.write_sequence(|writer| writer.next().write_oid(&oid(algorithm)))
+}
+
+fn write_attribute<F>(writer: DERWriter, attribute_type: &[u64], value: F)
+where
+    F: FnOnce(DERWriter),
+{
+    writer.write_sequence(|writer| {
+        writer.next().write_oid(&oid(attribute_type));
+        writer.next().write_set_of(|writer| value(writer.next()));
+    })
+}
+
+fn read_imprint(reader: BERReader<'_, '_>) -> ASN1Result<(ObjectIdentifier, Vec<u8>)> {
+    reader.read_sequence(|reader| {
+        let algorithm = read_algorithm(reader.next())?;
+        let hashed_message = reader.next().read_bytes()?;
+        Ok((algorithm, hashed_message))
+    })
+}
+
+fn read_algorithm(reader: BERReader<'_, '_>) -> ASN1Result<ObjectIdentifier> {
+    reader.read_sequence(|reader| {
+        let algorithm = reader.next().read_oid()?;
+        reader.read_optional(|reader| reader.read_null())?;
+        Ok(algorithm)
+    })
+}
+
+fn expect_oid(actual: &ObjectIdentifier, expected: &[u64]) -> ASN1Result<()> {
+    if actual.components().as_slice() == expected {
+        Ok(())
+    } else {
+        Err(ASN1Error::new(ASN1ErrorKind::Invalid))
+    }
+}
+
+fn oid(components: &[u64]) -> ObjectIdentifier {
+    ObjectIdentifier::from_slice(components)
+}
+
+/// Turns a DER `SET` into the `[0] IMPLICIT` form CMS uses for certificates
+/// and signed attributes.
+fn context_zero(mut set: Vec<u8>) -> Vec<u8> {
+    set[0] = 0xa0;
+    set
+}
+
+fn integer_bytes(value: &BigNumRef) -> Vec<u8> {
+    let bytes = value.to_vec();
+    if bytes.is_empty() { vec![0] } else { bytes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::crypto_config::Crypto,
+        services::crypto::{
+            pki_service::{issue_tsa_certificate, sign_csr},
+            test_crypto,
+        },
+    };
+    use openssl::{
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{X509NameBuilder, X509ReqBuilder},
+    };
+
+    fn test_tsa(ca: &Crypto) -> Tsa {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let certificate = issue_tsa_certificate(&private_key, ca).unwrap();
+        Tsa {
+            signer: Crypto {
+                private_key,
+                certificate,
+            },
+            policy: vec![1, 2, 3, 4, 1],
+        }
+    }
+
+    fn request(algorithm: &[u64], hashed_message: &[u8], nonce: Option<&[u8]>) -> Vec<u8> {
+        yasna::construct_der(|writer| {
+            writer.write_sequence(|writer| {
+                writer.next().write_u8(1);
+                writer.next().write_sequence(|writer| {
+                    write_algorithm(writer.next(), algorithm);
+                    writer.next().write_bytes(hashed_message);
+                });
+                if let Some(nonce) = nonce {
+                    writer.next().write_bigint_bytes(nonce, true);
+                }
+                writer.next().write_bool(true);
+            })
+        })
+    }
+
+    /// Returns the `PKIStatus`, the first set `PKIFailureInfo` bit and the token.
+    fn read_response(response: &[u8]) -> (u8, Option<usize>, Option<Vec<u8>>) {
+        yasna::parse_der(response, |reader| {
+            reader.read_sequence(|reader| {
+                let (status, failure) = reader.next().read_sequence(|reader| {
+                    let status = reader.next().read_u8()?;
+                    reader.read_optional(|reader| reader.read_der())?;
+                    let failure = reader.read_optional(|reader| reader.read_bitvec_bytes())?;
+                    Ok((status, failure))
+                })?;
+                let token = reader.read_optional(|reader| reader.read_der())?;
+                let failure = failure.and_then(|(bytes, _)| {
+                    (0..bytes.len() * 8).find(|bit| bytes[bit / 8] & (0x80 >> (bit % 8)) != 0)
+                });
+                Ok((status, failure, token))
+            })
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn granted_token_verifies_and_echoes_nonce() {
+        let ca = test_crypto("STC Test CA");
+        let tsa = test_tsa(&ca);
+        let expected = MessageImprint::of(ImprintAlgorithm::Sha256, b"signature value").unwrap();
+        let response = respond(
+            &tsa,
+            &request(OID_SHA256, &expected.hashed_message, Some(&[0x12, 0x34])),
+        );
+
+        let (status, failure, token) = read_response(&response);
+        assert_eq!((status, failure), (STATUS_GRANTED, None));
+        let info = verify_token(&token.unwrap(), &expected, &ca.certificate).unwrap();
+        assert_eq!(info.policy, tsa.policy);
+        assert_eq!(info.nonce.as_deref(), Some([0x12, 0x34].as_slice()));
+        assert!(info.gen_time <= OffsetDateTime::now_utc());
+    }
+
+    #[test]
+    fn sha512_imprint_is_accepted() {
+        let ca = test_crypto("STC Test CA");
+        let tsa = test_tsa(&ca);
+        let expected = MessageImprint::of(ImprintAlgorithm::Sha512, b"data").unwrap();
+        let (status, _, token) = read_response(&respond(
+            &tsa,
+            &request(OID_SHA512, &expected.hashed_message, None),
+        ));
+        assert_eq!(status, STATUS_GRANTED);
+        verify_token(&token.unwrap(), &expected, &ca.certificate).unwrap();
+    }
+
+    #[test]
+    fn unsupported_requests_are_rejected_with_failure_info() {
+        let ca = test_crypto("STC Test CA");
+        let tsa = test_tsa(&ca);
+        let sha1 = [1, 3, 14, 3, 2, 26];
+        let cases = [
+            (request(&sha1, &[0; 20], None), FailureInfo::BadAlg),
+            (
+                request(OID_SHA256, &[0; 20], None),
+                FailureInfo::BadDataFormat,
+            ),
+            (b"not der".to_vec(), FailureInfo::BadDataFormat),
+        ];
+        for (request, failure) in cases {
+            let (status, bit, token) = read_response(&respond(&tsa, &request));
+            assert_eq!(status, STATUS_REJECTION);
+            assert_eq!(bit, Some(failure as usize));
+            assert!(token.is_none());
+        }
+    }
+
+    #[test]
+    fn token_is_bound_to_imprint_and_ca() {
+        let ca = test_crypto("STC Test CA");
+        let tsa = test_tsa(&ca);
+        let expected = MessageImprint::of(ImprintAlgorithm::Sha256, b"signature value").unwrap();
+        let token = timestamp(&tsa, &expected, None, true).unwrap();
+
+        let other = MessageImprint::of(ImprintAlgorithm::Sha256, b"other value").unwrap();
+        let err = verify_token(&token, &other, &ca.certificate).unwrap_err();
+        assert!(err.to_string().contains("does not cover the expected data"));
+
+        let err =
+            verify_token(&token, &expected, &test_crypto("STC Test CA").certificate).unwrap_err();
+        assert!(err.to_string().contains("not issued by the STC CA"));
+    }
+
+    #[tokio::test]
+    async fn token_signed_by_device_certificate_is_rejected() {
+        let ca = test_crypto("STC Test CA");
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "Device 1").unwrap();
+        let mut req = X509ReqBuilder::new().unwrap();
+        req.set_subject_name(&name.build()).unwrap();
+        req.set_pubkey(&private_key).unwrap();
+        req.sign(&private_key, MessageDigest::sha256()).unwrap();
+        let certificate = sign_csr(&req.build(), &ca).await.unwrap();
+        let device = Tsa {
+            signer: Crypto {
+                private_key,
+                certificate,
+            },
+            policy: vec![1, 2, 3, 4, 1],
+        };
+        let expected = MessageImprint::of(ImprintAlgorithm::Sha256, b"signature value").unwrap();
+        let token = timestamp(&device, &expected, None, true).unwrap();
+
+        let err = verify_token(&token, &expected, &ca.certificate).unwrap_err();
+        assert!(err.to_string().contains("critical timeStamping"));
+    }
+}
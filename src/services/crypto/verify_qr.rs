@@ -1,37 +1,221 @@
 use anyhow::{Context, bail};
 use base64::{Engine, engine::general_purpose};
-use openssl::memcmp;
+use openssl::{
+    memcmp,
+    nid::Nid,
+    x509::{X509, X509NameRef},
+};
 use tracing::instrument;
 
 use crate::{
     config::crypto_config::Crypto,
-    services::{crypto::pki_service::verify_signature_with_cert, xml::edit_tlv::extract_records},
+    models::qr_verification::{QrFactsDto, QrSignerDto, QrSignerKind},
+    services::{
+        crypto::pki_service::{
+            verfiy_supplier_tin_with_ca, verify_cert_with_ca, verify_signature_with_cert,
+        },
+        xml::edit_tlv::{
+            TAG_CERTIFICATE, TAG_INVOICE_HASH, TAG_INVOICE_TOTAL, TAG_SELLER_NAME, TAG_SELLER_TIN,
+            TAG_SIGNATURE, TAG_TIMESTAMP, TAG_VAT_TOTAL, extract_records,
+        },
+    },
 };
 
+/// A QR whose signature checked out, with what it says about the invoice.
+#[derive(Debug)]
+pub struct VerifiedQr {
+    pub invoice_hash: Vec<u8>,
+    pub facts: QrFactsDto,
+    pub signer: QrSignerDto,
+}
+
+/// Verifies the signature in QR tag 7 over the invoice hash in tag 6 with the
+/// certificate in tag 8. That certificate is either the STC certificate
+/// (cleared invoices) or a device certificate issued by the STC CA whose
+/// `organizationName` is the seller TIN in tag 2 (reported invoices).
 #[instrument(skip(qr_b64, crypto), fields(qr_length = qr_b64.len()))]
-pub fn verify_qr_signature(qr_b64: &str, crypto: &Crypto) -> anyhow::Result<()> {
+pub fn verify_qr_signature(qr_b64: &str, crypto: &Crypto) -> anyhow::Result<VerifiedQr> {
     let tlv_bytes = general_purpose::STANDARD.decode(qr_b64)?;
     let records = extract_records(&tlv_bytes)?;
+    let mut facts = QrFactsDto::default();
     let mut signature: Option<Vec<u8>> = None;
     let mut hash: Option<Vec<u8>> = None;
     let mut certificate: Option<Vec<u8>> = None;
     for (tag, value) in records {
         match tag {
-            6 => hash = Some(value),
-            7 => signature = Some(value),
-            8 => certificate = Some(value),
+            TAG_SELLER_NAME => facts.seller_name = Some(tag_text(tag, value)?),
+            TAG_SELLER_TIN => facts.seller_tin = Some(tag_text(tag, value)?),
+            TAG_TIMESTAMP => facts.timestamp = Some(tag_text(tag, value)?),
+            TAG_INVOICE_TOTAL => facts.invoice_total = Some(tag_text(tag, value)?),
+            TAG_VAT_TOTAL => facts.vat_total = Some(tag_text(tag, value)?),
+            TAG_INVOICE_HASH => hash = Some(value),
+            TAG_SIGNATURE => signature = Some(value),
+            TAG_CERTIFICATE => certificate = Some(value),
             _ => {}
         }
     }
     let hash = hash.context("QR is missing invoice hash tag")?;
     let signature = signature.context("QR is missing signature tag")?;
     let certificate = certificate.context("QR is missing certificate tag")?;
+    let certificate = X509::from_der(&certificate).context("failed to parse the QR certificate")?;
+
     let server_certificate = crypto.certificate.to_der()?;
-    if !memcmp::eq(&certificate, &server_certificate) {
-        bail!("QR certificate does not match server certificate");
-    }
-    if !verify_signature_with_cert(&hash, &signature, &crypto.certificate)? {
+    let qr_certificate = certificate.to_der()?;
+    let kind = if qr_certificate.len() == server_certificate.len()
+        && memcmp::eq(&qr_certificate, &server_certificate)
+    {
+        QrSignerKind::Stc
+    } else {
+        if !verify_cert_with_ca(&crypto.certificate, &certificate)? {
+            bail!("QR certificate is not issued by the STC CA");
+        }
+        let seller_tin = facts
+            .seller_tin
+            .as_ref()
+            .context("QR is missing seller TIN tag")?;
+        verfiy_supplier_tin_with_ca(seller_tin, &certificate)
+            .context("QR seller TIN does not match the device certificate")?;
+        QrSignerKind::Device
+    };
+
+    if !verify_signature_with_cert(&hash, &signature, &certificate)? {
         bail!("invalid QR signature");
     }
-    Ok(())
+
+    facts.invoice_hash = general_purpose::STANDARD.encode(&hash);
+    Ok(VerifiedQr {
+        invoice_hash: hash,
+        facts,
+        signer: signer_identity(&certificate, kind)?,
+    })
+}
+
+fn tag_text(tag: u8, value: Vec<u8>) -> anyhow::Result<String> {
+    String::from_utf8(value).with_context(|| format!("QR tag {tag} is not valid UTF-8"))
+}
+
+fn signer_identity(certificate: &X509, kind: QrSignerKind) -> anyhow::Result<QrSignerDto> {
+    let subject = certificate.subject_name();
+    Ok(QrSignerDto {
+        kind,
+        common_name: name_entry(subject, Nid::COMMONNAME),
+        organization: name_entry(subject, Nid::ORGANIZATIONNAME),
+        device_uuid: name_entry(subject, Nid::SERIALNUMBER),
+        issuer: name_entry(certificate.issuer_name(), Nid::COMMONNAME),
+        serial_number: certificate
+            .serial_number()
+            .to_bn()?
+            .to_hex_str()?
+            .to_string(),
+        not_after: certificate.not_after().to_string(),
+    })
+}
+
+fn name_entry(name: &X509NameRef, nid: Nid) -> Option<String> {
+    name.entries_by_nid(nid)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::crypto::pki_service::sign;
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{X509Builder, X509NameBuilder},
+    };
+
+    const HASH: [u8; 32] = [7; 32];
+
+    fn certificate(entries: &[(&str, &str)], issuer: Option<&Crypto>) -> Crypto {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        for (field, value) in entries {
+            name.append_entry_by_text(field, value).unwrap();
+        }
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(42).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder
+            .set_issuer_name(issuer.map_or(&name, |ca| ca.certificate.subject_name()))
+            .unwrap();
+        builder.set_pubkey(&private_key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let signing_key = issuer.map_or(&private_key, |ca| &ca.private_key);
+        builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+        Crypto {
+            private_key,
+            certificate: builder.build(),
+        }
+    }
+
+    fn qr(seller_tin: &str, signer: &Crypto) -> String {
+        let signature = sign(&HASH, signer).unwrap();
+        let certificate = signer.certificate.to_der().unwrap();
+        let mut bytes = Vec::new();
+        for (tag, value) in [
+            (TAG_SELLER_NAME, b"Smith & Sons".as_slice()),
+            (TAG_SELLER_TIN, seller_tin.as_bytes()),
+            (TAG_INVOICE_TOTAL, b"1150.00"),
+            (TAG_INVOICE_HASH, &HASH),
+            (TAG_SIGNATURE, &signature),
+            (TAG_CERTIFICATE, &certificate),
+        ] {
+            bytes.push(tag);
+            bytes.extend_from_slice(&[0x82, (value.len() >> 8) as u8, value.len() as u8]);
+            bytes.extend_from_slice(value);
+        }
+        general_purpose::STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn decodes_stc_and_device_signed_qrs() {
+        let stc = certificate(&[("CN", "STC")], None);
+        let verified = verify_qr_signature(&qr("123456789", &stc), &stc).unwrap();
+        assert_eq!(verified.signer.kind, QrSignerKind::Stc);
+        assert_eq!(verified.facts.seller_name.as_deref(), Some("Smith & Sons"));
+        assert_eq!(verified.facts.invoice_total.as_deref(), Some("1150.00"));
+        assert_eq!(verified.invoice_hash, HASH);
+
+        let device_uuid = "550e8400-e29b-41d4-a716-446655440000";
+        let device = certificate(
+            &[
+                ("CN", "POS-1"),
+                ("O", "123456789"),
+                ("serialNumber", device_uuid),
+            ],
+            Some(&stc),
+        );
+        let verified = verify_qr_signature(&qr("123456789", &device), &stc).unwrap();
+        assert_eq!(verified.signer.kind, QrSignerKind::Device);
+        assert_eq!(verified.signer.organization.as_deref(), Some("123456789"));
+        assert_eq!(verified.signer.device_uuid.as_deref(), Some(device_uuid));
+        assert_eq!(verified.signer.issuer.as_deref(), Some("STC"));
+    }
+
+    #[test]
+    fn rejects_foreign_certificates_and_other_sellers() {
+        let stc = certificate(&[("CN", "STC")], None);
+        let device = certificate(&[("CN", "POS-1"), ("O", "123456789")], Some(&stc));
+        let err = verify_qr_signature(&qr("987654321", &device), &stc).unwrap_err();
+        assert!(format!("{err:#}").contains("seller TIN does not match"));
+
+        let other_ca = certificate(&[("CN", "Other CA")], None);
+        let foreign = certificate(&[("CN", "POS-2"), ("O", "123456789")], Some(&other_ca));
+        assert!(verify_qr_signature(&qr("123456789", &foreign), &stc).is_err());
+    }
 }
@@ -1,6 +1,6 @@
 use std::io::Cursor;
 
-use anyhow::{Context, anyhow, bail};
+use anyhow::{Context, bail};
 use base64::{Engine, engine::general_purpose};
 use openssl::{
     bn::BigNum,
@@ -8,32 +8,46 @@ use openssl::{
     x509::{X509, X509NameRef},
 };
 use quick_xml::{
-    Reader, Writer,
+    NsReader, Writer,
     events::{BytesStart, Event},
+    name::{NamespaceResolver, QName},
 };
 use time::OffsetDateTime;
 use time::format_description::well_known::Rfc3339;
 
-use crate::services::{
-    crypto::pki_service::{compute_hash, verify_signature_with_cert},
-    xml::{
-        c14n11::canonicalize_c14n11,
-        extractors::{extract_signed_info, extract_signed_properties},
+use crate::{
+    config::signature_config::SignatureAlgorithms,
+    services::{
+        crypto::{
+            pki_service::{compute_hash, verify_signature_with_cert},
+            tsa_service::{MessageImprint, verify_token},
+            xmldsig_algorithms::{Canonicalization, DigestMethod, EXC_C14N},
+        },
+        xml::{
+            c14n11::canonicalize_c14n11,
+            extractors::{extract_signed_info, extract_signed_properties},
+            invoice_document::{InvoiceDocument, SignatureBlock, push_ref},
+            namespaces::{
+                element_namespace, fragment_declarations, in_scope_declarations,
+                prefix_declarations,
+            },
+            xpath_filter::{XPathFilter, apply_filters},
+        },
     },
 };
 
-const C14N_11: &str = "http://www.w3.org/2006/12/xml-c14n11#";
-const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
-const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
-const XADES_SIGNED_PROPERTIES: &str = "http://uri.etsi.org/01903#SignedProperties";
-const XPATH_TRANSFORM: &str = "http://www.w3.org/TR/1999/REC-xpath-19991116";
-const DS_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
-const XADES_NS: &str = "http://uri.etsi.org/01903/v1.3.2#";
+pub(crate) use crate::services::crypto::xmldsig_algorithms::{C14N_11, SHA256};
+pub(crate) const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+pub(crate) const XADES_SIGNED_PROPERTIES: &str = "http://uri.etsi.org/01903#SignedProperties";
+pub(crate) const COUNTERSIGNED_SIGNATURE: &str = "http://uri.etsi.org/01903#CountersignedSignature";
+pub(crate) const XPATH_TRANSFORM: &str = "http://www.w3.org/TR/1999/REC-xpath-19991116";
+pub(crate) use crate::services::xml::namespaces::{DS_NS, XADES_NS};
 
 #[derive(Debug, Default)]
 struct SignatureProfile {
     signature_id: Option<String>,
     canonicalization_method: Option<String>,
+    canonicalization_prefixes: Vec<String>,
     signature_method: Option<String>,
     signature_value: Option<Vec<u8>>,
     qualifying_target: Option<String>,
@@ -45,6 +59,7 @@ struct SignatureProfile {
     issuer_name: Option<String>,
     serial_number: Option<BigNum>,
     references: Vec<SignedReference>,
+    signature_timestamps: Vec<Vec<u8>>,
 }
 
 #[derive(Debug)]
@@ -53,7 +68,25 @@ struct SignedReference {
     reference_type: Option<String>,
     digest_method: Option<String>,
     digest_value: Option<Vec<u8>>,
-    transforms: Vec<String>,
+    transforms: Vec<ReferenceTransform>,
+}
+
+#[derive(Debug)]
+struct ReferenceTransform {
+    algorithm: String,
+    inclusive_prefixes: Vec<String>,
+    /// Expression of an XPath filter transform.
+    xpath: Option<XPathFilter>,
+}
+
+impl ReferenceTransform {
+    fn new(e: &BytesStart<'_>) -> anyhow::Result<Self> {
+        Ok(Self {
+            algorithm: required_attr(e, b"Algorithm", "Transform Algorithm")?,
+            inclusive_prefixes: Vec::new(),
+            xpath: None,
+        })
+    }
 }
 
 impl SignedReference {
@@ -66,6 +99,13 @@ impl SignedReference {
             transforms: Vec::new(),
         })
     }
+
+    /// `InclusiveNamespaces PrefixList` of the final transform.
+    fn inclusive_prefixes(&self) -> &[String] {
+        self.transforms
+            .last()
+            .map_or(&[], |transform| &transform.inclusive_prefixes)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -76,112 +116,377 @@ enum TextField {
     IssuerName,
     SerialNumber,
     SigningTime,
+    EncapsulatedTimeStamp,
 }
 
 /// Validates the XMLDSig/XAdES-BES subset used by this service.
 ///
-/// Supported profile is intentionally narrow: RSA-SHA256, SHA-256 digests,
-/// and C14N 1.1. Unknown algorithms fail closed.
+/// The signature method is RSA-SHA256. Canonicalization and digest methods
+/// are taken from the signature and must be in `algorithms`; anything else
+/// fails closed. `document` is the parsed form of `invoice_xml`.
 pub fn validate_xades_bes_signature(
+    document: &InvoiceDocument,
     invoice_xml: &[u8],
-    received_invoice_hash: &[u8],
     certificate: &X509,
+    algorithms: &SignatureAlgorithms,
 ) -> anyhow::Result<()> {
-    // print!("{:?}", String::from_utf8_lossy(invoice_xml));
-    let invoice_xml = canonicalize_c14n11(invoice_xml.to_vec())?;
-    // println!(
-    //     "after canonicalization: {:?}",
-    //     String::from_utf8_lossy(&invoice_xml)
-    // );
-    let signature_xml = extract_single_signature(&invoice_xml)?;
-    let signed_info = extract_signed_info(&signature_xml, Some(DS_NS.as_bytes()))
-        .context("failed to extract SignedInfo from signature")?;
-    let signed_properties = extract_signed_properties(&signature_xml, Some(XADES_NS.as_bytes()))
-        .context("failed to extract SignedProperties from signature")?;
-    let profile = parse_signature_profile(&signature_xml)?;
+    let parts = read_signature(document.signature()?)?;
+
+    enforce_profile_structure(&parts.profile)?;
+    verify_invoice_reference(&parts.profile, document, invoice_xml, algorithms)?;
+    verify_signed_layer(&parts, certificate, algorithms, "Invalid invoice signature")
+}
 
-    enforce_profile_structure(&profile)?;
+/// Validates the STC clearance stamp: a signature by `stc_certificate` over
+/// the invoice content and, through a `CountersignedSignature` reference, the
+/// supplier's untouched `ds:Signature`. The stamp must carry one
+/// `SignatureTimeStamp` from a TSA certified by `stc_certificate`.
+pub fn validate_stc_stamp(
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+    stc_certificate: &X509,
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<()> {
+    let stamp = document.stamp()?.context("STC stamp not found")?;
+    let supplier = document.signature()?;
+    let parts = read_signature(stamp)?;
+
+    enforce_stamp_structure(&parts.profile)?;
+    verify_invoice_reference(&parts.profile, document, invoice_xml, algorithms)?;
+
+    let supplier_id = supplier
+        .id
+        .as_deref()
+        .context("supplier ds:Signature is missing Id")?;
+    let countersigned_ref = unique_reference(&parts.profile, |r| {
+        r.reference_type.as_deref() == Some(COUNTERSIGNED_SIGNATURE)
+    })
+    .context("missing CountersignedSignature reference")?;
+    let digest_method = validate_reference_algorithms(countersigned_ref, algorithms)?;
+    let canonicalization = reference_canonicalization(countersigned_ref, algorithms)?;
+    if countersigned_ref.uri.as_deref() != Some(format!("#{supplier_id}").as_str()) {
+        bail!("CountersignedSignature reference does not point at the supplier signature");
+    }
+    let countersigned_ref_digest = countersigned_ref
+        .digest_value
+        .as_ref()
+        .context("CountersignedSignature reference is missing DigestValue")?;
+    if !digest_eq(
+        countersigned_ref_digest,
+        &countersigned_digest(supplier, &canonicalization, digest_method)?,
+    ) {
+        bail!("Countersigned supplier signature digest mismatch");
+    }
+
+    let stamp_certificate = general_purpose::STANDARD
+        .decode(stamp.certificate()?)
+        .context("invalid STC stamp certificate base64")?;
+    if stamp_certificate != stc_certificate.to_der()? {
+        bail!("STC stamp certificate does not match server certificate");
+    }
+
+    verify_signed_layer(
+        &parts,
+        stc_certificate,
+        algorithms,
+        "Invalid STC stamp signature",
+    )?;
+    verify_signature_timestamp(&parts.profile, stc_certificate)
+}
 
-    // let invoice_hash = compute_hash(canonicalized_invoice)?;
-    // if !memcmp::eq(received_invoice_hash, &invoice_hash) {
-    //     bail!("Invoice hash mismatch");
-    // }
+/// Validates both layers of a cleared invoice: the supplier's XAdES-BES
+/// signature and the STC stamp over it.
+pub fn validate_cleared_invoice(
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+    supplier_certificate: &X509,
+    stc_certificate: &X509,
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<()> {
+    validate_xades_bes_signature(document, invoice_xml, supplier_certificate, algorithms)?;
+    validate_stc_stamp(document, invoice_xml, stc_certificate, algorithms)
+}
+
+/// Digest covered by a `CountersignedSignature` reference: the supplier's
+/// `ds:Signature` canonicalized and digested with the reference's algorithms,
+/// with the XMLDSig and XAdES namespaces, and any inclusive prefixes, declared
+/// on it under the prefixes in scope.
+pub fn countersigned_digest(
+    supplier: &SignatureBlock,
+    canonicalization: &Canonicalization,
+    digest_method: DigestMethod,
+) -> anyhow::Result<Vec<u8>> {
+    let mut reader = NsReader::from_reader(supplier.xml.as_slice());
+    let mut writer = Writer::new(Vec::new());
+    let mut buf = Vec::new();
+    let mut root = true;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) if root => {
+                root = false;
+                let mut in_scope = supplier.namespaces.clone();
+                in_scope.extend(in_scope_declarations(reader.resolver()));
+                let mut declarations = fragment_declarations(&in_scope, &e, &[DS_NS, XADES_NS]);
+                for declaration in
+                    prefix_declarations(&in_scope, &e, canonicalization.inclusive_prefixes())
+                {
+                    if !declarations.contains(&declaration) {
+                        declarations.push(declaration);
+                    }
+                }
+                let mut element = e.to_owned();
+                for (attribute, namespace) in declarations {
+                    element.push_attribute((attribute.as_str(), namespace.as_str()));
+                }
+                writer.write_event(Event::Start(element))?;
+            }
+            Ok(event) => writer.write_event(event)?,
+            Err(e) => bail!("signature XML error: {e}"),
+        }
+        buf.clear();
+    }
+    digest_method.digest(&canonicalization.canonicalize(writer.into_inner())?)
+}
+
+/// Message imprint of a `SignatureTimeStamp`: SHA-256 over the C14N 1.1 form
+/// of `ds:SignatureValue`, with the `ds` and `xades` namespaces declared on it.
+pub fn signature_timestamp_digest(signature_value: &str) -> anyhow::Result<Vec<u8>> {
+    let element = format!(
+        r#"<ds:SignatureValue xmlns:ds="{DS_NS}" xmlns:xades="{XADES_NS}">{}</ds:SignatureValue>"#,
+        quick_xml::escape::escape(signature_value)
+    );
+    compute_hash(&canonicalize_c14n11(element.into_bytes())?)
+}
+
+fn verify_signature_timestamp(
+    profile: &SignatureProfile,
+    tsa_ca_certificate: &X509,
+) -> anyhow::Result<()> {
+    let [token] = profile.signature_timestamps.as_slice() else {
+        bail!("STC stamp must contain exactly one SignatureTimeStamp");
+    };
+    let signature_value = profile
+        .signature_value
+        .as_ref()
+        .context("signature is missing SignatureValue")?;
+    let imprint = MessageImprint::sha256(signature_timestamp_digest(
+        &general_purpose::STANDARD.encode(signature_value),
+    )?);
+    verify_token(token, &imprint, tsa_ca_certificate)
+        .context("invalid STC stamp SignatureTimeStamp")?;
+    Ok(())
+}
 
+struct SignatureParts {
+    signed_info: Vec<u8>,
+    signed_properties: Vec<u8>,
+    profile: SignatureProfile,
+}
+
+fn read_signature(signature: &SignatureBlock) -> anyhow::Result<SignatureParts> {
+    let signature_xml = signature.scoped_xml()?;
+    let profile = parse_signature_profile(&signature_xml)?;
+    let signed_info = extract_signed_info(
+        &signature_xml,
+        Some(DS_NS.as_bytes()),
+        &profile.canonicalization_prefixes,
+    )
+    .context("failed to extract SignedInfo from signature")?;
+    let signed_properties_prefixes = profile
+        .references
+        .iter()
+        .find(|r| r.reference_type.as_deref() == Some(XADES_SIGNED_PROPERTIES))
+        .map_or(&[][..], SignedReference::inclusive_prefixes);
+    let signed_properties = extract_signed_properties(
+        &signature_xml,
+        Some(XADES_NS.as_bytes()),
+        signed_properties_prefixes,
+    )
+    .context("failed to extract SignedProperties from signature")?;
+    Ok(SignatureParts {
+        signed_info,
+        signed_properties,
+        profile,
+    })
+}
+
+/// The invoice content the supplier signature covers: the octets its
+/// `URI=""` reference selects from `invoice_xml`. The invoice hash is the
+/// SHA-256 of these octets, so the reference's algorithms are checked against
+/// `algorithms` before anything is canonicalized.
+pub fn signed_invoice_content(
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<Vec<u8>> {
+    let profile = parse_signature_profile(&document.signature()?.scoped_xml()?)?;
     let invoice_ref = unique_reference(&profile, |r| r.uri.as_deref() == Some(""))
         .context("missing invoice reference")?;
-    validate_reference_algorithms(invoice_ref)?;
+    validate_reference_algorithms(invoice_ref, algorithms)?;
+    invoice_reference_content(invoice_ref, document, invoice_xml)
+}
+
+/// Checks the `URI=""` reference: its digest must match the digest of the
+/// content its own transforms select from `invoice_xml`.
+fn verify_invoice_reference(
+    profile: &SignatureProfile,
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<()> {
+    let invoice_ref = unique_reference(profile, |r| r.uri.as_deref() == Some(""))
+        .context("missing invoice reference")?;
+    let digest_method = validate_reference_algorithms(invoice_ref, algorithms)?;
     let invoice_ref_digest = invoice_ref
         .digest_value
         .as_ref()
         .context("invoice reference is missing DigestValue")?;
-    if !memcmp::eq(invoice_ref_digest, received_invoice_hash) {
+    let selected = invoice_reference_content(invoice_ref, document, invoice_xml)?;
+    if !digest_eq(invoice_ref_digest, &digest_method.digest(&selected)?) {
         bail!("Signed invoice digest mismatch");
     }
+    Ok(())
+}
+
+/// XPath filter transforms followed by the canonicalization transform a
+/// `URI=""` reference must end with.
+#[derive(Debug, Clone)]
+pub struct InvoiceTransforms {
+    pub filters: Vec<XPathFilter>,
+    pub canonicalization: String,
+    pub inclusive_prefixes: Vec<String>,
+}
+
+/// The transforms of the supplier's `URI=""` reference, which the STC stamp
+/// repeats so that its invoice digest covers the same content.
+pub fn invoice_reference_transforms(
+    signature: &SignatureBlock,
+) -> anyhow::Result<InvoiceTransforms> {
+    let profile = parse_signature_profile(&signature.scoped_xml()?)?;
+    let invoice_ref = unique_reference(&profile, |r| r.uri.as_deref() == Some(""))
+        .context("missing invoice reference")?;
+    invoice_transforms(invoice_ref)
+}
+
+fn invoice_transforms(reference: &SignedReference) -> anyhow::Result<InvoiceTransforms> {
+    let Some((last, filters)) = reference.transforms.split_last() else {
+        bail!("invoice reference must end with a canonicalization transform");
+    };
+    if last.algorithm == XPATH_TRANSFORM {
+        bail!("invoice reference must end with a canonicalization transform");
+    }
+    let filters = filters
+        .iter()
+        .map(|transform| {
+            if transform.algorithm != XPATH_TRANSFORM {
+                bail!("unsupported Reference Transform");
+            }
+            transform
+                .xpath
+                .clone()
+                .context("XPath transform is missing ds:XPath")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(InvoiceTransforms {
+        filters,
+        canonicalization: last.algorithm.clone(),
+        inclusive_prefixes: last.inclusive_prefixes.clone(),
+    })
+}
+
+/// The octets a `URI=""` reference selects: its XPath filter transforms
+/// evaluated over `invoice_xml`, then the canonicalization it must end with.
+/// The filters may only leave out what the stripped invoice leaves out: the
+/// UBL extensions, the signature and the QR reference.
+fn invoice_reference_content(
+    reference: &SignedReference,
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let transforms = invoice_transforms(reference)?;
+    let canonicalization =
+        Canonicalization::from_uri(&transforms.canonicalization, &transforms.inclusive_prefixes)
+            .context("unsupported Reference Transform")?
+            .without_comments();
+    let selected =
+        canonicalization.canonicalize(apply_filters(invoice_xml, &transforms.filters)?)?;
+    if selected != canonicalization.canonicalize(document.stripped_invoice.clone())? {
+        bail!(
+            "unsupported XPath filter: the invoice reference must select the invoice without its UBL extensions, signature and QR reference"
+        );
+    }
+    Ok(selected)
+}
+
+fn prefix_list(list: &str) -> Vec<String> {
+    list.split_whitespace().map(str::to_owned).collect()
+}
 
+/// Constant-time digest comparison that tolerates digests of other lengths.
+fn digest_eq(expected: &[u8], actual: &[u8]) -> bool {
+    expected.len() == actual.len() && memcmp::eq(expected, actual)
+}
+
+/// Checks the SignedProperties reference, the certificate binding and the
+/// SignatureValue of one signature layer.
+fn verify_signed_layer(
+    parts: &SignatureParts,
+    certificate: &X509,
+    algorithms: &SignatureAlgorithms,
+    invalid_signature: &str,
+) -> anyhow::Result<()> {
+    let profile = &parts.profile;
     let signed_properties_id = profile
         .signed_properties_id
         .as_ref()
         .context("SignedProperties is missing Id")?;
     let signed_properties_uri = format!("#{signed_properties_id}");
-    let signed_properties_ref = unique_reference(&profile, |r| {
+    let signed_properties_ref = unique_reference(profile, |r| {
         r.uri.as_deref() == Some(signed_properties_uri.as_str())
     })
     .context("missing SignedProperties reference")?;
-    validate_reference_algorithms(signed_properties_ref)?;
+    let digest_method = validate_reference_algorithms(signed_properties_ref, algorithms)?;
     if signed_properties_ref.reference_type.as_deref() != Some(XADES_SIGNED_PROPERTIES) {
         bail!("SignedProperties reference has invalid Type");
     }
 
-    let signed_properties_hash = compute_hash(&canonicalize_c14n11(signed_properties)?)?;
+    let canonicalization = reference_canonicalization(signed_properties_ref, algorithms)?;
+    let signed_properties_hash =
+        digest_method.digest(&canonicalization.canonicalize(parts.signed_properties.clone())?)?;
     let signed_properties_ref_digest = signed_properties_ref
         .digest_value
         .as_ref()
         .context("SignedProperties reference is missing DigestValue")?;
-    if !memcmp::eq(signed_properties_ref_digest, &signed_properties_hash) {
+    if !digest_eq(signed_properties_ref_digest, &signed_properties_hash) {
         bail!("SignedProperties digest mismatch");
     }
 
-    validate_certificate_binding(&profile, certificate)?;
-
-    let signed_info_canonical = canonicalize_c14n11(signed_info)?;
+    validate_certificate_binding(profile, certificate, algorithms)?;
+
+    let signed_info_canonical = algorithms
+        .canonicalization(
+            profile
+                .canonicalization_method
+                .as_deref()
+                .unwrap_or_default(),
+            &profile.canonicalization_prefixes,
+        )
+        .context("unsupported CanonicalizationMethod")?
+        .canonicalize(parts.signed_info.clone())?;
     let signature_value = profile
         .signature_value
         .as_ref()
         .context("signature is missing SignatureValue")?;
     if !verify_signature_with_cert(&signed_info_canonical, signature_value, certificate)? {
-        bail!("Invalid invoice signature");
+        bail!("{invalid_signature}");
     }
 
     Ok(())
 }
 
 fn enforce_profile_structure(profile: &SignatureProfile) -> anyhow::Result<()> {
-    let signature_id = profile
-        .signature_id
-        .as_ref()
-        .context("ds:Signature is missing Id")?;
-    if profile.canonicalization_method.as_deref() != Some(C14N_11) {
-        bail!("unsupported CanonicalizationMethod");
-    }
-    if profile.signature_method.as_deref() != Some(RSA_SHA256) {
-        bail!("unsupported SignatureMethod");
-    }
-    let expected_target = format!("#{signature_id}");
-    if profile.qualifying_target.as_deref() != Some(expected_target.as_str()) {
-        bail!("QualifyingProperties Target does not match Signature Id");
-    }
-    if profile.signed_properties_id.is_none() {
-        bail!("SignedProperties is missing Id");
-    }
-    let signing_time = profile.signing_time.as_deref().unwrap_or_default().trim();
-    if signing_time.is_empty() {
-        bail!("SignedSignatureProperties is missing SigningTime");
-    }
-    OffsetDateTime::parse(signing_time, &Rfc3339)
-        .context("invalid SigningTime format, expected RFC 3339 dateTime")?;
-    if !profile.signing_certificate_seen {
-        bail!("SignedSignatureProperties is missing SigningCertificate");
-    }
+    enforce_common_structure(profile)?;
     if profile.references.len() != 2 {
         bail!(
             "SignedInfo must contain exactly 2 references, found {}",
@@ -210,32 +515,113 @@ fn enforce_profile_structure(profile: &SignatureProfile) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn validate_reference_algorithms(reference: &SignedReference) -> anyhow::Result<()> {
-    if reference.digest_method.as_deref() != Some(SHA256) {
-        bail!("unsupported Reference DigestMethod");
+fn enforce_stamp_structure(profile: &SignatureProfile) -> anyhow::Result<()> {
+    enforce_common_structure(profile)?;
+    if profile.references.len() != 3 {
+        bail!(
+            "STC stamp SignedInfo must contain exactly 3 references, found {}",
+            profile.references.len()
+        );
+    }
+    let count = |predicate: &dyn Fn(&SignedReference) -> bool| {
+        profile.references.iter().filter(|r| predicate(r)).count()
+    };
+    if count(&|r| r.uri.as_deref() == Some("") && r.reference_type.is_none()) != 1 {
+        bail!("STC stamp must contain exactly one invoice reference (URI='')");
     }
+    if count(&|r| r.reference_type.as_deref() == Some(COUNTERSIGNED_SIGNATURE)) != 1 {
+        bail!("STC stamp must contain exactly one CountersignedSignature reference");
+    }
+    if count(&|r| r.reference_type.as_deref() == Some(XADES_SIGNED_PROPERTIES)) != 1 {
+        bail!("STC stamp must contain exactly one SignedProperties reference");
+    }
+    Ok(())
+}
+
+/// Checks shared by every signature layer: Id, algorithms, qualifying
+/// properties target and signed signature properties.
+fn enforce_common_structure(profile: &SignatureProfile) -> anyhow::Result<()> {
+    let signature_id = profile
+        .signature_id
+        .as_ref()
+        .context("ds:Signature is missing Id")?;
+    if profile.canonicalization_method.is_none() {
+        bail!("missing CanonicalizationMethod");
+    }
+    if profile.signature_method.as_deref() != Some(RSA_SHA256) {
+        bail!("unsupported SignatureMethod");
+    }
+    let expected_target = format!("#{signature_id}");
+    if profile.qualifying_target.as_deref() != Some(expected_target.as_str()) {
+        bail!("QualifyingProperties Target does not match Signature Id");
+    }
+    if profile.signed_properties_id.is_none() {
+        bail!("SignedProperties is missing Id");
+    }
+    let signing_time = profile.signing_time.as_deref().unwrap_or_default().trim();
+    if signing_time.is_empty() {
+        bail!("SignedSignatureProperties is missing SigningTime");
+    }
+    OffsetDateTime::parse(signing_time, &Rfc3339)
+        .context("invalid SigningTime format, expected RFC 3339 dateTime")?;
+    if !profile.signing_certificate_seen {
+        bail!("SignedSignatureProperties is missing SigningCertificate");
+    }
+    Ok(())
+}
+
+/// Checks that the reference's digest method and transforms are allowed and
+/// returns its digest method.
+fn validate_reference_algorithms(
+    reference: &SignedReference,
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<DigestMethod> {
+    let digest_method = algorithms
+        .digest(reference.digest_method.as_deref().unwrap_or_default())
+        .context("unsupported Reference DigestMethod")?;
     for transform in &reference.transforms {
-        if transform != XPATH_TRANSFORM && transform != C14N_11 {
-            bail!("unsupported Reference Transform");
+        if transform.algorithm != XPATH_TRANSFORM {
+            algorithms
+                .canonicalization(&transform.algorithm, &transform.inclusive_prefixes)
+                .context("unsupported Reference Transform")?;
         }
     }
-    Ok(())
+    Ok(digest_method)
+}
+
+/// Canonicalization of a same-document `#id` reference: its only transform,
+/// or C14N 1.1 when it lists none.
+fn reference_canonicalization(
+    reference: &SignedReference,
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<Canonicalization> {
+    let canonicalization = match reference.transforms.as_slice() {
+        [] => algorithms.canonicalization(C14N_11, &[]),
+        [transform] if transform.algorithm != XPATH_TRANSFORM => {
+            algorithms.canonicalization(&transform.algorithm, &transform.inclusive_prefixes)
+        }
+        _ => bail!("unsupported Reference Transform"),
+    };
+    Ok(canonicalization
+        .context("unsupported Reference Transform")?
+        .without_comments())
 }
 
 fn validate_certificate_binding(
     profile: &SignatureProfile,
     certificate: &X509,
+    algorithms: &SignatureAlgorithms,
 ) -> anyhow::Result<()> {
-    if profile.cert_digest_method.as_deref() != Some(SHA256) {
-        bail!("unsupported certificate DigestMethod");
-    }
+    let digest_method = algorithms
+        .digest(profile.cert_digest_method.as_deref().unwrap_or_default())
+        .context("unsupported certificate DigestMethod")?;
     let cert_der = certificate.to_der()?;
-    let cert_hash = compute_hash(&cert_der)?;
+    let cert_hash = digest_method.digest(&cert_der)?;
     let cert_digest = profile
         .cert_digest_value
         .as_ref()
         .context("SigningCertificate is missing CertDigest DigestValue")?;
-    if !memcmp::eq(cert_digest, &cert_hash) {
+    if !digest_eq(cert_digest, &cert_hash) {
         bail!("SigningCertificate CertDigest mismatch");
     }
 
@@ -307,22 +693,27 @@ where
     Ok(reference)
 }
 
+/// Reads the signature profile. Elements are matched by namespace URI and
+/// local name, whatever prefix the signer chose.
 fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProfile> {
-    let mut reader = Reader::from_reader(Cursor::new(signature_xml));
+    let mut reader = NsReader::from_reader(Cursor::new(signature_xml));
     reader.config_mut().trim_text(true);
 
     let mut buf = Vec::new();
     let mut profile = SignatureProfile::default();
     let mut in_signed_info = false;
+    let mut in_canonicalization_method = false;
+    let mut in_transform = false;
     let mut in_cert_digest = false;
     let mut current_reference: Option<SignedReference> = None;
     let mut text_field: Option<TextField> = None;
+    let mut xpath: Option<(String, Vec<(String, String)>)> = None;
 
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                let name = e.name();
-                match name.as_ref() {
+                let name = profile_name(reader.resolver(), e.name());
+                match name.as_slice() {
                     b"ds:Signature" => set_once(
                         &mut profile.signature_id,
                         attr_value(&e, b"Id")?,
@@ -335,6 +726,7 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                             attr_value(&e, b"Algorithm")?,
                             "CanonicalizationMethod",
                         )?;
+                        in_canonicalization_method = true;
                     }
                     b"ds:SignatureMethod" if in_signed_info => {
                         set_once(
@@ -351,8 +743,24 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                     }
                     b"ds:Transform" if in_signed_info => {
                         if let Some(reference) = current_reference.as_mut() {
-                            let algorithm = required_attr(&e, b"Algorithm", "Transform Algorithm")?;
-                            reference.transforms.push(algorithm);
+                            reference.transforms.push(ReferenceTransform::new(&e)?);
+                        }
+                        in_transform = true;
+                    }
+                    b"ds:XPath" if in_transform => {
+                        xpath = Some((String::new(), in_scope_declarations(reader.resolver())));
+                    }
+                    b"ec:InclusiveNamespaces" => {
+                        let prefixes =
+                            prefix_list(&required_attr(&e, b"PrefixList", "PrefixList")?);
+                        if in_canonicalization_method {
+                            profile.canonicalization_prefixes = prefixes;
+                        } else if let Some(transform) = current_reference
+                            .as_mut()
+                            .filter(|_| in_transform)
+                            .and_then(|reference| reference.transforms.last_mut())
+                        {
+                            transform.inclusive_prefixes = prefixes;
                         }
                     }
                     b"ds:DigestMethod" if in_signed_info => {
@@ -383,6 +791,9 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                         )?;
                     }
                     b"xades:SigningTime" => text_field = Some(TextField::SigningTime),
+                    b"xades:EncapsulatedTimeStamp" => {
+                        text_field = Some(TextField::EncapsulatedTimeStamp)
+                    }
                     b"xades:SigningCertificate" => profile.signing_certificate_seen = true,
                     b"xades:CertDigest" => in_cert_digest = true,
                     b"ds:DigestMethod" if in_cert_digest => {
@@ -399,8 +810,8 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                 }
             }
             Ok(Event::Empty(e)) => {
-                let name = e.name();
-                match name.as_ref() {
+                let name = profile_name(reader.resolver(), e.name());
+                match name.as_slice() {
                     b"ds:CanonicalizationMethod" if in_signed_info => {
                         set_once(
                             &mut profile.canonicalization_method,
@@ -417,8 +828,20 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                     }
                     b"ds:Transform" if in_signed_info => {
                         if let Some(reference) = current_reference.as_mut() {
-                            let algorithm = required_attr(&e, b"Algorithm", "Transform Algorithm")?;
-                            reference.transforms.push(algorithm);
+                            reference.transforms.push(ReferenceTransform::new(&e)?);
+                        }
+                    }
+                    b"ec:InclusiveNamespaces" => {
+                        let prefixes =
+                            prefix_list(&required_attr(&e, b"PrefixList", "PrefixList")?);
+                        if in_canonicalization_method {
+                            profile.canonicalization_prefixes = prefixes;
+                        } else if let Some(transform) = current_reference
+                            .as_mut()
+                            .filter(|_| in_transform)
+                            .and_then(|reference| reference.transforms.last_mut())
+                        {
+                            transform.inclusive_prefixes = prefixes;
                         }
                     }
                     b"ds:DigestMethod" if in_signed_info => {
@@ -444,10 +867,37 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                 if let Some(field) = text_field {
                     let text = e.decode().context("failed to decode signature XML text")?;
                     apply_text_field(&mut profile, current_reference.as_mut(), field, text.trim())?;
+                } else if let Some((expression, _)) = xpath.as_mut() {
+                    expression.push_str(&e.decode().context("failed to decode ds:XPath")?);
+                }
+            }
+            Ok(Event::GeneralRef(e)) => {
+                if let Some((expression, _)) = xpath.as_mut() {
+                    push_ref(expression, &e)?;
                 }
             }
-            Ok(Event::End(e)) => match e.name().as_ref() {
+            Ok(Event::End(e)) => match profile_name(reader.resolver(), e.name()).as_slice() {
                 b"ds:SignedInfo" => in_signed_info = false,
+                b"ds:CanonicalizationMethod" => in_canonicalization_method = false,
+                b"ds:Transform" => in_transform = false,
+                b"ds:XPath" => {
+                    if let Some((expression, declarations)) = xpath.take() {
+                        let filter = XPathFilter::new(&expression, |prefix| {
+                            declarations
+                                .iter()
+                                .find(|(attribute, _)| {
+                                    attribute.strip_prefix("xmlns:") == Some(prefix)
+                                })
+                                .map(|(_, namespace)| namespace.as_str())
+                        })?;
+                        if let Some(transform) = current_reference
+                            .as_mut()
+                            .and_then(|reference| reference.transforms.last_mut())
+                        {
+                            transform.xpath = Some(filter);
+                        }
+                    }
+                }
                 b"ds:Reference" if in_signed_info => {
                     let reference = current_reference
                         .take()
@@ -459,7 +909,8 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
                 | b"ds:DigestValue"
                 | b"ds:X509IssuerName"
                 | b"ds:X509SerialNumber"
-                | b"xades:SigningTime" => text_field = None,
+                | b"xades:SigningTime"
+                | b"xades:EncapsulatedTimeStamp" => text_field = None,
                 _ => {}
             },
             Ok(Event::Eof) => break,
@@ -472,6 +923,18 @@ fn parse_signature_profile(signature_xml: &[u8]) -> anyhow::Result<SignatureProf
     Ok(profile)
 }
 
+/// `name` spelled with the `ds:`/`xades:`/`ec:` prefix of its namespace, or
+/// empty for elements outside the signature vocabulary.
+fn profile_name(resolver: &NamespaceResolver, name: QName) -> Vec<u8> {
+    let prefix: &[u8] = match element_namespace(resolver, name) {
+        Some(namespace) if namespace == DS_NS.as_bytes() => b"ds:",
+        Some(namespace) if namespace == XADES_NS.as_bytes() => b"xades:",
+        Some(namespace) if namespace == EXC_C14N.as_bytes() => b"ec:",
+        _ => return Vec::new(),
+    };
+    [prefix, name.local_name().as_ref()].concat()
+}
+
 fn apply_text_field(
     profile: &mut SignatureProfile,
     current_reference: Option<&mut SignedReference>,
@@ -523,94 +986,14 @@ fn apply_text_field(
             Some(text.to_owned()),
             "SigningTime",
         ),
-    }
-}
-
-fn extract_single_signature(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
-    reader.config_mut().trim_text(false);
-
-    let mut buf = Vec::new();
-    let mut signatures = Vec::new();
-    let mut writer: Option<Writer<Vec<u8>>> = None;
-    let mut depth = 0usize;
-
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                if writer.is_none() && e.local_name().as_ref() == b"Signature" {
-                    let mut w = Writer::new(Vec::new());
-                    w.write_event(Event::Start(e.to_owned()))?;
-                    writer = Some(w);
-                    depth = 1;
-                } else if let Some(w) = writer.as_mut() {
-                    depth += 1;
-                    w.write_event(Event::Start(e.to_owned()))?;
-                }
-            }
-            Ok(Event::Empty(e)) => {
-                if let Some(w) = writer.as_mut() {
-                    w.write_event(Event::Empty(e.to_owned()))?;
-                }
-            }
-            Ok(Event::Text(e)) => {
-                if let Some(w) = writer.as_mut() {
-                    w.write_event(Event::Text(e.to_owned()))?;
-                }
-            }
-            Ok(Event::CData(e)) => {
-                if let Some(w) = writer.as_mut() {
-                    w.write_event(Event::CData(e.to_owned()))?;
-                }
-            }
-            Ok(Event::Comment(e)) => {
-                if let Some(w) = writer.as_mut() {
-                    w.write_event(Event::Comment(e.to_owned()))?;
-                }
-            }
-            Ok(Event::End(e)) => {
-                if let Some(mut w) = writer.take() {
-                    w.write_event(Event::End(e.to_owned()))?;
-                    depth -= 1;
-                    if depth == 0 {
-                        let candidate = w.into_inner();
-                        if contains_signed_info(&candidate)? {
-                            signatures.push(candidate);
-                        }
-                    } else {
-                        writer = Some(w);
-                    }
-                }
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => bail!("XML error while extracting Signature: {e}"),
-            _ => {}
-        }
-        buf.clear();
-    }
-
-    match signatures.len() {
-        1 => Ok(signatures.remove(0)),
-        0 => Err(anyhow!("ds:Signature not found")),
-        _ => Err(anyhow!("multiple ds:Signature elements found")),
-    }
-}
-
-fn contains_signed_info(xml: &[u8]) -> anyhow::Result<bool> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
-    let mut buf = Vec::new();
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
-                if e.name().as_ref() == b"ds:SignedInfo" {
-                    return Ok(true);
-                }
-            }
-            Ok(Event::Eof) => return Ok(false),
-            Err(e) => bail!("XML error while checking Signature: {e}"),
-            _ => {}
+        TextField::EncapsulatedTimeStamp => {
+            profile.signature_timestamps.push(
+                general_purpose::STANDARD
+                    .decode(text)
+                    .context("invalid EncapsulatedTimeStamp base64")?,
+            );
+            Ok(())
         }
-        buf.clear();
     }
 }
 
@@ -640,13 +1023,19 @@ fn set_once<T>(slot: &mut Option<T>, value: Option<T>, name: &str) -> anyhow::Re
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::xml::extractors::{extract_crt, extract_invoice};
+    use crate::services::xml::invoice_document::InvoiceDocument;
     use std::fs;
 
+    fn single_signature(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let document = InvoiceDocument::parse(xml)?;
+        Ok(document.signature()?.xml.clone())
+    }
+
     fn valid_profile() -> SignatureProfile {
         SignatureProfile {
             signature_id: Some("sig".to_owned()),
             canonicalization_method: Some(C14N_11.to_owned()),
+            canonicalization_prefixes: vec![],
             signature_method: Some(RSA_SHA256.to_owned()),
             signature_value: None,
             qualifying_target: Some("#sig".to_owned()),
@@ -673,13 +1062,14 @@ mod tests {
                     transforms: vec![],
                 },
             ],
+            signature_timestamps: vec![],
         }
     }
 
     #[test]
     fn duplicate_signature_blocks_are_rejected() {
         let xml = br#"<Root><ds:Signature Id="signature"><ds:SignedInfo/></ds:Signature><ds:Signature Id="signature2"><ds:SignedInfo/></ds:Signature></Root>"#;
-        let err = extract_single_signature(xml).unwrap_err().to_string();
+        let err = single_signature(xml).unwrap_err().to_string();
         assert!(err.contains("multiple ds:Signature"));
     }
 
@@ -690,26 +1080,36 @@ mod tests {
             reference_type: None,
             digest_method: Some(SHA256.to_owned()),
             digest_value: Some(vec![0; 32]),
-            transforms: vec!["http://example.com/unsupported".to_owned()],
+            transforms: vec![ReferenceTransform {
+                algorithm: "http://example.com/unsupported".to_owned(),
+                inclusive_prefixes: vec![],
+                xpath: None,
+            }],
         };
-        assert!(validate_reference_algorithms(&reference).is_err());
+        assert!(
+            validate_reference_algorithms(&reference, &SignatureAlgorithms::default()).is_err()
+        );
     }
 
     #[test]
     fn test_invoice_fixture_rejects_rsa_signature_mismatch() {
         let xml = fs::read("test.xml").expect("failed to read test.xml");
-        let canonicalized_invoice = canonicalize_c14n11(extract_invoice(&xml).unwrap()).unwrap();
-        let invoice_hash = compute_hash(&canonicalized_invoice).unwrap();
-        let certificate_b64 = extract_crt(&xml).unwrap();
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        let certificate_b64 = document.signature().unwrap().certificate().unwrap();
         let certificate_der = general_purpose::STANDARD.decode(certificate_b64).unwrap();
         let certificate = X509::from_der(&certificate_der).unwrap();
         let tampered_xml = String::from_utf8(xml)
             .unwrap()
             .replacen("XmYUmlqH", "AmYUmlqH", 1);
-        let err =
-            validate_xades_bes_signature(tampered_xml.as_bytes(), &invoice_hash, &certificate)
-                .unwrap_err()
-                .to_string();
+        let tampered = InvoiceDocument::parse(tampered_xml.as_bytes()).unwrap();
+        let err = validate_xades_bes_signature(
+            &tampered,
+            tampered_xml.as_bytes(),
+            &certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err()
+        .to_string();
         assert!(err.contains("Invalid invoice signature"));
     }
 
@@ -803,8 +1203,7 @@ mod tests {
                 <ds:SignatureMethod Algorithm="http://www.w3.org/2001/04/xmldsig-more#rsa-sha256"/>
             </ds:SignedInfo>
         </foo:Signature>"#;
-        let sig = extract_single_signature(xml).unwrap();
-        let profile = parse_signature_profile(&sig).unwrap();
+        let profile = parse_signature_profile(xml).unwrap();
         assert!(profile.signature_id.is_none());
         let err = enforce_profile_structure(&profile).unwrap_err().to_string();
         assert!(err.contains("Signature") && err.contains("Id"));
@@ -886,28 +1285,31 @@ mod tests {
     #[test]
     fn wrong_signature_namespace_rejected_by_extract_single() {
         let xml = br#"<ds:Signature Id="sig"><ds:SignedInfo/></ds:Signature>"#;
-        assert!(extract_single_signature(xml).is_ok());
+        assert!(single_signature(xml).is_ok());
 
         let xml =
             br#"<foo:Signature xmlns:foo="http://wrong" Id="sig"><ds:SignedInfo/></foo:Signature>"#;
-        assert!(extract_single_signature(xml).is_ok());
+        assert!(single_signature(xml).is_err());
+
+        let xml = br#"<Signature xmlns="http://www.w3.org/2000/09/xmldsig#" Id="sig"><SignedInfo/></Signature>"#;
+        assert!(single_signature(xml).is_ok());
     }
 
     #[test]
     fn namespace_aware_extract_signed_info() {
         let valid = br"<ds:SignedInfo><ds:CanonicalizationMethod Algorithm='http://www.w3.org/2006/12/xml-c14n11#'/></ds:SignedInfo>";
-        assert!(extract_signed_info(valid, Some(DS_NS.as_bytes())).is_ok());
+        assert!(extract_signed_info(valid, Some(DS_NS.as_bytes()), &[]).is_ok());
 
         let invalid = br"<foo:SignedInfo xmlns:foo='http://wrong'><ds:CanonicalizationMethod Algorithm='http://www.w3.org/2006/12/xml-c14n11#'/></foo:SignedInfo>";
-        assert!(extract_signed_info(invalid, Some(DS_NS.as_bytes())).is_err());
+        assert!(extract_signed_info(invalid, Some(DS_NS.as_bytes()), &[]).is_err());
     }
 
     #[test]
     fn namespace_aware_extract_signed_properties() {
         let valid = br"<xades:SignedProperties xmlns:xades='http://uri.etsi.org/01903/v1.3.2#' Id='sp'><xades:SignedSignatureProperties/></xades:SignedProperties>";
-        assert!(extract_signed_properties(valid, Some(XADES_NS.as_bytes())).is_ok());
+        assert!(extract_signed_properties(valid, Some(XADES_NS.as_bytes()), &[]).is_ok());
 
         let invalid = br"<foo:SignedProperties xmlns:foo='http://wrong' Id='sp'><xades:SignedSignatureProperties/></foo:SignedProperties>";
-        assert!(extract_signed_properties(invalid, Some(XADES_NS.as_bytes())).is_err());
+        assert!(extract_signed_properties(invalid, Some(XADES_NS.as_bytes()), &[]).is_err());
     }
 }
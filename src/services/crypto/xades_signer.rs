@@ -0,0 +1,753 @@
+use anyhow::Context;
+use base64::{Engine, engine::general_purpose};
+use quick_xml::escape::escape;
+
+use crate::{
+    config::{crypto_config::Crypto, tsa_config::Tsa},
+    services::{
+        crypto::{
+            pki_service::{compute_hash, sign},
+            tsa_service::{MessageImprint, timestamp},
+            xades_bes::{
+                C14N_11, COUNTERSIGNED_SIGNATURE, DS_NS, InvoiceTransforms, RSA_SHA256, SHA256,
+                XADES_NS, XADES_SIGNED_PROPERTIES, XPATH_TRANSFORM, countersigned_digest,
+                invoice_reference_transforms, signature_timestamp_digest,
+            },
+            xmldsig_algorithms::{Canonicalization, DigestMethod, EXC_C14N},
+        },
+        xml::{
+            c14n11::canonicalize_c14n11,
+            extractors::{extract_signed_info, extract_signed_properties},
+            invoice_document::SignatureBlock,
+            xpath_filter::XPathFilter,
+        },
+    },
+};
+
+/// `ds:Signature` Id of the STC stamp added during clearance.
+pub const STC_STAMP_ID: &str = "stcStamp";
+const STC_SIGNED_PROPERTIES_ID: &str = "stcSignedProperties";
+
+/// XPath filters that select the hashed invoice content, as used by the
+/// supplier's invoice reference.
+pub const INVOICE_XPATH_FILTERS: [&str; 3] = [
+    "not(//ancestor-or-self::ext:UBLExtensions)",
+    "not(//ancestor-or-self::cac:Signature)",
+    "not(//ancestor-or-self::cac:AdditionalDocumentReference[cbc:ID=\"QR\"])",
+];
+
+pub enum Transform<'a> {
+    XPath(&'a str),
+    /// An XPath filter written with its own namespace declarations.
+    Filter(&'a XPathFilter),
+    C14n11,
+    /// A canonicalization transform with an optional `InclusiveNamespaces`
+    /// prefix list.
+    Canonicalization(&'a str, &'a [String]),
+}
+
+pub struct SignatureReference<'a> {
+    pub uri: &'a str,
+    pub reference_type: Option<&'a str>,
+    pub transforms: Vec<Transform<'a>>,
+    pub digest: &'a [u8],
+}
+
+impl<'a> SignatureReference<'a> {
+    /// The `URI=""` reference over the invoice content.
+    pub fn invoice(invoice_hash: &'a [u8]) -> Self {
+        let mut transforms: Vec<_> = INVOICE_XPATH_FILTERS
+            .iter()
+            .map(|filter| Transform::XPath(filter))
+            .collect();
+        transforms.push(Transform::C14n11);
+        Self {
+            uri: "",
+            reference_type: None,
+            transforms,
+            digest: invoice_hash,
+        }
+    }
+
+    /// The `URI=""` reference over the invoice content, with the transforms
+    /// that selected it.
+    pub fn invoice_with(transforms: &'a InvoiceTransforms, invoice_hash: &'a [u8]) -> Self {
+        let mut written: Vec<_> = transforms.filters.iter().map(Transform::Filter).collect();
+        written.push(Transform::Canonicalization(
+            &transforms.canonicalization,
+            &transforms.inclusive_prefixes,
+        ));
+        Self {
+            uri: "",
+            reference_type: None,
+            transforms: written,
+            digest: invoice_hash,
+        }
+    }
+}
+
+/// Builds an enveloped XAdES-BES `ds:Signature` element signed with `crypto`.
+/// A reference to the generated `SignedProperties` is appended to
+/// `references`. With a `tsa`, a `SignatureTimeStamp` over the
+/// `SignatureValue` is added to the unsigned properties (XAdES-T).
+pub fn sign_xades(
+    signature_id: &str,
+    signed_properties_id: &str,
+    references: &[SignatureReference],
+    crypto: &Crypto,
+    tsa: Option<&Tsa>,
+    signing_time: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let certificate_der = crypto.certificate.to_der()?;
+    let issuer = crypto
+        .certificate
+        .issuer_name()
+        .entries()
+        .map(|entry| {
+            let name = entry.object().nid().short_name().unwrap_or("UNKNOWN");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{name}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let serial = crypto.certificate.serial_number().to_bn()?.to_dec_str()?;
+
+    let signed_properties = format!(
+        concat!(
+            r#"<xades:SignedProperties Id="{id}"><xades:SignedSignatureProperties>"#,
+            r#"<xades:SigningTime>{time}</xades:SigningTime><xades:SigningCertificate><xades:Cert>"#,
+            r#"<xades:CertDigest><ds:DigestMethod Algorithm="{sha256}"/><ds:DigestValue>{digest}</ds:DigestValue></xades:CertDigest>"#,
+            r#"<xades:IssuerSerial><ds:X509IssuerName>{issuer}</ds:X509IssuerName><ds:X509SerialNumber>{serial}</ds:X509SerialNumber></xades:IssuerSerial>"#,
+            r#"</xades:Cert></xades:SigningCertificate></xades:SignedSignatureProperties></xades:SignedProperties>"#,
+        ),
+        id = escape(signed_properties_id),
+        time = escape(signing_time),
+        sha256 = SHA256,
+        digest = general_purpose::STANDARD.encode(compute_hash(&certificate_der)?),
+        issuer = escape(&issuer),
+        serial = serial,
+    );
+    let signed_properties_hash = compute_hash(&canonicalize_c14n11(extract_signed_properties(
+        signed_properties.as_bytes(),
+        None,
+        &[],
+    )?)?)?;
+
+    let mut signed_info = format!(
+        r#"<ds:SignedInfo><ds:CanonicalizationMethod Algorithm="{C14N_11}"/><ds:SignatureMethod Algorithm="{RSA_SHA256}"/>"#
+    );
+    for reference in references {
+        write_reference(&mut signed_info, reference);
+    }
+    write_reference(
+        &mut signed_info,
+        &SignatureReference {
+            uri: &format!("#{signed_properties_id}"),
+            reference_type: Some(XADES_SIGNED_PROPERTIES),
+            transforms: Vec::new(),
+            digest: &signed_properties_hash,
+        },
+    );
+    signed_info.push_str("</ds:SignedInfo>");
+
+    let signed_info_canonical =
+        canonicalize_c14n11(extract_signed_info(signed_info.as_bytes(), None, &[])?)?;
+    let signature_value =
+        sign(&signed_info_canonical, crypto).context("failed to sign SignedInfo")?;
+    let signature_value = general_purpose::STANDARD.encode(signature_value);
+
+    let unsigned_properties = match tsa {
+        Some(tsa) => {
+            let imprint = MessageImprint::sha256(signature_timestamp_digest(&signature_value)?);
+            let token = timestamp(tsa, &imprint, None, true)
+                .context("failed to time-stamp SignatureValue")?;
+            format!(
+                concat!(
+                    r#"<xades:UnsignedProperties><xades:UnsignedSignatureProperties>"#,
+                    r#"<xades:SignatureTimeStamp Id="{id}-timestamp"><ds:CanonicalizationMethod Algorithm="{c14n}"/>"#,
+                    r#"<xades:EncapsulatedTimeStamp>{token}</xades:EncapsulatedTimeStamp></xades:SignatureTimeStamp>"#,
+                    r#"</xades:UnsignedSignatureProperties></xades:UnsignedProperties>"#,
+                ),
+                id = escape(signature_id),
+                c14n = C14N_11,
+                token = general_purpose::STANDARD.encode(token),
+            )
+        }
+        None => String::new(),
+    };
+
+    let signature = format!(
+        concat!(
+            r#"<ds:Signature xmlns:ds="{ds}" xmlns:xades="{xades}" Id="{id}">{signed_info}"#,
+            r#"<ds:SignatureValue>{value}</ds:SignatureValue>"#,
+            r#"<ds:KeyInfo><ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data></ds:KeyInfo>"#,
+            r##"<ds:Object><xades:QualifyingProperties Target="#{id}">{signed_properties}{unsigned_properties}</xades:QualifyingProperties></ds:Object>"##,
+            r#"</ds:Signature>"#,
+        ),
+        ds = DS_NS,
+        xades = XADES_NS,
+        id = escape(signature_id),
+        signed_info = signed_info,
+        value = signature_value,
+        certificate = general_purpose::STANDARD.encode(certificate_der),
+        signed_properties = signed_properties,
+        unsigned_properties = unsigned_properties,
+    );
+    Ok(signature.into_bytes())
+}
+
+/// Builds the STC clearance stamp: a `sac:SignatureInformation` holding an
+/// STC signature over the invoice content and the supplier's `ds:Signature`,
+/// time-stamped by the built-in TSA. The invoice reference repeats the
+/// transforms of the supplier's, which selected the content `invoice_hash`
+/// was computed over.
+pub fn build_stc_stamp(
+    supplier: &SignatureBlock,
+    invoice_hash: &[u8],
+    crypto: &Crypto,
+    tsa: &Tsa,
+    signing_time: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let supplier_id = supplier
+        .id
+        .as_deref()
+        .context("supplier ds:Signature is missing Id")?;
+    let supplier_digest = countersigned_digest(
+        supplier,
+        &Canonicalization::from_uri(C14N_11, &[])?,
+        DigestMethod::Sha256,
+    )?;
+    let invoice_transforms = invoice_reference_transforms(supplier)?;
+    let countersigned_uri = format!("#{supplier_id}");
+    let references = [
+        SignatureReference::invoice_with(&invoice_transforms, invoice_hash),
+        SignatureReference {
+            uri: &countersigned_uri,
+            reference_type: Some(COUNTERSIGNED_SIGNATURE),
+            transforms: vec![Transform::C14n11],
+            digest: &supplier_digest,
+        },
+    ];
+    let signature = sign_xades(
+        STC_STAMP_ID,
+        STC_SIGNED_PROPERTIES_ID,
+        &references,
+        crypto,
+        Some(tsa),
+        signing_time,
+    )?;
+
+    let mut stamp = b"<sac:SignatureInformation>".to_vec();
+    stamp.extend_from_slice(&signature);
+    stamp.extend_from_slice(b"</sac:SignatureInformation>");
+    Ok(stamp)
+}
+
+fn write_reference(signed_info: &mut String, reference: &SignatureReference) {
+    signed_info.push_str(&format!(r#"<ds:Reference URI="{}""#, escape(reference.uri)));
+    if let Some(reference_type) = reference.reference_type {
+        signed_info.push_str(&format!(r#" Type="{reference_type}""#));
+    }
+    signed_info.push('>');
+    if !reference.transforms.is_empty() {
+        signed_info.push_str("<ds:Transforms>");
+        for transform in &reference.transforms {
+            match transform {
+                Transform::XPath(filter) => signed_info.push_str(&format!(
+                    r#"<ds:Transform Algorithm="{XPATH_TRANSFORM}"><ds:XPath>{}</ds:XPath></ds:Transform>"#,
+                    escape(*filter)
+                )),
+                Transform::Filter(filter) => {
+                    signed_info.push_str(&format!(
+                        r#"<ds:Transform Algorithm="{XPATH_TRANSFORM}"><ds:XPath"#
+                    ));
+                    for (prefix, uri) in filter.namespaces() {
+                        signed_info.push_str(&format!(r#" xmlns:{prefix}="{}""#, escape(uri)));
+                    }
+                    signed_info.push_str(&format!(
+                        r#">{}</ds:XPath></ds:Transform>"#,
+                        escape(filter.source())
+                    ));
+                }
+                Transform::C14n11 => signed_info
+                    .push_str(&format!(r#"<ds:Transform Algorithm="{C14N_11}"/>"#)),
+                Transform::Canonicalization(algorithm, []) => signed_info
+                    .push_str(&format!(r#"<ds:Transform Algorithm="{}"/>"#, escape(*algorithm))),
+                Transform::Canonicalization(algorithm, prefixes) => signed_info.push_str(&format!(
+                    r#"<ds:Transform Algorithm="{algorithm}"><ec:InclusiveNamespaces xmlns:ec="{EXC_C14N}" PrefixList="{prefixes}"/></ds:Transform>"#,
+                    algorithm = escape(*algorithm),
+                    prefixes = escape(prefixes.join(" ")),
+                )),
+            }
+        }
+        signed_info.push_str("</ds:Transforms>");
+    }
+    signed_info.push_str(&format!(
+        r#"<ds:DigestMethod Algorithm="{SHA256}"/><ds:DigestValue>{}</ds:DigestValue></ds:Reference>"#,
+        general_purpose::STANDARD.encode(reference.digest)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::signature_config::SignatureAlgorithms;
+    use crate::services::{
+        crypto::{
+            pki_service::issue_tsa_certificate,
+            test_crypto,
+            xades_bes::{
+                signed_invoice_content, validate_cleared_invoice, validate_stc_stamp,
+                validate_xades_bes_signature,
+            },
+        },
+        xml::{
+            editors::{edit_signature, edit_signed_info, insert_signature_information},
+            invoice_document::InvoiceDocument,
+        },
+    };
+    use openssl::{pkey::PKey, rsa::Rsa};
+
+    const SIGNING_TIME: &str = "2026-06-08T10:15:30Z";
+
+    fn invoice(signature: &str) -> String {
+        format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2" xmlns:ext="urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2"><ext:UBLExtensions><ext:UBLExtension><ext:ExtensionContent><sig:UBLDocumentSignatures xmlns:sig="urn:oasis:names:specification:ubl:schema:xsd:CommonSignatureComponents-2" xmlns:sac="urn:oasis:names:specification:ubl:schema:xsd:SignatureAggregateComponents-2"><sac:SignatureInformation>{signature}</sac:SignatureInformation></sig:UBLDocumentSignatures></ext:ExtensionContent></ext:UBLExtension></ext:UBLExtensions>
+  <cbc:ProfileID>clearance:1.0</cbc:ProfileID>
+  <cbc:ID>S001</cbc:ID>
+  <cac:AdditionalDocumentReference><cbc:ID>QR</cbc:ID><cac:Attachment><cbc:EmbeddedDocumentBinaryObject mimeCode="text/plain">AA==</cbc:EmbeddedDocumentBinaryObject></cac:Attachment></cac:AdditionalDocumentReference>
+</Invoice>"#
+        )
+    }
+
+    /// A supplier-signed invoice and its hash.
+    fn signed_invoice(supplier: &Crypto) -> (Vec<u8>, Vec<u8>) {
+        let unsigned = InvoiceDocument::parse(invoice("").as_bytes()).unwrap();
+        let invoice_hash =
+            compute_hash(&canonicalize_c14n11(unsigned.stripped_invoice).unwrap()).unwrap();
+        let signature = sign_xades(
+            "signature",
+            "xadesSignedProperties",
+            &[SignatureReference::invoice(&invoice_hash)],
+            supplier,
+            None,
+            SIGNING_TIME,
+        )
+        .unwrap();
+        let xml = invoice(std::str::from_utf8(&signature).unwrap());
+        (xml.into_bytes(), invoice_hash)
+    }
+
+    fn test_tsa(ca: &Crypto) -> Tsa {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let certificate = issue_tsa_certificate(&private_key, ca).unwrap();
+        Tsa {
+            signer: Crypto {
+                private_key,
+                certificate,
+            },
+            policy: vec![1, 2, 3, 4, 1],
+        }
+    }
+
+    fn stamp(xml: &[u8], invoice_hash: &[u8], stc: &Crypto, tsa: &Tsa) -> Vec<u8> {
+        let document = InvoiceDocument::parse(xml).unwrap();
+        let stamp = build_stc_stamp(
+            document.signature().unwrap(),
+            invoice_hash,
+            stc,
+            tsa,
+            SIGNING_TIME,
+        )
+        .unwrap();
+        insert_signature_information(xml, &stamp).unwrap()
+    }
+
+    #[test]
+    fn supplier_signature_validates() {
+        let supplier = test_crypto("Supplier");
+        let (xml, _) = signed_invoice(&supplier);
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        validate_xades_bes_signature(
+            &document,
+            &xml,
+            &supplier.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+    }
+
+    /// An invoice signed by `supplier` whose invoice reference carries the
+    /// given XPath filters and `invoice_hash` as its digest.
+    fn invoice_with_filters(supplier: &Crypto, filters: &[&str], invoice_hash: &[u8]) -> Vec<u8> {
+        let mut transforms: Vec<_> = filters.iter().map(|f| Transform::XPath(f)).collect();
+        transforms.push(Transform::C14n11);
+        let reference = SignatureReference {
+            uri: "",
+            reference_type: None,
+            transforms,
+            digest: invoice_hash,
+        };
+        let signature = sign_xades(
+            "signature",
+            "xadesSignedProperties",
+            &[reference],
+            supplier,
+            None,
+            SIGNING_TIME,
+        )
+        .unwrap();
+        invoice(std::str::from_utf8(&signature).unwrap()).into_bytes()
+    }
+
+    #[test]
+    fn xpath_filters_must_select_exactly_the_invoice_content() {
+        let supplier = test_crypto("Supplier");
+        let (_, invoice_hash) = signed_invoice(&supplier);
+        let algorithms = SignatureAlgorithms::default();
+        let mut cuts_more = INVOICE_XPATH_FILTERS.to_vec();
+        cuts_more.push("not(//ancestor-or-self::cbc:ProfileID)");
+        // Without the QR filter the QR reference would be hashed; with an
+        // extra filter the profile would be left out of the hash.
+        for filters in [&INVOICE_XPATH_FILTERS[..2], &cuts_more[..]] {
+            let xml = invoice_with_filters(&supplier, filters, &invoice_hash);
+            let document = InvoiceDocument::parse(&xml).unwrap();
+            let err = signed_invoice_content(&document, &xml, &algorithms).unwrap_err();
+            assert!(err.to_string().contains("must select the invoice"), "{err}");
+            let err =
+                validate_xades_bes_signature(&document, &xml, &supplier.certificate, &algorithms)
+                    .unwrap_err();
+            assert!(err.to_string().contains("must select the invoice"), "{err}");
+        }
+
+        // Filters spelled differently that select the same content validate.
+        let filters = [
+            "not(ancestor-or-self::ext:UBLExtensions)",
+            "not(ancestor-or-self::cac:Signature)",
+            "not(ancestor-or-self::cac:AdditionalDocumentReference[cbc:ID='QR'])",
+        ];
+        let xml = invoice_with_filters(&supplier, &filters, &invoice_hash);
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        validate_xades_bes_signature(&document, &xml, &supplier.certificate, &algorithms).unwrap();
+        assert_eq!(
+            compute_hash(&signed_invoice_content(&document, &xml, &algorithms).unwrap()).unwrap(),
+            invoice_hash
+        );
+    }
+
+    #[test]
+    fn invoice_content_checks_the_allow_list_first() {
+        let supplier = test_crypto("Supplier");
+        let (xml, _) = signed_invoice(&supplier);
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        let algorithms = SignatureAlgorithms::new([SHA256]).unwrap();
+        let err = signed_invoice_content(&document, &xml, &algorithms).unwrap_err();
+        assert!(err.to_string().contains("unsupported Reference Transform"));
+    }
+
+    #[test]
+    fn unsupported_xpath_filters_are_rejected() {
+        let supplier = test_crypto("Supplier");
+        let (_, invoice_hash) = signed_invoice(&supplier);
+        for (filter, expected) in [
+            (
+                "not(self::cac:AdditionalDocumentReference)",
+                "keeps an element inside an excluded one",
+            ),
+            (
+                "not(//ancestor-or-self::zz:Signature)",
+                "undeclared namespace prefix",
+            ),
+        ] {
+            let xml = invoice_with_filters(&supplier, &[filter], &invoice_hash);
+            let document = InvoiceDocument::parse(&xml).unwrap();
+            let err = validate_xades_bes_signature(
+                &document,
+                &xml,
+                &supplier.certificate,
+                &SignatureAlgorithms::default(),
+            )
+            .unwrap_err();
+            assert!(format!("{err:#}").contains(expected), "{filter}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn signature_under_other_prefixes_validates() {
+        let supplier = test_crypto("Supplier");
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        // Re-sign with the XMLDSig and XAdES vocabularies under other prefixes.
+        let renamed = String::from_utf8(xml)
+            .unwrap()
+            .replace("ds:", "dsig:")
+            .replace("xmlns:ds=", "xmlns:dsig=")
+            .replace("xades:", "xa:")
+            .replace("xmlns:xades=", "xmlns:xa=");
+        let signed_properties =
+            extract_signed_properties(renamed.as_bytes(), Some(XADES_NS.as_bytes()), &[]).unwrap();
+        let signed_properties_hash =
+            compute_hash(&canonicalize_c14n11(signed_properties).unwrap()).unwrap();
+        let xml =
+            edit_signed_info(renamed.as_bytes(), &invoice_hash, &signed_properties_hash).unwrap();
+        let signed_info =
+            canonicalize_c14n11(extract_signed_info(&xml, Some(DS_NS.as_bytes()), &[]).unwrap())
+                .unwrap();
+        let signature_value =
+            general_purpose::STANDARD.encode(sign(&signed_info, &supplier).unwrap());
+        let xml = edit_signature(&xml, signature_value).unwrap();
+
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        assert!(
+            document
+                .signature()
+                .unwrap()
+                .xml
+                .starts_with(b"<dsig:Signature")
+        );
+        validate_xades_bes_signature(
+            &document,
+            &xml,
+            &supplier.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn algorithms_are_taken_from_the_signature_and_the_allow_list() {
+        use crate::services::crypto::xmldsig_algorithms::{EXC_C14N, SHA512};
+
+        let supplier = test_crypto("Supplier");
+        let (xml, _) = signed_invoice(&supplier);
+        let certificate_der = supplier.certificate.to_der().unwrap();
+        let sha512 = DigestMethod::Sha512;
+        // Switch to exclusive C14N, keeping `xades` through the PrefixList on
+        // SignedInfo, and to SHA-512 for every digest.
+        let xml = String::from_utf8(xml)
+            .unwrap()
+            .replace(
+                &format!(r#"<ds:CanonicalizationMethod Algorithm="{C14N_11}"/>"#),
+                &format!(
+                    r#"<ds:CanonicalizationMethod Algorithm="{EXC_C14N}"><ec:InclusiveNamespaces xmlns:ec="{EXC_C14N}" PrefixList="xades"/></ds:CanonicalizationMethod>"#
+                ),
+            )
+            .replace(C14N_11, EXC_C14N)
+            .replace(SHA256, SHA512)
+            .replace(
+                &general_purpose::STANDARD.encode(compute_hash(&certificate_der).unwrap()),
+                &general_purpose::STANDARD.encode(sha512.digest(&certificate_der).unwrap()),
+            );
+        let document = InvoiceDocument::parse(xml.as_bytes()).unwrap();
+        let invoice_digest = sha512
+            .digest(
+                &signed_invoice_content(&document, xml.as_bytes(), &SignatureAlgorithms::default())
+                    .unwrap(),
+            )
+            .unwrap();
+        let signed_properties =
+            extract_signed_properties(xml.as_bytes(), Some(XADES_NS.as_bytes()), &[]).unwrap();
+        // A reference without transforms keeps the C14N 1.1 default.
+        let signed_properties_digest = sha512
+            .digest(&canonicalize_c14n11(signed_properties).unwrap())
+            .unwrap();
+        let xml =
+            edit_signed_info(xml.as_bytes(), &invoice_digest, &signed_properties_digest).unwrap();
+        let prefixes = ["xades".to_owned()];
+        let signed_info = Canonicalization::from_uri(EXC_C14N, &prefixes)
+            .unwrap()
+            .canonicalize(extract_signed_info(&xml, Some(DS_NS.as_bytes()), &prefixes).unwrap())
+            .unwrap();
+        assert!(signed_info.starts_with(br#"<ds:SignedInfo xmlns:ds=""#));
+        assert!(String::from_utf8_lossy(&signed_info).contains("xmlns:xades="));
+        let signature_value =
+            general_purpose::STANDARD.encode(sign(&signed_info, &supplier).unwrap());
+        let xml = edit_signature(&xml, signature_value).unwrap();
+
+        let document = InvoiceDocument::parse(&xml).unwrap();
+        validate_xades_bes_signature(
+            &document,
+            &xml,
+            &supplier.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+
+        let sha256_only = SignatureAlgorithms::new([C14N_11, EXC_C14N, SHA256]).unwrap();
+        let err =
+            validate_xades_bes_signature(&document, &xml, &supplier.certificate, &sha256_only)
+                .unwrap_err();
+        assert!(format!("{err:#}").contains(&format!("digest algorithm not allowed: {SHA512}")));
+    }
+
+    #[test]
+    fn stamp_keeps_supplier_signature_and_validates_both_layers() {
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        let cleared = stamp(&xml, &invoice_hash, &stc, &test_tsa(&stc));
+
+        let original = InvoiceDocument::parse(&xml).unwrap();
+        let document = InvoiceDocument::parse(&cleared).unwrap();
+        assert_eq!(
+            document.signature().unwrap().xml,
+            original.signature().unwrap().xml
+        );
+        assert_eq!(
+            document.stamp().unwrap().unwrap().id.as_deref(),
+            Some(STC_STAMP_ID)
+        );
+        validate_cleared_invoice(
+            &document,
+            &cleared,
+            &supplier.certificate,
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn stamp_repeats_the_supplier_invoice_transforms() {
+        use crate::services::xml::namespaces::{CAC_NS, CBC_NS, EXT_NS};
+
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let (_, invoice_hash) = signed_invoice(&supplier);
+        // Prefixes the invoice does not declare, bound on the ds:XPath.
+        let namespace_of = |prefix: &str| match prefix {
+            "u" => Some(EXT_NS),
+            "a" => Some(CAC_NS),
+            "b" => Some(CBC_NS),
+            _ => None,
+        };
+        let filters = [
+            "not(ancestor-or-self::u:UBLExtensions)",
+            "not(ancestor-or-self::a:Signature)",
+            "not(ancestor-or-self::a:AdditionalDocumentReference[b:ID='QR'])",
+        ]
+        .map(|expression| XPathFilter::new(expression, namespace_of).unwrap());
+        let mut transforms: Vec<_> = filters.iter().map(Transform::Filter).collect();
+        transforms.push(Transform::C14n11);
+        let signature = sign_xades(
+            "signature",
+            "xadesSignedProperties",
+            &[SignatureReference {
+                uri: "",
+                reference_type: None,
+                transforms,
+                digest: &invoice_hash,
+            }],
+            &supplier,
+            None,
+            SIGNING_TIME,
+        )
+        .unwrap();
+        let xml = invoice(std::str::from_utf8(&signature).unwrap()).into_bytes();
+        let cleared = stamp(&xml, &invoice_hash, &stc, &test_tsa(&stc));
+
+        let document = InvoiceDocument::parse(&cleared).unwrap();
+        let stamp_xml = &document.stamp().unwrap().unwrap().xml;
+        let stamp_xml = std::str::from_utf8(stamp_xml).unwrap();
+        assert!(stamp_xml.contains("[b:ID=&apos;QR&apos;]"), "{stamp_xml}");
+        assert!(!stamp_xml.contains(INVOICE_XPATH_FILTERS[0]));
+        validate_cleared_invoice(
+            &document,
+            &cleared,
+            &supplier.certificate,
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn tampered_supplier_signature_breaks_the_stamp() {
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        let cleared = String::from_utf8(stamp(&xml, &invoice_hash, &stc, &test_tsa(&stc))).unwrap();
+        let tampered = cleared.replacen(
+            "<xades:SigningTime>2026-06-08T10:15:30Z",
+            "<xades:SigningTime>2026-06-08T10:15:31Z",
+            1,
+        );
+        let document = InvoiceDocument::parse(tampered.as_bytes()).unwrap();
+        let err = validate_stc_stamp(
+            &document,
+            tampered.as_bytes(),
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Countersigned supplier signature digest mismatch"));
+    }
+
+    #[test]
+    fn stamp_from_another_key_is_rejected() {
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let other = test_crypto("STC Test CA");
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        let cleared = stamp(&xml, &invoice_hash, &other, &test_tsa(&other));
+        let document = InvoiceDocument::parse(&cleared).unwrap();
+        let err = validate_stc_stamp(
+            &document,
+            &cleared,
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("does not match server certificate"));
+    }
+
+    #[test]
+    fn stamp_carries_a_signature_timestamp_from_the_stc_tsa() {
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        let cleared = String::from_utf8(stamp(&xml, &invoice_hash, &stc, &test_tsa(&stc))).unwrap();
+        assert_eq!(cleared.matches("<xades:SignatureTimeStamp").count(), 1);
+
+        let start = cleared.find("<xades:UnsignedProperties>").unwrap();
+        let end = cleared.find("</xades:UnsignedProperties>").unwrap();
+        let without_timestamp = format!(
+            "{}{}",
+            &cleared[..start],
+            &cleared[end + "</xades:UnsignedProperties>".len()..]
+        );
+        let document = InvoiceDocument::parse(without_timestamp.as_bytes()).unwrap();
+        let err = validate_stc_stamp(
+            &document,
+            without_timestamp.as_bytes(),
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("exactly one SignatureTimeStamp"));
+    }
+
+    #[test]
+    fn timestamp_from_a_foreign_tsa_is_rejected() {
+        let supplier = test_crypto("Supplier");
+        let stc = test_crypto("STC Test CA");
+        let foreign_tsa = test_tsa(&test_crypto("Other CA"));
+        let (xml, invoice_hash) = signed_invoice(&supplier);
+        let cleared = stamp(&xml, &invoice_hash, &stc, &foreign_tsa);
+        let document = InvoiceDocument::parse(&cleared).unwrap();
+        let err = validate_stc_stamp(
+            &document,
+            &cleared,
+            &stc.certificate,
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("not issued by the STC CA"));
+    }
+}
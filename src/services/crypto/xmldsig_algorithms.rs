@@ -0,0 +1,158 @@
+use anyhow::bail;
+use openssl::hash::{MessageDigest, hash};
+use xml_c14n::{CanonicalizationMode, CanonicalizationOptions, canonicalize_xml};
+
+pub const C14N_10: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315";
+pub const C14N_10_WITH_COMMENTS: &str =
+    "http://www.w3.org/TR/2001/REC-xml-c14n-20010315#WithComments";
+pub const C14N_11: &str = "http://www.w3.org/2006/12/xml-c14n11#";
+pub const C14N_11_WITH_COMMENTS: &str = "http://www.w3.org/2006/12/xml-c14n11#WithComments";
+pub const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+pub const EXC_C14N_WITH_COMMENTS: &str = "http://www.w3.org/2001/10/xml-exc-c14n#WithComments";
+
+pub const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+pub const SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#sha384";
+pub const SHA512: &str = "http://www.w3.org/2001/04/xmlenc#sha512";
+
+/// Every canonicalization and digest algorithm this service implements.
+pub const SUPPORTED_ALGORITHMS: [&str; 9] = [
+    C14N_10,
+    C14N_10_WITH_COMMENTS,
+    C14N_11,
+    C14N_11_WITH_COMMENTS,
+    EXC_C14N,
+    EXC_C14N_WITH_COMMENTS,
+    SHA256,
+    SHA384,
+    SHA512,
+];
+
+/// A canonicalization algorithm named by a `CanonicalizationMethod` or
+/// `Transform`, with the `InclusiveNamespaces PrefixList` of exclusive C14N.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canonicalization {
+    mode: CanonicalizationMode,
+    with_comments: bool,
+    inclusive_prefixes: Vec<String>,
+}
+
+impl Canonicalization {
+    pub fn from_uri(uri: &str, inclusive_prefixes: &[String]) -> anyhow::Result<Self> {
+        let (mode, with_comments) = match uri {
+            C14N_10 => (CanonicalizationMode::Canonical1_0, false),
+            C14N_10_WITH_COMMENTS => (CanonicalizationMode::Canonical1_0, true),
+            C14N_11 => (CanonicalizationMode::Canonical1_1, false),
+            C14N_11_WITH_COMMENTS => (CanonicalizationMode::Canonical1_1, true),
+            EXC_C14N => (CanonicalizationMode::ExclusiveCanonical1_0, false),
+            EXC_C14N_WITH_COMMENTS => (CanonicalizationMode::ExclusiveCanonical1_0, true),
+            _ => bail!("unsupported canonicalization algorithm: {uri}"),
+        };
+        if !inclusive_prefixes.is_empty() && mode != CanonicalizationMode::ExclusiveCanonical1_0 {
+            bail!("InclusiveNamespaces is only allowed with exclusive canonicalization");
+        }
+        Ok(Self {
+            mode,
+            with_comments,
+            inclusive_prefixes: inclusive_prefixes.to_vec(),
+        })
+    }
+
+    /// Prefixes listed in `InclusiveNamespaces PrefixList`; `#default` stands
+    /// for the default namespace.
+    pub fn inclusive_prefixes(&self) -> &[String] {
+        &self.inclusive_prefixes
+    }
+
+    /// The same algorithm applied to a same-document reference (`URI=""` or
+    /// `#id`): such references select a node-set without comments, so the
+    /// `#WithComments` variants have nothing to keep.
+    pub fn without_comments(self) -> Self {
+        Self {
+            with_comments: false,
+            ..self
+        }
+    }
+
+    pub fn canonicalize(&self, xml: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let options = CanonicalizationOptions {
+            mode: self.mode,
+            keep_comments: self.with_comments,
+            inclusive_ns_prefixes: self.inclusive_prefixes.clone(),
+        };
+        let canonical = canonicalize_xml(std::str::from_utf8(&xml)?, options)?;
+        Ok(canonical.into_bytes())
+    }
+}
+
+/// A `DigestMethod` algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestMethod {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestMethod {
+    pub fn from_uri(uri: &str) -> anyhow::Result<Self> {
+        match uri {
+            SHA256 => Ok(Self::Sha256),
+            SHA384 => Ok(Self::Sha384),
+            SHA512 => Ok(Self::Sha512),
+            _ => bail!("unsupported digest algorithm: {uri}"),
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let md = match self {
+            Self::Sha256 => MessageDigest::sha256(),
+            Self::Sha384 => MessageDigest::sha384(),
+            Self::Sha512 => MessageDigest::sha512(),
+        };
+        Ok(hash(md, data)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<a xmlns:u="urn:unused" xmlns:k="urn:kept"><!-- note --><b>text</b></a>"#;
+
+    fn canonical(uri: &str, prefixes: &[&str]) -> String {
+        let prefixes: Vec<_> = prefixes.iter().map(|p| p.to_string()).collect();
+        let method = Canonicalization::from_uri(uri, &prefixes).unwrap();
+        String::from_utf8(method.canonicalize(XML.as_bytes().to_vec()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn canonicalization_follows_the_algorithm_uri() {
+        assert_eq!(
+            canonical(C14N_11, &[]),
+            r#"<a xmlns:k="urn:kept" xmlns:u="urn:unused"><b>text</b></a>"#
+        );
+        assert_eq!(
+            canonical(C14N_10_WITH_COMMENTS, &[]),
+            r#"<a xmlns:k="urn:kept" xmlns:u="urn:unused"><!-- note --><b>text</b></a>"#
+        );
+        assert_eq!(canonical(EXC_C14N, &[]), "<a><b>text</b></a>");
+        assert_eq!(
+            canonical(EXC_C14N, &["k"]),
+            r#"<a xmlns:k="urn:kept"><b>text</b></a>"#
+        );
+    }
+
+    #[test]
+    fn unknown_algorithms_and_misplaced_prefix_lists_are_rejected() {
+        assert!(Canonicalization::from_uri("http://example.com/c14n", &[]).is_err());
+        assert!(Canonicalization::from_uri(C14N_11, &["k".to_owned()]).is_err());
+        assert!(DigestMethod::from_uri("http://www.w3.org/2000/09/xmldsig#sha1").is_err());
+        assert_eq!(
+            DigestMethod::from_uri(SHA512)
+                .unwrap()
+                .digest(b"abc")
+                .unwrap()
+                .len(),
+            64
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, bail};
+use chrono::NaiveDate;
+use sqlx::{PgConnection, PgPool};
+use tracing::instrument;
+
+use crate::{
+    config::code_lists::{CodeList, CodeLists},
+    models::exchange_rate::ExchangeRateDto,
+    services::xml::decimal::decimal,
+};
+
+/// Currency tax is reported in; rates are quoted in it.
+pub const REPORTING_CURRENCY: &str = "SDG";
+
+/// Rows listed by [`list_exchange_rates`].
+const LIST_LIMIT: i64 = 100;
+
+/// A published rate: SDG per unit of `currency` on `rate_date`.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate_date: NaiveDate,
+    pub rate: String,
+}
+
+/// Reads a `currency,date,rate` CSV file with that header. Blank lines and
+/// lines starting with `#` are skipped. Currencies must be ISO 4217 codes
+/// other than SDG, and each currency and date may appear once.
+pub fn parse_exchange_rates(
+    csv: &str,
+    code_lists: &CodeLists,
+) -> anyhow::Result<Vec<ExchangeRate>> {
+    let mut lines = csv
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let header = lines.next().map(|(_, line)| line.replace(' ', ""));
+    if header.as_deref() != Some("currency,date,rate") {
+        bail!("invalid exchange rate file: the header must be currency,date,rate");
+    }
+
+    let mut seen = HashSet::new();
+    let mut rates = Vec::new();
+    for (number, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [currency, date, rate] = fields.as_slice() else {
+            bail!("invalid exchange rate file: line {number} needs currency, date and rate");
+        };
+        if *currency == REPORTING_CURRENCY || !code_lists.contains(CodeList::Currency, currency) {
+            bail!("invalid exchange rate file: line {number} has currency {currency:?}");
+        }
+        let rate_date = NaiveDate::parse_from_str(date, "%Y-%m-%d").with_context(|| {
+            format!("invalid exchange rate file: line {number} has date {date:?}")
+        })?;
+        let Some(rate) = decimal(rate).filter(|rate| rate.parse::<f64>().is_ok_and(|r| r > 0.0))
+        else {
+            bail!("invalid exchange rate file: line {number} has rate {rate:?}");
+        };
+        if !seen.insert((currency.to_string(), rate_date)) {
+            bail!("invalid exchange rate file: line {number} repeats {currency} on {date}");
+        }
+        rates.push(ExchangeRate {
+            currency: currency.to_string(),
+            rate_date,
+            rate,
+        });
+    }
+    if rates.is_empty() {
+        bail!("invalid exchange rate file: no rates");
+    }
+    Ok(rates)
+}
+
+/// Inserts the rates, replacing any already published for the same currency
+/// and date.
+#[instrument(skip(rates, pool), fields(rates = rates.len()))]
+pub async fn import_exchange_rates(rates: &[ExchangeRate], pool: &PgPool) -> anyhow::Result<usize> {
+    let currencies: Vec<&str> = rates.iter().map(|rate| rate.currency.as_str()).collect();
+    let dates: Vec<NaiveDate> = rates.iter().map(|rate| rate.rate_date).collect();
+    let values: Vec<&str> = rates.iter().map(|rate| rate.rate.as_str()).collect();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO exchange_rates (currency, rate_date, rate)
+        SELECT currency, rate_date, rate::numeric
+        FROM UNNEST($1::TEXT[], $2::DATE[], $3::TEXT[]) AS imported(currency, rate_date, rate)
+        ON CONFLICT (currency, rate_date)
+        DO UPDATE SET rate = EXCLUDED.rate, imported_at = now()
+        "#,
+    )
+    .bind(currencies)
+    .bind(dates)
+    .bind(values)
+    .execute(pool)
+    .await
+    .context("failed to import exchange rates")?;
+    Ok(result.rows_affected() as usize)
+}
+
+/// The latest rates, newest first, optionally for one currency.
+#[instrument(skip(pool))]
+pub async fn list_exchange_rates(
+    currency: Option<&str>,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<ExchangeRateDto>> {
+    sqlx::query_as::<_, ExchangeRateDto>(
+        r#"
+        SELECT
+            currency,
+            rate_date::TEXT AS rate_date,
+            rate::TEXT AS rate,
+            to_char(imported_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS imported_at
+        FROM exchange_rates
+        WHERE $1::TEXT IS NULL OR currency = $1
+        ORDER BY rate_date DESC, currency
+        LIMIT $2
+        "#,
+    )
+    .bind(currency)
+    .bind(LIST_LIMIT)
+    .fetch_all(pool)
+    .await
+    .context("failed to list exchange rates")
+}
+
+/// The rate in force on `date`: the latest published on or before it.
+#[instrument(skip(conn))]
+pub async fn find_exchange_rate(
+    currency: &str,
+    date: NaiveDate,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<ExchangeRate>> {
+    sqlx::query_as::<_, ExchangeRate>(
+        r#"
+        SELECT currency, rate_date, rate::TEXT AS rate
+        FROM exchange_rates
+        WHERE currency = $1 AND rate_date <= $2
+        ORDER BY rate_date DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(currency)
+    .bind(date)
+    .fetch_optional(conn)
+    .await
+    .context("failed to fetch exchange rate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rate_files() {
+        let csv = "# Central bank rates\ncurrency, date, rate\nUSD,2026-03-01,600.50\n\nEUR,2026-03-01,650\n";
+        let rates = parse_exchange_rates(csv, &CodeLists::default()).unwrap();
+        assert_eq!(
+            rates,
+            [
+                ExchangeRate {
+                    currency: "USD".into(),
+                    rate_date: NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(),
+                    rate: "600.50".into(),
+                },
+                ExchangeRate {
+                    currency: "EUR".into(),
+                    rate_date: NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(),
+                    rate: "650".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rate_files() {
+        let lists = CodeLists::default();
+        let error = |csv: &str| parse_exchange_rates(csv, &lists).unwrap_err().to_string();
+        assert!(error("USD,2026-03-01,600").contains("header"));
+        assert!(error("currency,date,rate\n").contains("no rates"));
+        assert!(error("currency,date,rate\nSDG,2026-03-01,1").contains("line 2"));
+        assert!(error("currency,date,rate\nUSD,01/03/2026,600").contains("has date"));
+        assert!(error("currency,date,rate\nUSD,2026-03-01,0").contains("has rate"));
+        assert!(
+            error("currency,date,rate\nUSD,2026-03-01,600\nUSD,2026-03-01,601").contains("repeats")
+        );
+    }
+}
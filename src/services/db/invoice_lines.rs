@@ -0,0 +1,180 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    models::taxpayer_portal::{TaxCategoryBreakdownDto, TopItemDto},
+    services::xml::{decimal::decimal, invoice_document::InvoiceLine},
+};
+
+/// Credit notes count against sales: their amounts are summed negated.
+const SIGNED: &str = "CASE WHEN i.invoice_type_code = '381' THEN -1 ELSE 1 END";
+
+/// Invoices of one taxpayer issued between `from` and `to`, both inclusive
+/// and optional.
+const TAXPAYER_PERIOD: &str = r#"
+    FROM invoice_lines l
+    INNER JOIN invoices i ON i.uuid = l.invoice_uuid
+    INNER JOIN devices d ON d.device_uuid = i.device_id
+    WHERE d.tin = $1
+      AND ($2::DATE IS NULL OR i.issue_date >= $2::DATE)
+      AND ($3::DATE IS NULL OR i.issue_date <= $3::DATE)
+"#;
+
+/// Writes the lines of a saved invoice, numbered in document order.
+/// Quantities, prices, amounts and rates that are not plain decimals are
+/// stored as `NULL`.
+#[instrument(skip(tx, lines), fields(uuid = %invoice_uuid, lines = lines.len()))]
+pub async fn save_invoice_lines<'a>(
+    tx: &mut Transaction<'a, Postgres>,
+    invoice_uuid: &Uuid,
+    lines: &[InvoiceLine],
+) -> anyhow::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let text = |field: fn(&InvoiceLine) -> Option<&str>| -> Vec<Option<String>> {
+        lines
+            .iter()
+            .map(|line| field(line).map(ToOwned::to_owned))
+            .collect()
+    };
+    let number = |field: fn(&InvoiceLine) -> Option<&str>| -> Vec<Option<String>> {
+        lines
+            .iter()
+            .map(|line| field(line).and_then(decimal))
+            .collect()
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO invoice_lines (
+            invoice_uuid,
+            line_number,
+            line_id,
+            item_name,
+            classification_code,
+            quantity,
+            unit_code,
+            unit_price,
+            net_amount,
+            tax_amount,
+            tax_category,
+            tax_percent
+        )
+        SELECT $1, line.*
+        FROM UNNEST(
+            $2::INTEGER[],
+            $3::TEXT[],
+            $4::TEXT[],
+            $5::TEXT[],
+            $6::NUMERIC[],
+            $7::TEXT[],
+            $8::NUMERIC[],
+            $9::NUMERIC[],
+            $10::NUMERIC[],
+            $11::TEXT[],
+            $12::NUMERIC[]
+        ) AS line
+        "#,
+    )
+    .bind(invoice_uuid)
+    .bind((1..=lines.len() as i32).collect::<Vec<_>>())
+    .bind(lines.iter().map(|line| line.id.clone()).collect::<Vec<_>>())
+    .bind(text(|line| line.item_name.as_deref()))
+    .bind(text(|line| line.classification_code.as_deref()))
+    .bind(number(|line| line.quantity.as_deref()))
+    .bind(text(|line| line.unit_code.as_deref()))
+    .bind(number(|line| {
+        line.price.as_ref().map(|price| price.value.as_str())
+    }))
+    .bind(number(|line| {
+        line.line_extension
+            .as_ref()
+            .map(|amount| amount.value.as_str())
+    }))
+    .bind(number(|line| {
+        line.tax_amount.as_ref().map(|amount| amount.value.as_str())
+    }))
+    .bind(text(|line| line.tax_category.as_deref()))
+    .bind(number(|line| line.tax_percent.as_deref()))
+    .execute(&mut **tx)
+    .await
+    .context("failed to store invoice lines")?;
+
+    Ok(())
+}
+
+/// Items of a taxpayer's invoices in the period, ranked by net amount, per
+/// currency.
+#[instrument(skip(pool))]
+pub async fn fetch_top_items(
+    tin: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    limit: i64,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<TopItemDto>> {
+    let query = format!(
+        r#"
+        SELECT
+            l.item_name,
+            l.classification_code,
+            l.unit_code,
+            i.currency,
+            SUM({SIGNED} * l.quantity)::TEXT AS quantity,
+            SUM({SIGNED} * l.net_amount)::TEXT AS net_amount,
+            SUM({SIGNED} * l.tax_amount)::TEXT AS tax_amount,
+            COUNT(*) AS lines,
+            COUNT(DISTINCT l.invoice_uuid) AS invoices
+        {TAXPAYER_PERIOD}
+        GROUP BY l.item_name, l.classification_code, l.unit_code, i.currency
+        ORDER BY SUM({SIGNED} * l.net_amount) DESC NULLS LAST, l.item_name
+        LIMIT $4
+        "#
+    );
+    sqlx::query_as::<_, TopItemDto>(&query)
+        .bind(tin)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .context("failed to fetch top invoice items")
+}
+
+/// Net and tax amounts of a taxpayer's invoice lines in the period, per
+/// currency, tax category and rate.
+#[instrument(skip(pool))]
+pub async fn fetch_tax_category_breakdown(
+    tin: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<TaxCategoryBreakdownDto>> {
+    let query = format!(
+        r#"
+        SELECT
+            i.currency,
+            l.tax_category,
+            l.tax_percent::TEXT AS tax_percent,
+            SUM({SIGNED} * l.net_amount)::TEXT AS taxable_amount,
+            SUM({SIGNED} * l.tax_amount)::TEXT AS tax_amount,
+            COUNT(*) AS lines,
+            COUNT(DISTINCT l.invoice_uuid) AS invoices
+        {TAXPAYER_PERIOD}
+        GROUP BY i.currency, l.tax_category, l.tax_percent
+        ORDER BY i.currency, l.tax_category, l.tax_percent
+        "#
+    );
+    sqlx::query_as::<_, TaxCategoryBreakdownDto>(&query)
+        .bind(tin)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+        .context("failed to fetch tax category breakdown")
+}
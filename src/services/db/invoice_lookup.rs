@@ -0,0 +1,97 @@
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Whether an invoice with this hash is stored in `invoices`.
+#[instrument(skip(invoice_hash, pool))]
+pub async fn invoice_exists_by_hash(invoice_hash: &[u8], pool: &PgPool) -> anyhow::Result<bool> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM invoices WHERE hash = $1)")
+        .bind(invoice_hash)
+        .fetch_one(pool)
+        .await
+        .context("failed to look up invoice by hash")
+}
+
+/// The stored XML of an invoice: the cleared invoice for clearance, the
+/// submitted one for reporting.
+#[instrument(skip(pool), fields(uuid = %invoice_uuid))]
+pub async fn fetch_invoice_bytes(
+    invoice_uuid: &Uuid,
+    pool: &PgPool,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let invoice_bytes = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+        "SELECT invoice_bytes FROM invoices WHERE uuid = $1",
+    )
+    .bind(invoice_uuid)
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch invoice")?;
+    Ok(invoice_bytes.flatten())
+}
+
+/// Like [`fetch_invoice_bytes`], but only for invoices submitted by a device
+/// of the taxpayer `tin`.
+#[instrument(skip(pool), fields(uuid = %invoice_uuid))]
+pub async fn fetch_taxpayer_invoice_bytes(
+    invoice_uuid: &Uuid,
+    tin: &str,
+    pool: &PgPool,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let invoice_bytes = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+        r#"
+        SELECT i.invoice_bytes
+        FROM invoices i
+        INNER JOIN devices d ON d.device_uuid = i.device_id
+        WHERE i.uuid = $1 AND d.tin = $2
+        "#,
+    )
+    .bind(invoice_uuid)
+    .bind(tin)
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch taxpayer invoice")?;
+    Ok(invoice_bytes.flatten())
+}
+
+/// Another stored invoice of the taxpayer `tin` with this number and
+/// document type, from any of its devices, not counting those already
+/// flagged as duplicates. `uuid` itself is skipped so a resubmission is
+/// reported as a duplicate UUID instead.
+#[instrument(skip(conn))]
+pub async fn find_invoice_by_number(
+    tin: &str,
+    invoice_number: &str,
+    invoice_type_code: &str,
+    uuid: &Uuid,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<Uuid>> {
+    sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT uuid
+        FROM invoices
+        WHERE supplier_tin = $1 AND invoice_number = $2 AND invoice_type_code = $3
+          AND NOT ('duplicate_invoice_number' = ANY (flags)) AND uuid <> $4
+        LIMIT 1
+        "#,
+    )
+    .bind(tin)
+    .bind(invoice_number)
+    .bind(invoice_type_code)
+    .bind(uuid)
+    .fetch_optional(conn)
+    .await
+    .context("failed to look up invoice by number")
+}
+
+/// Serializes invoice number checks of the taxpayer `tin` until the
+/// transaction ends, so two devices cannot both pass the check for one
+/// number.
+pub async fn lock_invoice_numbers(tin: &str, conn: &mut PgConnection) -> anyhow::Result<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('invoice_numbers:' || $1))")
+        .bind(tin)
+        .execute(conn)
+        .await
+        .context("failed to lock invoice numbers")?;
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use anyhow::Context;
+use chrono::{NaiveDate, NaiveTime};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::services::{
+    db::invoice_lookup::lock_invoice_numbers,
+    xml::{
+        decimal::decimal,
+        invoice_document::{Amount, InvoiceDocument},
+    },
+};
+
+/// Rows parsed per round trip by the backfill.
+const BACKFILL_BATCH: i64 = 200;
+
+/// Business facts of an invoice stored next to its XML so reports can filter
+/// and aggregate without re-parsing `invoice_bytes`. Values the invoice does
+/// not carry, or carries in an unreadable form, are left `NULL`.
+#[derive(Debug, Default, PartialEq)]
+pub struct InvoiceMetadata {
+    pub invoice_number: Option<String>,
+    pub issue_date: Option<NaiveDate>,
+    pub issue_time: Option<NaiveTime>,
+    pub invoice_type_code: Option<String>,
+    pub invoice_type_name: Option<String>,
+    pub currency: Option<String>,
+    pub buyer_tin: Option<String>,
+    pub buyer_name: Option<String>,
+    /// `TaxExclusiveAmount`.
+    pub net_amount: Option<String>,
+    /// Document-level `TaxAmount` in the document currency.
+    pub tax_amount: Option<String>,
+    pub payable_amount: Option<String>,
+    /// `TaxCurrencyCode`, when it differs from the document currency.
+    pub tax_currency: Option<String>,
+    /// Document-level `TaxAmount` in `tax_currency`.
+    pub tax_currency_amount: Option<String>,
+}
+
+impl InvoiceMetadata {
+    pub fn from_document(document: &InvoiceDocument) -> Self {
+        let currency = non_empty(&document.document_currency);
+        let tax_amount = document.document_tax_total().and_then(amount);
+        let tax_currency = document
+            .tax_currency
+            .as_deref()
+            .and_then(non_empty)
+            .filter(|tax_currency| Some(tax_currency) != currency.as_ref());
+        let tax_currency_amount = tax_currency.as_ref().and_then(|tax_currency| {
+            document
+                .tax_totals
+                .iter()
+                .find(|tax| tax.currency.as_ref() == Some(tax_currency))
+                .and_then(amount)
+        });
+
+        Self {
+            invoice_number: non_empty(&document.id),
+            issue_date: NaiveDate::parse_from_str(document.issue_date.trim(), "%Y-%m-%d").ok(),
+            issue_time: parse_time(&document.issue_time),
+            invoice_type_code: non_empty(&document.invoice_type_code),
+            invoice_type_name: document.invoice_type_name.clone(),
+            currency,
+            buyer_tin: non_empty(&document.customer.tin),
+            buyer_name: document.customer.registration_name.clone(),
+            net_amount: document.totals.tax_exclusive.as_ref().and_then(amount),
+            tax_amount,
+            payable_amount: document.totals.payable.as_ref().and_then(amount),
+            tax_currency,
+            tax_currency_amount,
+        }
+    }
+}
+
+/// Fills the metadata columns of invoices saved before they existed. Runs
+/// once at startup; rows that fail to parse are logged and marked with the
+/// error, so later runs skip them.
+pub async fn invoice_metadata_backfill(pool: PgPool) {
+    match backfill_invoice_metadata(&pool).await {
+        Ok((0, 0)) => {}
+        Ok((updated, skipped)) => {
+            tracing::info!(updated, skipped, "Backfilled invoice metadata")
+        }
+        Err(e) => tracing::error!(error = %e, "Invoice metadata backfill failed"),
+    }
+}
+
+/// Returns how many rows were updated and how many could not be parsed.
+#[instrument(skip(pool))]
+pub async fn backfill_invoice_metadata(pool: &PgPool) -> anyhow::Result<(u64, u64)> {
+    let (mut updated, mut skipped) = (0, 0);
+    let mut after = Uuid::nil();
+    loop {
+        let rows: Vec<(Uuid, Vec<u8>)> = sqlx::query_as(
+            r#"
+            SELECT uuid, invoice_bytes
+            FROM invoices
+            WHERE metadata_extracted_at IS NULL AND uuid > $1
+            ORDER BY uuid
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(BACKFILL_BATCH)
+        .fetch_all(pool)
+        .await
+        .context("failed to fetch invoices without metadata")?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        after = *last;
+
+        let parsed = tokio::task::spawn_blocking(move || {
+            rows.into_iter()
+                .map(|(uuid, bytes)| {
+                    let metadata = InvoiceDocument::parse(&bytes)
+                        .map(|document| InvoiceMetadata::from_document(&document));
+                    (uuid, metadata)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        for (uuid, metadata) in parsed {
+            match metadata {
+                Ok(metadata) => {
+                    store_metadata(pool, uuid, &metadata)
+                        .await
+                        .with_context(|| format!("failed to store metadata of invoice {uuid}"))?;
+                    updated += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(uuid = %uuid, error = %e, "Stored invoice could not be parsed for metadata");
+                    sqlx::query!(
+                        r#"
+                        UPDATE invoices
+                        SET metadata_extracted_at = now(), metadata_error = $2
+                        WHERE uuid = $1
+                        "#,
+                        uuid,
+                        format!("{e:#}"),
+                    )
+                    .execute(pool)
+                    .await
+                    .with_context(|| format!("failed to mark invoice {uuid} as unparseable"))?;
+                    skipped += 1;
+                }
+            }
+        }
+    }
+    Ok((updated, skipped))
+}
+
+async fn store_metadata(
+    pool: &PgPool,
+    uuid: Uuid,
+    metadata: &InvoiceMetadata,
+) -> anyhow::Result<()> {
+    // The duplicate check below runs under the supplier's invoice number
+    // lock, so a concurrent clearance cannot take the number in between.
+    let mut tx = pool.begin().await?;
+    let supplier_tin: Option<String> =
+        sqlx::query_scalar("SELECT supplier_tin FROM invoices WHERE uuid = $1")
+            .bind(uuid)
+            .fetch_one(&mut *tx)
+            .await?;
+    if let Some(supplier_tin) = &supplier_tin {
+        lock_invoice_numbers(supplier_tin, &mut tx).await?;
+    }
+
+    // Amounts travel as text and are cast, so no decimal type is needed here.
+    // A number another invoice of the supplier already holds is flagged as a
+    // duplicate, as the unique index on invoice numbers requires.
+    sqlx::query!(
+        r#"
+        UPDATE invoices i
+        SET invoice_number = $2, issue_date = $3, issue_time = $4,
+            invoice_type_code = $5, invoice_type_name = $6, currency = $7,
+            buyer_tin = $8, buyer_name = $9, net_amount = $10::text::numeric,
+            tax_amount = $11::text::numeric, payable_amount = $12::text::numeric,
+            tax_currency = $13, tax_currency_amount = $14::text::numeric,
+            metadata_extracted_at = now(),
+            flags = CASE
+                WHEN NOT ('duplicate_invoice_number' = ANY (i.flags)) AND EXISTS (
+                    SELECT 1
+                    FROM invoices other
+                    WHERE other.supplier_tin = i.supplier_tin
+                      AND other.invoice_number = $2
+                      AND other.invoice_type_code = $5
+                      AND NOT ('duplicate_invoice_number' = ANY (other.flags))
+                      AND other.uuid <> i.uuid
+                )
+                THEN array_append(i.flags, 'duplicate_invoice_number')
+                ELSE i.flags
+            END
+        WHERE uuid = $1
+        "#,
+        uuid,
+        metadata.invoice_number,
+        metadata.issue_date as _,
+        metadata.issue_time as _,
+        metadata.invoice_type_code,
+        metadata.invoice_type_name,
+        metadata.currency,
+        metadata.buyer_tin,
+        metadata.buyer_name,
+        metadata.net_amount,
+        metadata.tax_amount,
+        metadata.payable_amount,
+        metadata.tax_currency,
+        metadata.tax_currency_amount,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// `IssueTime` as `HH:MM:SS`, optionally with fractional seconds and a `Z`
+/// or offset suffix, which is dropped.
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    let value = value.trim();
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let local = match value.get(8..).and_then(|rest| rest.find(['+', '-'])) {
+        Some(offset) => &value[..8 + offset],
+        None => value,
+    };
+    NaiveTime::parse_from_str(local, "%H:%M:%S%.f").ok()
+}
+
+fn amount(amount: &Amount) -> Option<String> {
+    decimal(&amount.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVOICE: &str = r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:ID>INV-0042</cbc:ID>
+  <cbc:UUID>3cf5ee18-ee25-44ea-a444-2c37ba7f28be</cbc:UUID>
+  <cbc:IssueDate>2026-03-01</cbc:IssueDate>
+  <cbc:IssueTime>14:05:09Z</cbc:IssueTime>
+  <cbc:InvoiceTypeCode name="0100000">388</cbc:InvoiceTypeCode>
+  <cbc:DocumentCurrencyCode>USD</cbc:DocumentCurrencyCode>
+  <cbc:TaxCurrencyCode>SDG</cbc:TaxCurrencyCode>
+  <cac:AccountingCustomerParty>
+    <cac:Party>
+      <cac:PartyTaxScheme><cbc:CompanyID>200022</cbc:CompanyID></cac:PartyTaxScheme>
+      <cac:PartyLegalEntity><cbc:RegistrationName>Nile Traders</cbc:RegistrationName></cac:PartyLegalEntity>
+    </cac:Party>
+  </cac:AccountingCustomerParty>
+  <cac:TaxTotal><cbc:TaxAmount currencyID="SDG">90000.00</cbc:TaxAmount></cac:TaxTotal>
+  <cac:TaxTotal><cbc:TaxAmount currencyID="USD">150.00</cbc:TaxAmount></cac:TaxTotal>
+  <cac:LegalMonetaryTotal>
+    <cbc:TaxExclusiveAmount currencyID="USD">1000.00</cbc:TaxExclusiveAmount>
+    <cbc:PayableAmount currencyID="USD">1150.00</cbc:PayableAmount>
+  </cac:LegalMonetaryTotal>
+</Invoice>"#;
+
+    #[test]
+    fn extracts_metadata_from_the_document() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        let metadata = InvoiceMetadata::from_document(&document);
+        assert_eq!(
+            metadata,
+            InvoiceMetadata {
+                invoice_number: Some("INV-0042".into()),
+                issue_date: NaiveDate::from_ymd_opt(2026, 3, 1),
+                issue_time: NaiveTime::from_hms_opt(14, 5, 9),
+                invoice_type_code: Some("388".into()),
+                invoice_type_name: Some("0100000".into()),
+                currency: Some("USD".into()),
+                buyer_tin: Some("200022".into()),
+                buyer_name: Some("Nile Traders".into()),
+                net_amount: Some("1000.00".into()),
+                tax_amount: Some("150.00".into()),
+                payable_amount: Some("1150.00".into()),
+                tax_currency: Some("SDG".into()),
+                tax_currency_amount: Some("90000.00".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_unreadable_values_empty() {
+        assert_eq!(
+            parse_time("09:30:00.250+03:00"),
+            NaiveTime::from_hms_milli_opt(9, 30, 0, 250)
+        );
+        assert_eq!(
+            parse_time("09:30:00+03:00"),
+            NaiveTime::from_hms_opt(9, 30, 0)
+        );
+        assert_eq!(parse_time("9.30"), None);
+    }
+}
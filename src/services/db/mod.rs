@@ -1,8 +1,16 @@
 pub mod device_service;
+pub mod exchange_rate_service;
 pub mod icv_service;
+pub mod invoice_lines;
+pub mod invoice_lookup;
+pub mod invoice_metadata;
+pub mod number_series_service;
 pub mod pih_service;
+pub mod receipt_service;
 pub mod rejected_invoice_service;
 pub mod save_invoice;
+pub mod tax_period_service;
 pub mod taxpayer_auth;
 pub mod tin_service;
 pub mod token_checking;
+pub mod vat_return;
@@ -0,0 +1,177 @@
+use anyhow::{Context, bail};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::models::number_series::NumberSeriesDto;
+
+const NUMBER_SERIES_COLUMNS: &str = r#"
+    device_uuid::TEXT AS device_uuid,
+    prefix,
+    next_number,
+    to_char(updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS updated_at
+"#;
+
+/// Gapless numbering enforced for one device's invoices.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct NumberSeries {
+    pub prefix: String,
+    pub next_number: i64,
+}
+
+impl NumberSeries {
+    /// A series starting at `next_number`. The prefix may not contain
+    /// whitespace.
+    pub fn new(prefix: &str, next_number: i64) -> anyhow::Result<Self> {
+        if next_number < 1 {
+            bail!("invalid number series: next_number must be at least 1");
+        }
+        if prefix.chars().any(char::is_whitespace) {
+            bail!("invalid number series: prefix {prefix:?} contains whitespace");
+        }
+        Ok(Self {
+            prefix: prefix.to_owned(),
+            next_number,
+        })
+    }
+
+    /// Checks that `invoice_number` is the prefix followed by the next
+    /// number, leading zeros allowed: `INV-0042` and `INV-42` are both 42.
+    pub fn verify(&self, invoice_number: &str) -> anyhow::Result<()> {
+        let number = invoice_number
+            .trim()
+            .strip_prefix(self.prefix.as_str())
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<i64>().ok());
+        if number != Some(self.next_number) {
+            bail!(
+                "invoice number out of sequence: expected {}{}, got {invoice_number:?}",
+                self.prefix,
+                self.next_number
+            );
+        }
+        Ok(())
+    }
+}
+
+#[instrument(skip(pool), fields(device_uuid = %device_uuid))]
+pub async fn get_number_series(
+    device_uuid: &Uuid,
+    pool: &PgPool,
+) -> anyhow::Result<Option<NumberSeriesDto>> {
+    sqlx::query_as::<_, NumberSeriesDto>(&format!(
+        "SELECT {NUMBER_SERIES_COLUMNS} FROM invoice_number_series WHERE device_uuid = $1"
+    ))
+    .bind(device_uuid)
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch number series")
+}
+
+/// Enforces the series for the device, replacing its current one.
+#[instrument(skip(pool), fields(device_uuid = %device_uuid))]
+pub async fn set_number_series(
+    device_uuid: &Uuid,
+    series: &NumberSeries,
+    pool: &PgPool,
+) -> anyhow::Result<NumberSeriesDto> {
+    sqlx::query_as::<_, NumberSeriesDto>(&format!(
+        r#"
+        INSERT INTO invoice_number_series (device_uuid, prefix, next_number)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (device_uuid)
+        DO UPDATE SET prefix = EXCLUDED.prefix, next_number = EXCLUDED.next_number, updated_at = now()
+        RETURNING {NUMBER_SERIES_COLUMNS}
+        "#
+    ))
+    .bind(device_uuid)
+    .bind(&series.prefix)
+    .bind(series.next_number)
+    .fetch_one(pool)
+    .await
+    .context("failed to set number series")
+}
+
+/// Stops enforcing numbering for the device. Returns whether it had a series.
+#[instrument(skip(pool), fields(device_uuid = %device_uuid))]
+pub async fn delete_number_series(device_uuid: &Uuid, pool: &PgPool) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM invoice_number_series WHERE device_uuid = $1")
+        .bind(device_uuid)
+        .execute(pool)
+        .await
+        .context("failed to delete number series")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The device's series, read without locking for sandbox dry runs.
+#[instrument(skip(pool), fields(device_uuid = %device_uuid))]
+pub async fn fetch_number_series(
+    device_uuid: &Uuid,
+    pool: &PgPool,
+) -> anyhow::Result<Option<NumberSeries>> {
+    sqlx::query_as::<_, NumberSeries>(
+        "SELECT prefix, next_number FROM invoice_number_series WHERE device_uuid = $1",
+    )
+    .bind(device_uuid)
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch number series")
+}
+
+#[instrument(skip(tx), fields(device_uuid = %device_uuid))]
+pub async fn fetch_number_series_for_update(
+    device_uuid: &Uuid,
+    tx: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Option<NumberSeries>> {
+    sqlx::query_as::<_, NumberSeries>(
+        "SELECT prefix, next_number FROM invoice_number_series WHERE device_uuid = $1 FOR UPDATE",
+    )
+    .bind(device_uuid)
+    .fetch_optional(&mut **tx)
+    .await
+    .context("failed to fetch number series")
+}
+
+#[instrument(skip(tx), fields(device_uuid = %device_uuid))]
+pub async fn update_number_series(
+    tx: &mut Transaction<'_, Postgres>,
+    device_uuid: &Uuid,
+    next_number: i64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE invoice_number_series SET next_number = $2, updated_at = now() WHERE device_uuid = $1",
+    )
+    .bind(device_uuid)
+    .bind(next_number)
+    .execute(&mut **tx)
+    .await
+    .context("failed to advance number series")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invoices_must_carry_the_next_number() {
+        let series = NumberSeries::new("INV-", 42).unwrap();
+        series.verify("INV-42").unwrap();
+        series.verify("INV-0042").unwrap();
+        for wrong in ["INV-43", "INV-41", "42", "INV-", "INV-42a", "inv-42"] {
+            let err = series.verify(wrong).unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with("invoice number out of sequence: expected INV-42"),
+                "{wrong}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_series() {
+        assert!(NumberSeries::new("INV-", 0).is_err());
+        assert!(NumberSeries::new("INV 2026-", 1).is_err());
+        assert_eq!(NumberSeries::new("", 1).unwrap().prefix, "");
+    }
+}
@@ -3,11 +3,11 @@ use base64::{Engine, engine::general_purpose};
 use openssl::memcmp;
 use tracing::instrument;
 
-use crate::services::xml::extractors::extract_pih;
+use crate::services::xml::invoice_document::InvoiceDocument;
 
-#[instrument(skip(invoice, expected_pih), fields(expected_pih_len = expected_pih.len()))]
-pub fn verify_pih(invoice: &[u8], expected_pih: &[u8]) -> anyhow::Result<()> {
-    let ex_pih_b64 = extract_pih(invoice).context("failed to extract the PIH")?;
+#[instrument(skip(document, expected_pih), fields(expected_pih_len = expected_pih.len()))]
+pub fn verify_pih(document: &InvoiceDocument, expected_pih: &[u8]) -> anyhow::Result<()> {
+    let ex_pih_b64 = document.pih().context("failed to extract the PIH")?;
     let ex_pih = general_purpose::STANDARD
         .decode(ex_pih_b64)
         .context("failed to decode the extracted PIH")?;
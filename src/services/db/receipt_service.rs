@@ -0,0 +1,83 @@
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction, types::time::OffsetDateTime};
+use tracing::instrument;
+use uuid::Uuid;
+
+pub struct ReceiptRecord<'a> {
+    pub invoice_uuid: &'a Uuid,
+    pub device_id: &'a Uuid,
+    pub icv: i32,
+    pub invoice_hash: &'a [u8],
+    pub received_at: OffsetDateTime,
+    pub accepted_at: OffsetDateTime,
+    pub signed_receipt: &'a str,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct StoredReceipt {
+    pub invoice_uuid: Uuid,
+    pub device_id: Uuid,
+    pub icv: i32,
+    pub invoice_hash: Vec<u8>,
+    pub received_at: String,
+    pub accepted_at: String,
+    pub signed_receipt: String,
+}
+
+#[instrument(skip(tx, record), fields(uuid = %record.invoice_uuid, device_uuid = %record.device_id))]
+pub async fn save_receipt<'a>(
+    tx: &mut Transaction<'a, Postgres>,
+    record: ReceiptRecord<'_>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO invoice_receipts (
+            invoice_uuid,
+            device_id,
+            icv,
+            invoice_hash,
+            received_at,
+            accepted_at,
+            signed_receipt
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(record.invoice_uuid)
+    .bind(record.device_id)
+    .bind(record.icv)
+    .bind(record.invoice_hash)
+    .bind(record.received_at)
+    .bind(record.accepted_at)
+    .bind(record.signed_receipt)
+    .execute(&mut **tx)
+    .await
+    .context("failed to store invoice receipt")?;
+
+    Ok(())
+}
+
+#[instrument(skip(pool), fields(uuid = %invoice_uuid))]
+pub async fn fetch_receipt(
+    invoice_uuid: &Uuid,
+    pool: &PgPool,
+) -> anyhow::Result<Option<StoredReceipt>> {
+    sqlx::query_as::<_, StoredReceipt>(
+        r#"
+        SELECT
+            invoice_uuid,
+            device_id,
+            icv,
+            invoice_hash,
+            to_char(received_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS received_at,
+            to_char(accepted_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS accepted_at,
+            signed_receipt
+        FROM invoice_receipts
+        WHERE invoice_uuid = $1
+        "#,
+    )
+    .bind(invoice_uuid)
+    .fetch_optional(pool)
+    .await
+    .context("failed to fetch invoice receipt")
+}
@@ -8,7 +8,7 @@ pub struct RejectedInvoiceRecord<'a> {
     pub submitted: &'a SubmitInvoiceDto,
     pub endpoint: &'static str,
     pub invoice_type: &'static str,
-    pub api_error: ApiError,
+    pub api_error: &'a ApiError,
     pub supplier_tin: Option<&'a str>,
     pub device_id: Option<Uuid>,
 }
@@ -2,27 +2,73 @@ use sqlx::{Postgres, Transaction};
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::models::submit_invoice::InvoiceType;
+use crate::{
+    models::submit_invoice::{InvoiceFlag, InvoiceType},
+    services::db::invoice_metadata::InvoiceMetadata,
+};
 
-#[instrument(skip(tx, invoice_bytes, hash), fields(uuid = %uuid, device_uuid = %device_id, invoice_type = %invoice_type.as_str()))]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(tx, invoice_bytes, hash, metadata), fields(uuid = %uuid, device_uuid = %device_id, invoice_type = %invoice_type.as_str()))]
 pub async fn save_invoice<'a>(
     tx: &mut Transaction<'a, Postgres>,
     invoice_bytes: &[u8],
     uuid: &Uuid,
     hash: Vec<u8>,
     device_id: &Uuid,
+    supplier_tin: &str,
     invoice_type: InvoiceType,
+    metadata: &InvoiceMetadata,
+    flags: &[InvoiceFlag],
 ) -> anyhow::Result<()> {
+    let late_by_seconds = flags.iter().find_map(|flag| match flag {
+        InvoiceFlag::LateReport { late_by_seconds } => Some(*late_by_seconds),
+        _ => None,
+    });
+    let rule_warnings = flags.iter().find_map(|flag| match flag {
+        InvoiceFlag::RuleWarning { codes } => Some(codes.clone()),
+        _ => None,
+    });
+    let flags: Vec<String> = flags.iter().map(|flag| flag.as_str().to_owned()).collect();
+    // Amounts travel as text and are cast, so no decimal type is needed here.
     let result = sqlx::query!(
         r#"
-        INSERT INTO invoices (invoice_bytes, uuid, hash, device_id, invoice_type)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO invoices (
+            invoice_bytes, uuid, hash, device_id, supplier_tin, invoice_type, flags,
+            late_by_seconds, rule_warnings, invoice_number, issue_date, issue_time,
+            invoice_type_code, invoice_type_name, currency, buyer_tin, buyer_name,
+            net_amount, tax_amount, payable_amount, tax_currency, tax_currency_amount,
+            metadata_extracted_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7,
+            $8, $9, $10, $11, $12,
+            $13, $14, $15, $16, $17,
+            $18::text::numeric, $19::text::numeric, $20::text::numeric, $21, $22::text::numeric,
+            now()
+        )
         "#,
         invoice_bytes,
         uuid,
         hash,
         device_id,
+        supplier_tin,
         invoice_type.as_str(),
+        &flags,
+        late_by_seconds,
+        rule_warnings.as_deref(),
+        metadata.invoice_number,
+        metadata.issue_date as _,
+        metadata.issue_time as _,
+        metadata.invoice_type_code,
+        metadata.invoice_type_name,
+        metadata.currency,
+        metadata.buyer_tin,
+        metadata.buyer_name,
+        metadata.net_amount,
+        metadata.tax_amount,
+        metadata.payable_amount,
+        metadata.tax_currency,
+        metadata.tax_currency_amount,
     )
     .execute(&mut **tx)
     .await;
@@ -32,6 +78,13 @@ pub async fn save_invoice<'a>(
         Err(sqlx::Error::Database(e)) if e.constraint() == Some("invoices_pkey") => {
             anyhow::bail!("Invoice UUID already exists")
         }
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("idx_invoices_supplier_number") => {
+            anyhow::bail!(
+                "duplicate invoice number: {} (type {}) is already used by another invoice",
+                metadata.invoice_number.as_deref().unwrap_or_default(),
+                metadata.invoice_type_code.as_deref().unwrap_or_default()
+            )
+        }
         Err(e) => Err(e.into()),
     }
 }
@@ -0,0 +1,212 @@
+use anyhow::{Context, bail};
+use chrono::NaiveDate;
+use sqlx::{PgConnection, PgPool};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::models::tax_period::TaxPeriodDto;
+
+const TAX_PERIOD_COLUMNS: &str = r#"
+    id::TEXT AS id,
+    tin,
+    period_from::TEXT AS period_from,
+    period_to::TEXT AS period_to,
+    status,
+    to_char(filed_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS filed_at,
+    to_char(locked_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS locked_at,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS created_at
+"#;
+
+/// Moves a period one step along `open` -> `filed` -> `locked`.
+#[derive(Debug, Clone, Copy)]
+pub enum TaxPeriodAction {
+    File,
+    Lock,
+}
+
+impl TaxPeriodAction {
+    fn required_status(self) -> &'static str {
+        match self {
+            Self::File => "open",
+            Self::Lock => "filed",
+        }
+    }
+
+    fn next_status(self) -> &'static str {
+        match self {
+            Self::File => "filed",
+            Self::Lock => "locked",
+        }
+    }
+}
+
+/// Status and bounds of the period covering a date.
+#[derive(Debug, sqlx::FromRow)]
+pub struct TaxPeriodBounds {
+    pub status: String,
+    pub period_from: NaiveDate,
+    pub period_to: NaiveDate,
+}
+
+impl TaxPeriodBounds {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        (self.period_from..=self.period_to).contains(&date)
+    }
+}
+
+#[instrument(skip(pool))]
+pub async fn list_tax_periods(tin: &str, pool: &PgPool) -> anyhow::Result<Vec<TaxPeriodDto>> {
+    sqlx::query_as::<_, TaxPeriodDto>(&format!(
+        "SELECT {TAX_PERIOD_COLUMNS} FROM tax_periods WHERE tin = $1 ORDER BY period_from DESC"
+    ))
+    .bind(tin)
+    .fetch_all(pool)
+    .await
+    .context("failed to list tax periods")
+}
+
+/// Defines an open period. Periods of one taxpayer may not overlap.
+#[instrument(skip(pool))]
+pub async fn create_tax_period(
+    tin: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    pool: &PgPool,
+) -> anyhow::Result<TaxPeriodDto> {
+    let mut tx = pool.begin().await?;
+    // Serializes period creation per taxpayer so the overlap check holds.
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('tax_periods:' || $1))")
+        .bind(tin)
+        .execute(&mut *tx)
+        .await?;
+
+    let overlaps = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM tax_periods
+            WHERE tin = $1 AND period_from <= $3 AND period_to >= $2
+        )
+        "#,
+    )
+    .bind(tin)
+    .bind(from)
+    .bind(to)
+    .fetch_one(&mut *tx)
+    .await?;
+    if overlaps {
+        bail!("tax period overlaps an existing period");
+    }
+
+    let period = sqlx::query_as::<_, TaxPeriodDto>(&format!(
+        r#"
+        INSERT INTO tax_periods (id, tin, period_from, period_to)
+        VALUES ($1, $2, $3, $4)
+        RETURNING {TAX_PERIOD_COLUMNS}
+        "#
+    ))
+    .bind(Uuid::new_v4())
+    .bind(tin)
+    .bind(from)
+    .bind(to)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(period)
+}
+
+/// Files or locks a period. With `tin`, only that taxpayer's periods are
+/// found.
+#[instrument(skip(pool))]
+pub async fn transition_tax_period(
+    id: &Uuid,
+    tin: Option<&str>,
+    action: TaxPeriodAction,
+    pool: &PgPool,
+) -> anyhow::Result<TaxPeriodDto> {
+    let stamp = match action {
+        TaxPeriodAction::File => "filed_at",
+        TaxPeriodAction::Lock => "locked_at",
+    };
+    let updated = sqlx::query_as::<_, TaxPeriodDto>(&format!(
+        r#"
+        UPDATE tax_periods
+        SET status = $3, {stamp} = now()
+        WHERE id = $1 AND ($2::TEXT IS NULL OR tin = $2) AND status = $4
+        RETURNING {TAX_PERIOD_COLUMNS}
+        "#
+    ))
+    .bind(id)
+    .bind(tin)
+    .bind(action.next_status())
+    .bind(action.required_status())
+    .fetch_optional(pool)
+    .await?;
+    if let Some(period) = updated {
+        return Ok(period);
+    }
+
+    let status = sqlx::query_scalar::<_, String>(
+        "SELECT status FROM tax_periods WHERE id = $1 AND ($2::TEXT IS NULL OR tin = $2)",
+    )
+    .bind(id)
+    .bind(tin)
+    .fetch_optional(pool)
+    .await?;
+    match status {
+        None => bail!("tax period not found"),
+        Some(status) => bail!(
+            "tax period cannot become {} from {status}",
+            action.next_status()
+        ),
+    }
+}
+
+/// The period of `tin` covering `date`, if one is defined. The row is locked
+/// `FOR SHARE`, so the period cannot be filed or locked until the
+/// transaction that checked it ends.
+#[instrument(skip(conn))]
+pub async fn find_tax_period(
+    tin: &str,
+    date: NaiveDate,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<TaxPeriodBounds>> {
+    sqlx::query_as::<_, TaxPeriodBounds>(
+        r#"
+        SELECT status, period_from, period_to
+        FROM tax_periods
+        WHERE tin = $1 AND period_from <= $2 AND period_to >= $2
+        LIMIT 1
+        FOR SHARE
+        "#,
+    )
+    .bind(tin)
+    .bind(date)
+    .fetch_optional(conn)
+    .await
+    .context("failed to look up the tax period")
+}
+
+/// Issue date of the supplier's stored invoice with this number.
+#[instrument(skip(conn))]
+pub async fn fetch_invoice_issue_date(
+    supplier_tin: &str,
+    invoice_number: &str,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<NaiveDate>> {
+    let issue_date = sqlx::query_scalar::<_, Option<NaiveDate>>(
+        r#"
+        SELECT i.issue_date
+        FROM invoices i
+        INNER JOIN devices d ON d.device_uuid = i.device_id
+        WHERE d.tin = $1 AND i.invoice_number = $2
+        ORDER BY i.created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(supplier_tin)
+    .bind(invoice_number)
+    .fetch_optional(conn)
+    .await
+    .context("failed to look up the referenced invoice")?;
+    Ok(issue_date.flatten())
+}
@@ -1,5 +1,5 @@
 use anyhow::bail;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use tracing::instrument;
 
 #[instrument(skip(pool), fields(tin = %String::from_utf8_lossy(supplier_tin)))]
@@ -14,9 +14,12 @@ pub async fn verify_supplier_tin(supplier_tin: &[u8], pool: &PgPool) -> anyhow::
 
     Ok(())
 }
-#[instrument(skip(pool), fields(tin = %String::from_utf8_lossy(customer_tin)))]
-pub async fn verify_customer_tin(customer_tin: &[u8], pool: &PgPool) -> anyhow::Result<()> {
-    match check_customer_tin(customer_tin, pool).await {
+#[instrument(skip(conn), fields(tin = %String::from_utf8_lossy(customer_tin)))]
+pub async fn verify_customer_tin(
+    customer_tin: &[u8],
+    conn: &mut PgConnection,
+) -> anyhow::Result<()> {
+    match check_customer_tin(customer_tin, conn).await {
         Ok(b) => match b {
             true => {}
             false => bail!("invalid customer TIN"),
@@ -42,7 +45,7 @@ async fn check_supplier_tin(extracted_tin: &[u8], pool: &PgPool) -> anyhow::Resu
     .await?;
     Ok(exists)
 }
-async fn check_customer_tin(extracted_tin: &[u8], pool: &PgPool) -> anyhow::Result<bool> {
+async fn check_customer_tin(extracted_tin: &[u8], conn: &mut PgConnection) -> anyhow::Result<bool> {
     let exists = sqlx::query_scalar!(
         r#"
     SELECT EXISTS(
@@ -53,7 +56,7 @@ async fn check_customer_tin(extracted_tin: &[u8], pool: &PgPool) -> anyhow::Resu
     "#,
         std::str::from_utf8(extracted_tin)?
     )
-    .fetch_one(pool)
+    .fetch_one(conn)
     .await?;
     Ok(exists)
 }
@@ -0,0 +1,115 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::models::vat_return::{VatReturnLine, VatReturnTotals};
+
+/// Lines of the invoices a taxpayer issued (`output`) or received as the
+/// buyer (`input`) in the period, with credit notes negated.
+const PERIOD_LINES: &str = r#"
+    WITH period_lines AS (
+        SELECT 'output' AS direction, i.uuid, i.invoice_type_code, i.currency,
+               l.tax_category, l.tax_percent, l.net_amount, l.tax_amount
+        FROM invoice_lines l
+        INNER JOIN invoices i ON i.uuid = l.invoice_uuid
+        INNER JOIN devices d ON d.device_uuid = i.device_id
+        WHERE d.tin = $1 AND i.issue_date BETWEEN $2 AND $3
+
+        UNION ALL
+
+        SELECT 'input' AS direction, i.uuid, i.invoice_type_code, i.currency,
+               l.tax_category, l.tax_percent, l.net_amount, l.tax_amount
+        FROM invoice_lines l
+        INNER JOIN invoices i ON i.uuid = l.invoice_uuid
+        WHERE i.buyer_tin = $1 AND i.issue_date BETWEEN $2 AND $3
+    ),
+    signed_lines AS (
+        SELECT *, CASE WHEN invoice_type_code = '381' THEN -1 ELSE 1 END AS sign
+        FROM period_lines
+    )
+"#;
+
+#[derive(sqlx::FromRow)]
+struct DirectedLine {
+    direction: String,
+    #[sqlx(flatten)]
+    line: VatReturnLine,
+}
+
+/// Output and input lines per currency, tax category and rate.
+#[instrument(skip(pool))]
+pub async fn fetch_vat_return_lines(
+    tin: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    pool: &PgPool,
+) -> anyhow::Result<(Vec<VatReturnLine>, Vec<VatReturnLine>)> {
+    let query = format!(
+        r#"
+        {PERIOD_LINES}
+        SELECT
+            direction,
+            currency,
+            tax_category,
+            tax_percent::TEXT AS tax_percent,
+            COALESCE(SUM(sign * net_amount), 0)::TEXT AS taxable_amount,
+            COALESCE(SUM(sign * tax_amount), 0)::TEXT AS tax_amount,
+            COUNT(DISTINCT uuid) FILTER (WHERE COALESCE(invoice_type_code, '') NOT IN ('381', '383')) AS invoices,
+            COUNT(DISTINCT uuid) FILTER (WHERE invoice_type_code = '381') AS credit_notes,
+            COUNT(DISTINCT uuid) FILTER (WHERE invoice_type_code = '383') AS debit_notes
+        FROM signed_lines
+        GROUP BY direction, currency, tax_category, tax_percent
+        ORDER BY currency, tax_category, tax_percent
+        "#
+    );
+    let rows = sqlx::query_as::<_, DirectedLine>(&query)
+        .bind(tin)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+        .context("failed to fetch VAT return lines")?;
+
+    let (output, input): (Vec<_>, Vec<_>) =
+        rows.into_iter().partition(|row| row.direction == "output");
+    Ok((
+        output.into_iter().map(|row| row.line).collect(),
+        input.into_iter().map(|row| row.line).collect(),
+    ))
+}
+
+/// Output, input and net VAT per currency.
+#[instrument(skip(pool))]
+pub async fn fetch_vat_return_totals(
+    tin: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<VatReturnTotals>> {
+    let query = format!(
+        r#"
+        {PERIOD_LINES}
+        SELECT
+            currency,
+            COALESCE(SUM(sign * net_amount) FILTER (WHERE direction = 'output'), 0)::TEXT AS output_taxable_amount,
+            COALESCE(SUM(sign * tax_amount) FILTER (WHERE direction = 'output'), 0)::TEXT AS output_tax_amount,
+            COALESCE(SUM(sign * net_amount) FILTER (WHERE direction = 'input'), 0)::TEXT AS input_taxable_amount,
+            COALESCE(SUM(sign * tax_amount) FILTER (WHERE direction = 'input'), 0)::TEXT AS input_tax_amount,
+            (
+                COALESCE(SUM(sign * tax_amount) FILTER (WHERE direction = 'output'), 0)
+                - COALESCE(SUM(sign * tax_amount) FILTER (WHERE direction = 'input'), 0)
+            )::TEXT AS net_vat
+        FROM signed_lines
+        GROUP BY currency
+        ORDER BY currency
+        "#
+    );
+    sqlx::query_as::<_, VatReturnTotals>(&query)
+        .bind(tin)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+        .context("failed to fetch VAT return totals")
+}
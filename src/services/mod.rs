@@ -1,4 +1,6 @@
+pub mod cpu_pool;
 pub mod crypto;
 pub mod db;
+pub mod pdf;
 pub mod pipeline;
 pub mod xml;
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, anyhow};
+use pdf_writer::{
+    Filter, Finish, Name, Pdf, Rect, Ref, Str,
+    types::{CidFontType, FontFlags, SystemInfo, UnicodeCmap},
+};
+use subsetter::Profile;
+use ttf_parser::{Face, GlyphId};
+
+use crate::services::pdf::deflate;
+
+static DEJAVU_SANS: &[u8] =
+    include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/fonts/DejaVuSans.ttf"));
+
+/// Subset tag plus PostScript name, as PDF requires for subset fonts.
+const BASE_FONT: Name = Name(b"STCINV+DejaVuSans");
+
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+
+/// The embedded DejaVu Sans face. Text is encoded as glyph ids (Identity-H),
+/// and every glyph used is remembered so only those are embedded.
+pub struct Font {
+    face: Face<'static>,
+    units_per_em: f32,
+    /// Glyphs used so far, with the character each one shows.
+    used: BTreeMap<u16, char>,
+    /// Shown instead of characters the face has no glyph for: PDF/A does not
+    /// allow `.notdef` to be painted.
+    fallback: GlyphId,
+}
+
+impl Font {
+    pub fn dejavu_sans() -> anyhow::Result<Self> {
+        let face = Face::parse(DEJAVU_SANS, 0).context("failed to parse the embedded font")?;
+        let fallback = face
+            .glyph_index('?')
+            .context("embedded font has no '?' glyph")?;
+        Ok(Self {
+            units_per_em: f32::from(face.units_per_em()),
+            face,
+            used: BTreeMap::new(),
+            fallback,
+        })
+    }
+
+    /// Encodes `text` as big-endian glyph ids for a `Tj` string.
+    pub fn encode(&mut self, text: &str) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(text.len() * 2);
+        for ch in text.chars() {
+            let (glyph, shown) = self.glyph(ch);
+            self.used.entry(glyph.0).or_insert(shown);
+            encoded.extend_from_slice(&glyph.0.to_be_bytes());
+        }
+        encoded
+    }
+
+    /// Width of `text` set at `size` points.
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+        let units: f32 = text.chars().map(|ch| self.advance(self.glyph(ch).0)).sum();
+        units * size / 1000.0
+    }
+
+    /// `text`, cut with an ellipsis so it fits in `max_width` at `size`.
+    pub fn fit(&self, text: &str, size: f32, max_width: f32) -> String {
+        if self.width(text, size) <= max_width {
+            return text.to_owned();
+        }
+        let mut fitted = String::new();
+        for ch in text.chars() {
+            fitted.push(ch);
+            if self.width(&fitted, size) + self.width("…", size) > max_width {
+                fitted.pop();
+                break;
+            }
+        }
+        fitted.push('…');
+        fitted
+    }
+
+    /// Writes the font as a Type0 font at `font_ref` with its descendant
+    /// CIDFont, descriptor, subset program and ToUnicode map.
+    pub fn write(&self, pdf: &mut Pdf, font_ref: Ref, next_ref: &mut Ref) -> anyhow::Result<()> {
+        let cid_font_ref = next_ref.bump();
+        let descriptor_ref = next_ref.bump();
+        let program_ref = next_ref.bump();
+        let cmap_ref = next_ref.bump();
+
+        pdf.type0_font(font_ref)
+            .base_font(BASE_FONT)
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid_font_ref)
+            .to_unicode(cmap_ref);
+
+        let mut cid_font = pdf.cid_font(cid_font_ref);
+        cid_font
+            .subtype(CidFontType::Type2)
+            .base_font(BASE_FONT)
+            .system_info(SYSTEM_INFO)
+            .font_descriptor(descriptor_ref)
+            .default_width(0.0)
+            .cid_to_gid_map_predefined(Name(b"Identity"));
+        let mut widths = cid_font.widths();
+        for glyph in self.used.keys() {
+            widths.consecutive(*glyph, [self.advance(GlyphId(*glyph))]);
+        }
+        widths.finish();
+        cid_font.finish();
+
+        let bbox = self.face.global_bounding_box();
+        pdf.font_descriptor(descriptor_ref)
+            .name(BASE_FONT)
+            .flags(FontFlags::NON_SYMBOLIC)
+            .bbox(Rect::new(
+                self.scale(bbox.x_min),
+                self.scale(bbox.y_min),
+                self.scale(bbox.x_max),
+                self.scale(bbox.y_max),
+            ))
+            .italic_angle(0.0)
+            .ascent(self.scale(self.face.ascender()))
+            .descent(self.scale(self.face.descender()))
+            .cap_height(
+                self.scale(
+                    self.face
+                        .capital_height()
+                        .unwrap_or_else(|| self.face.ascender()),
+                ),
+            )
+            .stem_v(80.0)
+            .font_file2(program_ref);
+
+        let glyphs: Vec<u16> = self.used.keys().copied().collect();
+        let program = subsetter::subset(DEJAVU_SANS, 0, Profile::pdf(&glyphs))
+            .map_err(|e| anyhow!("failed to subset the embedded font: {e}"))?;
+        pdf.stream(program_ref, &deflate(&program))
+            .filter(Filter::FlateDecode)
+            .pair(Name(b"Length1"), program.len() as i32);
+
+        let mut cmap = UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+        for (glyph, ch) in &self.used {
+            cmap.pair(*glyph, *ch);
+        }
+        pdf.cmap(cmap_ref, &cmap.finish());
+        Ok(())
+    }
+
+    fn glyph(&self, ch: char) -> (GlyphId, char) {
+        match self.face.glyph_index(ch) {
+            Some(glyph) if glyph.0 != 0 => (glyph, ch),
+            _ => (self.fallback, '?'),
+        }
+    }
+
+    /// Advance width in text space units (1/1000 em).
+    fn advance(&self, glyph: GlyphId) -> f32 {
+        f32::from(self.face.glyph_hor_advance(glyph).unwrap_or(0)) * 1000.0 / self.units_per_em
+    }
+
+    fn scale(&self, units: i16) -> f32 {
+        f32::from(units) * 1000.0 / self.units_per_em
+    }
+}
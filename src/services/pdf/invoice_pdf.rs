@@ -0,0 +1,534 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use pdf_writer::{
+    Content, Date, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr, types::ColorSpaceOperand,
+};
+use xmp_writer::{Timezone, XmpWriter};
+
+use crate::services::{
+    pdf::{deflate, font::Font},
+    xml::{
+        invoice_document::{Address, Amount, InvoiceDocument, Party},
+        qr_image::{QUIET_ZONE, QrErrorCorrection, encode_qr},
+    },
+};
+
+/// A4 portrait, in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+const RIGHT: f32 = PAGE_WIDTH - MARGIN;
+/// Kept free at the bottom of every page for the footer.
+const FOOTER_HEIGHT: f32 = 30.0;
+const QR_SIZE: f32 = 110.0;
+
+/// Line table columns: left edges of the text columns, right edges of the
+/// numeric ones.
+const COLUMN_ID: f32 = MARGIN;
+const COLUMN_ITEM: f32 = MARGIN + 24.0;
+const COLUMN_ITEM_WIDTH: f32 = 190.0;
+const COLUMN_QUANTITY: f32 = 335.0;
+const COLUMN_PRICE: f32 = 400.0;
+const COLUMN_RATE: f32 = 440.0;
+const COLUMN_VAT: f32 = 490.0;
+const COLUMN_NET: f32 = RIGHT;
+const TOTALS_LABEL: f32 = 330.0;
+
+const FONT: Name = Name(b"F1");
+const GRAY: Name = Name(b"CS1");
+
+/// File name of the UBL invoice embedded in the PDF.
+pub const EMBEDDED_XML_NAME: &str = "invoice.xml";
+
+/// Renders a stored invoice as a PDF/A-3b document: supplier and buyer,
+/// invoice lines, totals and the QR code, with `invoice_xml` embedded
+/// unchanged as an associated file.
+///
+/// All text is set in an embedded DejaVu Sans subset, left to right and
+/// without shaping, and all colour is in a calibrated gray space, so the
+/// document needs no output intent.
+pub fn render_invoice_pdf(
+    invoice_xml: &[u8],
+    generated_at: DateTime<Utc>,
+) -> anyhow::Result<Vec<u8>> {
+    let document = InvoiceDocument::parse(invoice_xml)?;
+    let mut layout = Layout::new(Font::dejavu_sans()?);
+    layout.header(&document)?;
+    layout.parties(&document);
+    layout.lines(&document);
+    layout.totals(&document);
+    layout.footers();
+    write_pdf(&document, invoice_xml, layout, generated_at)
+}
+
+fn title(document: &InvoiceDocument) -> &'static str {
+    let simplified = document
+        .invoice_type_name
+        .as_deref()
+        .is_some_and(|name| name.starts_with("02"));
+    match (document.invoice_type_code.as_str(), simplified) {
+        ("381", _) => "Credit Note",
+        ("383", _) => "Debit Note",
+        (_, true) => "Simplified Tax Invoice",
+        _ => "Tax Invoice",
+    }
+}
+
+struct Layout {
+    font: Font,
+    pages: Vec<Content>,
+    /// Baseline of the last line written on the current page.
+    y: f32,
+}
+
+impl Layout {
+    fn new(font: Font) -> Self {
+        let mut layout = Self {
+            font,
+            pages: Vec::new(),
+            y: 0.0,
+        };
+        layout.new_page();
+        layout
+    }
+
+    fn new_page(&mut self) {
+        let mut content = Content::new();
+        content
+            .set_fill_color_space(ColorSpaceOperand::Named(GRAY))
+            .set_fill_color([0.0])
+            .set_stroke_color_space(ColorSpaceOperand::Named(GRAY))
+            .set_stroke_color([0.0]);
+        self.pages.push(content);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page unless `height` more points fit on this one.
+    /// Returns whether it did.
+    fn reserve(&mut self, height: f32) -> bool {
+        if self.y - height < MARGIN + FOOTER_HEIGHT {
+            self.new_page();
+            return true;
+        }
+        false
+    }
+
+    fn text_on(&mut self, page: usize, x: f32, y: f32, size: f32, text: &str) {
+        let encoded = self.font.encode(text);
+        self.pages[page]
+            .begin_text()
+            .set_font(FONT, size)
+            .next_line(x, y)
+            .show(Str(&encoded))
+            .end_text();
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        self.text_on(self.pages.len() - 1, x, y, size, text);
+    }
+
+    fn text_right(&mut self, right: f32, y: f32, size: f32, text: &str) {
+        let x = right - self.font.width(text, size);
+        self.text(x, y, size, text);
+    }
+
+    /// Moves the cursor down one line and writes `text` there.
+    fn line(&mut self, x: f32, size: f32, text: &str) {
+        self.y -= size * 1.4;
+        self.text(x, self.y, size, text);
+    }
+
+    fn rule(&mut self, y: f32) {
+        if let Some(content) = self.pages.last_mut() {
+            content
+                .set_line_width(0.5)
+                .move_to(MARGIN, y)
+                .line_to(RIGHT, y)
+                .stroke();
+        }
+    }
+
+    fn header(&mut self, document: &InvoiceDocument) -> anyhow::Result<()> {
+        let top = self.y;
+        self.qr(document.qr()?, RIGHT - QR_SIZE, top - QR_SIZE)?;
+
+        self.line(MARGIN, 18.0, title(document));
+        let status = match document.stamp()? {
+            Some(_) => "Cleared by the STC",
+            None => "Reported to the STC",
+        };
+        self.line(MARGIN, 9.0, status);
+        self.y -= 6.0;
+
+        let issued_at = format!("{} {}", document.issue_date, document.issue_time);
+        let icv = document.references.icv.clone().unwrap_or_default();
+        for (label, value) in [
+            ("Invoice number", document.id.as_str()),
+            ("UUID", document.uuid.as_str()),
+            ("Issue date", issued_at.trim()),
+            ("Currency", document.document_currency.as_str()),
+            ("Invoice counter (ICV)", icv.as_str()),
+        ] {
+            self.line(MARGIN, 9.0, label);
+            self.text(MARGIN + 110.0, self.y, 9.0, or_dash(value));
+        }
+        self.y = self.y.min(top - QR_SIZE) - 16.0;
+        Ok(())
+    }
+
+    /// Draws the QR symbol, quiet zone included, as a `QR_SIZE` square with
+    /// its lower-left corner at (`x`, `y`).
+    fn qr(&mut self, qr_b64: &str, x: f32, y: f32) -> anyhow::Result<()> {
+        let code = encode_qr(qr_b64, QrErrorCorrection::Medium)?;
+        let module = QR_SIZE / (code.size() + 2 * QUIET_ZONE) as f32;
+        let Some(content) = self.pages.last_mut() else {
+            return Ok(());
+        };
+        for row in 0..code.size() {
+            for column in 0..code.size() {
+                if code.get_module(column, row) {
+                    content.rect(
+                        x + (column + QUIET_ZONE) as f32 * module,
+                        y + QR_SIZE - (row + QUIET_ZONE + 1) as f32 * module,
+                        module,
+                        module,
+                    );
+                }
+            }
+        }
+        content.fill_nonzero();
+        Ok(())
+    }
+
+    fn parties(&mut self, document: &InvoiceDocument) {
+        let top = self.y;
+        let width = (RIGHT - MARGIN) / 2.0;
+        let seller_bottom = self.party(MARGIN, top, width, "Seller", &document.supplier);
+        let buyer_bottom = self.party(MARGIN + width, top, width, "Buyer", &document.customer);
+        self.y = seller_bottom.min(buyer_bottom) - 18.0;
+    }
+
+    /// Writes a party block from `top` down and returns where it ended.
+    fn party(&mut self, x: f32, top: f32, width: f32, heading: &str, party: &Party) -> f32 {
+        self.y = top;
+        self.line(x, 11.0, heading);
+        let mut rows = vec![
+            party
+                .registration_name
+                .clone()
+                .unwrap_or_else(|| "-".into()),
+            format!("TIN: {}", or_dash(&party.tin)),
+        ];
+        rows.extend(address_lines(&party.address));
+        for row in rows {
+            let row = self.font.fit(&row, 9.0, width - 12.0);
+            self.line(x, 9.0, &row);
+        }
+        self.y
+    }
+
+    fn lines(&mut self, document: &InvoiceDocument) {
+        self.reserve(48.0);
+        self.table_header();
+        for line in &document.lines {
+            if self.reserve(14.0) {
+                self.table_header();
+            }
+            self.y -= 13.0;
+            let y = self.y;
+            let id = self.font.fit(&line.id, 8.5, COLUMN_ITEM - COLUMN_ID - 4.0);
+            self.text(COLUMN_ID, y, 8.5, &id);
+            let item = self.font.fit(
+                line.item_name.as_deref().unwrap_or("-"),
+                8.5,
+                COLUMN_ITEM_WIDTH,
+            );
+            self.text(COLUMN_ITEM, y, 8.5, &item);
+            let quantity = [line.quantity.as_deref(), line.unit_code.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.text_right(COLUMN_QUANTITY, y, 8.5, or_dash(&quantity));
+            self.text_right(COLUMN_PRICE, y, 8.5, &amount_value(line.price.as_ref()));
+            let rate = line
+                .tax_percent
+                .as_deref()
+                .map_or_else(|| "-".to_owned(), |percent| format!("{percent}%"));
+            self.text_right(COLUMN_RATE, y, 8.5, &rate);
+            self.text_right(COLUMN_VAT, y, 8.5, &amount_value(line.tax_amount.as_ref()));
+            self.text_right(
+                COLUMN_NET,
+                y,
+                8.5,
+                &amount_value(line.line_extension.as_ref()),
+            );
+        }
+        self.rule(self.y - 5.0);
+        self.y -= 12.0;
+    }
+
+    fn table_header(&mut self) {
+        self.y -= 12.0;
+        let y = self.y;
+        self.text(COLUMN_ID, y, 8.0, "#");
+        self.text(COLUMN_ITEM, y, 8.0, "Item");
+        self.text_right(COLUMN_QUANTITY, y, 8.0, "Quantity");
+        self.text_right(COLUMN_PRICE, y, 8.0, "Unit price");
+        self.text_right(COLUMN_RATE, y, 8.0, "VAT rate");
+        self.text_right(COLUMN_VAT, y, 8.0, "VAT");
+        self.text_right(COLUMN_NET, y, 8.0, "Net amount");
+        self.rule(y - 5.0);
+        self.y -= 4.0;
+    }
+
+    fn totals(&mut self, document: &InvoiceDocument) {
+        let totals = &document.totals;
+        let mut rows: Vec<(&str, &Amount)> = [
+            ("Sum of line net amounts", totals.line_extension.as_ref()),
+            ("Allowances", totals.allowance_total.as_ref()),
+            ("Charges", totals.charge_total.as_ref()),
+            ("Total excluding VAT", totals.tax_exclusive.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(label, amount)| Some((label, amount?)))
+        .collect();
+        rows.extend(document.tax_totals.iter().map(|tax| ("VAT total", tax)));
+        rows.extend(
+            [
+                ("Total including VAT", totals.tax_inclusive.as_ref()),
+                ("Prepaid", totals.prepaid.as_ref()),
+                ("Amount payable", totals.payable.as_ref()),
+            ]
+            .into_iter()
+            .filter_map(|(label, amount)| Some((label, amount?))),
+        );
+
+        self.reserve(rows.len() as f32 * 14.0);
+        for (label, amount) in rows {
+            let size = if label == "Amount payable" { 11.0 } else { 9.5 };
+            self.y -= 14.0;
+            self.text(TOTALS_LABEL, self.y, size, label);
+            self.text_right(RIGHT, self.y, size, &amount_text(amount));
+        }
+    }
+
+    fn footers(&mut self) {
+        let count = self.pages.len();
+        let note =
+            format!("The original UBL invoice is embedded in this PDF as {EMBEDDED_XML_NAME}.");
+        for page in 0..count {
+            let number = format!("Page {} of {count}", page + 1);
+            let x = RIGHT - self.font.width(&number, 8.0);
+            self.text_on(page, MARGIN, MARGIN, 8.0, &note);
+            self.text_on(page, x, MARGIN, 8.0, &number);
+        }
+    }
+}
+
+fn write_pdf(
+    document: &InvoiceDocument,
+    invoice_xml: &[u8],
+    layout: Layout,
+    generated_at: DateTime<Utc>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut next_ref = Ref::new(1);
+    let catalog_ref = next_ref.bump();
+    let pages_ref = next_ref.bump();
+    let font_ref = next_ref.bump();
+    let gray_ref = next_ref.bump();
+    let metadata_ref = next_ref.bump();
+    let file_spec_ref = next_ref.bump();
+    let embedded_ref = next_ref.bump();
+    let page_refs: Vec<(Ref, Ref)> = layout
+        .pages
+        .iter()
+        .map(|_| (next_ref.bump(), next_ref.bump()))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    let file_id = openssl::sha::sha256(invoice_xml)[..16].to_vec();
+    pdf.set_file_id((file_id.clone(), file_id));
+
+    let mut catalog = pdf.catalog(catalog_ref);
+    catalog.pages(pages_ref).metadata(metadata_ref);
+    catalog
+        .names()
+        .embedded_files()
+        .names()
+        .insert(Str(EMBEDDED_XML_NAME.as_bytes()), file_spec_ref);
+    catalog.insert(Name(b"AF")).array().item(file_spec_ref);
+    catalog.finish();
+
+    pdf.pages(pages_ref)
+        .kids(page_refs.iter().map(|(page_ref, _)| *page_ref))
+        .count(page_refs.len() as i32);
+    for ((page_ref, content_ref), content) in page_refs.iter().zip(layout.pages) {
+        let mut page = pdf.page(*page_ref);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .parent(pages_ref)
+            .contents(*content_ref);
+        let mut resources = page.resources();
+        resources.fonts().pair(FONT, font_ref);
+        resources.color_spaces().pair(GRAY, gray_ref);
+        resources.finish();
+        page.finish();
+        pdf.stream(*content_ref, &deflate(&content.finish()))
+            .filter(Filter::FlateDecode);
+    }
+
+    layout.font.write(&mut pdf, font_ref, &mut next_ref)?;
+    // D65 white point with sRGB-like gamma.
+    pdf.color_space(gray_ref)
+        .cal_gray([0.9505, 1.0, 1.089], None, Some(2.2));
+
+    let mut file_spec = pdf.file_spec(file_spec_ref);
+    file_spec
+        .path(Str(EMBEDDED_XML_NAME.as_bytes()))
+        .unic_file(TextStr(EMBEDDED_XML_NAME))
+        .description(TextStr("UBL 2.1 invoice as cleared or reported"));
+    file_spec
+        .insert(Name(b"EF"))
+        .dict()
+        .pair(Name(b"F"), embedded_ref)
+        .pair(Name(b"UF"), embedded_ref);
+    file_spec.pair(Name(b"AFRelationship"), Name(b"Source"));
+    file_spec.finish();
+
+    let compressed_xml = deflate(invoice_xml);
+    let mut embedded = pdf.embedded_file(embedded_ref, &compressed_xml);
+    embedded.subtype(Name(b"application/xml"));
+    embedded.filter(Filter::FlateDecode);
+    embedded
+        .params()
+        .size(invoice_xml.len() as i32)
+        .modification_date(pdf_date(generated_at));
+    embedded.finish();
+
+    let title = format!("{} {}", title(document), document.id);
+    let mut xmp = XmpWriter::new();
+    xmp.title([(None, title.as_str())])
+        .format("application/pdf")
+        .creator_tool("STC Server")
+        .producer("STC Server")
+        .create_date(xmp_date(generated_at))
+        .modify_date(xmp_date(generated_at))
+        .pdfa_part("3")
+        .pdfa_conformance("B");
+    let xmp = xmp.finish(None);
+    pdf.metadata(metadata_ref, xmp.as_bytes());
+
+    Ok(pdf.finish())
+}
+
+fn address_lines(address: &Address) -> Vec<String> {
+    let join = |parts: [&Option<String>; 2]| {
+        parts
+            .into_iter()
+            .flatten()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    [
+        join([&address.building_number, &address.street_name]),
+        join([&address.postal_zone, &address.city_name]),
+        join([&address.country_code, &None]),
+    ]
+    .into_iter()
+    .filter(|line| !line.is_empty())
+    .collect()
+}
+
+fn amount_value(amount: Option<&Amount>) -> String {
+    amount.map_or_else(|| "-".to_owned(), |amount| amount.value.clone())
+}
+
+fn amount_text(amount: &Amount) -> String {
+    match &amount.currency {
+        Some(currency) => format!("{} {currency}", amount.value),
+        None => amount.value.clone(),
+    }
+}
+
+fn or_dash(value: &str) -> &str {
+    if value.trim().is_empty() { "-" } else { value }
+}
+
+fn pdf_date(at: DateTime<Utc>) -> Date {
+    Date::new(at.year() as u16)
+        .month(at.month() as u8)
+        .day(at.day() as u8)
+        .hour(at.hour() as u8)
+        .minute(at.minute() as u8)
+        .second(at.second() as u8)
+        .utc_offset_hour(0)
+}
+
+fn xmp_date(at: DateTime<Utc>) -> xmp_writer::DateTime {
+    xmp_writer::DateTime::new(
+        at.year() as u16,
+        at.month() as u8,
+        at.day() as u8,
+        at.hour() as u8,
+        at.minute() as u8,
+        at.second() as u8,
+        Timezone::Utc,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine, engine::general_purpose};
+
+    fn invoice(lines: usize) -> String {
+        let qr = general_purpose::STANDARD.encode([1, 3, b'A', b'B', b'C', 6, 2, 9, 9]);
+        let line = r#"<cac:InvoiceLine><cbc:ID>1</cbc:ID><cbc:InvoicedQuantity unitCode="PCE">2</cbc:InvoicedQuantity><cbc:LineExtensionAmount currencyID="SDG">3000.00</cbc:LineExtensionAmount><cac:TaxTotal><cbc:TaxAmount currencyID="SDG">450.00</cbc:TaxAmount></cac:TaxTotal><cac:Item><cbc:Name>Laptop</cbc:Name><cac:ClassifiedTaxCategory><cbc:ID>S</cbc:ID><cbc:Percent>15</cbc:Percent></cac:ClassifiedTaxCategory></cac:Item><cac:Price><cbc:PriceAmount currencyID="SDG">1500.00</cbc:PriceAmount></cac:Price></cac:InvoiceLine>"#;
+        format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2"><cbc:ID>S003</cbc:ID><cbc:UUID>b17f3393-232f-43c3-8448-38d8c09b04df</cbc:UUID><cbc:IssueDate>2024-03-01</cbc:IssueDate><cbc:IssueTime>14:40:40</cbc:IssueTime><cbc:InvoiceTypeCode name="0200000">388</cbc:InvoiceTypeCode><cbc:DocumentCurrencyCode>SDG</cbc:DocumentCurrencyCode><cac:AdditionalDocumentReference><cbc:ID>QR</cbc:ID><cac:Attachment><cbc:EmbeddedDocumentBinaryObject mimeCode="text/plain">{qr}</cbc:EmbeddedDocumentBinaryObject></cac:Attachment></cac:AdditionalDocumentReference><cac:AccountingSupplierParty><cac:Party><cac:PostalAddress><cbc:StreetName>Baladyia st</cbc:StreetName><cbc:CityName>الخرطوم</cbc:CityName></cac:PostalAddress><cac:PartyTaxScheme><cbc:CompanyID>123456789</cbc:CompanyID></cac:PartyTaxScheme><cac:PartyLegalEntity><cbc:RegistrationName>Smith &amp; Sons</cbc:RegistrationName></cac:PartyLegalEntity></cac:Party></cac:AccountingSupplierParty>{}<cac:TaxTotal><cbc:TaxAmount currencyID="SDG">450.00</cbc:TaxAmount></cac:TaxTotal><cac:LegalMonetaryTotal><cbc:PayableAmount currencyID="SDG">3450.00</cbc:PayableAmount></cac:LegalMonetaryTotal></Invoice>"#,
+            line.repeat(lines)
+        )
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn renders_a_pdf_a3_with_the_invoice_embedded() {
+        let xml = invoice(1);
+        let pdf = render_invoice_pdf(xml.as_bytes(), Utc::now()).unwrap();
+        assert!(pdf.starts_with(b"%PDF-1.7"));
+        assert!(contains(&pdf, b"<pdfaid:part>3</pdfaid:part>"));
+        assert!(contains(
+            &pdf,
+            b"<pdfaid:conformance>B</pdfaid:conformance>"
+        ));
+        assert!(contains(&pdf, b"/Type /EmbeddedFile"));
+        assert!(contains(&pdf, b"/Subtype /application#2Fxml"));
+        assert!(contains(&pdf, b"/AFRelationship /Source"));
+        assert!(contains(&pdf, b"/FontFile2"));
+        assert!(contains(&pdf, b"Simplified Tax Invoice S003"));
+        assert!(!contains(&pdf, b"/DeviceGray"));
+
+        let embedded = miniz_oxide::inflate::decompress_to_vec_zlib(&deflate(xml.as_bytes()));
+        assert_eq!(embedded.unwrap(), xml.as_bytes());
+        assert!(contains(&pdf, &deflate(xml.as_bytes())));
+    }
+
+    #[test]
+    fn long_invoices_flow_onto_more_pages() {
+        let pdf = render_invoice_pdf(invoice(120).as_bytes(), Utc::now()).unwrap();
+        assert!(contains(&pdf, b"/Count 3"));
+    }
+
+    #[test]
+    fn rejects_invoices_without_a_qr() {
+        let xml = invoice(1).replace("<cbc:ID>QR</cbc:ID>", "<cbc:ID>XX</cbc:ID>");
+        let err = render_invoice_pdf(xml.as_bytes(), Utc::now()).unwrap_err();
+        assert!(err.to_string().contains("QR not found"));
+    }
+}
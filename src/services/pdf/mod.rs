@@ -0,0 +1,7 @@
+pub mod font;
+pub mod invoice_pdf;
+
+/// zlib-compresses stream data for `/FlateDecode`.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
@@ -0,0 +1,190 @@
+use anyhow::anyhow;
+use fastxml::{XmlContext, XmlDocument, XmlNode};
+
+use crate::{
+    config::business_rules::{BusinessRule, RuleSet, Severity},
+    models::responses::ValidationIssue,
+    services::{pipeline::validation_service::ValidationIssues, xml::xpath::holds_at},
+};
+
+/// Runs the rule set over the parsed invoice. Failed `error` rules reject it
+/// with every failure and its XPath; failed `warning` rules are returned so
+/// the invoice can be flagged.
+pub fn check_business_rules(
+    xml: &XmlDocument,
+    rules: &RuleSet,
+) -> anyhow::Result<Vec<ValidationIssue>> {
+    let (errors, warnings): (Vec<_>, Vec<_>) = evaluate_rules(rules, xml)?
+        .into_iter()
+        .partition(|(rule, _)| rule.severity == Severity::Error);
+    let issues = |failures: Vec<(&BusinessRule, String)>| {
+        failures
+            .into_iter()
+            .map(|(rule, xpath)| ValidationIssue {
+                code: rule.code.clone(),
+                message: rule.message.clone(),
+                xpath,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if errors.is_empty() {
+        Ok(issues(warnings))
+    } else {
+        Err(ValidationIssues {
+            summary: "business rule violation",
+            issues: issues(errors),
+            rules_version: Some(rules.version.clone()),
+        }
+        .into())
+    }
+}
+
+/// Evaluates every rule on each of its context nodes and returns the failed
+/// ones with the XPath of the node.
+fn evaluate_rules<'a>(
+    rules: &'a RuleSet,
+    xml: &XmlDocument,
+) -> anyhow::Result<Vec<(&'a BusinessRule, String)>> {
+    let context = xpath_context(rules, xml)?;
+    let mut failures = Vec::new();
+    for rule in &rules.rules {
+        for node in context_nodes(&context, rule)? {
+            if !holds(&context, rule, &node)? {
+                failures.push((rule, xpath_of(&node)));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Evaluates every context, and every assertion on the root element, so
+/// XPaths that do not parse or use undeclared prefixes are caught before any
+/// invoice matches them.
+pub fn compile_rules(rules: &RuleSet, xml: &XmlDocument) -> anyhow::Result<()> {
+    let context = xpath_context(rules, xml)?;
+    let root = context.find_nodes("/*")?;
+    for rule in &rules.rules {
+        context_nodes(&context, rule)?;
+        for node in &root {
+            holds(&context, rule, node)?;
+        }
+    }
+    Ok(())
+}
+
+fn xpath_context(rules: &RuleSet, xml: &XmlDocument) -> anyhow::Result<XmlContext> {
+    let mut context = fastxml::create_context(xml)?;
+    for (prefix, uri) in &rules.namespaces {
+        context.register_namespace(prefix, uri)?;
+    }
+    Ok(context)
+}
+
+fn context_nodes(context: &XmlContext, rule: &BusinessRule) -> anyhow::Result<Vec<XmlNode>> {
+    context
+        .find_nodes(&rule.context)
+        .map_err(|e| anyhow!("rule {} has an invalid context: {e}", rule.code))
+}
+
+fn holds(context: &XmlContext, rule: &BusinessRule, node: &XmlNode) -> anyhow::Result<bool> {
+    holds_at(context, &rule.assert, node)
+        .map_err(|e| anyhow!("rule {} has an invalid assertion: {e}", rule.code))
+}
+
+/// Location of an element as `/Invoice/cac:InvoiceLine[2]/cbc:ID`, with a
+/// position only where siblings share the name.
+fn xpath_of(node: &XmlNode) -> String {
+    let mut steps = Vec::new();
+    let mut current = Some(node.clone());
+    while let Some(node) = current.filter(XmlNode::is_element) {
+        let parent = node.get_parent();
+        let step = match &parent {
+            None => node.get_name(),
+            Some(parent) => {
+                let name = node.qname();
+                let namesakes: Vec<XmlNode> = parent
+                    .get_child_elements()
+                    .into_iter()
+                    .filter(|sibling| sibling.qname() == name)
+                    .collect();
+                match namesakes.iter().position(|sibling| *sibling == node) {
+                    Some(index) if namesakes.len() > 1 => format!("{name}[{}]", index + 1),
+                    _ => name,
+                }
+            }
+        };
+        steps.push(step);
+        current = parent;
+    }
+    steps.reverse();
+    format!("/{}", steps.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(type_code: &str, second_item: &str, city: &str) -> XmlDocument {
+        let xml = format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:ID>S003</cbc:ID>
+  <cbc:IssueDate>2026-03-18</cbc:IssueDate>
+  <cbc:InvoiceTypeCode name="0100000">{type_code}</cbc:InvoiceTypeCode>
+  <cac:AccountingSupplierParty><cac:Party>
+    <cac:PartyLegalEntity><cbc:RegistrationName>My Supplier</cbc:RegistrationName></cac:PartyLegalEntity>
+    <cac:PostalAddress><cbc:CityName>{city}</cbc:CityName></cac:PostalAddress>
+  </cac:Party></cac:AccountingSupplierParty>
+  <cac:LegalMonetaryTotal><cbc:PayableAmount currencyID="SDG">3450.00</cbc:PayableAmount></cac:LegalMonetaryTotal>
+  <cac:InvoiceLine><cbc:ID>1</cbc:ID><cbc:InvoicedQuantity unitCode="PCE">2</cbc:InvoicedQuantity><cac:Item><cbc:Name>Laptop</cbc:Name></cac:Item></cac:InvoiceLine>
+  <cac:InvoiceLine><cbc:ID>2</cbc:ID><cbc:InvoicedQuantity unitCode="PCE">1</cbc:InvoicedQuantity><cac:Item><cbc:Name>{second_item}</cbc:Name></cac:Item></cac:InvoiceLine>
+</Invoice>"#
+        );
+        fastxml::parse(xml.as_str()).unwrap()
+    }
+
+    #[test]
+    fn accepts_invoices_meeting_the_embedded_rules() {
+        let warnings =
+            check_business_rules(&invoice("388", "Mouse", "Khartoum"), &RuleSet::default());
+        assert_eq!(warnings.unwrap(), []);
+        let sample = std::fs::read_to_string("invoice.xml").unwrap();
+        let sample = fastxml::parse(sample.as_str()).unwrap();
+        assert_eq!(
+            check_business_rules(&sample, &RuleSet::default()).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn reports_every_failed_rule_with_its_xpath() {
+        let err = check_business_rules(&invoice("381", " ", "Khartoum"), &RuleSet::default())
+            .unwrap_err();
+        let failed = err.downcast::<ValidationIssues>().unwrap();
+        assert_eq!(failed.rules_version, Some(RuleSet::default().version));
+        let issues = failed.issues;
+        let located: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|issue| (issue.code.as_str(), issue.xpath.as_str()))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                ("SD-BR-06", "/Invoice"),
+                ("SD-BR-25", "/Invoice/cac:InvoiceLine[2]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_warnings_do_not_reject() {
+        let warnings =
+            check_business_rules(&invoice("388", "Mouse", ""), &RuleSet::default()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "SD-BR-W01");
+        assert_eq!(
+            warnings[0].xpath,
+            "/Invoice/cac:AccountingSupplierParty/cac:Party"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use actix_web::web::Data;
 use base64::{Engine, engine::general_purpose};
 use fastxml::schema::CompiledSchema;
@@ -5,21 +7,30 @@ use sqlx::PgPool;
 use tracing::instrument;
 
 use crate::{
-    config::crypto_config::Crypto,
+    config::{
+        crypto_config::Crypto, signature_config::SignatureAlgorithms, tsa_config::Tsa,
+        validation_config::ValidationPolicy,
+    },
     models::submit_invoice::{IntermediateInvoiceDto, InvoiceType},
     services::{
+        cpu_pool::CpuPool,
         db::device_service::fetch_device_for_update,
         db::icv_service::{update_icv_and_pih, verify_icv},
+        db::invoice_lines::save_invoice_lines,
+        db::invoice_metadata::InvoiceMetadata,
+        db::number_series_service::{fetch_number_series_for_update, update_number_series},
         db::pih_service::verify_pih,
         db::save_invoice::save_invoice,
         pipeline::clear_invoice::clear_invoice,
-        pipeline::validation_service::validate_invoice,
-        xml::extractors::extract_icv,
+        pipeline::validation_service::{
+            ValidatedInvoice, validate_invoice, verify_invoice_number, verify_tax_period,
+        },
     },
 };
 
+#[allow(clippy::too_many_arguments)]
 #[instrument(
-    skip(db_pool, crypto, schema, intermediate),
+    skip(db_pool, crypto, tsa, schema, algorithms, policy, intermediate, cpu),
     fields(
         uuid = %intermediate.uuid,
         device_uuid = %intermediate.device.device_uuid,
@@ -31,16 +42,39 @@ use crate::{
 pub async fn process_clearance(
     intermediate: IntermediateInvoiceDto,
     db_pool: &PgPool,
-    crypto: &Crypto,
+    crypto: &Data<Crypto>,
+    tsa: &Data<Tsa>,
     sandbox: bool,
     schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    policy: &ValidationPolicy,
     invoice_type: InvoiceType,
+    cpu: &CpuPool,
 ) -> anyhow::Result<String> {
+    let intermediate = Arc::new(intermediate);
+
     // Run shared pipeline
-    let hash = validate_invoice(&intermediate, db_pool, crypto, schema, invoice_type).await?;
+    let ValidatedInvoice { hash, mut flags } = validate_invoice(
+        &intermediate,
+        db_pool,
+        crypto,
+        sandbox,
+        schema,
+        algorithms,
+        policy,
+        invoice_type,
+        cpu,
+    )
+    .await?;
 
     // Clearance-specific logic: Stamping
-    let (hash, cleared_invoice_bytes) = clear_invoice(&intermediate, crypto, hash)?;
+    let (hash, cleared_invoice_bytes) = {
+        let intermediate = Arc::clone(&intermediate);
+        let crypto = crypto.clone();
+        let tsa = tsa.clone();
+        cpu.run(move || clear_invoice(&intermediate, &crypto, &tsa, hash))
+            .await?
+    };
 
     // Store it
     if !sandbox {
@@ -50,11 +84,21 @@ pub async fn process_clearance(
         let device = fetch_device_for_update(&intermediate.device.device_uuid, &mut tx).await?;
 
         // Verify ICV
-        let icv = extract_icv(&intermediate.invoice_bytes)?;
+        let icv = intermediate.document.icv()?;
         verify_icv(icv, device.current_icv)?;
 
         // Verify PIH against the locked device row.
-        verify_pih(&intermediate.invoice_bytes, &device.last_pih)?;
+        verify_pih(&intermediate.document, &device.last_pih)?;
+
+        // Verify the invoice number against the device's number series.
+        let series = fetch_number_series_for_update(&device.device_uuid, &mut tx).await?;
+        if let Some(series) = &series {
+            series.verify(&intermediate.document.id)?;
+        }
+
+        // Check the tax period and the invoice number under the device lock.
+        flags.extend(verify_tax_period(&intermediate, &mut tx).await?);
+        flags.extend(verify_invoice_number(&intermediate, policy, &mut tx).await?);
 
         // Update ICV and PIH
         update_icv_and_pih(
@@ -64,6 +108,9 @@ pub async fn process_clearance(
             hash.clone(),
         )
         .await?;
+        if let Some(series) = series {
+            update_number_series(&mut tx, &device.device_uuid, series.next_number + 1).await?;
+        }
 
         // Save invoice
         save_invoice(
@@ -72,9 +119,13 @@ pub async fn process_clearance(
             &intermediate.uuid,
             hash,
             &device.device_uuid,
+            &intermediate.supplier,
             InvoiceType::Clearance,
+            &InvoiceMetadata::from_document(&intermediate.document),
+            &flags,
         )
         .await?;
+        save_invoice_lines(&mut tx, &intermediate.uuid, &intermediate.document.lines).await?;
 
         tx.commit().await?;
     }
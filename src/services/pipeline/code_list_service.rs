@@ -0,0 +1,149 @@
+use crate::{
+    config::code_lists::{CodeList, CodeLists},
+    models::responses::ValidationIssue,
+    services::{
+        pipeline::validation_service::ValidationIssues, xml::invoice_document::InvoiceDocument,
+    },
+};
+
+/// Checks the coded values the schema only types as strings: currencies,
+/// country codes, line units, the invoice type and payment means. Every value
+/// outside its list is reported with its XPath; absent values are left to
+/// the schema.
+pub fn check_code_lists(document: &InvoiceDocument, lists: &CodeLists) -> anyhow::Result<()> {
+    let mut issues = Vec::new();
+    let mut check = |list: CodeList, value: &str, xpath: String| {
+        let value = value.trim();
+        if !value.is_empty() && !lists.contains(list, value) {
+            issues.push(ValidationIssue {
+                code: list.issue_code().to_owned(),
+                message: format!("{value:?} is not in the {}", lists.title(list)),
+                xpath,
+            });
+        }
+    };
+
+    check(
+        CodeList::DocumentType,
+        &document.invoice_type_code,
+        "/Invoice/cbc:InvoiceTypeCode".to_owned(),
+    );
+    check(
+        CodeList::Currency,
+        &document.document_currency,
+        "/Invoice/cbc:DocumentCurrencyCode".to_owned(),
+    );
+    if let Some(currency) = &document.tax_currency {
+        check(
+            CodeList::Currency,
+            currency,
+            "/Invoice/cbc:TaxCurrencyCode".to_owned(),
+        );
+    }
+    for (party, element) in [
+        (&document.supplier, "AccountingSupplierParty"),
+        (&document.customer, "AccountingCustomerParty"),
+    ] {
+        if let Some(country) = &party.address.country_code {
+            check(
+                CodeList::Country,
+                country,
+                format!(
+                    "/Invoice/cac:{element}/cac:Party/cac:PostalAddress/cac:Country/cbc:IdentificationCode"
+                ),
+            );
+        }
+    }
+    for (index, code) in document.payment_means_codes.iter().enumerate() {
+        check(
+            CodeList::PaymentMeans,
+            code,
+            format!(
+                "/Invoice/cac:PaymentMeans[{}]/cbc:PaymentMeansCode",
+                index + 1
+            ),
+        );
+    }
+    for (index, line) in document.lines.iter().enumerate() {
+        if let Some(unit) = &line.unit_code {
+            check(
+                CodeList::Unit,
+                unit,
+                format!(
+                    "/Invoice/cac:InvoiceLine[{}]/cbc:InvoicedQuantity/@unitCode",
+                    index + 1
+                ),
+            );
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationIssues {
+            summary: "code list violation",
+            issues,
+            rules_version: None,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(currency: &str, country: &str, unit: &str, type_code: &str) -> InvoiceDocument {
+        let xml = format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:InvoiceTypeCode name="0100000">{type_code}</cbc:InvoiceTypeCode>
+  <cbc:DocumentCurrencyCode>{currency}</cbc:DocumentCurrencyCode>
+  <cbc:TaxCurrencyCode>SDG</cbc:TaxCurrencyCode>
+  <cac:AccountingSupplierParty><cac:Party><cac:PostalAddress><cac:Country><cbc:IdentificationCode>{country}</cbc:IdentificationCode></cac:Country></cac:PostalAddress></cac:Party></cac:AccountingSupplierParty>
+  <cac:PaymentMeans><cbc:PaymentMeansCode>10</cbc:PaymentMeansCode></cac:PaymentMeans>
+  <cac:InvoiceLine><cbc:ID>1</cbc:ID><cbc:InvoicedQuantity unitCode="H87">1</cbc:InvoicedQuantity></cac:InvoiceLine>
+  <cac:InvoiceLine><cbc:ID>2</cbc:ID><cbc:InvoicedQuantity unitCode="{unit}">1</cbc:InvoicedQuantity></cac:InvoiceLine>
+</Invoice>"#
+        );
+        InvoiceDocument::parse(xml.as_bytes()).unwrap()
+    }
+
+    fn issues(document: &InvoiceDocument) -> Vec<ValidationIssue> {
+        let err = check_code_lists(document, &CodeLists::default()).unwrap_err();
+        err.downcast::<ValidationIssues>().unwrap().issues
+    }
+
+    #[test]
+    fn accepts_listed_codes() {
+        let document = invoice("USD", "SD", "KGM", "381");
+        check_code_lists(&document, &CodeLists::default()).unwrap();
+    }
+
+    #[test]
+    fn reports_every_unlisted_code_with_its_xpath() {
+        let issues = issues(&invoice("SDD", "XX", "BOX", "999"));
+        let located: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|issue| (issue.code.as_str(), issue.xpath.as_str()))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                ("unknown_document_type_code", "/Invoice/cbc:InvoiceTypeCode"),
+                ("unknown_currency_code", "/Invoice/cbc:DocumentCurrencyCode"),
+                (
+                    "unknown_country_code",
+                    "/Invoice/cac:AccountingSupplierParty/cac:Party/cac:PostalAddress/cac:Country/cbc:IdentificationCode"
+                ),
+                (
+                    "unknown_unit_code",
+                    "/Invoice/cac:InvoiceLine[2]/cbc:InvoicedQuantity/@unitCode"
+                ),
+            ]
+        );
+        assert_eq!(
+            issues[1].message,
+            "\"SDD\" is not in the ISO 4217 currency codes"
+        );
+    }
+}
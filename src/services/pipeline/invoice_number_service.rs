@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    config::validation_config::{DuplicateInvoiceNumbers, ValidationPolicy},
+    models::submit_invoice::InvoiceFlag,
+    services::{
+        db::invoice_lookup::{find_invoice_by_number, lock_invoice_numbers},
+        xml::invoice_document::InvoiceDocument,
+    },
+};
+
+/// Checks that the supplier has not stored another invoice with the same
+/// `cbc:ID` and `cbc:InvoiceTypeCode`, from any of its devices. A duplicate
+/// is rejected or flagged as the policy says. Invoices without a number are
+/// left to the business rules.
+///
+/// Takes the supplier's invoice number lock, so it must run in the
+/// transaction that saves the invoice; the unique index on
+/// `invoices (supplier_tin, invoice_number, invoice_type_code)` backs it up.
+pub async fn check_invoice_number(
+    document: &InvoiceDocument,
+    supplier_tin: &str,
+    uuid: &Uuid,
+    policy: &ValidationPolicy,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let Some((number, type_code)) = invoice_number_key(document) else {
+        return Ok(None);
+    };
+    lock_invoice_numbers(supplier_tin, conn).await?;
+    let existing = find_invoice_by_number(supplier_tin, &number, &type_code, uuid, conn).await?;
+    duplicate_outcome(
+        existing,
+        &number,
+        &type_code,
+        policy.duplicate_invoice_numbers,
+    )
+}
+
+/// Like [`check_invoice_number`], against the invoices a sandbox batch has
+/// accepted so far, which are never stored. `accepted` maps each
+/// [`invoice_number_key`] to the invoice holding it.
+pub fn check_batch_invoice_number(
+    document: &InvoiceDocument,
+    accepted: &HashMap<(String, String), Uuid>,
+    policy: &ValidationPolicy,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let Some(key) = invoice_number_key(document) else {
+        return Ok(None);
+    };
+    duplicate_outcome(
+        accepted.get(&key).copied(),
+        &key.0,
+        &key.1,
+        policy.duplicate_invoice_numbers,
+    )
+}
+
+/// The invoice number and document type code a supplier may use once, or
+/// `None` for an invoice without a number.
+pub fn invoice_number_key(document: &InvoiceDocument) -> Option<(String, String)> {
+    let number = document.id.trim();
+    (!number.is_empty()).then(|| {
+        (
+            number.to_owned(),
+            document.invoice_type_code.trim().to_owned(),
+        )
+    })
+}
+
+fn duplicate_outcome(
+    existing: Option<Uuid>,
+    number: &str,
+    type_code: &str,
+    action: DuplicateInvoiceNumbers,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    match (existing, action) {
+        (None, _) => Ok(None),
+        (Some(_), DuplicateInvoiceNumbers::Warn) => Ok(Some(InvoiceFlag::DuplicateInvoiceNumber)),
+        (Some(existing), DuplicateInvoiceNumbers::Reject) => bail!(
+            "duplicate invoice number: {number} (type {type_code}) is already used by invoice {existing}"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_are_rejected_or_flagged() {
+        let existing = Some(Uuid::nil());
+        for action in [
+            DuplicateInvoiceNumbers::Reject,
+            DuplicateInvoiceNumbers::Warn,
+        ] {
+            assert_eq!(
+                duplicate_outcome(None, "S003", "388", action).unwrap(),
+                None
+            );
+        }
+        assert_eq!(
+            duplicate_outcome(existing, "S003", "388", DuplicateInvoiceNumbers::Warn).unwrap(),
+            Some(InvoiceFlag::DuplicateInvoiceNumber)
+        );
+        let err = duplicate_outcome(existing, "S003", "388", DuplicateInvoiceNumbers::Reject)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("duplicate invoice number: S003")
+        );
+    }
+
+    #[test]
+    fn batch_duplicates_match_number_and_type() {
+        let xml = r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:ID> S003 </cbc:ID>
+  <cbc:InvoiceTypeCode name="0200000">388</cbc:InvoiceTypeCode>
+</Invoice>"#;
+        let document = InvoiceDocument::parse(xml.as_bytes()).unwrap();
+        let key = invoice_number_key(&document).unwrap();
+        assert_eq!(key, ("S003".to_owned(), "388".to_owned()));
+
+        let policy = ValidationPolicy::default();
+        let mut accepted = HashMap::new();
+        assert_eq!(
+            check_batch_invoice_number(&document, &accepted, &policy).unwrap(),
+            None
+        );
+        accepted.insert(("S003".to_owned(), "381".to_owned()), Uuid::nil());
+        assert_eq!(
+            check_batch_invoice_number(&document, &accepted, &policy).unwrap(),
+            None
+        );
+        accepted.insert(key, Uuid::nil());
+        assert!(check_batch_invoice_number(&document, &accepted, &policy).is_err());
+    }
+}
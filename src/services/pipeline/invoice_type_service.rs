@@ -1,12 +1,14 @@
 use anyhow::bail;
 
-use crate::{models::submit_invoice::InvoiceType, services::xml::extractors::extract_profile_id};
+use crate::{
+    models::submit_invoice::InvoiceType, services::xml::invoice_document::InvoiceDocument,
+};
 
 pub fn verify_invoice_type(
-    invoice_bytes: &[u8],
+    document: &InvoiceDocument,
     invoice_type: &InvoiceType,
 ) -> anyhow::Result<bool> {
-    let ex_invoice_type = match extract_profile_id(invoice_bytes) {
+    let ex_invoice_type = match document.profile_id() {
         Ok(invoice_id) => {
             if invoice_id.contains("reporting") {
                 InvoiceType::Reporting
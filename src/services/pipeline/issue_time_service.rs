@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::{
+    config::validation_config::ValidationPolicy,
+    models::submit_invoice::{InvoiceFlag, InvoiceType},
+    services::xml::invoice_document::InvoiceDocument,
+};
+
+/// Checks `IssueDate`/`IssueTime` against the submission time. No invoice
+/// may be dated further ahead than the clock skew allows; clearance invoices
+/// must be submitted within `clearance_max_age`. Reporting invoices received
+/// after `reporting_window` are accepted with a [`InvoiceFlag::LateReport`].
+pub fn check_issue_time(
+    document: &InvoiceDocument,
+    received_at: OffsetDateTime,
+    invoice_type: InvoiceType,
+    policy: &ValidationPolicy,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let (issued_at, offset_assumed) = parse_issued_at(
+        &document.issue_date,
+        &document.issue_time,
+        policy.default_issue_offset,
+    )
+    .ok_or_else(|| {
+        anyhow!(
+            "issue date or time unreadable: {} {}",
+            document.issue_date.trim(),
+            document.issue_time.trim()
+        )
+    })?;
+    if offset_assumed {
+        info!(
+            uuid = %document.uuid,
+            issue_time = %document.issue_time.trim(),
+            offset = %policy.default_issue_offset,
+            "IssueTime has no offset; read in the default issue offset"
+        );
+    }
+    let received_at =
+        DateTime::<Utc>::from_timestamp(received_at.unix_timestamp(), received_at.nanosecond())
+            .ok_or_else(|| anyhow!("submission time out of range"))?;
+
+    assess(issued_at, received_at, invoice_type, policy)
+}
+
+fn assess(
+    issued_at: DateTime<Utc>,
+    received_at: DateTime<Utc>,
+    invoice_type: InvoiceType,
+    policy: &ValidationPolicy,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let age = received_at - issued_at;
+    if -age > policy.max_clock_skew {
+        bail!(
+            "issued in the future: issued at {issued_at}, received at {received_at}, allowed skew {}s",
+            policy.max_clock_skew.num_seconds()
+        );
+    }
+
+    match invoice_type {
+        InvoiceType::Clearance if age > policy.clearance_max_age => bail!(
+            "clearance submission late: issued at {issued_at}, received at {received_at}, allowed {} minutes",
+            policy.clearance_max_age.num_minutes()
+        ),
+        InvoiceType::Reporting if age > policy.reporting_window => {
+            Ok(Some(InvoiceFlag::LateReport {
+                late_by_seconds: (age - policy.reporting_window).num_seconds(),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `IssueDate` and `IssueTime` (`HH:MM:SS`, optional fractional seconds and
+/// `Z` or `±HH:MM`) as an instant, and whether the offset was assumed: a time
+/// without offset is read in `default_offset`.
+fn parse_issued_at(
+    date: &str,
+    time: &str,
+    default_offset: FixedOffset,
+) -> Option<(DateTime<Utc>, bool)> {
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()?;
+    let time = time.trim();
+    let (local, offset, assumed) = if let Some(local) = time.strip_suffix('Z') {
+        (local, FixedOffset::east_opt(0)?, false)
+    } else {
+        match time.get(8..).and_then(|rest| rest.find(['+', '-'])) {
+            Some(index) => {
+                let (local, offset) = time.split_at(8 + index);
+                (local, FixedOffset::from_str(offset).ok()?, false)
+            }
+            None => (time, default_offset, true),
+        }
+    };
+    let time = NaiveTime::parse_from_str(local, "%H:%M:%S%.f").ok()?;
+    offset
+        .from_local_datetime(&date.and_time(time))
+        .single()
+        .map(|issued_at| (issued_at.with_timezone(&Utc), assumed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn reads_issue_time_offsets() {
+        let sudan = FixedOffset::east_opt(2 * 3600).unwrap();
+        let expected = at("2026-03-01T10:00:00Z");
+        assert_eq!(
+            parse_issued_at("2026-03-01", "10:00:00Z", sudan),
+            Some((expected, false))
+        );
+        assert_eq!(
+            parse_issued_at("2026-03-01", "12:00:00", sudan),
+            Some((expected, true))
+        );
+        assert_eq!(
+            parse_issued_at("2026-03-01", "13:00:00.000+03:00", sudan),
+            Some((expected, false))
+        );
+        assert_eq!(parse_issued_at("2026-03-01", "", sudan), None);
+        assert_eq!(parse_issued_at("01/03/2026", "10:00:00Z", sudan), None);
+    }
+
+    #[test]
+    fn clearance_must_be_recent_and_not_in_the_future() {
+        let policy = ValidationPolicy::default();
+        let received = at("2026-03-01T10:00:00Z");
+        let clearance = |issued| assess(at(issued), received, InvoiceType::Clearance, &policy);
+
+        assert_eq!(clearance("2026-03-01T09:50:00Z").unwrap(), None);
+        assert_eq!(clearance("2026-03-01T10:04:00Z").unwrap(), None);
+        let err = clearance("2026-03-01T10:06:00Z").unwrap_err();
+        assert!(err.to_string().starts_with("issued in the future"));
+        let err = clearance("2026-03-01T09:40:00Z").unwrap_err();
+        assert!(err.to_string().starts_with("clearance submission late"));
+    }
+
+    #[test]
+    fn offset_less_time_is_checked_in_the_default_offset() {
+        let policy = ValidationPolicy::default();
+        let received = at("2026-03-01T10:00:00Z");
+        let clearance = |time| {
+            let (issued_at, assumed) =
+                parse_issued_at("2026-03-01", time, policy.default_issue_offset).unwrap();
+            assert!(assumed);
+            assess(issued_at, received, InvoiceType::Clearance, &policy)
+        };
+
+        // 12:05 and 12:06 in UTC+02:00 are 10:05 and 10:06 UTC.
+        assert_eq!(clearance("12:05:00").unwrap(), None);
+        let err = clearance("12:06:00").unwrap_err();
+        assert!(err.to_string().starts_with("issued in the future"));
+    }
+
+    #[test]
+    fn late_reports_are_flagged_with_their_lateness() {
+        let policy = ValidationPolicy::default();
+        let received = at("2026-03-02T12:00:00Z");
+        let report = |issued| assess(at(issued), received, InvoiceType::Reporting, &policy);
+
+        assert_eq!(report("2026-03-01T12:30:00Z").unwrap(), None);
+        assert_eq!(
+            report("2026-03-01T10:00:00Z").unwrap(),
+            Some(InvoiceFlag::LateReport {
+                late_by_seconds: 2 * 3600
+            })
+        );
+    }
+}
@@ -1,7 +1,17 @@
+pub mod business_rule_service;
 pub mod clear_invoice;
 pub mod clearance_service;
+pub mod code_list_service;
 pub mod enrollment_service;
+pub mod invoice_number_service;
 pub mod invoice_type_service;
+pub mod issue_time_service;
 pub mod onboarding_service;
+pub mod qr_service;
+pub mod receipt_service;
 pub mod reporting_service;
+pub mod tax_category_service;
+pub mod tax_currency_service;
+pub mod tax_period_service;
 pub mod validation_service;
+pub mod vat_return_service;
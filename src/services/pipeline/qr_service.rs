@@ -0,0 +1,212 @@
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose};
+
+use crate::services::xml::{
+    edit_tlv::{
+        TAG_INVOICE_HASH, TAG_INVOICE_TOTAL, TAG_SELLER_NAME, TAG_SELLER_TIN, TAG_TIMESTAMP,
+        TAG_VAT_TOTAL, extract_records,
+    },
+    invoice_document::InvoiceDocument,
+};
+
+/// Cross-checks the QR TLV of `document` against the invoice body: seller
+/// name, seller TIN, timestamp, total with VAT and VAT total in the document
+/// currency (tags 1-5), and the invoice hash (tag 6) against `invoice_hash`.
+pub fn verify_qr_content(document: &InvoiceDocument, invoice_hash: &[u8]) -> anyhow::Result<()> {
+    let tlv_bytes = general_purpose::STANDARD
+        .decode(document.qr()?)
+        .context("invoice QR is malformed")?;
+    let records = extract_records(&tlv_bytes).context("invoice QR is malformed")?;
+    let qr = QrRecords(records);
+
+    let seller_name = document
+        .supplier
+        .registration_name
+        .as_deref()
+        .context("QR mismatch: invoice has no supplier RegistrationName")?;
+    if qr.text(TAG_SELLER_NAME, "seller name")?.trim() != seller_name.trim() {
+        bail!("QR mismatch: tag 1 does not match the supplier RegistrationName");
+    }
+
+    if qr.text(TAG_SELLER_TIN, "seller TIN")?.trim() != document.supplier.tin {
+        bail!("QR mismatch: tag 2 does not match the supplier CompanyID");
+    }
+
+    let issued_at = format!(
+        "{}T{}",
+        document.issue_date.trim(),
+        without_utc_suffix(document.issue_time.trim())
+    );
+    if without_utc_suffix(qr.text(TAG_TIMESTAMP, "timestamp")?.trim()) != issued_at {
+        bail!("QR mismatch: tag 3 does not match IssueDate and IssueTime");
+    }
+
+    let tax_inclusive = document
+        .totals
+        .tax_inclusive
+        .as_ref()
+        .context("QR mismatch: invoice has no TaxInclusiveAmount")?;
+    if !same_amount(
+        qr.text(TAG_INVOICE_TOTAL, "invoice total")?,
+        &tax_inclusive.value,
+    ) {
+        bail!("QR mismatch: tag 4 does not match the TaxInclusiveAmount");
+    }
+
+    let vat_total = document
+        .document_tax_total()
+        .context("QR mismatch: invoice has no TaxAmount in the document currency")?;
+    if !same_amount(qr.text(TAG_VAT_TOTAL, "VAT total")?, &vat_total.value) {
+        bail!("QR mismatch: tag 5 does not match the TaxAmount in the document currency");
+    }
+
+    let qr_hash = qr.value(TAG_INVOICE_HASH, "invoice hash")?;
+    if qr_hash.len() != invoice_hash.len() || !openssl::memcmp::eq(qr_hash, invoice_hash) {
+        bail!("QR mismatch: tag 6 is not the invoice hash");
+    }
+
+    Ok(())
+}
+
+struct QrRecords(Vec<(u8, Vec<u8>)>);
+
+impl QrRecords {
+    fn value(&self, tag: u8, name: &str) -> anyhow::Result<&[u8]> {
+        self.0
+            .iter()
+            .find(|(record_tag, _)| *record_tag == tag)
+            .map(|(_, value)| value.as_slice())
+            .with_context(|| format!("QR mismatch: missing tag {tag} ({name})"))
+    }
+
+    fn text(&self, tag: u8, name: &str) -> anyhow::Result<&str> {
+        std::str::from_utf8(self.value(tag, name)?).context("invoice QR is malformed")
+    }
+}
+
+fn without_utc_suffix(timestamp: &str) -> &str {
+    timestamp.strip_suffix('Z').unwrap_or(timestamp)
+}
+
+/// Compares decimal amounts as written, ignoring trailing fractional zeros,
+/// so `1150`, `1150.0` and `1150.00` are the same amount.
+fn same_amount(left: &str, right: &str) -> bool {
+    fn normalize(amount: &str) -> &str {
+        let amount = amount.trim();
+        if amount.contains('.') {
+            amount.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            amount
+        }
+    }
+    normalize(left) == normalize(right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::xml::invoice_document::{Amount, Party};
+
+    const HASH: [u8; 4] = [1, 2, 3, 4];
+
+    fn tlv(records: &[(u8, &[u8])]) -> String {
+        let mut bytes = Vec::new();
+        for (tag, value) in records {
+            bytes.push(*tag);
+            bytes.push(value.len() as u8);
+            bytes.extend_from_slice(value);
+        }
+        general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn document(qr: String) -> InvoiceDocument {
+        let mut document = InvoiceDocument {
+            document_currency: "SDG".to_owned(),
+            issue_date: "2024-03-01".to_owned(),
+            issue_time: "14:40:40".to_owned(),
+            supplier: Party {
+                tin: "123456789".to_owned(),
+                registration_name: Some("Smith & Sons".to_owned()),
+                ..Default::default()
+            },
+            tax_totals: vec![Amount {
+                value: "150.00".to_owned(),
+                currency: Some("SDG".to_owned()),
+            }],
+            ..Default::default()
+        };
+        document.totals.tax_inclusive = Some(Amount {
+            value: "1150.00".to_owned(),
+            currency: Some("SDG".to_owned()),
+        });
+        document.references.qr = Some(qr);
+        document
+    }
+
+    fn qr(total: &[u8]) -> String {
+        tlv(&[
+            (1, b"Smith & Sons"),
+            (2, b"123456789"),
+            (3, b"2024-03-01T14:40:40Z"),
+            (4, total),
+            (5, b"150"),
+            (6, &HASH),
+            (7, b"sig"),
+            (8, b"cert"),
+        ])
+    }
+
+    #[test]
+    fn accepts_a_qr_matching_the_invoice() {
+        verify_qr_content(&document(qr(b"1150.0")), &HASH).unwrap();
+    }
+
+    #[test]
+    fn rejects_fields_that_disagree_with_the_invoice() {
+        let err = verify_qr_content(&document(qr(b"1000.00")), &HASH).unwrap_err();
+        assert!(err.to_string().contains("tag 4"));
+
+        let err = verify_qr_content(&document(qr(b"1150.00")), &[9; 4]).unwrap_err();
+        assert!(err.to_string().contains("tag 6"));
+
+        let missing_vat = tlv(&[
+            (1, b"Smith & Sons"),
+            (2, b"123456789"),
+            (3, b"2024-03-01T14:40:40"),
+            (4, b"1150.00"),
+        ]);
+        let err = verify_qr_content(&document(missing_vat), &HASH).unwrap_err();
+        assert!(err.to_string().contains("missing tag 5"));
+    }
+
+    #[test]
+    fn vat_total_is_the_tax_total_in_the_document_currency() {
+        // A USD invoice with its TaxTotal converted to SDG: tag 5 must carry
+        // the USD amount, not the SDG one.
+        let usd_tax = |qr| {
+            let mut document = document(qr);
+            document.document_currency = "USD".to_owned();
+            document.tax_currency = Some("SDG".to_owned());
+            document.tax_totals.insert(
+                0,
+                Amount {
+                    value: "3.00".to_owned(),
+                    currency: Some("USD".to_owned()),
+                },
+            );
+            document
+        };
+        let err = verify_qr_content(&usd_tax(qr(b"1150.00")), &HASH).unwrap_err();
+        assert!(err.to_string().contains("tag 5"));
+
+        let usd_qr = tlv(&[
+            (1, b"Smith & Sons"),
+            (2, b"123456789"),
+            (3, b"2024-03-01T14:40:40"),
+            (4, b"1150.00"),
+            (5, b"3"),
+            (6, &HASH),
+        ]);
+        verify_qr_content(&usd_tax(usd_qr), &HASH).unwrap();
+    }
+}
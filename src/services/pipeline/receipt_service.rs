@@ -0,0 +1,57 @@
+use actix_web::web::Data;
+use base64::{Engine, engine::general_purpose};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use uuid::Uuid;
+
+use crate::{
+    config::crypto_config::Crypto,
+    models::receipt::{InvoiceReceipt, InvoiceReceiptDto},
+    services::{cpu_pool::CpuPool, crypto::receipt_signing::sign_receipt},
+};
+
+pub struct ReceiptFacts {
+    pub invoice_uuid: Uuid,
+    pub invoice_hash: Vec<u8>,
+    pub device_uuid: Uuid,
+    pub icv: i32,
+    pub received_at: OffsetDateTime,
+    pub accepted_at: OffsetDateTime,
+}
+
+/// Builds and signs the acknowledgment receipt for an accepted invoice.
+pub fn issue_receipt(facts: &ReceiptFacts, crypto: &Crypto) -> anyhow::Result<InvoiceReceiptDto> {
+    let receipt = InvoiceReceipt {
+        invoice_uuid: facts.invoice_uuid.to_string(),
+        invoice_hash: general_purpose::STANDARD.encode(&facts.invoice_hash),
+        device_uuid: facts.device_uuid.to_string(),
+        icv: facts.icv,
+        received_at: receipt_timestamp(facts.received_at)?,
+        accepted_at: receipt_timestamp(facts.accepted_at)?,
+    };
+    let signed_receipt = sign_receipt(&receipt, crypto)?;
+    Ok(InvoiceReceiptDto {
+        receipt,
+        signed_receipt,
+        rules_version: None,
+    })
+}
+
+/// Runs [`issue_receipt`] on the CPU pool, keeping RSA signing off the executor.
+pub async fn issue_receipt_on_pool(
+    facts: ReceiptFacts,
+    crypto: &Data<Crypto>,
+    cpu: &CpuPool,
+) -> anyhow::Result<InvoiceReceiptDto> {
+    let crypto = crypto.clone();
+    cpu.run(move || issue_receipt(&facts, &crypto)).await
+}
+
+/// Current UTC time truncated to whole seconds, matching the stored receipt precision.
+pub fn receipt_now() -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    now.replace_nanosecond(0).unwrap_or(now)
+}
+
+fn receipt_timestamp(value: OffsetDateTime) -> anyhow::Result<String> {
+    Ok(value.format(&Rfc3339)?)
+}
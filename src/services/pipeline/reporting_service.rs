@@ -1,23 +1,118 @@
+use std::{collections::HashMap, sync::Arc};
+
 use actix_web::web::Data;
 use fastxml::schema::CompiledSchema;
-use sqlx::PgPool;
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use time::OffsetDateTime;
 use tracing::instrument;
+use uuid::Uuid;
 
 use crate::{
-    config::crypto_config::Crypto,
-    models::submit_invoice::{IntermediateInvoiceDto, InvoiceType},
+    config::{
+        crypto_config::Crypto, signature_config::SignatureAlgorithms,
+        validation_config::ValidationPolicy,
+    },
+    models::{
+        device::Device,
+        receipt::InvoiceReceiptDto,
+        submit_invoice::{IntermediateInvoiceDto, InvoiceFlag, InvoiceType},
+    },
     services::{
-        db::device_service::fetch_device_for_update,
+        cpu_pool::CpuPool,
+        db::device_service::{fetch_device, fetch_device_for_update},
         db::icv_service::{update_icv_and_pih, verify_icv},
+        db::invoice_lines::save_invoice_lines,
+        db::invoice_metadata::InvoiceMetadata,
+        db::number_series_service::{
+            NumberSeries, fetch_number_series, fetch_number_series_for_update, update_number_series,
+        },
         db::pih_service::verify_pih,
+        db::receipt_service::{ReceiptRecord, save_receipt},
         db::save_invoice::save_invoice,
-        pipeline::validation_service::validate_invoice,
-        xml::extractors::extract_icv,
+        pipeline::invoice_number_service::{check_batch_invoice_number, invoice_number_key},
+        pipeline::receipt_service::{ReceiptFacts, issue_receipt_on_pool, receipt_now},
+        pipeline::validation_service::{
+            ValidatedInvoice, validate_invoice, verify_invoice_number, verify_tax_period,
+        },
+        xml::invoice_document::InvoiceDocument,
     },
 };
 
+/// Result of a batch reporting pass: receipts for the accepted prefix and,
+/// if the pass stopped early, the failing invoice.
+pub struct BatchReportOutcome {
+    pub receipts: Vec<InvoiceReceiptDto>,
+    pub failure: Option<BatchFailure>,
+    /// ICV the device chain expects next after the accepted prefix.
+    pub next_icv: i32,
+}
+
+pub struct BatchFailure {
+    pub index: usize,
+    pub error: anyhow::Error,
+}
+
+/// Chain position of a device, advanced in memory as invoices are accepted.
+struct ChainHead {
+    device_uuid: Uuid,
+    current_icv: i32,
+    last_pih: Vec<u8>,
+    /// Gapless invoice numbering, when enforced for the device.
+    number_series: Option<NumberSeries>,
+    /// Invoice numbers accepted in this pass, with the invoice holding each.
+    accepted_numbers: HashMap<(String, String), Uuid>,
+}
+
+impl ChainHead {
+    fn new(device: Device, number_series: Option<NumberSeries>) -> Self {
+        Self {
+            device_uuid: device.device_uuid,
+            current_icv: device.current_icv,
+            last_pih: device.last_pih,
+            number_series,
+            accepted_numbers: HashMap::new(),
+        }
+    }
+
+    fn verify_next(&self, icv: i32, document: &InvoiceDocument) -> anyhow::Result<()> {
+        verify_icv(icv, self.current_icv)?;
+        verify_pih(document, &self.last_pih)?;
+        if let Some(series) = &self.number_series {
+            series.verify(&document.id)?;
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, uuid: Uuid, document: &InvoiceDocument, hash: Vec<u8>) {
+        self.current_icv += 1;
+        self.last_pih = hash;
+        if let Some(series) = &mut self.number_series {
+            series.next_number += 1;
+        }
+        if let Some(key) = invoice_number_key(document) {
+            self.accepted_numbers.entry(key).or_insert(uuid);
+        }
+    }
+
+    /// Writes the advanced head back to the locked device.
+    async fn store(&self, tx: &mut Transaction<'_, Postgres>) -> anyhow::Result<()> {
+        update_icv_and_pih(
+            tx,
+            &self.device_uuid,
+            self.current_icv,
+            self.last_pih.clone(),
+        )
+        .await?;
+        if let Some(series) = &self.number_series {
+            update_number_series(tx, &self.device_uuid, series.next_number).await?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 #[instrument(
-    skip(db_pool, crypto, schema, intermediate),
+    skip(db_pool, crypto, schema, algorithms, policy, intermediate, cpu),
     fields(
         uuid = %intermediate.uuid,
         device_uuid = %intermediate.device.device_uuid,
@@ -29,50 +124,322 @@ use crate::{
 pub async fn process_reporting(
     intermediate: IntermediateInvoiceDto,
     db_pool: &PgPool,
-    crypto: &Crypto,
+    crypto: &Data<Crypto>,
     sandbox: bool,
     schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    policy: &ValidationPolicy,
     invoice_type: InvoiceType,
-) -> anyhow::Result<()> {
+    cpu: &CpuPool,
+) -> anyhow::Result<InvoiceReceiptDto> {
+    let intermediate = Arc::new(intermediate);
+
     // Run shared pipeline
-    let hash = validate_invoice(&intermediate, db_pool, crypto, schema, invoice_type).await?;
+    let mut validated = validate_invoice(
+        &intermediate,
+        db_pool,
+        crypto,
+        sandbox,
+        schema,
+        algorithms,
+        policy,
+        invoice_type,
+        cpu,
+    )
+    .await?;
+    let icv = intermediate.document.icv()?;
+
+    // Sign the receipt before locking the device, so the chain lock is never
+    // held while waiting for the CPU pool. It is only returned once the
+    // invoice is committed.
+    let receipt = issue_report_receipt(&intermediate, &validated.hash, icv, crypto, cpu).await?;
+
+    if sandbox {
+        return Ok(receipt.receipt);
+    }
 
     // Store the raw invoice directly
-    if !sandbox {
-        let mut tx = db_pool.begin().await?;
+    let mut tx = db_pool.begin().await?;
 
-        // Fetch device with lock to prevent race conditions
-        let device = fetch_device_for_update(&intermediate.device.device_uuid, &mut tx).await?;
+    // Fetch device with lock to prevent race conditions
+    let device_uuid = intermediate.device.device_uuid;
+    let device = fetch_device_for_update(&device_uuid, &mut tx).await?;
+    let mut head = ChainHead::new(
+        device,
+        fetch_number_series_for_update(&device_uuid, &mut tx).await?,
+    );
 
-        // Verify ICV hasn't changed since validation
-        let icv = extract_icv(&intermediate.invoice_bytes)?;
-        verify_icv(icv, device.current_icv)?;
+    // Verify ICV, PIH, tax period and invoice number against the locked
+    // device row.
+    head.verify_next(icv, &intermediate.document)?;
+    validated
+        .flags
+        .extend(verify_tax_period(&intermediate, &mut tx).await?);
+    validated
+        .flags
+        .extend(verify_invoice_number(&intermediate, policy, &mut tx).await?);
 
-        // Verify PIH against the locked device row.
-        verify_pih(&intermediate.invoice_bytes, &device.last_pih)?;
+    store_reported_invoice(&mut tx, &intermediate, &validated, icv, &receipt).await?;
 
-        // Update ICV and PIH
-        update_icv_and_pih(
-            &mut tx,
-            &device.device_uuid,
-            device.current_icv + 1,
-            hash.clone(),
-        )
-        .await?;
+    // Update ICV, PIH and the number series
+    head.advance(intermediate.uuid, &intermediate.document, validated.hash);
+    head.store(&mut tx).await?;
 
-        // Save invoice
-        save_invoice(
-            &mut tx,
-            &intermediate.invoice_bytes,
-            &intermediate.uuid,
-            hash,
-            &device.device_uuid,
-            InvoiceType::Reporting,
-        )
-        .await?;
+    tx.commit().await?;
 
-        tx.commit().await?;
+    Ok(receipt.receipt)
+}
+
+/// Reports an ordered run of invoices from one device in a single locked pass
+/// over its chain. Stops at the first rejected invoice; everything before it
+/// is committed (in production) and the device chain is advanced once.
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip(invoices, db_pool, crypto, schema, algorithms, policy, cpu),
+    fields(count = invoices.len(), sandbox)
+)]
+pub async fn process_reporting_batch(
+    invoices: Vec<IntermediateInvoiceDto>,
+    db_pool: &PgPool,
+    crypto: &Data<Crypto>,
+    sandbox: bool,
+    schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    policy: &ValidationPolicy,
+    cpu: &CpuPool,
+) -> anyhow::Result<BatchReportOutcome> {
+    let Some(first) = invoices.first() else {
+        anyhow::bail!("batch contains no invoices");
+    };
+    let device_uuid = first.device.device_uuid;
+
+    let mut receipts = Vec::with_capacity(invoices.len());
+    let mut failure = None;
+
+    if sandbox {
+        // Dry run against the stored chain head without persisting anything.
+        let mut head = ChainHead::new(
+            fetch_device(&device_uuid, db_pool).await?,
+            fetch_number_series(&device_uuid, db_pool).await?,
+        );
+        for (index, intermediate) in invoices.into_iter().enumerate() {
+            let intermediate = Arc::new(intermediate);
+            let result = async {
+                let (validated, icv) = validate_batch_item(
+                    &intermediate,
+                    &head,
+                    db_pool,
+                    crypto,
+                    sandbox,
+                    schema.clone(),
+                    algorithms,
+                    policy,
+                    cpu,
+                )
+                .await?;
+                let receipt =
+                    issue_report_receipt(&intermediate, &validated.hash, icv, crypto, cpu).await?;
+                anyhow::Ok((receipt.receipt, validated.hash))
+            }
+            .await;
+            match result {
+                Ok((receipt, hash)) => {
+                    head.advance(intermediate.uuid, &intermediate.document, hash);
+                    receipts.push(receipt);
+                }
+                Err(error) => {
+                    failure = Some(BatchFailure { index, error });
+                    break;
+                }
+            }
+        }
+        return Ok(BatchReportOutcome {
+            receipts,
+            failure,
+            next_icv: head.current_icv + 1,
+        });
     }
 
-    Ok(())
+    let mut tx = db_pool.begin().await?;
+
+    // Lock the device row once for the whole batch.
+    let device = fetch_device_for_update(&device_uuid, &mut tx).await?;
+    let mut head = ChainHead::new(
+        device,
+        fetch_number_series_for_update(&device_uuid, &mut tx).await?,
+    );
+
+    for (index, intermediate) in invoices.into_iter().enumerate() {
+        let intermediate = Arc::new(intermediate);
+        // Each invoice runs in its own savepoint so a failed insert does not
+        // poison the accepted prefix.
+        let mut savepoint = tx.begin().await?;
+        let result = async {
+            // Validated in the savepoint so the stateful checks see the
+            // invoices saved earlier in the batch.
+            let (mut validated, icv) = validate_batch_item(
+                &intermediate,
+                &head,
+                &mut savepoint,
+                crypto,
+                sandbox,
+                schema.clone(),
+                algorithms,
+                policy,
+                cpu,
+            )
+            .await?;
+            validated
+                .flags
+                .extend(verify_tax_period(&intermediate, &mut savepoint).await?);
+            validated
+                .flags
+                .extend(verify_invoice_number(&intermediate, policy, &mut savepoint).await?);
+            let receipt =
+                issue_report_receipt(&intermediate, &validated.hash, icv, crypto, cpu).await?;
+            store_reported_invoice(&mut savepoint, &intermediate, &validated, icv, &receipt)
+                .await?;
+            anyhow::Ok((receipt.receipt, validated.hash))
+        }
+        .await;
+        match result {
+            Ok((receipt, hash)) => {
+                savepoint.commit().await?;
+                head.advance(intermediate.uuid, &intermediate.document, hash);
+                receipts.push(receipt);
+            }
+            Err(error) => {
+                savepoint.rollback().await?;
+                failure = Some(BatchFailure { index, error });
+                break;
+            }
+        }
+    }
+
+    if !receipts.is_empty() {
+        head.store(&mut tx).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(BatchReportOutcome {
+        receipts,
+        failure,
+        next_icv: head.current_icv + 1,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn validate_batch_item<'c>(
+    intermediate: &Arc<IntermediateInvoiceDto>,
+    head: &ChainHead,
+    db: impl Acquire<'c, Database = Postgres>,
+    crypto: &Data<Crypto>,
+    sandbox: bool,
+    schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    policy: &ValidationPolicy,
+    cpu: &CpuPool,
+) -> anyhow::Result<(ValidatedInvoice, i32)> {
+    if intermediate.device.device_uuid != head.device_uuid {
+        anyhow::bail!("batch device mismatch: all invoices must come from the same device");
+    }
+    let mut validated = validate_invoice(
+        intermediate,
+        db,
+        crypto,
+        sandbox,
+        schema,
+        algorithms,
+        policy,
+        InvoiceType::Reporting,
+        cpu,
+    )
+    .await?;
+    // The dry run stores nothing, so earlier invoices of a sandbox batch are
+    // only known to the chain head.
+    if sandbox
+        && !validated
+            .flags
+            .contains(&InvoiceFlag::DuplicateInvoiceNumber)
+    {
+        validated.flags.extend(check_batch_invoice_number(
+            &intermediate.document,
+            &head.accepted_numbers,
+            policy,
+        )?);
+    }
+    let icv = intermediate.document.icv()?;
+    head.verify_next(icv, &intermediate.document)?;
+    Ok((validated, icv))
+}
+
+/// A signed receipt and the acceptance time it carries.
+struct IssuedReceipt {
+    receipt: InvoiceReceiptDto,
+    accepted_at: OffsetDateTime,
+}
+
+/// Signs the acknowledgment receipt of a validated reporting invoice.
+async fn issue_report_receipt(
+    intermediate: &IntermediateInvoiceDto,
+    hash: &[u8],
+    icv: i32,
+    crypto: &Data<Crypto>,
+    cpu: &CpuPool,
+) -> anyhow::Result<IssuedReceipt> {
+    let accepted_at = receipt_now();
+    let receipt = issue_receipt_on_pool(
+        ReceiptFacts {
+            invoice_uuid: intermediate.uuid,
+            invoice_hash: hash.to_vec(),
+            device_uuid: intermediate.device.device_uuid,
+            icv,
+            received_at: intermediate.received_at,
+            accepted_at,
+        },
+        crypto,
+        cpu,
+    )
+    .await?;
+    Ok(IssuedReceipt {
+        receipt,
+        accepted_at,
+    })
+}
+
+/// Saves an accepted reporting invoice together with its signed receipt.
+async fn store_reported_invoice(
+    tx: &mut Transaction<'_, Postgres>,
+    intermediate: &IntermediateInvoiceDto,
+    validated: &ValidatedInvoice,
+    icv: i32,
+    receipt: &IssuedReceipt,
+) -> anyhow::Result<()> {
+    save_invoice(
+        tx,
+        &intermediate.invoice_bytes,
+        &intermediate.uuid,
+        validated.hash.clone(),
+        &intermediate.device.device_uuid,
+        &intermediate.supplier,
+        InvoiceType::Reporting,
+        &InvoiceMetadata::from_document(&intermediate.document),
+        &validated.flags,
+    )
+    .await?;
+    save_invoice_lines(tx, &intermediate.uuid, &intermediate.document.lines).await?;
+    save_receipt(
+        tx,
+        ReceiptRecord {
+            invoice_uuid: &intermediate.uuid,
+            device_id: &intermediate.device.device_uuid,
+            icv,
+            invoice_hash: &validated.hash,
+            received_at: intermediate.received_at,
+            accepted_at: receipt.accepted_at,
+            signed_receipt: &receipt.receipt.signed_receipt,
+        },
+    )
+    .await
 }
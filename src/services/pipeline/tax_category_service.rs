@@ -0,0 +1,213 @@
+use anyhow::bail;
+use chrono::NaiveDate;
+
+use crate::{
+    config::tax_registry::{TaxRegistry, normalize_percent},
+    services::xml::{
+        decimal::decimal,
+        invoice_document::{Amount, InvoiceDocument},
+    },
+};
+
+/// Rounding allowed between a tax amount and its taxable amount times the
+/// rate, per invoice line.
+const ROUNDING_TOLERANCE: f64 = 0.01;
+
+/// Where a tax category appears and what it declares.
+struct Declared<'a> {
+    place: String,
+    category: Option<&'a str>,
+    percent: Option<&'a str>,
+    exemption_reason_code: Option<&'a str>,
+    /// Subtotals must name the exemption of a zero-rated or exempt supply;
+    /// lines may.
+    exemption_required: bool,
+    taxable_amount: Option<&'a Amount>,
+    tax_amount: Option<&'a Amount>,
+    /// Allowed difference between `tax_amount` and the computed tax.
+    tolerance: f64,
+}
+
+/// Checks every line's `ClassifiedTaxCategory`, which each line must have,
+/// and every document-level `TaxSubtotal` against the registry in force on
+/// the `IssueDate`: the category must exist, its percent must be a rate valid on that day, the
+/// exemption reason code must be one the category allows, and the tax
+/// amount must match the rate.
+pub fn check_tax_categories(
+    document: &InvoiceDocument,
+    registry: &TaxRegistry,
+) -> anyhow::Result<()> {
+    let issue_date = NaiveDate::parse_from_str(document.issue_date.trim(), "%Y-%m-%d").ok();
+
+    for line in &document.lines {
+        if line.tax_category.is_none() {
+            bail!(
+                "unknown tax category: line {} has no ClassifiedTaxCategory",
+                line.id
+            );
+        }
+        check(
+            registry,
+            issue_date,
+            Declared {
+                place: format!("line {}", line.id),
+                category: line.tax_category.as_deref(),
+                percent: line.tax_percent.as_deref(),
+                exemption_reason_code: line.tax_exemption_reason_code.as_deref(),
+                exemption_required: false,
+                taxable_amount: line.line_extension.as_ref(),
+                tax_amount: line.tax_amount.as_ref(),
+                tolerance: ROUNDING_TOLERANCE,
+            },
+        )?;
+    }
+
+    // Line taxes are rounded one by one, so a subtotal may drift by up to one
+    // rounding step per line.
+    let subtotal_tolerance = ROUNDING_TOLERANCE * document.lines.len().max(1) as f64;
+    for (index, subtotal) in document.tax_subtotals.iter().enumerate() {
+        check(
+            registry,
+            issue_date,
+            Declared {
+                place: format!("tax subtotal {}", index + 1),
+                category: subtotal.category.as_deref(),
+                percent: subtotal.percent.as_deref(),
+                exemption_reason_code: subtotal.exemption_reason_code.as_deref(),
+                exemption_required: true,
+                taxable_amount: subtotal.taxable_amount.as_ref(),
+                tax_amount: subtotal.tax_amount.as_ref(),
+                tolerance: subtotal_tolerance,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn check(
+    registry: &TaxRegistry,
+    issue_date: Option<NaiveDate>,
+    declared: Declared,
+) -> anyhow::Result<()> {
+    let place = &declared.place;
+    let id = declared.category.map(str::trim).unwrap_or_default();
+    let Some(category) = registry.category(id) else {
+        bail!("unknown tax category: {place} uses {id:?}");
+    };
+
+    let allowed: Vec<&str> = category
+        .rates_on(issue_date)
+        .map(|rate| rate.percent.as_str())
+        .collect();
+    let percent = declared.percent.and_then(normalize_percent);
+    let Some(percent) = percent.filter(|percent| allowed.contains(&percent.as_str())) else {
+        bail!(
+            "tax rate not allowed: {place} declares category {id} at {:?} percent, allowed {allowed:?}",
+            declared.percent.unwrap_or_default()
+        );
+    };
+
+    match declared.exemption_reason_code.map(str::trim) {
+        Some(code)
+            if !category
+                .exemption_reason_codes
+                .iter()
+                .any(|allowed| allowed == code) =>
+        {
+            bail!("tax exemption reason code {code:?} is not allowed for category {id} on {place}")
+        }
+        None if declared.exemption_required && category.requires_exemption_reason() => {
+            bail!("tax exemption reason code missing for category {id} on {place}")
+        }
+        _ => {}
+    }
+
+    let number = |amount: Option<&Amount>| {
+        amount
+            .and_then(|amount| decimal(&amount.value))
+            .and_then(|value| value.parse::<f64>().ok())
+    };
+    if let (Some(taxable), Some(tax), Ok(rate)) = (
+        number(declared.taxable_amount),
+        number(declared.tax_amount),
+        percent.parse::<f64>(),
+    ) {
+        let expected = taxable * rate / 100.0;
+        if (tax - expected).abs() > declared.tolerance + f64::EPSILON * expected.abs() {
+            bail!(
+                "tax amount does not match the rate: {place} declares {tax} on {taxable} at {percent} percent, expected {expected:.2}"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(line_category: &str, subtotal_category: &str) -> InvoiceDocument {
+        let xml = format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:IssueDate>2026-03-01</cbc:IssueDate>
+  <cac:InvoiceLine><cbc:ID>1</cbc:ID><cbc:LineExtensionAmount currencyID="SDG">1000.00</cbc:LineExtensionAmount><cac:TaxTotal><cbc:TaxAmount currencyID="SDG">170.00</cbc:TaxAmount></cac:TaxTotal><cac:Item><cac:ClassifiedTaxCategory>{line_category}</cac:ClassifiedTaxCategory></cac:Item></cac:InvoiceLine>
+  <cac:TaxTotal><cbc:TaxAmount currencyID="SDG">170.00</cbc:TaxAmount><cac:TaxSubtotal><cbc:TaxableAmount currencyID="SDG">1000.00</cbc:TaxableAmount><cbc:TaxAmount currencyID="SDG">170.00</cbc:TaxAmount><cac:TaxCategory>{subtotal_category}</cac:TaxCategory></cac:TaxSubtotal></cac:TaxTotal>
+</Invoice>"#
+        );
+        InvoiceDocument::parse(xml.as_bytes()).unwrap()
+    }
+
+    fn error(line_category: &str, subtotal_category: &str) -> String {
+        check_tax_categories(
+            &invoice(line_category, subtotal_category),
+            &TaxRegistry::default(),
+        )
+        .unwrap_err()
+        .to_string()
+    }
+
+    const STANDARD: &str = "<cbc:ID>S</cbc:ID><cbc:Percent>17.00</cbc:Percent>";
+
+    #[test]
+    fn accepts_registered_categories_and_rates() {
+        let document = invoice(STANDARD, STANDARD);
+        check_tax_categories(&document, &TaxRegistry::default()).unwrap();
+    }
+
+    #[test]
+    fn rejects_unregistered_categories_and_rates() {
+        assert!(
+            error("<cbc:ID>Q</cbc:ID><cbc:Percent>17</cbc:Percent>", STANDARD)
+                .starts_with("unknown tax category")
+        );
+        assert!(
+            error("<cbc:ID>S</cbc:ID><cbc:Percent>15</cbc:Percent>", STANDARD)
+                .starts_with("tax rate not allowed")
+        );
+        assert!(error(STANDARD, "<cbc:ID>S</cbc:ID>").starts_with("tax rate not allowed"));
+        assert!(error("", STANDARD).contains("line 1 has no ClassifiedTaxCategory"));
+    }
+
+    #[test]
+    fn exempt_subtotals_need_an_allowed_reason_code() {
+        let exempt = "<cbc:ID>E</cbc:ID><cbc:Percent>0</cbc:Percent>";
+        assert!(error(STANDARD, exempt).starts_with("tax exemption reason code missing"));
+        let unknown =
+            format!("{exempt}<cbc:TaxExemptionReasonCode>VATEX-XX</cbc:TaxExemptionReasonCode>");
+        assert!(error(STANDARD, &unknown).contains("is not allowed for category E"));
+    }
+
+    #[test]
+    fn tax_amounts_must_match_the_rate() {
+        let mut document = invoice(STANDARD, STANDARD);
+        document.lines[0].tax_amount.as_mut().unwrap().value = "150.00".to_owned();
+        let err = check_tax_categories(&document, &TaxRegistry::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("tax amount does not match the rate")
+        );
+
+        document.lines[0].tax_amount.as_mut().unwrap().value = "170.01".to_owned();
+        check_tax_categories(&document, &TaxRegistry::default()).unwrap();
+    }
+}
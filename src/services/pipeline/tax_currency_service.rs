@@ -0,0 +1,185 @@
+use anyhow::bail;
+use chrono::NaiveDate;
+use sqlx::PgConnection;
+
+use crate::{
+    config::validation_config::ValidationPolicy,
+    services::{
+        db::exchange_rate_service::{ExchangeRate, REPORTING_CURRENCY, find_exchange_rate},
+        xml::{
+            decimal::decimal,
+            invoice_document::{Amount, InvoiceDocument},
+        },
+    },
+};
+
+/// Tax totals of a foreign-currency invoice.
+#[derive(Debug, PartialEq)]
+struct ForeignTaxTotals<'a> {
+    currency: &'a str,
+    issue_date: NaiveDate,
+    tax: f64,
+    sdg_tax: f64,
+}
+
+/// Checks that an invoice in another currency reports its tax in SDG too:
+/// `TaxCurrencyCode` must be SDG, and the SDG `TaxTotal` must match the
+/// document-currency one converted at the rate published for the
+/// `IssueDate`, within the policy tolerance. SDG invoices pass unchecked.
+pub async fn check_tax_currency(
+    document: &InvoiceDocument,
+    policy: &ValidationPolicy,
+    conn: &mut PgConnection,
+) -> anyhow::Result<()> {
+    let Some(totals) = foreign_tax_totals(document)? else {
+        return Ok(());
+    };
+    let rate = find_exchange_rate(totals.currency, totals.issue_date, conn).await?;
+    reconcile(&totals, rate.as_ref(), policy)
+}
+
+fn foreign_tax_totals(document: &InvoiceDocument) -> anyhow::Result<Option<ForeignTaxTotals<'_>>> {
+    let currency = document.document_currency.trim();
+    if currency.is_empty() || currency == REPORTING_CURRENCY {
+        return Ok(None);
+    }
+    if document.tax_currency.as_deref().map(str::trim) != Some(REPORTING_CURRENCY) {
+        bail!(
+            "sdg tax total missing: invoice in {currency} must declare TaxCurrencyCode {REPORTING_CURRENCY}"
+        );
+    }
+
+    let total_in = |wanted: &str, untagged: bool| {
+        document
+            .tax_totals
+            .iter()
+            .find(|tax| match tax.currency.as_deref() {
+                Some(tagged) => tagged.trim() == wanted,
+                None => untagged,
+            })
+            .and_then(number)
+    };
+    let Some(tax) = total_in(currency, true) else {
+        bail!("sdg tax total missing: invoice has no readable TaxTotal in {currency}");
+    };
+    let Some(sdg_tax) = total_in(REPORTING_CURRENCY, false) else {
+        bail!(
+            "sdg tax total missing: invoice in {currency} has no TaxTotal in {REPORTING_CURRENCY}"
+        );
+    };
+    // An unreadable date is the issue time check's to report.
+    let Ok(issue_date) = NaiveDate::parse_from_str(document.issue_date.trim(), "%Y-%m-%d") else {
+        return Ok(None);
+    };
+    Ok(Some(ForeignTaxTotals {
+        currency,
+        issue_date,
+        tax,
+        sdg_tax,
+    }))
+}
+
+fn reconcile(
+    totals: &ForeignTaxTotals,
+    rate: Option<&ExchangeRate>,
+    policy: &ValidationPolicy,
+) -> anyhow::Result<()> {
+    let ForeignTaxTotals {
+        currency,
+        issue_date,
+        tax,
+        sdg_tax,
+    } = *totals;
+    let Some((published, rate)) = rate
+        .filter(|rate| issue_date - rate.rate_date <= policy.exchange_rate_max_age)
+        .and_then(|rate| Some((rate.rate_date, rate.rate.parse::<f64>().ok()?)))
+    else {
+        bail!("exchange rate missing: no {currency} rate published for {issue_date}");
+    };
+
+    let expected = tax * rate;
+    let tolerance = (expected.abs() * policy.exchange_rate_tolerance_percent / 100.0).max(0.01);
+    if (sdg_tax - expected).abs() > tolerance {
+        bail!(
+            "sdg tax amount does not match the exchange rate: {sdg_tax} {REPORTING_CURRENCY} declared for {tax} {currency}, expected {expected:.2} at the {published} rate {rate}"
+        );
+    }
+    Ok(())
+}
+
+fn number(amount: &Amount) -> Option<f64> {
+    decimal(&amount.value)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(currency: &str, tax_currency: &str, tax_totals: &str) -> InvoiceDocument {
+        let xml = format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+  <cbc:IssueDate>2026-03-02</cbc:IssueDate>
+  <cbc:DocumentCurrencyCode>{currency}</cbc:DocumentCurrencyCode>
+  {tax_currency}
+  {tax_totals}
+</Invoice>"#
+        );
+        InvoiceDocument::parse(xml.as_bytes()).unwrap()
+    }
+
+    const SDG_TAX_CURRENCY: &str = "<cbc:TaxCurrencyCode>SDG</cbc:TaxCurrencyCode>";
+    const BOTH_TOTALS: &str = r#"<cac:TaxTotal><cbc:TaxAmount currencyID="USD">170.00</cbc:TaxAmount></cac:TaxTotal><cac:TaxTotal><cbc:TaxAmount currencyID="SDG">102000.00</cbc:TaxAmount></cac:TaxTotal>"#;
+
+    fn rate(date: &str, rate: &str) -> ExchangeRate {
+        ExchangeRate {
+            currency: "USD".into(),
+            rate_date: date.parse().unwrap(),
+            rate: rate.into(),
+        }
+    }
+
+    fn reconciled(rate: Option<ExchangeRate>) -> anyhow::Result<()> {
+        let document = invoice("USD", SDG_TAX_CURRENCY, BOTH_TOTALS);
+        let totals = foreign_tax_totals(&document).unwrap().unwrap();
+        reconcile(&totals, rate.as_ref(), &ValidationPolicy::default())
+    }
+
+    #[test]
+    fn sdg_invoices_are_not_checked() {
+        let document = invoice("SDG", "", "");
+        assert_eq!(foreign_tax_totals(&document).unwrap(), None);
+    }
+
+    #[test]
+    fn foreign_invoices_need_an_sdg_tax_total() {
+        let missing = |document: InvoiceDocument| {
+            foreign_tax_totals(&document)
+                .unwrap_err()
+                .to_string()
+                .starts_with("sdg tax total missing")
+        };
+        assert!(missing(invoice("USD", "", BOTH_TOTALS)));
+        let usd_only = r#"<cac:TaxTotal><cbc:TaxAmount currencyID="USD">170.00</cbc:TaxAmount></cac:TaxTotal>"#;
+        assert!(missing(invoice("USD", SDG_TAX_CURRENCY, usd_only)));
+    }
+
+    #[test]
+    fn sdg_tax_must_match_the_published_rate() {
+        // 170 USD at 600 is 102000 SDG; the default tolerance is 1%.
+        reconciled(Some(rate("2026-03-01", "600"))).unwrap();
+        reconciled(Some(rate("2026-03-01", "605.5"))).unwrap();
+        let err = reconciled(Some(rate("2026-03-01", "650"))).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("sdg tax amount does not match the exchange rate")
+        );
+    }
+
+    #[test]
+    fn stale_or_missing_rates_are_rejected() {
+        for stale in [None, Some(rate("2026-02-20", "600"))] {
+            let err = reconciled(stale).unwrap_err();
+            assert!(err.to_string().starts_with("exchange rate missing"));
+        }
+    }
+}
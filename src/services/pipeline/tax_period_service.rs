@@ -0,0 +1,146 @@
+use anyhow::bail;
+use chrono::NaiveDate;
+use sqlx::PgConnection;
+
+use crate::{
+    models::submit_invoice::InvoiceFlag,
+    services::{
+        db::tax_period_service::{TaxPeriodBounds, fetch_invoice_issue_date, find_tax_period},
+        xml::invoice_document::{BillingReference, InvoiceDocument},
+    },
+};
+
+const CREDIT_NOTE: &str = "381";
+const DEBIT_NOTE: &str = "383";
+
+/// Checks the invoice's `IssueDate` against the supplier's tax periods.
+/// Invoices in a filed period are flagged. Invoices in a locked period are
+/// rejected, unless they are credit or debit notes whose billing reference
+/// points at a stored invoice issued in that period.
+pub async fn check_tax_period(
+    document: &InvoiceDocument,
+    supplier_tin: &str,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    // A malformed date is the schema check's to report.
+    let Ok(issue_date) = NaiveDate::parse_from_str(document.issue_date.trim(), "%Y-%m-%d") else {
+        return Ok(None);
+    };
+    let Some(period) = find_tax_period(supplier_tin, issue_date, conn).await? else {
+        return Ok(None);
+    };
+
+    let mut referenced_dates = Vec::new();
+    if period.status == "locked" && is_correction(document) {
+        for reference in &document.billing_references {
+            let stored = fetch_invoice_issue_date(supplier_tin, reference.id.trim(), conn).await?;
+            referenced_dates.push(referenced_date(reference, stored)?);
+        }
+    }
+    assess(
+        &period,
+        issue_date,
+        is_correction(document),
+        &referenced_dates,
+    )
+}
+
+/// The issue date of the invoice a billing reference points at, as stored.
+/// The `cbc:IssueDate` the supplier wrote in the reference is not trusted, and
+/// a reference to an invoice that is not stored is rejected.
+fn referenced_date(
+    reference: &BillingReference,
+    stored: Option<NaiveDate>,
+) -> anyhow::Result<NaiveDate> {
+    match stored {
+        Some(date) => Ok(date),
+        None => bail!(
+            "tax period locked: the referenced invoice {} is not stored",
+            reference.id.trim()
+        ),
+    }
+}
+
+fn is_correction(document: &InvoiceDocument) -> bool {
+    matches!(document.invoice_type_code.trim(), CREDIT_NOTE | DEBIT_NOTE)
+}
+
+fn assess(
+    period: &TaxPeriodBounds,
+    issue_date: NaiveDate,
+    is_correction: bool,
+    referenced_dates: &[NaiveDate],
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    match period.status.as_str() {
+        "filed" => Ok(Some(InvoiceFlag::FiledPeriod)),
+        "locked" if !is_correction => bail!(
+            "tax period locked: IssueDate {issue_date} falls in the locked period {} to {}",
+            period.period_from,
+            period.period_to
+        ),
+        "locked" if referenced_dates.iter().any(|date| period.contains(*date)) => {
+            Ok(Some(InvoiceFlag::LockedPeriodCorrection))
+        }
+        "locked" => bail!(
+            "tax period locked: the credit or debit note does not reference an invoice issued in the locked period {} to {}",
+            period.period_from,
+            period.period_to
+        ),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period(status: &str) -> TaxPeriodBounds {
+        TaxPeriodBounds {
+            status: status.to_owned(),
+            period_from: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(),
+            period_to: NaiveDate::from_ymd_opt(2026, 1, 31).unwrap(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap()
+    }
+
+    #[test]
+    fn flags_filed_periods_and_ignores_open_ones() {
+        assert_eq!(assess(&period("open"), date(5), false, &[]).unwrap(), None);
+        assert_eq!(
+            assess(&period("filed"), date(5), false, &[]).unwrap(),
+            Some(InvoiceFlag::FiledPeriod)
+        );
+    }
+
+    #[test]
+    fn locked_periods_only_take_corrections_of_their_own_invoices() {
+        let err = assess(&period("locked"), date(5), false, &[]).unwrap_err();
+        assert!(err.to_string().starts_with("tax period locked"));
+
+        assert_eq!(
+            assess(&period("locked"), date(5), true, &[date(20)]).unwrap(),
+            Some(InvoiceFlag::LockedPeriodCorrection)
+        );
+        let outside = NaiveDate::from_ymd_opt(2025, 12, 31).unwrap();
+        assert!(assess(&period("locked"), date(5), true, &[outside]).is_err());
+        assert!(assess(&period("locked"), date(5), true, &[]).is_err());
+    }
+
+    #[test]
+    fn billing_reference_dates_come_from_the_stored_invoice() {
+        let fabricated = BillingReference {
+            id: "INV-7".to_owned(),
+            issue_date: Some("2026-01-20".to_owned()),
+        };
+        let stored = NaiveDate::from_ymd_opt(2025, 12, 31).unwrap();
+        let referenced = referenced_date(&fabricated, Some(stored)).unwrap();
+        assert_eq!(referenced, stored);
+        assert!(assess(&period("locked"), date(5), true, &[referenced]).is_err());
+
+        let err = referenced_date(&fabricated, None).unwrap_err();
+        assert!(err.to_string().contains("INV-7 is not stored"));
+    }
+}
@@ -1,50 +1,261 @@
+use std::{fmt, sync::Arc};
+
 use actix_web::web::Data;
 use anyhow::{Context, anyhow, bail};
 use fastxml::schema::CompiledSchema;
-use sqlx::PgPool;
-use tracing::{error, instrument};
+use sqlx::{Acquire, PgConnection, Postgres};
+use tracing::{error, instrument, warn};
 
 use crate::{
-    config::crypto_config::Crypto,
-    models::submit_invoice::{IntermediateInvoiceDto, InvoiceType},
+    config::{
+        business_rules::RuleSet, code_lists::CodeLists, crypto_config::Crypto,
+        signature_config::SignatureAlgorithms, validation_config::ValidationPolicy,
+    },
+    models::{
+        responses::ValidationIssue,
+        submit_invoice::{IntermediateInvoiceDto, InvoiceFlag, InvoiceType},
+    },
     services::{
+        cpu_pool::CpuPool,
         crypto::pki_service::{compute_hash, verfiy_supplier_tin_with_ca, verify_cert_with_ca},
         crypto::xades_bes::validate_xades_bes_signature,
         db::tin_service::verify_customer_tin,
-        pipeline::invoice_type_service::verify_invoice_type,
-        xml::{extractors::extract_customer_tin, schema_validation::validate_schema},
+        pipeline::{
+            business_rule_service::check_business_rules, code_list_service::check_code_lists,
+            invoice_number_service::check_invoice_number,
+            invoice_type_service::verify_invoice_type, issue_time_service::check_issue_time,
+            qr_service::verify_qr_content, tax_category_service::check_tax_categories,
+            tax_currency_service::check_tax_currency, tax_period_service::check_tax_period,
+        },
+        xml::schema_validation::validate_schema,
     },
 };
 
+/// An invoice that passed validation.
+pub struct ValidatedInvoice {
+    pub hash: Vec<u8>,
+    /// Stored with the invoice for follow-up; see [`InvoiceFlag`].
+    pub flags: Vec<InvoiceFlag>,
+}
+
+/// Rejection listing every failed check of one kind, so the device can fix
+/// them all at once.
+#[derive(Debug)]
+pub struct ValidationIssues {
+    /// Names the kind of check, e.g. "code list violation".
+    pub summary: &'static str,
+    pub issues: Vec<ValidationIssue>,
+    /// Version of the rule set that raised the issues, for business rules.
+    pub rules_version: Option<String>,
+}
+
+impl fmt::Display for ValidationIssues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.summary)?;
+        for issue in &self.issues {
+            write!(f, " {} at {};", issue.message, issue.xpath)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationIssues {}
+
+/// Runs the shared validation pipeline. The stateful checks run on a
+/// connection taken from `db` once the document checks pass, so a batch can
+/// pass its transaction and see the invoices it has already saved.
+#[allow(clippy::too_many_arguments)]
 #[instrument(
-    skip(db_pool, crypto, schema, intermediate),
+    skip(db, crypto, schema, algorithms, policy, intermediate, cpu),
     fields(
         uuid = %intermediate.uuid,
         device_uuid = %intermediate.device.device_uuid,
         supplier_tin = %intermediate.supplier,
+        sandbox,
         invoice_type = ?invoice_type
     )
 )]
-pub async fn validate_invoice(
+pub async fn validate_invoice<'c>(
+    intermediate: &Arc<IntermediateInvoiceDto>,
+    db: impl Acquire<'c, Database = Postgres>,
+    crypto: &Data<Crypto>,
+    sandbox: bool,
+    schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    policy: &ValidationPolicy,
+    invoice_type: InvoiceType,
+    cpu: &CpuPool,
+) -> anyhow::Result<ValidatedInvoice> {
+    // Steps 1-8 are CPU-bound and run on the blocking pool.
+    let (computed_hash, rule_warnings) = {
+        let intermediate = Arc::clone(intermediate);
+        let crypto = crypto.clone();
+        let algorithms = algorithms.clone();
+        let code_lists = Arc::clone(&policy.code_lists);
+        let rules = Arc::clone(policy.business_rules(sandbox));
+        cpu.run(move || {
+            verify_document(
+                &intermediate,
+                &crypto,
+                schema,
+                &algorithms,
+                &code_lists,
+                &rules,
+                invoice_type,
+            )
+        })
+        .await?
+    };
+
+    let uuid = &intermediate.uuid;
+    let supplier_tin = &intermediate.supplier;
+    let mut conn = db.acquire().await?;
+
+    match invoice_type {
+        InvoiceType::Reporting => {}
+        InvoiceType::Clearance => {
+            // 9. Verify the customer TIN against the database.
+            let customer_tin = &intermediate.document.customer.tin;
+            if let Err(e) = verify_customer_tin(customer_tin.as_bytes(), &mut conn).await {
+                error!(uuid = %uuid, supplier_tin = %supplier_tin, customer_id = %customer_tin, "Customer TIN not found in database: {}", e);
+                return Err(e);
+            }
+
+            // 10. Verify customer TIN != supplier TIN.
+            if customer_tin == supplier_tin {
+                let e = anyhow!("Customer TIN equals Supplier TIN");
+                error!(uuid = %uuid, supplier_tin = %supplier_tin, customer_id = %customer_tin, "Customer TIN equals Supplier TIN: {}", e);
+                bail!(e);
+            }
+        }
+    }
+
+    // 11. Check the IssueDate against the supplier's tax periods. Production
+    // runs this check in the transaction that saves the invoice.
+    let mut flags: Vec<InvoiceFlag> = Vec::new();
+    if sandbox {
+        flags.extend(verify_tax_period(intermediate, &mut conn).await?);
+    }
+
+    // 12. Check IssueDate/IssueTime against the submission time.
+    match check_issue_time(
+        &intermediate.document,
+        intermediate.received_at,
+        invoice_type,
+        policy,
+    ) {
+        Ok(flag) => flags.extend(flag),
+        Err(e) => {
+            error!(uuid = %uuid, issue_date = %intermediate.document.issue_date, issue_time = %intermediate.document.issue_time, "Issue time check failed: {}", e);
+            return Err(e);
+        }
+    }
+
+    // 13. Check line and subtotal tax categories against the tax registry.
+    if let Err(e) = check_tax_categories(&intermediate.document, &policy.tax_registry) {
+        error!(uuid = %uuid, supplier_tin = %supplier_tin, "Tax category check failed: {}", e);
+        return Err(e);
+    }
+
+    // 14. Reconcile the SDG tax total of foreign-currency invoices.
+    if let Err(e) = check_tax_currency(&intermediate.document, policy, &mut conn).await {
+        error!(uuid = %uuid, currency = %intermediate.document.document_currency, "Tax currency check failed: {}", e);
+        return Err(e);
+    }
+
+    // 15. Check the invoice number is not reused by the supplier. Production
+    // runs this check in the transaction that saves the invoice.
+    if sandbox {
+        flags.extend(verify_invoice_number(intermediate, policy, &mut conn).await?);
+    }
+
+    if !rule_warnings.is_empty() {
+        let mut codes: Vec<String> = rule_warnings.into_iter().map(|issue| issue.code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        warn!(uuid = %uuid, rules = ?codes, "Invoice accepted with business rule warnings");
+        flags.push(InvoiceFlag::RuleWarning { codes });
+    }
+
+    Ok(ValidatedInvoice {
+        hash: computed_hash,
+        flags,
+    })
+}
+
+/// Step 11 of [`validate_invoice`]: [`check_tax_period`] with logging.
+/// Production clearance and reporting call it after locking the device, in
+/// the transaction that saves the invoice.
+pub async fn verify_tax_period(
+    intermediate: &IntermediateInvoiceDto,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let uuid = &intermediate.uuid;
+    let supplier_tin = &intermediate.supplier;
+    check_tax_period(&intermediate.document, supplier_tin, conn)
+        .await
+        .inspect_err(|e| {
+            error!(uuid = %uuid, supplier_tin = %supplier_tin, issue_date = %intermediate.document.issue_date, "Tax period check failed: {}", e);
+        })
+}
+
+/// Step 15 of [`validate_invoice`]: [`check_invoice_number`] with logging.
+/// Production clearance and reporting call it after locking the device, in
+/// the transaction that saves the invoice.
+pub async fn verify_invoice_number(
+    intermediate: &IntermediateInvoiceDto,
+    policy: &ValidationPolicy,
+    conn: &mut PgConnection,
+) -> anyhow::Result<Option<InvoiceFlag>> {
+    let uuid = &intermediate.uuid;
+    let supplier_tin = &intermediate.supplier;
+    check_invoice_number(&intermediate.document, supplier_tin, uuid, policy, conn)
+        .await
+        .inspect_err(|e| {
+            error!(uuid = %uuid, supplier_tin = %supplier_tin, invoice_number = %intermediate.document.id, "Invoice number check failed: {}", e);
+        })
+}
+
+/// Stateless document checks: schema, code lists, business rules, profile,
+/// hash, QR, signature, certificate and supplier binding. Returns the computed
+/// invoice hash and the failed `warning` rules.
+#[allow(clippy::too_many_arguments)]
+fn verify_document(
     intermediate: &IntermediateInvoiceDto,
-    db_pool: &PgPool,
     crypto: &Crypto,
     schema: Data<CompiledSchema>,
+    algorithms: &Data<SignatureAlgorithms>,
+    code_lists: &CodeLists,
+    rules: &RuleSet,
     invoice_type: InvoiceType,
-) -> anyhow::Result<Vec<u8>> {
+) -> anyhow::Result<(Vec<u8>, Vec<ValidationIssue>)> {
     let uuid = &intermediate.uuid;
     let supplier_tin = &intermediate.supplier;
 
-    // 1. Validate Schema
+    // 1. Validate the schema, the code list values and the business rules.
     let xml_body = std::str::from_utf8(&intermediate.invoice_bytes)
         .context("Invoice XML is not valid UTF-8")?;
-    if let Err(e) = validate_schema(schema, xml_body) {
-        error!(uuid = %uuid, "Schema validation failed: {}", e);
+    let xml = match validate_schema(schema, xml_body) {
+        Ok(xml) => xml,
+        Err(e) => {
+            error!(uuid = %uuid, "Schema validation failed: {}", e);
+            return Err(e);
+        }
+    };
+    if let Err(e) = check_code_lists(&intermediate.document, code_lists) {
+        error!(uuid = %uuid, "Code list validation failed: {}", e);
         return Err(e);
     }
+    let rule_warnings = match check_business_rules(&xml, rules) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            error!(uuid = %uuid, rules_version = %rules.version, "Business rule validation failed: {}", e);
+            return Err(e);
+        }
+    };
 
     // 2. Verify invoice type
-    match verify_invoice_type(&intermediate.invoice_bytes, &invoice_type) {
+    match verify_invoice_type(&intermediate.document, &invoice_type) {
         Ok(_) => {}
         Err(e) => {
             error!(uuid = %uuid, invoice_type = ?invoice_type, "Invoice type mismatch: {}", e);
@@ -60,52 +271,40 @@ pub async fn validate_invoice(
         bail!("Invoice hash mismatch");
     }
 
-    // 4. Verify XAdES-BES signature structure, references, certificate binding, and SignatureValue.
+    // 4. Verify the QR TLV against the invoice fields and hash.
+    if let Err(e) = verify_qr_content(&intermediate.document, &computed_hash) {
+        error!(uuid = %uuid, "QR content validation failed: {}", e);
+        return Err(e);
+    }
+
+    // 5. Verify XAdES-BES signature structure, references, certificate binding, and SignatureValue.
     if let Err(e) = validate_xades_bes_signature(
+        &intermediate.document,
         &intermediate.invoice_bytes,
-        &intermediate.invoice_hash,
         &intermediate.certificate,
+        algorithms,
     ) {
         error!(uuid = %uuid, "XAdES-BES signature validation failed: {}", e);
         return Err(e);
     }
 
-    // 5. Verify certificate chain.
-    if !verify_cert_with_ca(&crypto.certificate, &intermediate.certificate).await? {
+    // 6. Verify certificate chain.
+    if !verify_cert_with_ca(&crypto.certificate, &intermediate.certificate)? {
         error!(uuid = %uuid, "Certificate verification failed");
         bail!("Certificate verification failed");
     }
 
-    // 6. Verify supplier TIN with certificate.
+    // 7. Verify supplier TIN with certificate.
     if let Err(e) = verfiy_supplier_tin_with_ca(supplier_tin, &intermediate.certificate) {
         error!(uuid = %uuid, supplier_tin = %supplier_tin, "Supplier TIN mismatch with certificate: {}", e);
         return Err(e);
     }
 
-    // 7. Verify supplier TIN is the one enrolled for this device.
+    // 8. Verify supplier TIN is the one enrolled for this device.
     if supplier_tin != &intermediate.device.tin {
         error!(uuid = %uuid, supplier_tin = %supplier_tin, device_tin = %intermediate.device.tin, "Supplier TIN mismatch with enrolled device");
         bail!("Supplier TIN mismatch with enrolled device");
     }
 
-    match invoice_type {
-        InvoiceType::Reporting => {}
-        InvoiceType::Clearance => {
-            // 8. Extract customer TIN and verify it against the database.
-            let customer_tin = extract_customer_tin(&intermediate.invoice_bytes)?;
-            if let Err(e) = verify_customer_tin(customer_tin.as_bytes(), db_pool).await {
-                error!(uuid = %uuid, supplier_tin = %supplier_tin, customer_id = %customer_tin, "Customer TIN not found in database: {}", e);
-                return Err(e);
-            }
-
-            // 9. Verify customer TIN != supplier TIN.
-            if &customer_tin == supplier_tin {
-                let e = anyhow!("Customer TIN equals Supplier TIN");
-                error!(uuid = %uuid, supplier_tin = %supplier_tin, customer_id = %customer_tin, "Customer TIN equals Supplier TIN: {}", e);
-                bail!(e);
-            }
-        }
-    }
-
-    Ok(computed_hash)
+    Ok((computed_hash, rule_warnings))
 }
@@ -0,0 +1,57 @@
+use actix_web::web::Data;
+use chrono::{NaiveDate, SecondsFormat, Utc};
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::{
+    config::crypto_config::Crypto,
+    models::vat_return::{SignedVatReturnDto, VatReturn},
+    services::{
+        cpu_pool::CpuPool,
+        crypto::receipt_signing::sign_jws,
+        db::vat_return::{fetch_vat_return_lines, fetch_vat_return_totals},
+    },
+};
+
+/// Computes the draft VAT return of `tin` for invoices issued from `from` to
+/// `to`, both inclusive.
+#[instrument(skip(pool))]
+pub async fn compute_vat_return(
+    tin: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    pool: &PgPool,
+) -> anyhow::Result<VatReturn> {
+    let (output, input) = fetch_vat_return_lines(tin, from, to, pool).await?;
+    let totals = fetch_vat_return_totals(tin, from, to, pool).await?;
+    Ok(VatReturn {
+        tin: tin.to_owned(),
+        period_from: from.to_string(),
+        period_to: to.to_string(),
+        status: "draft".to_owned(),
+        generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        output,
+        input,
+        totals,
+    })
+}
+
+/// JWS `typ` of exported draft returns.
+const VAT_RETURN_JWS_TYPE: &str = "vat-return+jws";
+
+/// Signs a draft return on the CPU pool, keeping RSA signing off the executor.
+pub async fn sign_vat_return(
+    draft: VatReturn,
+    crypto: &Data<Crypto>,
+    cpu: &CpuPool,
+) -> anyhow::Result<SignedVatReturnDto> {
+    let crypto = crypto.clone();
+    cpu.run(move || {
+        let signed_draft = sign_jws(&draft, VAT_RETURN_JWS_TYPE, &crypto)?;
+        Ok(SignedVatReturnDto {
+            draft,
+            signed_draft,
+        })
+    })
+    .await
+}
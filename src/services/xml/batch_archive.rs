@@ -0,0 +1,181 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{Context, bail};
+use zip::ZipArchive;
+
+use crate::{
+    config::{
+        batch_config::BatchLimits, signature_config::SignatureAlgorithms, xml_config::XmlLimits,
+    },
+    models::submit_invoice::SubmitInvoiceDto,
+};
+
+/// Reads a ZIP batch of signed invoice XML files, in archive order, into
+/// submission DTOs. The UUID and hash are derived from each document; any
+/// problem with an individual invoice is left for the regular parse step.
+pub fn read_invoice_archive(
+    archive_bytes: &[u8],
+    limits: &BatchLimits,
+    xml_limits: &XmlLimits,
+    algorithms: &SignatureAlgorithms,
+) -> anyhow::Result<Vec<SubmitInvoiceDto>> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(archive_bytes)).context("invalid batch archive")?;
+
+    let mut invoices = Vec::new();
+    let mut total_bytes = 0usize;
+
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .context("invalid batch archive entry")?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_owned();
+        if !name.to_ascii_lowercase().ends_with(".xml") {
+            bail!("batch archive entry '{name}' is not an XML file");
+        }
+        if invoices.len() >= limits.max_items {
+            bail!("too many invoices in batch: limit is {}", limits.max_items);
+        }
+        if entry.size() > limits.max_entry_bytes as u64 {
+            bail!("batch archive entry '{name}' is too large");
+        }
+
+        let mut invoice_bytes = Vec::with_capacity(entry.size() as usize);
+        entry
+            .take(limits.max_entry_bytes as u64 + 1)
+            .read_to_end(&mut invoice_bytes)
+            .with_context(|| format!("failed to read batch archive entry '{name}'"))?;
+        if invoice_bytes.len() > limits.max_entry_bytes {
+            bail!("batch archive entry '{name}' is too large");
+        }
+        total_bytes += invoice_bytes.len();
+        if total_bytes > limits.max_bytes {
+            bail!("batch archive is too large once uncompressed");
+        }
+
+        invoices.push(SubmitInvoiceDto::from_xml(
+            &invoice_bytes,
+            xml_limits,
+            algorithms,
+        ));
+    }
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine, engine::general_purpose};
+    use std::io::Write;
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    const LIMITS: BatchLimits = BatchLimits {
+        max_items: 2,
+        max_bytes: 4096,
+        max_entry_bytes: 1024,
+    };
+
+    fn invoice(uuid: &str) -> String {
+        format!(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2" xmlns:ext="urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2"><ext:UBLExtensions><ext:UBLExtension><ext:ExtensionContent><ds:Signature xmlns:ds="http://www.w3.org/2000/09/xmldsig#" Id="signature"><ds:SignedInfo><ds:Reference URI=""><ds:Transforms><ds:Transform Algorithm="http://www.w3.org/TR/1999/REC-xpath-19991116"><ds:XPath>not(//ancestor-or-self::ext:UBLExtensions)</ds:XPath></ds:Transform><ds:Transform Algorithm="http://www.w3.org/2006/12/xml-c14n11#"/></ds:Transforms><ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/></ds:Reference></ds:SignedInfo></ds:Signature></ext:ExtensionContent></ext:UBLExtension></ext:UBLExtensions><cbc:UUID>{uuid}</cbc:UUID></Invoice>"#
+        )
+    }
+
+    fn archive(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            if name.ends_with('/') {
+                writer
+                    .add_directory(*name, SimpleFileOptions::default())
+                    .unwrap();
+            } else {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn reads_invoices_in_archive_order() {
+        let first = invoice("00000000-0000-0000-0000-000000000002");
+        let second = invoice("00000000-0000-0000-0000-000000000001");
+        let bytes = archive(&[("b.xml", &first), ("day/", ""), ("day/a.XML", &second)]);
+
+        let invoices = read_invoice_archive(
+            &bytes,
+            &LIMITS,
+            &XmlLimits::from_env(),
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap();
+
+        assert_eq!(invoices.len(), 2);
+        assert_eq!(invoices[0].uuid, "00000000-0000-0000-0000-000000000002");
+        assert_eq!(invoices[1].uuid, "00000000-0000-0000-0000-000000000001");
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(&invoices[0].invoice)
+                .unwrap(),
+            first.as_bytes()
+        );
+        assert!(!invoices[0].invoice_hash.is_empty());
+    }
+
+    #[test]
+    fn rejects_non_xml_entries() {
+        let bytes = archive(&[("notes.txt", "hello")]);
+        let err = read_invoice_archive(
+            &bytes,
+            &LIMITS,
+            &XmlLimits::from_env(),
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not an XML file"));
+    }
+
+    #[test]
+    fn enforces_item_and_size_limits() {
+        let xml = invoice("00000000-0000-0000-0000-000000000001");
+        let bytes = archive(&[("1.xml", &xml), ("2.xml", &xml), ("3.xml", &xml)]);
+        let err = read_invoice_archive(
+            &bytes,
+            &LIMITS,
+            &XmlLimits::from_env(),
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("too many invoices"));
+
+        let large = "x".repeat(LIMITS.max_entry_bytes + 1);
+        let bytes = archive(&[("big.xml", &large)]);
+        let err = read_invoice_archive(
+            &bytes,
+            &LIMITS,
+            &XmlLimits::from_env(),
+            &SignatureAlgorithms::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_a_zip() {
+        assert!(
+            read_invoice_archive(
+                b"not a zip",
+                &LIMITS,
+                &XmlLimits::from_env(),
+                &SignatureAlgorithms::default(),
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,25 @@
+/// `value`, if it is a plain decimal Postgres will accept as `numeric`.
+pub fn decimal(value: &str) -> Option<String> {
+    let value = value.trim();
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let mut parts = digits.splitn(2, '.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next().unwrap_or_default();
+    let valid = !whole.is_empty()
+        && whole.bytes().all(|b| b.is_ascii_digit())
+        && fraction.bytes().all(|b| b.is_ascii_digit());
+    valid.then(|| value.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_plain_decimals() {
+        assert_eq!(decimal(" -12.5 "), Some("-12.5".into()));
+        assert_eq!(decimal("1e3"), None);
+        assert_eq!(decimal("NaN"), None);
+        assert_eq!(decimal(".5"), None);
+    }
+}
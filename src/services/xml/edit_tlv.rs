@@ -1,6 +1,16 @@
 use anyhow::{Context, bail};
 use base64::{Engine, engine::general_purpose};
 
+/// QR TLV tags.
+pub const TAG_SELLER_NAME: u8 = 1;
+pub const TAG_SELLER_TIN: u8 = 2;
+pub const TAG_TIMESTAMP: u8 = 3;
+pub const TAG_INVOICE_TOTAL: u8 = 4;
+pub const TAG_VAT_TOTAL: u8 = 5;
+pub const TAG_INVOICE_HASH: u8 = 6;
+pub const TAG_SIGNATURE: u8 = 7;
+pub const TAG_CERTIFICATE: u8 = 8;
+
 /// Edits TLV-encoded QR code data by replacing hash, signature, and certificate values.
 /// Returns the modified data as a base64-encoded string.
 pub fn edit_tlv(
@@ -15,16 +25,16 @@ pub fn edit_tlv(
     let mut signature_found = false;
     let mut certificate_found = false;
     for (tag, value) in records.iter_mut() {
-        match tag {
-            6 => {
+        match *tag {
+            TAG_INVOICE_HASH => {
                 *value = hash.to_vec();
                 hash_found = true;
             }
-            7 => {
+            TAG_SIGNATURE => {
                 *value = signature.to_vec();
                 signature_found = true;
             }
-            8 => {
+            TAG_CERTIFICATE => {
                 *value = certificate.to_vec();
                 certificate_found = true;
             }
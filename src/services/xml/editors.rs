@@ -3,10 +3,13 @@ use base64::Engine;
 use base64::engine::general_purpose;
 use chrono::Utc;
 use quick_xml::events::{BytesText, Event};
-use quick_xml::{Reader, Writer};
+use quick_xml::{NsReader, Writer};
 use std::io::Cursor;
 
-use crate::services::xml::edit_tlv::edit_tlv;
+use crate::services::xml::{
+    edit_tlv::edit_tlv,
+    namespaces::{CAC_NS, CBC_NS, DS_NS, SIG_NS, XADES_NS, is_element},
+};
 
 /*
 1. Canonicalize invoice → hash invoice
@@ -28,7 +31,7 @@ build QR using invoice hash + signature
 */
 
 pub fn edit_signing_time(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
 
     // Example: trim whitespace around text if you want
     {
@@ -46,7 +49,7 @@ pub fn edit_signing_time(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                if e.local_name().as_ref() == b"SigningTime" {
+                if is_element(reader.resolver(), e.name(), XADES_NS, "SigningTime") {
                     in_signature = true;
                 }
 
@@ -54,7 +57,7 @@ pub fn edit_signing_time(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
             }
 
             Ok(Event::End(e)) => {
-                if e.local_name().as_ref() == b"SigningTime" {
+                if is_element(reader.resolver(), e.name(), XADES_NS, "SigningTime") {
                     in_signature = false;
                 }
 
@@ -103,7 +106,7 @@ pub fn edit_signed_info(
     invoice_hash: &[u8],
     signed_props_hash: &[u8],
 ) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
 
     let mut writer = Writer::new(Vec::new());
     let mut buf = Vec::new();
@@ -115,7 +118,7 @@ pub fn edit_signed_info(
         match reader.read_event_into(&mut buf)? {
             Event::Start(e) => {
                 // <ds:Reference>
-                if e.local_name().as_ref() == b"Reference" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "Reference") {
                     active_ref = ActiveReference::Other;
 
                     for attr in e.attributes().flatten() {
@@ -141,7 +144,7 @@ pub fn edit_signed_info(
                 }
 
                 // <ds:DigestValue>
-                if e.local_name().as_ref() == b"DigestValue" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "DigestValue") {
                     in_digest_value = true;
                 }
 
@@ -149,11 +152,11 @@ pub fn edit_signed_info(
             }
 
             Event::End(e) => {
-                if e.local_name().as_ref() == b"Reference" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "Reference") {
                     active_ref = ActiveReference::Other;
                 }
 
-                if e.local_name().as_ref() == b"DigestValue" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "DigestValue") {
                     in_digest_value = false;
                 }
 
@@ -200,7 +203,7 @@ pub fn edit_signed_info(
 }
 
 pub fn edit_signature(xml: &[u8], signature: String) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
 
     // Example: trim whitespace around text if you want
     {
@@ -218,7 +221,7 @@ pub fn edit_signature(xml: &[u8], signature: String) -> anyhow::Result<Vec<u8>>
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                if e.local_name().as_ref() == b"SignatureValue" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "SignatureValue") {
                     in_signature = true;
                 }
 
@@ -226,7 +229,7 @@ pub fn edit_signature(xml: &[u8], signature: String) -> anyhow::Result<Vec<u8>>
             }
 
             Ok(Event::End(e)) => {
-                if e.local_name().as_ref() == b"SignatureValue" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "SignatureValue") {
                     in_signature = false;
                 }
 
@@ -262,7 +265,7 @@ pub fn edit_signature(xml: &[u8], signature: String) -> anyhow::Result<Vec<u8>>
 }
 
 pub fn edit_certificate(xml: &[u8], certificate: String) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
 
     {
         let cfg = reader.config_mut();
@@ -278,7 +281,7 @@ pub fn edit_certificate(xml: &[u8], certificate: String) -> anyhow::Result<Vec<u
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                if e.local_name().as_ref() == b"X509Certificate" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "X509Certificate") {
                     in_certificate = true;
                     certificate_found = true;
                 }
@@ -287,7 +290,7 @@ pub fn edit_certificate(xml: &[u8], certificate: String) -> anyhow::Result<Vec<u
             }
 
             Ok(Event::End(e)) => {
-                if e.local_name().as_ref() == b"X509Certificate" {
+                if is_element(reader.resolver(), e.name(), DS_NS, "X509Certificate") {
                     in_certificate = false;
                 }
 
@@ -325,7 +328,40 @@ pub fn edit_certificate(xml: &[u8], certificate: String) -> anyhow::Result<Vec<u
     Ok(writer.into_inner())
 }
 
-// Helper to gracefully ignore XML namespaces (e.g., handles "cac:AdditionalDocumentReference" or "AdditionalDocumentReference")
+/// Appends a `sac:SignatureInformation` element to
+/// `sig:UBLDocumentSignatures`, leaving existing signatures untouched.
+pub fn insert_signature_information(
+    xml: &[u8],
+    signature_information: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
+    let mut writer = Writer::new(Vec::new());
+    let mut buf = Vec::new();
+    let mut inserted = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::End(e) => {
+                if !inserted
+                    && is_element(reader.resolver(), e.name(), SIG_NS, "UBLDocumentSignatures")
+                {
+                    writer.get_mut().extend_from_slice(signature_information);
+                    inserted = true;
+                }
+                writer.write_event(Event::End(e.to_owned()))?;
+            }
+            Event::Eof => break,
+            ev => writer.write_event(ev.to_owned())?,
+        }
+        buf.clear();
+    }
+
+    if !inserted {
+        bail!("UBLDocumentSignatures not found");
+    }
+
+    Ok(writer.into_inner())
+}
 
 pub fn edit_qr(
     xml: &[u8],
@@ -335,7 +371,7 @@ pub fn edit_qr(
 ) -> anyhow::Result<Vec<u8>> {
     // IMPORTANT: Do not configure reader.trim_text(true).
     // We must preserve exact whitespaces for signature validity.
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
     let mut writer = Writer::new(Vec::new());
     let mut buf = Vec::new();
 
@@ -350,16 +386,28 @@ pub fn edit_qr(
     loop {
         match reader.read_event_into(&mut buf)? {
             Event::Start(e) => {
-                if e.local_name().as_ref() == b"AdditionalDocumentReference" {
+                if is_element(
+                    reader.resolver(),
+                    e.name(),
+                    CAC_NS,
+                    "AdditionalDocumentReference",
+                ) {
                     in_additional_doc_ref = true;
                     is_qr_block = false; // Reset block state
                 }
 
-                if in_additional_doc_ref && e.local_name().as_ref() == b"ID" {
+                if in_additional_doc_ref && is_element(reader.resolver(), e.name(), CBC_NS, "ID") {
                     in_id_tag = true;
                 }
 
-                if is_qr_block && e.local_name().as_ref() == b"EmbeddedDocumentBinaryObject" {
+                if is_qr_block
+                    && is_element(
+                        reader.resolver(),
+                        e.name(),
+                        CBC_NS,
+                        "EmbeddedDocumentBinaryObject",
+                    )
+                {
                     in_binary_object = true;
                 }
 
@@ -367,16 +415,26 @@ pub fn edit_qr(
             }
 
             Event::End(e) => {
-                if e.local_name().as_ref() == b"AdditionalDocumentReference" {
+                if is_element(
+                    reader.resolver(),
+                    e.name(),
+                    CAC_NS,
+                    "AdditionalDocumentReference",
+                ) {
                     in_additional_doc_ref = false;
                     is_qr_block = false;
                 }
 
-                if e.local_name().as_ref() == b"ID" {
+                if is_element(reader.resolver(), e.name(), CBC_NS, "ID") {
                     in_id_tag = false;
                 }
 
-                if e.local_name().as_ref() == b"EmbeddedDocumentBinaryObject" {
+                if is_element(
+                    reader.resolver(),
+                    e.name(),
+                    CBC_NS,
+                    "EmbeddedDocumentBinaryObject",
+                ) {
                     in_binary_object = false;
                 }
 
@@ -463,7 +521,7 @@ mod tests {
     // Original simple tests
     #[test]
     fn test_clear_invoice() {
-        let xml = r#"<Root>
+        let xml = r#"<Root xmlns="http://uri.etsi.org/01903/v1.3.2#">
     <Data>Some data</Data>
     <SigningTime>2023-10-01T12:00:00Z</SigningTime>
     <MoreData>Other data</MoreData>
@@ -671,7 +729,7 @@ mod tests {
     // Legacy simple tests (preserved for backward compatibility)
     #[test]
     fn test_edit_signed_info_invoice_hash_replacement() {
-        let xml = r#"<Root><Reference Id="invoiceSignedData"><DigestValue>oldInvoiceHash</DigestValue></Reference></Root>"#;
+        let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Id="invoiceSignedData"><DigestValue>oldInvoiceHash</DigestValue></Reference></Root>"#;
         let result = String::from_utf8(
             edit_signed_info(xml.as_bytes(), b"newInvoiceHash", b"propHash").unwrap(),
         )
@@ -682,7 +740,7 @@ mod tests {
 
     #[test]
     fn test_edit_signed_info_signed_properties_hash_replacement() {
-        let xml = r#"<Root><Reference Type="http://www.w3.org/2000/09/xmldsig#SignatureProperties"><DigestValue>oldPropHash</DigestValue></Reference></Root>"#;
+        let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Type="http://www.w3.org/2000/09/xmldsig#SignatureProperties"><DigestValue>oldPropHash</DigestValue></Reference></Root>"#;
         let result = String::from_utf8(
             edit_signed_info(xml.as_bytes(), b"invHash", b"newPropHash").unwrap(),
         )
@@ -693,7 +751,7 @@ mod tests {
 
     #[test]
     fn test_edit_signed_info_preserves_other_digests() {
-        let xml = r#"<Root><Reference><DigestValue>otherHash</DigestValue></Reference></Root>"#;
+        let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference><DigestValue>otherHash</DigestValue></Reference></Root>"#;
         let result =
             String::from_utf8(edit_signed_info(xml.as_bytes(), b"invHash", b"propHash").unwrap())
                 .unwrap();
@@ -702,7 +760,7 @@ mod tests {
 
     #[test]
     fn test_edit_signed_info_preserves_structure() {
-        let xml = r#"<Root><Reference Id="invoiceSignedData"><DigestMethod Algorithm="test"/><DigestValue>hash</DigestValue></Reference></Root>"#;
+        let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Id="invoiceSignedData"><DigestMethod Algorithm="test"/><DigestValue>hash</DigestValue></Reference></Root>"#;
         let result =
             String::from_utf8(edit_signed_info(xml.as_bytes(), b"newHash", b"propHash").unwrap())
                 .unwrap();
@@ -712,7 +770,7 @@ mod tests {
 }
 #[test]
 fn test_edit_signing_time_updates_timestamp() {
-    let xml = r#"<Root>
+    let xml = r#"<Root xmlns="http://uri.etsi.org/01903/v1.3.2#">
     <Data>Some data</Data>
     <SigningTime>2023-10-01T12:00:00Z</SigningTime>
     <MoreData>Other data</MoreData>
@@ -727,7 +785,7 @@ fn test_edit_signing_time_updates_timestamp() {
 
 #[test]
 fn test_edit_signed_info_invoice_hash_replacement() {
-    let xml = r#"<Root><Reference Id="invoiceSignedData"><DigestValue>oldInvoiceHash</DigestValue></Reference></Root>"#;
+    let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Id="invoiceSignedData"><DigestValue>oldInvoiceHash</DigestValue></Reference></Root>"#;
     let result = String::from_utf8(
         edit_signed_info(xml.as_bytes(), b"newInvoiceHash", b"propHash").unwrap(),
     )
@@ -738,7 +796,7 @@ fn test_edit_signed_info_invoice_hash_replacement() {
 
 #[test]
 fn test_edit_signed_info_signed_properties_hash_replacement() {
-    let xml = r#"<Root><Reference Type="http://www.w3.org/2000/09/xmldsig#SignatureProperties"><DigestValue>oldPropHash</DigestValue></Reference></Root>"#;
+    let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Type="http://www.w3.org/2000/09/xmldsig#SignatureProperties"><DigestValue>oldPropHash</DigestValue></Reference></Root>"#;
     let result =
         String::from_utf8(edit_signed_info(xml.as_bytes(), b"invHash", b"newPropHash").unwrap())
             .unwrap();
@@ -748,7 +806,7 @@ fn test_edit_signed_info_signed_properties_hash_replacement() {
 
 #[test]
 fn test_edit_signed_info_preserves_other_digests() {
-    let xml = r#"<Root><Reference><DigestValue>otherHash</DigestValue></Reference></Root>"#;
+    let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference><DigestValue>otherHash</DigestValue></Reference></Root>"#;
     let result =
         String::from_utf8(edit_signed_info(xml.as_bytes(), b"invHash", b"propHash").unwrap())
             .unwrap();
@@ -757,7 +815,7 @@ fn test_edit_signed_info_preserves_other_digests() {
 
 #[test]
 fn test_edit_signed_info_preserves_structure() {
-    let xml = r#"<Root><Reference Id="invoiceSignedData"><DigestMethod Algorithm="test"/><DigestValue>hash</DigestValue></Reference></Root>"#;
+    let xml = r#"<Root xmlns="http://www.w3.org/2000/09/xmldsig#"><Reference Id="invoiceSignedData"><DigestMethod Algorithm="test"/><DigestValue>hash</DigestValue></Reference></Root>"#;
     let result =
         String::from_utf8(edit_signed_info(xml.as_bytes(), b"newHash", b"propHash").unwrap())
             .unwrap();
@@ -1,190 +1,43 @@
 use anyhow::{Context, bail};
 use openssl::bn::BigNum;
 use quick_xml::{
-    Reader, Writer,
+    NsReader, Reader, Writer,
     events::{BytesStart, Event},
+    name::NamespaceResolver,
 };
 use std::io::Cursor;
 
-const DS_NS: &[u8] = b"http://www.w3.org/2000/09/xmldsig#";
-const XADES_NS: &[u8] = b"http://uri.etsi.org/01903/v1.3.2#";
-
-fn validate_namespace(e: &BytesStart, expected_ns: &[u8]) -> anyhow::Result<()> {
-    let name_buf = e.name();
-    let qualified = name_buf.as_ref();
-    let prefix: &[u8] = match expected_ns {
-        DS_NS => b"ds:",
-        XADES_NS => b"xades:",
-        _ => return Ok(()),
-    };
-    if !qualified.starts_with(prefix) {
-        let name = std::str::from_utf8(qualified).unwrap_or("?");
+use crate::services::xml::{
+    invoice_document::InvoiceDocument,
+    namespaces::{
+        DS_NS, XADES_NS, element_namespace, fragment_declarations, in_scope_declarations,
+        is_element, prefix_declarations,
+    },
+};
+
+fn validate_namespace(
+    resolver: &NamespaceResolver,
+    e: &BytesStart,
+    expected_ns: &[u8],
+) -> anyhow::Result<()> {
+    if element_namespace(resolver, e.name()) != Some(expected_ns) {
+        let name = std::str::from_utf8(e.name().into_inner()).unwrap_or("?");
         let ns = std::str::from_utf8(expected_ns).unwrap_or("?");
         bail!("element '{name}' does not use expected namespace {ns}");
     }
     Ok(())
 }
 
-#[derive(PartialEq)]
-enum State {
-    Default,
-    Skipping,
-    InAdditionalDocRef,
-    InAdditionalDocRefId,
-}
-
 /// Extracts and cleans invoice XML by removing UBL extensions, signature,
 /// and QR document references.
 pub fn extract_invoice(raw_xml: &[u8]) -> anyhow::Result<Vec<u8>> {
-    // 1. Remove XML declaration if present
-    let xml_to_parse = if raw_xml.starts_with(b"<?xml") {
-        let pos = raw_xml
-            .iter()
-            .position(|&b| b == b'>')
-            .ok_or_else(|| anyhow::anyhow!("Invalid XML"))?;
-        &raw_xml[pos + 1..]
-    } else {
-        raw_xml
-    };
-
-    let mut reader = Reader::from_reader(Cursor::new(xml_to_parse));
-    let mut writer = Writer::new(Vec::new());
-    let mut adr_buffer: Vec<u8> = Vec::new();
-
-    let mut state = State::Default;
-    let mut skip_depth = 0;
-    let mut adr_depth = 0;
-    let mut is_qr_reference = false;
-    let mut buf = Vec::new();
-
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                let local_name = e.local_name();
-                let tag_bytes = local_name.as_ref();
-
-                match state {
-                    State::Skipping => {
-                        skip_depth += 1;
-                    }
-                    State::Default => {
-                        if tag_bytes == b"UBLExtensions" || tag_bytes == b"Signature" {
-                            state = State::Skipping;
-                            skip_depth = 1;
-                        } else if tag_bytes == b"AdditionalDocumentReference" {
-                            state = State::InAdditionalDocRef;
-                            adr_depth = 1;
-                            is_qr_reference = false;
-                            adr_buffer.clear();
-                            // In latest quick-xml, Event implements AsRef<[u8]>
-                            // We wrap in < > because as_ref() on Start event usually
-                            // contains the tag content without the brackets.
-                            adr_buffer.push(b'<');
-                            adr_buffer.extend_from_slice(e.as_ref());
-                            adr_buffer.push(b'>');
-                        } else {
-                            writer.write_event(Event::Start(e))?;
-                        }
-                    }
-                    State::InAdditionalDocRef | State::InAdditionalDocRefId => {
-                        adr_depth += 1;
-                        if tag_bytes == b"ID" && state == State::InAdditionalDocRef {
-                            state = State::InAdditionalDocRefId;
-                        }
-                        adr_buffer.push(b'<');
-                        adr_buffer.extend_from_slice(e.as_ref());
-                        adr_buffer.push(b'>');
-                    }
-                }
-            }
-
-            Ok(Event::Text(e)) => {
-                match state {
-                    State::Skipping => {}
-                    State::InAdditionalDocRefId => {
-                        // Check if the unescaped content is exactly "QR"
-                        if e.as_ref().trim_ascii() == b"QR" {
-                            is_qr_reference = true;
-                        }
-                        adr_buffer.extend_from_slice(e.as_ref());
-                    }
-                    State::InAdditionalDocRef => {
-                        adr_buffer.extend_from_slice(e.as_ref());
-                    }
-                    State::Default => {
-                        writer.write_event(Event::Text(e))?;
-                    }
-                }
-            }
-
-            Ok(Event::End(e)) => match state {
-                State::Skipping => {
-                    skip_depth -= 1;
-                    if skip_depth == 0 {
-                        state = State::Default;
-                    }
-                }
-                State::InAdditionalDocRefId | State::InAdditionalDocRef => {
-                    adr_depth -= 1;
-                    adr_buffer.extend_from_slice(b"</");
-                    adr_buffer.extend_from_slice(e.as_ref());
-                    adr_buffer.push(b'>');
-
-                    if state == State::InAdditionalDocRefId {
-                        state = State::InAdditionalDocRef;
-                    }
-
-                    if adr_depth == 0 {
-                        if !is_qr_reference {
-                            writer.get_mut().extend_from_slice(&adr_buffer);
-                        }
-                        state = State::Default;
-                    }
-                }
-                State::Default => {
-                    writer.write_event(Event::End(e))?;
-                }
-            },
-
-            Ok(Event::Empty(e)) => match state {
-                State::Skipping => {}
-                State::Default => {
-                    if e.local_name().as_ref() != b"UBLExtensions"
-                        && e.local_name().as_ref() != b"Signature"
-                    {
-                        writer.write_event(Event::Empty(e))?;
-                    }
-                }
-                _ => {
-                    adr_buffer.push(b'<');
-                    adr_buffer.extend_from_slice(e.as_ref());
-                    adr_buffer.extend_from_slice(b"/>");
-                }
-            },
-
-            Ok(Event::Eof) => break,
-
-            // Handle CData, Comments, Decl, PI explicitly to ensure byte-fidelity
-            Ok(ev) => match state {
-                State::Default => {
-                    writer.write_event(ev)?;
-                }
-                State::Skipping => {}
-                _ => {
-                    adr_buffer.extend_from_slice(ev.as_ref());
-                }
-            },
-            Err(e) => return Err(e.into()),
-        }
-        buf.clear();
-    }
-
-    Ok(writer.into_inner())
+    Ok(InvoiceDocument::parse(raw_xml)?.stripped_invoice)
 }
 
 /// Extracts the X509 certificate from signed XML.
+#[allow(clippy::collapsible_match)]
 pub fn extract_crt(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
     reader.config_mut().trim_text(true);
 
     let mut buf = Vec::with_capacity(1024);
@@ -197,24 +50,24 @@ pub fn extract_crt(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                let name = e.name();
-                let tag = name.as_ref();
+                let resolver = reader.resolver();
+                let is_ds = |local| is_element(resolver, e.name(), DS_NS, local);
 
                 if signature_depth > 0 {
                     signature_depth += 1;
-                } else if tag == b"ds:Signature" {
+                } else if is_ds("Signature") {
                     signature_depth = 1;
                 }
 
                 if signature_depth > 0 {
                     if key_info_depth > 0 {
                         key_info_depth += 1;
-                    } else if tag == b"ds:KeyInfo" {
+                    } else if is_ds("KeyInfo") {
                         key_info_depth = 1;
                     }
                 }
 
-                if key_info_depth > 0 && tag == b"ds:X509Certificate" {
+                if key_info_depth > 0 && is_ds("X509Certificate") {
                     current_certificate.clear();
                     in_certificate = true;
                 }
@@ -228,10 +81,9 @@ pub fn extract_crt(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
             }
 
             Ok(Event::End(e)) => {
-                let name = e.name();
-                let tag = name.as_ref();
-
-                if in_certificate && tag == b"ds:X509Certificate" {
+                if in_certificate
+                    && is_element(reader.resolver(), e.name(), DS_NS, "X509Certificate")
+                {
                     if current_certificate.is_empty() {
                         bail!("X509Certificate is empty");
                     }
@@ -256,12 +108,16 @@ pub fn extract_crt(xml: &[u8]) -> anyhow::Result<Vec<u8>> {
     Ok(certificate.into())
 }
 
-/// Extracts the SignedProperties element from XML signature.
+/// Extracts the SignedProperties element from XML signature, declaring the
+/// XAdES and XMLDSig namespaces on it under the prefixes in scope, along with
+/// the in-scope `inclusive_prefixes` of an exclusive canonicalization.
+#[allow(clippy::collapsible_match)]
 pub fn extract_signed_properties(
     xml: &[u8],
     expected_ns: Option<&[u8]>,
+    inclusive_prefixes: &[String],
 ) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
     reader.config_mut().trim_text(false);
 
     let mut buf = Vec::new();
@@ -273,16 +129,18 @@ pub fn extract_signed_properties(
     loop {
         match reader.read_event_into(&mut buf)? {
             Event::Start(e) => {
-                if e.local_name().as_ref() == b"SignedProperties" {
+                if !capturing && e.local_name().as_ref() == b"SignedProperties" {
                     if let Some(ns) = expected_ns {
-                        validate_namespace(&e, ns)?;
+                        validate_namespace(reader.resolver(), &e, ns)?;
                     }
                     capturing = true;
                     depth = 1;
-                    let mut elem = e.to_owned();
-                    elem.push_attribute(("xmlns:xades", "http://uri.etsi.org/01903/v1.3.2#"));
-                    elem.push_attribute(("xmlns:ds", "http://www.w3.org/2000/09/xmldsig#"));
-                    writer.write_event(Event::Start(elem))?;
+                    writer.write_event(Event::Start(fragment_root(
+                        reader.resolver(),
+                        &e,
+                        &[XADES_NS, DS_NS],
+                        inclusive_prefixes,
+                    )))?;
                 } else if capturing {
                     depth += 1;
                     writer.write_event(Event::Start(e.to_owned()))?;
@@ -327,9 +185,17 @@ pub fn extract_signed_properties(
     Ok(writer.into_inner().into_inner())
 }
 
-/// Extracts the SignedInfo element from XML signature.
-pub fn extract_signed_info(xml: &[u8], expected_ns: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
-    let mut reader = Reader::from_reader(Cursor::new(xml));
+/// Extracts the SignedInfo element from XML signature, declaring the XMLDSig
+/// namespace on it under the prefixes in scope, along with the in-scope
+/// `inclusive_prefixes` of an exclusive canonicalization. Comments are kept
+/// for the `#WithComments` canonicalization methods.
+#[allow(clippy::collapsible_match)]
+pub fn extract_signed_info(
+    xml: &[u8],
+    expected_ns: Option<&[u8]>,
+    inclusive_prefixes: &[String],
+) -> anyhow::Result<Vec<u8>> {
+    let mut reader = NsReader::from_reader(Cursor::new(xml));
     reader.config_mut().trim_text(false);
 
     let mut buf = Vec::new();
@@ -341,15 +207,18 @@ pub fn extract_signed_info(xml: &[u8], expected_ns: Option<&[u8]>) -> anyhow::Re
     loop {
         match reader.read_event_into(&mut buf)? {
             Event::Start(e) => {
-                if e.local_name().as_ref() == b"SignedInfo" {
+                if !capturing && e.local_name().as_ref() == b"SignedInfo" {
                     if let Some(ns) = expected_ns {
-                        validate_namespace(&e, ns)?;
+                        validate_namespace(reader.resolver(), &e, ns)?;
                     }
                     capturing = true;
                     depth = 1;
-                    let mut elem = e.to_owned();
-                    elem.push_attribute(("xmlns:ds", "http://www.w3.org/2000/09/xmldsig#"));
-                    writer.write_event(Event::Start(elem))?;
+                    writer.write_event(Event::Start(fragment_root(
+                        reader.resolver(),
+                        &e,
+                        &[DS_NS],
+                        inclusive_prefixes,
+                    )))?;
                 } else if capturing {
                     depth += 1;
                     writer.write_event(Event::Start(e.to_owned()))?;
@@ -368,6 +237,12 @@ pub fn extract_signed_info(xml: &[u8], expected_ns: Option<&[u8]>) -> anyhow::Re
                 }
             }
 
+            Event::Comment(e) => {
+                if capturing {
+                    writer.write_event(Event::Comment(e.to_owned()))?;
+                }
+            }
+
             Event::End(e) => {
                 if capturing {
                     writer.write_event(Event::End(e.to_owned()))?;
@@ -389,6 +264,27 @@ pub fn extract_signed_info(xml: &[u8], expected_ns: Option<&[u8]>) -> anyhow::Re
     Ok(writer.into_inner().into_inner())
 }
 
+/// `e` with the declarations it needs to be canonicalized on its own.
+fn fragment_root(
+    resolver: &NamespaceResolver,
+    e: &BytesStart,
+    namespaces: &[&str],
+    inclusive_prefixes: &[String],
+) -> BytesStart<'static> {
+    let in_scope = in_scope_declarations(resolver);
+    let mut declarations = fragment_declarations(&in_scope, e, namespaces);
+    for declaration in prefix_declarations(&in_scope, e, inclusive_prefixes) {
+        if !declarations.contains(&declaration) {
+            declarations.push(declaration);
+        }
+    }
+    let mut root = e.to_owned();
+    for (attribute, namespace) in declarations {
+        root.push_attribute((attribute.as_str(), namespace.as_str()));
+    }
+    root
+}
+
 /// Extracts the ICV (Invoice Counter Value) from invoice XML.
 pub fn extract_icv(invoice: &[u8]) -> anyhow::Result<i32> {
     let mut reader = Reader::from_reader(Cursor::new(invoice));
@@ -495,6 +391,7 @@ pub fn extract_crt_serial(invoice: &[u8]) -> anyhow::Result<BigNum> {
     BigNum::from_dec_str(&serial_value).context("Invalid X509 serial value")
 }
 /// Extracts the customer's company ID (TIN) from invoice XML.
+#[allow(clippy::collapsible_match)]
 pub fn extract_customer_tin(invoice: &[u8]) -> anyhow::Result<String> {
     let mut reader = Reader::from_reader(Cursor::new(invoice));
     reader.config_mut().trim_text(true);
@@ -544,6 +441,7 @@ pub fn extract_customer_tin(invoice: &[u8]) -> anyhow::Result<String> {
 }
 
 /// Extracts the supplier's company ID (TIN) from invoice XML.
+#[allow(clippy::collapsible_match)]
 pub fn extract_supplier_id(invoice: &[u8]) -> anyhow::Result<String> {
     let mut reader = Reader::from_reader(Cursor::new(invoice));
     reader.config_mut().trim_text(true);
@@ -600,6 +498,7 @@ enum PihState {
 }
 
 /// Extracts the PIH (Previous Invoice Hash) from invoice XML.
+#[allow(clippy::collapsible_match)]
 pub fn extract_pih(invoice: &[u8]) -> anyhow::Result<Vec<u8>> {
     let mut reader = Reader::from_reader(Cursor::new(invoice));
     reader.config_mut().trim_text(true);
@@ -675,6 +574,7 @@ enum ProfileState {
 }
 
 /// Extracts the profile ID (reporting or clearance) from invoice XML.
+#[allow(clippy::collapsible_match)]
 pub fn extract_profile_id(invoice: &[u8]) -> anyhow::Result<String> {
     let mut reader = Reader::from_reader(Cursor::new(invoice));
     reader.config_mut().trim_text(true);
@@ -722,6 +622,48 @@ pub fn extract_profile_id(invoice: &[u8]) -> anyhow::Result<String> {
     Ok(profile_id)
 }
 
+/// Extracts the invoice-level `cbc:UUID` (a direct child of the root element).
+#[allow(clippy::collapsible_match)]
+pub fn extract_uuid(invoice: &[u8]) -> anyhow::Result<String> {
+    let mut reader = Reader::from_reader(Cursor::new(invoice));
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::with_capacity(1024);
+    let mut depth = 0usize;
+    let mut in_uuid = false;
+    let mut uuid = String::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                depth += 1;
+                in_uuid = depth == 2 && e.local_name().as_ref() == b"UUID";
+            }
+            Ok(Event::Text(e)) => {
+                if in_uuid {
+                    uuid.push_str(&e.decode().context("failed to read the invoice UUID")?);
+                }
+            }
+            Ok(Event::End(_)) => {
+                if in_uuid {
+                    break;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => bail!("XML error: {e}"),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if uuid.is_empty() {
+        bail!("cbc:UUID not found in invoice");
+    }
+
+    Ok(uuid)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -942,7 +884,7 @@ mod tests {
                                 </xades:SignedProperties>
                             </xades:QualifyingProperties>"#;
 
-        assert_eq!(extract_signed_properties(xml.as_ref(), None).unwrap(),br#"<xades:SignedProperties Id="xadesSignedProperties" xmlns:xades="http://uri.etsi.org/01903/v1.3.2#" xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+        assert_eq!(extract_signed_properties(xml.as_ref(), None, &[]).unwrap(),br#"<xades:SignedProperties Id="xadesSignedProperties" xmlns:xades="http://uri.etsi.org/01903/v1.3.2#" xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
                                     <xades:SignedSignatureProperties>
                                         <xades:SigningTime>109384180981</xades:SigningTime> //the signging time (will change)
                                         <xades:SigningCertificate>
@@ -960,4 +902,26 @@ mod tests {
                                     </xades:SignedSignatureProperties>
                                 </xades:SignedProperties>"#);
     }
+
+    #[test]
+    fn test_extract_uuid_ignores_nested_uuids() {
+        let xml = br#"<Invoice xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+        <cbc:ID>SME00015</cbc:ID>
+        <cbc:UUID>550e8400-e29b-41d4-a716-446655440000</cbc:UUID>
+        <cac:AdditionalDocumentReference>
+            <cbc:ID>ICV</cbc:ID>
+            <cbc:UUID>23</cbc:UUID>
+        </cac:AdditionalDocumentReference>
+    </Invoice>"#;
+        assert_eq!(
+            extract_uuid(xml).unwrap(),
+            "550e8400-e29b-41d4-a716-446655440000"
+        );
+    }
+
+    #[test]
+    fn test_extract_uuid_missing() {
+        let xml = br#"<Invoice><cac:AdditionalDocumentReference><cbc:UUID>23</cbc:UUID></cac:AdditionalDocumentReference></Invoice>"#;
+        assert!(extract_uuid(xml).is_err());
+    }
 }
@@ -0,0 +1,165 @@
+use anyhow::{Context, bail};
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+
+use crate::config::xml_config::XmlLimits;
+
+/// Entities every XML document may reference without a DTD.
+const PREDEFINED_ENTITIES: [&[u8]; 5] = [b"lt", b"gt", b"amp", b"apos", b"quot"];
+
+/// Rejects `len` bytes of untrusted input larger than `limits.max_bytes`.
+pub fn check_size(len: usize, limits: &XmlLimits) -> anyhow::Result<()> {
+    if len > limits.max_bytes {
+        bail!(
+            "xml guard: document is {len} bytes, limit is {}",
+            limits.max_bytes
+        );
+    }
+    Ok(())
+}
+
+/// Streams `xml` once, without building a tree or expanding anything, and
+/// rejects DTDs, references to undeclared entities and documents beyond
+/// `limits`. Runs before any other parser sees untrusted XML.
+pub fn check_xml(xml: &[u8], limits: &XmlLimits) -> anyhow::Result<()> {
+    check_size(xml.len(), limits)?;
+
+    let mut reader = Reader::from_reader(xml);
+    let mut depth = 0usize;
+    let mut elements = 0usize;
+    loop {
+        match reader.read_event().context("invalid xml")? {
+            Event::DocType(_) => bail!("xml guard: DOCTYPE declarations are not allowed"),
+            Event::Start(e) => {
+                depth += 1;
+                elements += 1;
+                check_element(&e, depth, elements, limits)?;
+            }
+            Event::Empty(e) => {
+                elements += 1;
+                check_element(&e, depth + 1, elements, limits)?;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Text(text) => check_text_size(text.len(), limits)?,
+            Event::GeneralRef(reference) => check_entity(&reference)?,
+            Event::CData(data) => check_text_size(data.len(), limits)?,
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn check_element(
+    element: &BytesStart,
+    depth: usize,
+    elements: usize,
+    limits: &XmlLimits,
+) -> anyhow::Result<()> {
+    if depth > limits.max_depth {
+        bail!(
+            "xml guard: elements are nested deeper than {}",
+            limits.max_depth
+        );
+    }
+    if elements > limits.max_elements {
+        bail!(
+            "xml guard: document has more than {} elements",
+            limits.max_elements
+        );
+    }
+    let mut attributes = 0usize;
+    for attribute in element.attributes() {
+        let attribute = attribute.context("invalid xml attribute")?;
+        attributes += 1;
+        if attributes > limits.max_attributes {
+            bail!(
+                "xml guard: element has more than {} attributes",
+                limits.max_attributes
+            );
+        }
+        check_text_size(attribute.value.len(), limits)?;
+        check_references(&attribute.value)?;
+    }
+    Ok(())
+}
+
+fn check_text_size(len: usize, limits: &XmlLimits) -> anyhow::Result<()> {
+    if len > limits.max_text_bytes {
+        bail!(
+            "xml guard: text node is {len} bytes, limit is {}",
+            limits.max_text_bytes
+        );
+    }
+    Ok(())
+}
+
+/// Checks the entity references in a raw attribute value.
+fn check_references(raw: &[u8]) -> anyhow::Result<()> {
+    let mut rest = raw;
+    while let Some(start) = rest.iter().position(|&b| b == b'&') {
+        rest = &rest[start + 1..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b';')
+            .context("invalid xml: unterminated entity reference")?;
+        check_entity(&rest[..end])?;
+        rest = &rest[end + 1..];
+    }
+    Ok(())
+}
+
+/// Only character references and the predefined entities can be resolved
+/// without a DTD; anything else is an entity the document expects to be
+/// declared somewhere.
+fn check_entity(name: &[u8]) -> anyhow::Result<()> {
+    if !name.starts_with(b"#") && !PREDEFINED_ENTITIES.contains(&name) {
+        bail!(
+            "xml guard: undeclared entity reference &{};",
+            String::from_utf8_lossy(name)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: XmlLimits = XmlLimits {
+        max_bytes: 1024,
+        max_depth: 3,
+        max_elements: 5,
+        max_attributes: 2,
+        max_text_bytes: 16,
+    };
+
+    fn rejected(xml: &str) -> String {
+        check_xml(xml.as_bytes(), &LIMITS).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_documents_within_the_limits() {
+        let xml = r#"<?xml version="1.0"?><a x="1" y="&amp;"><b><c>&lt;&#x41;</c></b><d/></a>"#;
+        assert!(check_xml(xml.as_bytes(), &LIMITS).is_ok());
+    }
+
+    #[test]
+    fn rejects_dtds_and_undeclared_entities() {
+        let billion_laughs = r#"<?xml version="1.0"?><!DOCTYPE a [<!ENTITY lol "lol"><!ENTITY lol2 "&lol;&lol;">]><a>&lol2;</a>"#;
+        assert!(rejected(billion_laughs).contains("DOCTYPE"));
+        assert!(rejected("<a>&xxe;</a>").contains("undeclared entity"));
+        assert!(rejected(r#"<a b="&xxe;"/>"#).contains("undeclared entity"));
+    }
+
+    #[test]
+    fn enforces_the_configured_limits() {
+        assert!(rejected("<a><b><c><d/></c></b></a>").contains("nested deeper than 3"));
+        assert!(rejected("<a><b/><b/><b/><b/><b/></a>").contains("more than 5 elements"));
+        assert!(rejected(r#"<a x="1" y="2" z="3"/>"#).contains("more than 2 attributes"));
+        assert!(rejected("<a>0123456789abcdefg</a>").contains("text node is 17 bytes"));
+        let large = format!("<a>{}</a>", "<b/>".repeat(300));
+        assert!(rejected(&large).contains("limit is 1024"));
+    }
+}
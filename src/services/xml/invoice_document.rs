@@ -0,0 +1,1050 @@
+use anyhow::{Context, bail};
+use quick_xml::{
+    NsReader, Reader, Writer,
+    escape::resolve_predefined_entity,
+    events::{BytesRef, BytesStart, Event},
+    name::NamespaceResolver,
+};
+
+use crate::services::xml::namespaces::{DS_NS, XADES_NS, in_scope_declarations, is_element};
+
+/// Typed view of a submitted UBL invoice, built in a single pass over the raw
+/// bytes. Pipeline stages read from here instead of re-parsing the XML.
+#[derive(Debug, Default)]
+pub struct InvoiceDocument {
+    pub uuid: String,
+    pub id: String,
+    pub profile_id: String,
+    pub invoice_type_code: String,
+    /// `name` attribute of `cbc:InvoiceTypeCode` (transaction flags).
+    pub invoice_type_name: Option<String>,
+    pub issue_date: String,
+    pub issue_time: String,
+    pub document_currency: String,
+    pub tax_currency: Option<String>,
+    pub supplier: Party,
+    pub customer: Party,
+    pub totals: MonetaryTotals,
+    /// Document-level `cac:TaxTotal/cbc:TaxAmount` values, in document order.
+    pub tax_totals: Vec<Amount>,
+    /// `cac:TaxTotal/cac:TaxSubtotal` entries of every document-level tax
+    /// total.
+    pub tax_subtotals: Vec<TaxSubtotal>,
+    pub lines: Vec<InvoiceLine>,
+    /// Invoices a credit or debit note corrects.
+    pub billing_references: Vec<BillingReference>,
+    /// `cac:PaymentMeans/cbc:PaymentMeansCode` values, in document order.
+    pub payment_means_codes: Vec<String>,
+    pub references: DocumentReferences,
+    pub signatures: Vec<SignatureBlock>,
+    /// Invoice with UBL extensions, signatures and the QR reference removed:
+    /// the content the standard signature profile's XPath filters select.
+    pub stripped_invoice: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Party {
+    /// `cac:PartyTaxScheme/cbc:CompanyID`.
+    pub tin: String,
+    pub registration_name: Option<String>,
+    pub address: Address,
+}
+
+/// `cac:PostalAddress`.
+#[derive(Debug, Default)]
+pub struct Address {
+    pub street_name: Option<String>,
+    pub building_number: Option<String>,
+    pub city_name: Option<String>,
+    pub postal_zone: Option<String>,
+    /// `cac:Country/cbc:IdentificationCode`.
+    pub country_code: Option<String>,
+}
+
+/// A `cac:InvoiceLine`.
+#[derive(Debug, Default)]
+pub struct InvoiceLine {
+    pub id: String,
+    pub quantity: Option<String>,
+    /// `unitCode` attribute of `cbc:InvoicedQuantity`.
+    pub unit_code: Option<String>,
+    pub line_extension: Option<Amount>,
+    /// `cac:TaxTotal/cbc:TaxAmount` of the line.
+    pub tax_amount: Option<Amount>,
+    pub item_name: Option<String>,
+    /// `cac:CommodityClassification/cbc:ItemClassificationCode`.
+    pub classification_code: Option<String>,
+    /// `cac:ClassifiedTaxCategory/cbc:ID`.
+    pub tax_category: Option<String>,
+    /// `cac:ClassifiedTaxCategory/cbc:Percent`.
+    pub tax_percent: Option<String>,
+    /// `cac:ClassifiedTaxCategory/cbc:TaxExemptionReasonCode`.
+    pub tax_exemption_reason_code: Option<String>,
+    pub price: Option<Amount>,
+}
+
+/// A document-level `cac:TaxSubtotal`.
+#[derive(Debug, Default)]
+pub struct TaxSubtotal {
+    pub taxable_amount: Option<Amount>,
+    pub tax_amount: Option<Amount>,
+    /// `cac:TaxCategory/cbc:ID`.
+    pub category: Option<String>,
+    /// `cac:TaxCategory/cbc:Percent`.
+    pub percent: Option<String>,
+    /// `cac:TaxCategory/cbc:TaxExemptionReasonCode`.
+    pub exemption_reason_code: Option<String>,
+    /// `cac:TaxCategory/cbc:TaxExemptionReason`.
+    pub exemption_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub value: String,
+    pub currency: Option<String>,
+}
+
+/// `cac:LegalMonetaryTotal` amounts.
+#[derive(Debug, Default)]
+pub struct MonetaryTotals {
+    pub line_extension: Option<Amount>,
+    pub tax_exclusive: Option<Amount>,
+    pub tax_inclusive: Option<Amount>,
+    pub allowance_total: Option<Amount>,
+    pub charge_total: Option<Amount>,
+    pub prepaid: Option<Amount>,
+    pub payable: Option<Amount>,
+}
+
+/// `cac:BillingReference/cac:InvoiceDocumentReference`.
+#[derive(Debug, Default)]
+pub struct BillingReference {
+    /// `cbc:ID`: the number of the referenced invoice.
+    pub id: String,
+    pub issue_date: Option<String>,
+}
+
+/// Values carried in `cac:AdditionalDocumentReference` blocks.
+#[derive(Debug, Default)]
+pub struct DocumentReferences {
+    pub icv: Option<String>,
+    /// Base64 hash of the previous invoice.
+    pub pih: Option<String>,
+    /// Base64 TLV payload of the QR code.
+    pub qr: Option<String>,
+}
+
+const COUNTERSIGNED_SIGNATURE: &str = "http://uri.etsi.org/01903#CountersignedSignature";
+
+/// A `Signature` element containing `ds:SignedInfo`, captured verbatim.
+#[derive(Debug, Default)]
+pub struct SignatureBlock {
+    pub xml: Vec<u8>,
+    pub id: Option<String>,
+    /// URI of the `CountersignedSignature` reference, set on STC stamps.
+    pub countersigned: Option<String>,
+    pub certificates: Vec<String>,
+    pub signature_value: Option<String>,
+    pub signing_time: Option<String>,
+    /// Namespace declarations in scope on the `Signature` element but made
+    /// by its ancestors, as `(attribute, URI)` pairs.
+    pub namespaces: Vec<(String, String)>,
+}
+
+impl SignatureBlock {
+    /// Base64 DER of the signing certificate from `ds:KeyInfo`.
+    pub fn certificate(&self) -> anyhow::Result<&str> {
+        match self.certificates.as_slice() {
+            [] => bail!("X509Certificate not found in signature KeyInfo"),
+            [certificate] if certificate.is_empty() => bail!("X509Certificate is empty"),
+            [certificate] => Ok(certificate),
+            _ => bail!("multiple X509Certificate elements found in signature KeyInfo"),
+        }
+    }
+
+    /// The captured XML with the inherited namespace declarations added to
+    /// its root, so it can be parsed on its own.
+    pub fn scoped_xml(&self) -> anyhow::Result<Vec<u8>> {
+        if self.namespaces.is_empty() {
+            return Ok(self.xml.clone());
+        }
+        let mut reader = Reader::from_reader(self.xml.as_slice());
+        let Event::Start(root) = reader.read_event()? else {
+            bail!("signature block does not start with an element");
+        };
+        let mut root = root.into_owned();
+        for (attribute, namespace) in &self.namespaces {
+            root.push_attribute((attribute.as_str(), namespace.as_str()));
+        }
+        let mut writer = Writer::new(Vec::with_capacity(self.xml.len()));
+        writer.write_event(Event::Start(root))?;
+        let mut xml = writer.into_inner();
+        xml.extend_from_slice(&self.xml[reader.buffer_position() as usize..]);
+        Ok(xml)
+    }
+}
+
+impl InvoiceDocument {
+    pub fn parse(raw_xml: &[u8]) -> anyhow::Result<Self> {
+        let mut parser = Parser::default();
+        let mut reader = NsReader::from_reader(raw_xml);
+        let mut buf = Vec::new();
+
+        loop {
+            let event = reader
+                .read_event_into(&mut buf)
+                .map_err(|e| anyhow::anyhow!("XML error: {e}"))?;
+            if matches!(event, Event::Eof) {
+                break;
+            }
+            parser.capture(&event)?;
+            parser.capture_signature(&event, reader.resolver())?;
+            parser.strip(event)?;
+            buf.clear();
+        }
+
+        Ok(parser.finish())
+    }
+
+    /// The supplier's signature block.
+    pub fn signature(&self) -> anyhow::Result<&SignatureBlock> {
+        let mut signatures = self
+            .signatures
+            .iter()
+            .filter(|signature| signature.countersigned.is_none());
+        match (signatures.next(), signatures.next()) {
+            (Some(signature), None) => Ok(signature),
+            (None, _) => bail!("ds:Signature not found"),
+            _ => bail!("multiple ds:Signature elements found"),
+        }
+    }
+
+    /// The STC stamp counter-signing the supplier's signature, if cleared.
+    pub fn stamp(&self) -> anyhow::Result<Option<&SignatureBlock>> {
+        let mut stamps = self
+            .signatures
+            .iter()
+            .filter(|signature| signature.countersigned.is_some());
+        match (stamps.next(), stamps.next()) {
+            (stamp, None) => Ok(stamp),
+            _ => bail!("multiple STC stamps found"),
+        }
+    }
+
+    pub fn profile_id(&self) -> anyhow::Result<&str> {
+        if self.profile_id.is_empty() {
+            bail!("cbc:ProfileID not found in invoice");
+        }
+        Ok(&self.profile_id)
+    }
+
+    pub fn icv(&self) -> anyhow::Result<i32> {
+        let icv = self
+            .references
+            .icv
+            .as_deref()
+            .filter(|icv| !icv.is_empty())
+            .context("ICV not found in invoice")?;
+        icv.parse::<i32>()
+            .map_err(|_| anyhow::anyhow!("Invalid ICV value: {icv}"))
+    }
+
+    pub fn pih(&self) -> anyhow::Result<&str> {
+        self.references
+            .pih
+            .as_deref()
+            .filter(|pih| !pih.is_empty())
+            .context("PIH DigestValue not found in valid block")
+    }
+
+    /// Base64 TLV payload of the QR `cac:AdditionalDocumentReference`.
+    pub fn qr(&self) -> anyhow::Result<&str> {
+        self.references
+            .qr
+            .as_deref()
+            .map(str::trim)
+            .filter(|qr| !qr.is_empty())
+            .context("QR not found in invoice")
+    }
+
+    /// The document-level `TaxTotal` in the document currency. With a
+    /// `TaxCurrencyCode` the invoice carries a second one in that currency.
+    pub fn document_tax_total(&self) -> Option<&Amount> {
+        let currency = self.document_currency.trim();
+        self.tax_totals
+            .iter()
+            .find(|tax| match tax.currency.as_deref() {
+                None => true,
+                Some(tax_currency) => !currency.is_empty() && tax_currency.trim() == currency,
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Supplier,
+    Customer,
+}
+
+#[derive(Clone, Copy)]
+enum AddressField {
+    StreetName,
+    BuildingNumber,
+    CityName,
+    PostalZone,
+    CountryCode,
+}
+
+#[derive(Clone, Copy)]
+enum LineField {
+    Id,
+    Quantity,
+    LineExtension,
+    TaxAmount,
+    ItemName,
+    ClassificationCode,
+    TaxCategory,
+    TaxPercent,
+    TaxExemptionReasonCode,
+    Price,
+}
+
+#[derive(Clone, Copy)]
+enum SubtotalField {
+    TaxableAmount,
+    TaxAmount,
+    Category,
+    Percent,
+    ExemptionReasonCode,
+    ExemptionReason,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Uuid,
+    Id,
+    ProfileId,
+    InvoiceTypeCode,
+    IssueDate,
+    IssueTime,
+    DocumentCurrency,
+    TaxCurrency,
+    PartyTin(Role),
+    PartyName(Role),
+    PartyAddress(Role, AddressField),
+    TaxTotal,
+    LineExtension,
+    TaxExclusive,
+    TaxInclusive,
+    AllowanceTotal,
+    ChargeTotal,
+    Prepaid,
+    Payable,
+    ReferenceId,
+    ReferenceUuid,
+    ReferenceBinary,
+    BillingReferenceId,
+    BillingReferenceIssueDate,
+    PaymentMeansCode,
+    Line(LineField),
+    Subtotal(SubtotalField),
+}
+
+#[derive(Clone, Copy)]
+enum SignatureField {
+    Certificate,
+    SignatureValue,
+    SigningTime,
+}
+
+#[derive(Default)]
+struct PendingReference {
+    id: String,
+    uuid: Option<String>,
+    binary: Option<String>,
+}
+
+struct SignatureCapture {
+    writer: Writer<Vec<u8>>,
+    depth: usize,
+    has_signed_info: bool,
+    key_info_depth: usize,
+    field: Option<SignatureField>,
+    text: String,
+    block: SignatureBlock,
+}
+
+#[derive(PartialEq)]
+enum StripState {
+    Default,
+    Skipping(usize),
+    InReference(usize),
+}
+
+struct Parser {
+    document: InvoiceDocument,
+    /// Local names of the open elements.
+    path: Vec<String>,
+    field: Option<(Field, Option<String>)>,
+    text: String,
+    reference: Option<PendingReference>,
+    /// Id of the last closed top-level document reference.
+    last_reference_id: Option<String>,
+    signature: Option<SignatureCapture>,
+    strip_state: StripState,
+    stripped: Writer<Vec<u8>>,
+    reference_buffer: Writer<Vec<u8>>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            document: InvoiceDocument::default(),
+            path: Vec::new(),
+            field: None,
+            text: String::new(),
+            reference: None,
+            last_reference_id: None,
+            signature: None,
+            strip_state: StripState::Default,
+            stripped: Writer::new(Vec::new()),
+            reference_buffer: Writer::new(Vec::new()),
+        }
+    }
+}
+
+impl Parser {
+    fn finish(mut self) -> InvoiceDocument {
+        self.document.stripped_invoice = self.stripped.into_inner();
+        self.document
+    }
+
+    /// Tracks the element path and records the typed fields.
+    fn capture(&mut self, event: &Event) -> anyhow::Result<()> {
+        match event {
+            Event::Start(e) => {
+                let name = local_name(e)?;
+                if name == "AdditionalDocumentReference" {
+                    self.last_reference_id = None;
+                    if self.path.len() == 1 {
+                        self.reference = Some(PendingReference::default());
+                    }
+                }
+                if name == "InvoiceLine" && self.path.len() == 1 {
+                    self.document.lines.push(InvoiceLine::default());
+                }
+                if name == "TaxSubtotal" && self.path.len() == 2 && self.path[1] == "TaxTotal" {
+                    self.document.tax_subtotals.push(TaxSubtotal::default());
+                }
+                if name == "InvoiceDocumentReference"
+                    && self.path.len() == 2
+                    && self.path[1] == "BillingReference"
+                {
+                    self.document
+                        .billing_references
+                        .push(BillingReference::default());
+                }
+                self.field = classify(&self.path, &name).map(|field| {
+                    let attribute = match field {
+                        Field::InvoiceTypeCode => attr(e, b"name"),
+                        Field::Line(LineField::Quantity) => attr(e, b"unitCode"),
+                        _ => attr(e, b"currencyID"),
+                    };
+                    (field, attribute)
+                });
+                self.text.clear();
+                self.path.push(name);
+            }
+            Event::Text(e) if self.field.is_some() => {
+                self.text
+                    .push_str(&e.decode().context("failed to decode XML")?);
+            }
+            Event::CData(e) if self.field.is_some() => {
+                self.text
+                    .push_str(&e.decode().context("failed to decode XML")?);
+            }
+            Event::GeneralRef(e) if self.field.is_some() => push_ref(&mut self.text, e)?,
+            Event::End(_) => {
+                if let Some((field, attribute)) = self.field.take() {
+                    let value = self.text.trim().to_owned();
+                    self.record(field, value, attribute);
+                }
+                self.path.pop();
+                if self.path.len() == 1
+                    && let Some(reference) = self.reference.take()
+                {
+                    self.last_reference_id = Some(reference.id.clone());
+                    let references = &mut self.document.references;
+                    match reference.id.as_str() {
+                        "ICV" => set_first(&mut references.icv, reference.uuid),
+                        "PIH" => set_first(&mut references.pih, reference.binary),
+                        "QR" => set_first(&mut references.qr, reference.binary),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, field: Field, value: String, attribute: Option<String>) {
+        let document = &mut self.document;
+        let amount = || {
+            Some(Amount {
+                value: value.clone(),
+                currency: attribute.clone(),
+            })
+        };
+        match field {
+            Field::Uuid => document.uuid = value,
+            Field::Id => document.id = value,
+            Field::ProfileId => document.profile_id = value,
+            Field::InvoiceTypeCode => {
+                document.invoice_type_name = attribute;
+                document.invoice_type_code = value;
+            }
+            Field::IssueDate => document.issue_date = value,
+            Field::IssueTime => document.issue_time = value,
+            Field::DocumentCurrency => document.document_currency = value,
+            Field::TaxCurrency => document.tax_currency = Some(value),
+            Field::PartyTin(role) => party(document, role).tin.push_str(&value),
+            Field::PartyName(role) => party(document, role).registration_name = Some(value),
+            Field::PartyAddress(role, field) => {
+                let address = &mut party(document, role).address;
+                let slot = match field {
+                    AddressField::StreetName => &mut address.street_name,
+                    AddressField::BuildingNumber => &mut address.building_number,
+                    AddressField::CityName => &mut address.city_name,
+                    AddressField::PostalZone => &mut address.postal_zone,
+                    AddressField::CountryCode => &mut address.country_code,
+                };
+                *slot = Some(value);
+            }
+            Field::TaxTotal => document.tax_totals.extend(amount()),
+            Field::LineExtension => document.totals.line_extension = amount(),
+            Field::TaxExclusive => document.totals.tax_exclusive = amount(),
+            Field::TaxInclusive => document.totals.tax_inclusive = amount(),
+            Field::AllowanceTotal => document.totals.allowance_total = amount(),
+            Field::ChargeTotal => document.totals.charge_total = amount(),
+            Field::Prepaid => document.totals.prepaid = amount(),
+            Field::Payable => document.totals.payable = amount(),
+            Field::ReferenceId | Field::ReferenceUuid | Field::ReferenceBinary => {
+                if let Some(reference) = self.reference.as_mut() {
+                    match field {
+                        Field::ReferenceId => reference.id = value,
+                        Field::ReferenceUuid => reference.uuid = Some(value),
+                        _ => reference.binary = Some(value),
+                    }
+                }
+            }
+            Field::BillingReferenceId | Field::BillingReferenceIssueDate => {
+                let Some(reference) = document.billing_references.last_mut() else {
+                    return;
+                };
+                match field {
+                    Field::BillingReferenceId => reference.id = value,
+                    _ => reference.issue_date = Some(value),
+                }
+            }
+            Field::PaymentMeansCode => document.payment_means_codes.push(value),
+            Field::Line(field) => {
+                let Some(line) = document.lines.last_mut() else {
+                    return;
+                };
+                match field {
+                    LineField::Id => line.id = value,
+                    LineField::Quantity => {
+                        line.quantity = Some(value);
+                        line.unit_code = attribute;
+                    }
+                    LineField::LineExtension => line.line_extension = amount(),
+                    LineField::TaxAmount => line.tax_amount = amount(),
+                    LineField::ItemName => line.item_name = Some(value),
+                    LineField::ClassificationCode => line.classification_code = Some(value),
+                    LineField::TaxCategory => line.tax_category = Some(value),
+                    LineField::TaxPercent => line.tax_percent = Some(value),
+                    LineField::TaxExemptionReasonCode => {
+                        line.tax_exemption_reason_code = Some(value)
+                    }
+                    LineField::Price => line.price = amount(),
+                }
+            }
+            Field::Subtotal(field) => {
+                let Some(subtotal) = document.tax_subtotals.last_mut() else {
+                    return;
+                };
+                match field {
+                    SubtotalField::TaxableAmount => subtotal.taxable_amount = amount(),
+                    SubtotalField::TaxAmount => subtotal.tax_amount = amount(),
+                    SubtotalField::Category => subtotal.category = Some(value),
+                    SubtotalField::Percent => subtotal.percent = Some(value),
+                    SubtotalField::ExemptionReasonCode => {
+                        subtotal.exemption_reason_code = Some(value)
+                    }
+                    SubtotalField::ExemptionReason => subtotal.exemption_reason = Some(value),
+                }
+            }
+        }
+    }
+
+    /// Copies every `ds:Signature` subtree and keeps the ones holding a
+    /// `ds:SignedInfo`, together with their certificate and signing time.
+    /// Elements are matched by namespace URI, whatever their prefix.
+    fn capture_signature(
+        &mut self,
+        event: &Event,
+        resolver: &NamespaceResolver,
+    ) -> anyhow::Result<()> {
+        if self.signature.is_none() {
+            if let Event::Start(e) = event
+                && is_element(resolver, e.name(), DS_NS, "Signature")
+            {
+                let namespaces = in_scope_declarations(resolver)
+                    .into_iter()
+                    .filter(|(attribute, _)| {
+                        !e.attributes()
+                            .flatten()
+                            .any(|declared| declared.key.as_ref() == attribute.as_bytes())
+                    })
+                    .collect();
+                let mut writer = Writer::new(Vec::new());
+                writer.write_event(event.borrow())?;
+                self.signature = Some(SignatureCapture {
+                    writer,
+                    depth: 1,
+                    has_signed_info: false,
+                    key_info_depth: 0,
+                    field: None,
+                    text: String::new(),
+                    block: SignatureBlock {
+                        id: attr(e, b"Id"),
+                        namespaces,
+                        ..SignatureBlock::default()
+                    },
+                });
+            }
+            return Ok(());
+        }
+
+        let Some(capture) = self.signature.as_mut() else {
+            return Ok(());
+        };
+        capture.writer.write_event(event.borrow())?;
+
+        match event {
+            Event::Start(e) | Event::Empty(e) => {
+                let is_ds = |local| is_element(resolver, e.name(), DS_NS, local);
+                if is_ds("SignedInfo") {
+                    capture.has_signed_info = true;
+                }
+                if is_ds("Reference")
+                    && attr(e, b"Type").as_deref() == Some(COUNTERSIGNED_SIGNATURE)
+                {
+                    capture.block.countersigned = attr(e, b"URI");
+                }
+                if matches!(event, Event::Empty(_)) {
+                    return Ok(());
+                }
+                capture.depth += 1;
+                if capture.key_info_depth > 0 {
+                    capture.key_info_depth += 1;
+                } else if is_ds("KeyInfo") {
+                    capture.key_info_depth = 1;
+                }
+                capture.field = if capture.key_info_depth > 0 && is_ds("X509Certificate") {
+                    Some(SignatureField::Certificate)
+                } else if is_ds("SignatureValue") {
+                    Some(SignatureField::SignatureValue)
+                } else if is_element(resolver, e.name(), XADES_NS, "SigningTime") {
+                    Some(SignatureField::SigningTime)
+                } else {
+                    None
+                };
+                capture.text.clear();
+            }
+            Event::Text(e) if capture.field.is_some() => {
+                capture
+                    .text
+                    .push_str(&e.decode().context("failed to decode XML")?);
+            }
+            Event::End(_) => {
+                if let Some(field) = capture.field.take() {
+                    let value = capture.text.trim().to_owned();
+                    match field {
+                        SignatureField::Certificate => capture.block.certificates.push(value),
+                        SignatureField::SignatureValue => {
+                            capture.block.signature_value = Some(value)
+                        }
+                        SignatureField::SigningTime => capture.block.signing_time = Some(value),
+                    }
+                }
+                capture.key_info_depth = capture.key_info_depth.saturating_sub(1);
+                capture.depth -= 1;
+                if capture.depth == 0
+                    && let Some(capture) = self.signature.take()
+                    && capture.has_signed_info
+                {
+                    let mut block = capture.block;
+                    block.xml = capture.writer.into_inner();
+                    self.document.signatures.push(block);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Writes the hashed view of the invoice: drops the XML declaration, UBL
+    /// extensions, `Signature` elements and the QR document reference.
+    fn strip(&mut self, event: Event) -> anyhow::Result<()> {
+        match (&self.strip_state, &event) {
+            (StripState::Skipping(depth), Event::Start(_)) => {
+                self.strip_state = StripState::Skipping(depth + 1);
+            }
+            (StripState::Skipping(depth), Event::End(_)) => {
+                self.strip_state = match depth - 1 {
+                    0 => StripState::Default,
+                    depth => StripState::Skipping(depth),
+                };
+            }
+            (StripState::Skipping(_), _) => {}
+            (StripState::Default, Event::Start(e)) if is_stripped_element(e) => {
+                self.strip_state = StripState::Skipping(1);
+            }
+            (StripState::Default, Event::Empty(e)) if is_stripped_element(e) => {}
+            (StripState::Default, Event::Start(e))
+                if e.local_name().as_ref() == b"AdditionalDocumentReference" =>
+            {
+                self.reference_buffer = Writer::new(Vec::new());
+                self.reference_buffer.write_event(event)?;
+                self.strip_state = StripState::InReference(1);
+            }
+            (StripState::Default, Event::Decl(_)) => {}
+            (StripState::Default, _) => self.stripped.write_event(event)?,
+            (StripState::InReference(depth), _) => {
+                let depth = match event {
+                    Event::Start(_) => depth + 1,
+                    Event::End(_) => depth - 1,
+                    _ => *depth,
+                };
+                self.reference_buffer.write_event(event)?;
+                if depth > 0 {
+                    self.strip_state = StripState::InReference(depth);
+                    return Ok(());
+                }
+                self.strip_state = StripState::Default;
+                let buffered = std::mem::replace(&mut self.reference_buffer, Writer::new(vec![]));
+                // `capture` has already seen this end tag, so the reference
+                // id of a direct child is known here.
+                let is_qr = self.last_reference_id.as_deref() == Some("QR");
+                if !is_qr {
+                    self.stripped
+                        .get_mut()
+                        .extend_from_slice(&buffered.into_inner());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_stripped_element(e: &BytesStart) -> bool {
+    matches!(e.local_name().as_ref(), b"UBLExtensions" | b"Signature")
+}
+
+fn classify(path: &[String], name: &str) -> Option<Field> {
+    let parents: Vec<&str> = path.iter().skip(1).map(String::as_str).collect();
+    let field = match (parents.as_slice(), name) {
+        ([], "UUID") => Field::Uuid,
+        ([], "ID") => Field::Id,
+        ([], "ProfileID") => Field::ProfileId,
+        ([], "InvoiceTypeCode") => Field::InvoiceTypeCode,
+        ([], "IssueDate") => Field::IssueDate,
+        ([], "IssueTime") => Field::IssueTime,
+        ([], "DocumentCurrencyCode") => Field::DocumentCurrency,
+        ([], "TaxCurrencyCode") => Field::TaxCurrency,
+        (["AdditionalDocumentReference"], "ID") => Field::ReferenceId,
+        (["AdditionalDocumentReference"], "UUID") => Field::ReferenceUuid,
+        (["AdditionalDocumentReference", "Attachment"], "EmbeddedDocumentBinaryObject") => {
+            Field::ReferenceBinary
+        }
+        (["BillingReference", "InvoiceDocumentReference"], "ID") => Field::BillingReferenceId,
+        (["BillingReference", "InvoiceDocumentReference"], "IssueDate") => {
+            Field::BillingReferenceIssueDate
+        }
+        (["PaymentMeans"], "PaymentMeansCode") => Field::PaymentMeansCode,
+        ([party, .., "PartyTaxScheme"], "CompanyID") => Field::PartyTin(role(party)?),
+        ([party, .., "PartyLegalEntity"], "RegistrationName") => Field::PartyName(role(party)?),
+        ([party, .., "PostalAddress"], "StreetName") => {
+            Field::PartyAddress(role(party)?, AddressField::StreetName)
+        }
+        ([party, .., "PostalAddress"], "BuildingNumber") => {
+            Field::PartyAddress(role(party)?, AddressField::BuildingNumber)
+        }
+        ([party, .., "PostalAddress"], "CityName") => {
+            Field::PartyAddress(role(party)?, AddressField::CityName)
+        }
+        ([party, .., "PostalAddress"], "PostalZone") => {
+            Field::PartyAddress(role(party)?, AddressField::PostalZone)
+        }
+        ([party, .., "PostalAddress", "Country"], "IdentificationCode") => {
+            Field::PartyAddress(role(party)?, AddressField::CountryCode)
+        }
+        (["InvoiceLine"], "ID") => Field::Line(LineField::Id),
+        (["InvoiceLine"], "InvoicedQuantity") => Field::Line(LineField::Quantity),
+        (["InvoiceLine"], "LineExtensionAmount") => Field::Line(LineField::LineExtension),
+        (["InvoiceLine", "TaxTotal"], "TaxAmount") => Field::Line(LineField::TaxAmount),
+        (["InvoiceLine", "Item"], "Name") => Field::Line(LineField::ItemName),
+        (["InvoiceLine", "Item", "CommodityClassification"], "ItemClassificationCode") => {
+            Field::Line(LineField::ClassificationCode)
+        }
+        (["InvoiceLine", "Item", "ClassifiedTaxCategory"], "ID") => {
+            Field::Line(LineField::TaxCategory)
+        }
+        (["InvoiceLine", "Item", "ClassifiedTaxCategory"], "Percent") => {
+            Field::Line(LineField::TaxPercent)
+        }
+        (["InvoiceLine", "Item", "ClassifiedTaxCategory"], "TaxExemptionReasonCode") => {
+            Field::Line(LineField::TaxExemptionReasonCode)
+        }
+        (["InvoiceLine", "Price"], "PriceAmount") => Field::Line(LineField::Price),
+        (["TaxTotal"], "TaxAmount") => Field::TaxTotal,
+        (["TaxTotal", "TaxSubtotal"], "TaxableAmount") => {
+            Field::Subtotal(SubtotalField::TaxableAmount)
+        }
+        (["TaxTotal", "TaxSubtotal"], "TaxAmount") => Field::Subtotal(SubtotalField::TaxAmount),
+        (["TaxTotal", "TaxSubtotal", "TaxCategory"], "ID") => {
+            Field::Subtotal(SubtotalField::Category)
+        }
+        (["TaxTotal", "TaxSubtotal", "TaxCategory"], "Percent") => {
+            Field::Subtotal(SubtotalField::Percent)
+        }
+        (["TaxTotal", "TaxSubtotal", "TaxCategory"], "TaxExemptionReasonCode") => {
+            Field::Subtotal(SubtotalField::ExemptionReasonCode)
+        }
+        (["TaxTotal", "TaxSubtotal", "TaxCategory"], "TaxExemptionReason") => {
+            Field::Subtotal(SubtotalField::ExemptionReason)
+        }
+        (["LegalMonetaryTotal"], "LineExtensionAmount") => Field::LineExtension,
+        (["LegalMonetaryTotal"], "TaxExclusiveAmount") => Field::TaxExclusive,
+        (["LegalMonetaryTotal"], "TaxInclusiveAmount") => Field::TaxInclusive,
+        (["LegalMonetaryTotal"], "AllowanceTotalAmount") => Field::AllowanceTotal,
+        (["LegalMonetaryTotal"], "ChargeTotalAmount") => Field::ChargeTotal,
+        (["LegalMonetaryTotal"], "PrepaidAmount") => Field::Prepaid,
+        (["LegalMonetaryTotal"], "PayableAmount") => Field::Payable,
+        _ => return None,
+    };
+    Some(field)
+}
+
+fn role(party: &str) -> Option<Role> {
+    match party {
+        "AccountingSupplierParty" => Some(Role::Supplier),
+        "AccountingCustomerParty" => Some(Role::Customer),
+        _ => None,
+    }
+}
+
+fn party(document: &mut InvoiceDocument, role: Role) -> &mut Party {
+    match role {
+        Role::Supplier => &mut document.supplier,
+        Role::Customer => &mut document.customer,
+    }
+}
+
+fn set_first(slot: &mut Option<String>, value: Option<String>) {
+    if slot.is_none() {
+        *slot = value;
+    }
+}
+
+fn local_name(e: &BytesStart) -> anyhow::Result<String> {
+    Ok(std::str::from_utf8(e.local_name().as_ref())
+        .context("invalid XML element name")?
+        .to_owned())
+}
+
+fn attr(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == key)
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.into_owned()))
+}
+
+pub(crate) fn push_ref(text: &mut String, e: &BytesRef) -> anyhow::Result<()> {
+    if let Some(ch) = e.resolve_char_ref()? {
+        text.push(ch);
+        return Ok(());
+    }
+    let name = e.decode().context("failed to decode XML")?;
+    let resolved = resolve_predefined_entity(&name)
+        .with_context(|| format!("XML error: unknown entity &{name};"))?;
+    text.push_str(resolved);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVOICE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2" xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2" xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2" xmlns:ext="urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2"><ext:UBLExtensions><ext:UBLExtension><ext:ExtensionContent><ds:Signature xmlns:ds="http://www.w3.org/2000/09/xmldsig#" Id="signature"><ds:SignedInfo/><ds:SignatureValue>c2ln</ds:SignatureValue><ds:KeyInfo><ds:X509Data><ds:X509Certificate> Y2VydA== </ds:X509Certificate></ds:X509Data></ds:KeyInfo></ds:Signature></ext:ExtensionContent></ext:UBLExtension></ext:UBLExtensions>
+  <cbc:ProfileID>reporting:1.0</cbc:ProfileID>
+  <cbc:ID>S003</cbc:ID>
+  <cbc:UUID>b17f3393-232f-43c3-8448-38d8c09b04df</cbc:UUID>
+  <cbc:InvoiceTypeCode name="0100000">388</cbc:InvoiceTypeCode>
+  <cbc:DocumentCurrencyCode>SDG</cbc:DocumentCurrencyCode>
+  <cac:BillingReference><cac:InvoiceDocumentReference><cbc:ID>S001</cbc:ID><cbc:IssueDate>2026-01-31</cbc:IssueDate></cac:InvoiceDocumentReference></cac:BillingReference>
+  <cac:AdditionalDocumentReference><cbc:ID>ICV</cbc:ID><cbc:UUID>7</cbc:UUID></cac:AdditionalDocumentReference>
+  <cac:AdditionalDocumentReference><cbc:ID>PIH</cbc:ID><cac:Attachment><cbc:EmbeddedDocumentBinaryObject mimeCode="text/plain">cGlo</cbc:EmbeddedDocumentBinaryObject></cac:Attachment></cac:AdditionalDocumentReference>
+  <cac:AdditionalDocumentReference><cbc:ID>QR</cbc:ID><cac:Attachment><cbc:EmbeddedDocumentBinaryObject mimeCode="text/plain">cXI=</cbc:EmbeddedDocumentBinaryObject></cac:Attachment></cac:AdditionalDocumentReference>
+  <cac:Signature><cbc:ID>urn:oasis:names:specification:ubl:signature:Invoice</cbc:ID></cac:Signature>
+  <cac:AccountingSupplierParty><cac:Party><cac:PostalAddress><cbc:StreetName>Baladyia st</cbc:StreetName><cbc:CityName>Khartoum</cbc:CityName><cac:Country><cbc:IdentificationCode>SD</cbc:IdentificationCode></cac:Country></cac:PostalAddress><cac:PartyTaxScheme><cbc:CompanyID>123456789</cbc:CompanyID></cac:PartyTaxScheme><cac:PartyLegalEntity><cbc:RegistrationName>Smith &amp; Sons</cbc:RegistrationName></cac:PartyLegalEntity></cac:Party></cac:AccountingSupplierParty>
+  <cac:AccountingCustomerParty><cac:Party><cac:PartyTaxScheme><cbc:CompanyID>867857</cbc:CompanyID></cac:PartyTaxScheme></cac:Party></cac:AccountingCustomerParty>
+  <cac:PaymentMeans><cbc:PaymentMeansCode>30</cbc:PaymentMeansCode></cac:PaymentMeans>
+  <cac:InvoiceLine><cbc:ID>1</cbc:ID><cbc:InvoicedQuantity unitCode="PCE">2</cbc:InvoicedQuantity><cac:TaxTotal><cbc:TaxAmount currencyID="SDG">1.00</cbc:TaxAmount></cac:TaxTotal><cac:Item><cbc:Name>Laptop</cbc:Name><cac:ClassifiedTaxCategory><cbc:ID>S</cbc:ID><cbc:Percent>15</cbc:Percent><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:ClassifiedTaxCategory></cac:Item></cac:InvoiceLine>
+  <cac:TaxTotal><cbc:TaxAmount currencyID="SDG">450.00</cbc:TaxAmount><cac:TaxSubtotal><cbc:TaxableAmount currencyID="SDG">3000.00</cbc:TaxableAmount><cbc:TaxAmount currencyID="SDG">450.00</cbc:TaxAmount><cac:TaxCategory><cbc:ID>S</cbc:ID><cbc:Percent>15</cbc:Percent><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:TaxCategory></cac:TaxSubtotal><cac:TaxSubtotal><cbc:TaxableAmount currencyID="SDG">200.00</cbc:TaxableAmount><cbc:TaxAmount currencyID="SDG">0.00</cbc:TaxAmount><cac:TaxCategory><cbc:ID>E</cbc:ID><cbc:Percent>0</cbc:Percent><cbc:TaxExemptionReasonCode>VATEX-SD-HEALTH</cbc:TaxExemptionReasonCode><cbc:TaxExemptionReason>Medical services</cbc:TaxExemptionReason><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:TaxCategory></cac:TaxSubtotal></cac:TaxTotal>
+  <cac:LegalMonetaryTotal><cbc:PayableAmount currencyID="SDG">3450.00</cbc:PayableAmount></cac:LegalMonetaryTotal>
+</Invoice>"#;
+
+    #[test]
+    fn parses_typed_fields_in_one_pass() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        assert_eq!(document.uuid, "b17f3393-232f-43c3-8448-38d8c09b04df");
+        assert_eq!(document.id, "S003");
+        assert_eq!(document.profile_id().unwrap(), "reporting:1.0");
+        assert_eq!(document.invoice_type_code, "388");
+        assert_eq!(document.invoice_type_name.as_deref(), Some("0100000"));
+        let [reference] = document.billing_references.as_slice() else {
+            panic!("expected one billing reference");
+        };
+        assert_eq!(reference.id, "S001");
+        assert_eq!(reference.issue_date.as_deref(), Some("2026-01-31"));
+        assert_eq!(document.payment_means_codes, ["30"]);
+        assert_eq!(document.supplier.tin, "123456789");
+        assert_eq!(
+            document.supplier.registration_name.as_deref(),
+            Some("Smith & Sons")
+        );
+        assert_eq!(document.customer.tin, "867857");
+        assert_eq!(document.icv().unwrap(), 7);
+        assert_eq!(document.pih().unwrap(), "cGlo");
+        assert_eq!(document.references.qr.as_deref(), Some("cXI="));
+        assert_eq!(
+            document.tax_totals,
+            vec![Amount {
+                value: "450.00".to_owned(),
+                currency: Some("SDG".to_owned()),
+            }]
+        );
+        assert_eq!(document.totals.payable.unwrap().value, "3450.00");
+        assert_eq!(
+            document.supplier.address.city_name.as_deref(),
+            Some("Khartoum")
+        );
+        assert_eq!(
+            document.supplier.address.country_code.as_deref(),
+            Some("SD")
+        );
+        assert_eq!(document.customer.address.city_name, None);
+    }
+
+    #[test]
+    fn parses_invoice_lines() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        let [line] = document.lines.as_slice() else {
+            panic!("expected one invoice line");
+        };
+        assert_eq!(line.id, "1");
+        assert_eq!(line.quantity.as_deref(), Some("2"));
+        assert_eq!(line.unit_code.as_deref(), Some("PCE"));
+        assert_eq!(line.tax_amount.as_ref().unwrap().value, "1.00");
+        assert_eq!(line.item_name.as_deref(), Some("Laptop"));
+        assert_eq!(line.tax_category.as_deref(), Some("S"));
+        assert_eq!(line.tax_percent.as_deref(), Some("15"));
+        assert_eq!(document.tax_totals.len(), 1);
+    }
+
+    #[test]
+    fn parses_tax_subtotals() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        let [standard, exempt] = document.tax_subtotals.as_slice() else {
+            panic!("expected two tax subtotals");
+        };
+        assert_eq!(standard.taxable_amount.as_ref().unwrap().value, "3000.00");
+        assert_eq!(standard.tax_amount.as_ref().unwrap().value, "450.00");
+        assert_eq!(standard.category.as_deref(), Some("S"));
+        assert_eq!(standard.percent.as_deref(), Some("15"));
+        assert_eq!(standard.exemption_reason_code, None);
+        assert_eq!(exempt.category.as_deref(), Some("E"));
+        assert_eq!(
+            exempt.exemption_reason_code.as_deref(),
+            Some("VATEX-SD-HEALTH")
+        );
+        assert_eq!(exempt.exemption_reason.as_deref(), Some("Medical services"));
+    }
+
+    #[test]
+    fn captures_the_signature_block() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        let signature = document.signature().unwrap();
+        assert_eq!(signature.certificate().unwrap(), "Y2VydA==");
+        assert_eq!(signature.signature_value.as_deref(), Some("c2ln"));
+        assert!(signature.xml.starts_with(b"<ds:Signature"));
+        assert!(signature.xml.ends_with(b"</ds:Signature>"));
+    }
+
+    #[test]
+    fn strips_extensions_signatures_and_qr_reference() {
+        let document = InvoiceDocument::parse(INVOICE.as_bytes()).unwrap();
+        let stripped = String::from_utf8(document.stripped_invoice).unwrap();
+        assert!(stripped.starts_with("\n<Invoice"));
+        assert!(!stripped.contains("UBLExtensions"));
+        assert!(!stripped.contains("Signature"));
+        assert!(!stripped.contains("cXI="));
+        assert!(stripped.contains("<cbc:ID>PIH</cbc:ID>"));
+        assert!(stripped.contains("Smith &amp; Sons"));
+    }
+
+    #[test]
+    fn captures_signatures_by_namespace_with_inherited_declarations() {
+        let xml = br#"<Invoice xmlns:dsig="http://www.w3.org/2000/09/xmldsig#"><ext:UBLExtensions><dsig:Signature Id="signature"><dsig:SignedInfo/><dsig:KeyInfo><dsig:X509Data><dsig:X509Certificate>Q0VSVA==</dsig:X509Certificate></dsig:X509Data></dsig:KeyInfo></dsig:Signature></ext:UBLExtensions><cac:Signature><cbc:ID>urn:oasis:names:specification:ubl:signature:Invoice</cbc:ID></cac:Signature></Invoice>"#;
+        let document = InvoiceDocument::parse(xml).unwrap();
+        let signature = document.signature().unwrap();
+        assert_eq!(signature.certificate().unwrap(), "Q0VSVA==");
+        assert_eq!(
+            signature.namespaces,
+            vec![(
+                "xmlns:dsig".to_owned(),
+                "http://www.w3.org/2000/09/xmldsig#".to_owned()
+            )]
+        );
+        let scoped = String::from_utf8(signature.scoped_xml().unwrap()).unwrap();
+        assert!(scoped.starts_with(
+            r#"<dsig:Signature Id="signature" xmlns:dsig="http://www.w3.org/2000/09/xmldsig#">"#
+        ));
+    }
+
+    #[test]
+    fn missing_references_report_specific_errors() {
+        let document = InvoiceDocument::parse(b"<Invoice><cbc:ID>S1</cbc:ID></Invoice>").unwrap();
+        assert!(document.icv().unwrap_err().to_string().contains("ICV"));
+        assert!(document.pih().unwrap_err().to_string().contains("PIH"));
+        assert!(
+            document
+                .signature()
+                .unwrap_err()
+                .to_string()
+                .contains("ds:Signature not found")
+        );
+    }
+
+    #[test]
+    fn malformed_xml_is_rejected() {
+        let err = InvoiceDocument::parse(b"<Invoice><cbc:ID>S1</Invoice>")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("XML error"));
+    }
+}
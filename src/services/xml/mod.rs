@@ -1,5 +1,13 @@
+pub mod batch_archive;
 pub mod c14n11;
+pub mod decimal;
 pub mod edit_tlv;
 pub mod editors;
 pub mod extractors;
+pub mod guard;
+pub mod invoice_document;
+pub mod namespaces;
+pub mod qr_image;
 pub mod schema_validation;
+pub mod xpath;
+pub mod xpath_filter;
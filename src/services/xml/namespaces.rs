@@ -0,0 +1,178 @@
+use quick_xml::{
+    events::BytesStart,
+    name::{Namespace, NamespaceResolver, PrefixDeclaration, QName, ResolveResult},
+};
+
+pub const DS_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+pub const XADES_NS: &str = "http://uri.etsi.org/01903/v1.3.2#";
+pub const CAC_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+pub const CBC_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+pub const EXT_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2";
+pub const SIG_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonSignatureComponents-2";
+pub const SAC_NS: &str =
+    "urn:oasis:names:specification:ubl:schema:xsd:SignatureAggregateComponents-2";
+
+/// Prefixes assumed when a name uses them without a declaration in scope.
+/// Fragments cut out of an invoice (a `ds:SignedInfo`, a `sac:SignatureInformation`)
+/// rely on declarations made by the document they belong to.
+const CONVENTIONAL_PREFIXES: [(&str, &str); 7] = [
+    ("ds", DS_NS),
+    ("xades", XADES_NS),
+    ("cac", CAC_NS),
+    ("cbc", CBC_NS),
+    ("ext", EXT_NS),
+    ("sig", SIG_NS),
+    ("sac", SAC_NS),
+];
+
+/// Namespace URI of the element `name`, resolved in the scope of `resolver`.
+pub fn element_namespace<'r>(resolver: &'r NamespaceResolver, name: QName) -> Option<&'r [u8]> {
+    match resolver.resolve_element(name).0 {
+        ResolveResult::Bound(Namespace(namespace)) => Some(namespace),
+        ResolveResult::Unbound => None,
+        ResolveResult::Unknown(prefix) => CONVENTIONAL_PREFIXES
+            .iter()
+            .find(|(conventional, _)| conventional.as_bytes() == prefix.as_slice())
+            .map(|(_, namespace)| namespace.as_bytes()),
+    }
+}
+
+/// Whether `name` is the element `{namespace}local`, whatever its prefix.
+pub fn is_element(resolver: &NamespaceResolver, name: QName, namespace: &str, local: &str) -> bool {
+    name.local_name().as_ref() == local.as_bytes()
+        && element_namespace(resolver, name) == Some(namespace.as_bytes())
+}
+
+/// The namespace declarations in scope, as `(attribute, URI)` pairs such as
+/// `("xmlns:ds", DS_NS)` or `("xmlns", DS_NS)`.
+pub fn in_scope_declarations(resolver: &NamespaceResolver) -> Vec<(String, String)> {
+    resolver
+        .bindings()
+        .map(|(prefix, Namespace(namespace))| {
+            let attribute = match prefix {
+                PrefixDeclaration::Default => "xmlns".to_owned(),
+                PrefixDeclaration::Named(prefix) => {
+                    format!("xmlns:{}", String::from_utf8_lossy(prefix))
+                }
+            };
+            (attribute, String::from_utf8_lossy(namespace).into_owned())
+        })
+        .collect()
+}
+
+/// Declarations to add on `root` when it is canonicalized apart from its
+/// ancestors: every prefix in `in_scope` bound to one of `namespaces`, or the
+/// conventional prefix when a namespace has no binding. Declarations already
+/// present on `root` are left out.
+pub fn fragment_declarations(
+    in_scope: &[(String, String)],
+    root: &BytesStart,
+    namespaces: &[&str],
+) -> Vec<(String, String)> {
+    let mut declarations = Vec::new();
+    for namespace in namespaces {
+        let bound: Vec<_> = in_scope
+            .iter()
+            .filter(|(_, uri)| uri == namespace)
+            .cloned()
+            .collect();
+        if bound.is_empty() {
+            if let Some((prefix, _)) = CONVENTIONAL_PREFIXES
+                .iter()
+                .find(|(_, uri)| uri == namespace)
+            {
+                declarations.push((format!("xmlns:{prefix}"), namespace.to_string()));
+            }
+        } else {
+            declarations.extend(bound);
+        }
+    }
+    declarations.retain(|(attribute, _)| {
+        !root
+            .attributes()
+            .flatten()
+            .any(|attr| attr.key.as_ref() == attribute.as_bytes())
+    });
+    declarations.dedup();
+    declarations
+}
+
+/// Declarations for the prefixes of an `InclusiveNamespaces PrefixList`
+/// (`#default` for the default namespace) that are bound in `in_scope`, so an
+/// exclusive canonicalization of `root` apart from its ancestors still emits
+/// them. Declarations already present on `root` are left out.
+pub fn prefix_declarations(
+    in_scope: &[(String, String)],
+    root: &BytesStart,
+    prefixes: &[String],
+) -> Vec<(String, String)> {
+    prefixes
+        .iter()
+        .map(|prefix| match prefix.as_str() {
+            "#default" => "xmlns".to_owned(),
+            prefix => format!("xmlns:{prefix}"),
+        })
+        .filter(|attribute| {
+            !root
+                .attributes()
+                .flatten()
+                .any(|attr| attr.key.as_ref() == attribute.as_bytes())
+        })
+        .filter_map(|attribute| {
+            in_scope
+                .iter()
+                .find(|(declared, _)| *declared == attribute)
+                .cloned()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::{NsReader, events::Event};
+
+    /// In-scope declarations and the namespace of the first `local` element.
+    fn resolve(xml: &str, local: &str) -> (Vec<(String, String)>, Option<String>) {
+        let mut reader = NsReader::from_str(xml);
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) | Event::Empty(e)
+                    if e.local_name().as_ref() == local.as_bytes() =>
+                {
+                    let namespace = element_namespace(reader.resolver(), e.name())
+                        .map(|ns| String::from_utf8(ns.to_vec()).unwrap());
+                    return (in_scope_declarations(reader.resolver()), namespace);
+                }
+                Event::Eof => panic!("{local} not found"),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_any_prefix_and_the_default_namespace() {
+        let xml = format!(r#"<a xmlns="{DS_NS}"><b xmlns:x="{XADES_NS}"><x:SigningTime/></b></a>"#);
+        assert_eq!(resolve(&xml, "a").1.as_deref(), Some(DS_NS));
+        assert_eq!(resolve(&xml, "SigningTime").1.as_deref(), Some(XADES_NS));
+        assert_eq!(
+            resolve("<ds:SignedInfo/>", "SignedInfo").1.as_deref(),
+            Some(DS_NS)
+        );
+        assert_eq!(resolve("<SignedInfo/>", "SignedInfo").1, None);
+    }
+
+    #[test]
+    fn fragment_declarations_keep_the_document_prefixes() {
+        let xml = format!(r#"<a xmlns:dsig="{DS_NS}" xmlns:o="urn:other"><dsig:SignedInfo/></a>"#);
+        let (in_scope, _) = resolve(&xml, "SignedInfo");
+        let root = BytesStart::new("dsig:SignedInfo");
+        assert_eq!(
+            fragment_declarations(&in_scope, &root, &[DS_NS, XADES_NS]),
+            vec![
+                ("xmlns:dsig".to_owned(), DS_NS.to_owned()),
+                ("xmlns:xades".to_owned(), XADES_NS.to_owned()),
+            ]
+        );
+    }
+}
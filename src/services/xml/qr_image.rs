@@ -0,0 +1,213 @@
+use std::{fmt::Write as _, str::FromStr};
+
+use anyhow::{Context, anyhow, bail};
+use base64::{Engine, engine::general_purpose};
+use qrcodegen::{QrCode, QrCodeEcc};
+
+use crate::services::xml::edit_tlv::extract_records;
+
+/// Light modules around the symbol, as ISO/IEC 18004 requires.
+pub const QUIET_ZONE: i32 = 4;
+pub const MIN_QR_IMAGE_SIZE: u32 = 64;
+pub const MAX_QR_IMAGE_SIZE: u32 = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrImageFormat {
+    Png,
+    Svg,
+}
+
+impl QrImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Svg => "image/svg+xml",
+        }
+    }
+}
+
+impl FromStr for QrImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> anyhow::Result<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "svg" => Ok(Self::Svg),
+            _ => bail!("unsupported QR image format: {format}"),
+        }
+    }
+}
+
+/// QR error-correction level: roughly 7, 15, 25 and 30% of the symbol can be
+/// damaged and still read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrErrorCorrection {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+impl FromStr for QrErrorCorrection {
+    type Err = anyhow::Error;
+
+    fn from_str(level: &str) -> anyhow::Result<Self> {
+        match level.to_ascii_uppercase().as_str() {
+            "L" => Ok(Self::Low),
+            "M" => Ok(Self::Medium),
+            "Q" => Ok(Self::Quartile),
+            "H" => Ok(Self::High),
+            _ => bail!("unsupported QR error correction level: {level}"),
+        }
+    }
+}
+
+impl From<QrErrorCorrection> for QrCodeEcc {
+    fn from(level: QrErrorCorrection) -> Self {
+        match level {
+            QrErrorCorrection::Low => QrCodeEcc::Low,
+            QrErrorCorrection::Medium => QrCodeEcc::Medium,
+            QrErrorCorrection::Quartile => QrCodeEcc::Quartile,
+            QrErrorCorrection::High => QrCodeEcc::High,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QrImageOptions {
+    pub format: QrImageFormat,
+    /// Requested width and height in pixels, quiet zone included.
+    pub size: u32,
+    pub error_correction: QrErrorCorrection,
+}
+
+impl QrImageOptions {
+    pub fn new(
+        format: QrImageFormat,
+        size: u32,
+        error_correction: QrErrorCorrection,
+    ) -> anyhow::Result<Self> {
+        if !(MIN_QR_IMAGE_SIZE..=MAX_QR_IMAGE_SIZE).contains(&size) {
+            bail!(
+                "QR image size must be between {MIN_QR_IMAGE_SIZE} and {MAX_QR_IMAGE_SIZE} pixels"
+            );
+        }
+        Ok(Self {
+            format,
+            size,
+            error_correction,
+        })
+    }
+}
+
+/// Renders the base64 TLV of an invoice QR as a QR code image. The symbol
+/// encodes the base64 text itself, which is what scanners hand to
+/// `/verify_qr`.
+///
+/// SVG output is exactly `size` pixels wide. PNG output uses the largest
+/// whole number of pixels per module that fits in `size`, so small symbols
+/// stay sharp.
+pub fn render_qr(qr_b64: &str, options: &QrImageOptions) -> anyhow::Result<Vec<u8>> {
+    let code = encode_qr(qr_b64, options.error_correction)?;
+    match options.format {
+        QrImageFormat::Png => render_png(&code, options.size),
+        QrImageFormat::Svg => Ok(render_svg(&code, options.size).into_bytes()),
+    }
+}
+
+/// Encodes the base64 TLV of an invoice QR as a QR symbol, after checking
+/// that it decodes to well-formed TLV records.
+pub fn encode_qr(qr_b64: &str, error_correction: QrErrorCorrection) -> anyhow::Result<QrCode> {
+    let qr_b64 = qr_b64.trim();
+    extract_records(&general_purpose::STANDARD.decode(qr_b64)?)?;
+    QrCode::encode_text(qr_b64, error_correction.into())
+        .map_err(|_| anyhow!("QR payload is too long for the requested error correction level"))
+}
+
+fn render_png(code: &QrCode, size: u32) -> anyhow::Result<Vec<u8>> {
+    let modules = (code.size() + 2 * QUIET_ZONE) as u32;
+    let scale = (size / modules).max(1);
+    let dimension = modules * scale;
+
+    let mut pixels = Vec::with_capacity((dimension * dimension) as usize);
+    for y in 0..dimension {
+        for x in 0..dimension {
+            let dark = code.get_module(
+                (x / scale) as i32 - QUIET_ZONE,
+                (y / scale) as i32 - QUIET_ZONE,
+            );
+            pixels.push(if dark { 0x00 } else { 0xff });
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, dimension, dimension);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("failed to write QR PNG header")?;
+    writer
+        .write_image_data(&pixels)
+        .context("failed to write QR PNG data")?;
+    writer.finish().context("failed to finish QR PNG")?;
+    Ok(png)
+}
+
+fn render_svg(code: &QrCode, size: u32) -> String {
+    let modules = code.size() + 2 * QUIET_ZONE;
+    let mut svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" viewBox="0 0 {modules} {modules}" shape-rendering="crispEdges"><rect width="{modules}" height="{modules}" fill="#ffffff"/><path fill="#000000" d=""##
+    );
+    for y in 0..code.size() {
+        for x in 0..code.size() {
+            if code.get_module(x, y) {
+                let _ = write!(svg, "M{},{}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE);
+            }
+        }
+    }
+    svg.push_str(r#""/></svg>"#);
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qr_b64() -> String {
+        general_purpose::STANDARD.encode([1, 3, b'A', b'B', b'C', 6, 2, 9, 9])
+    }
+
+    fn options(format: QrImageFormat, size: u32) -> QrImageOptions {
+        QrImageOptions::new(format, size, QrErrorCorrection::Medium).unwrap()
+    }
+
+    #[test]
+    fn renders_png_and_svg() {
+        let png = render_qr(&qr_b64(), &options(QrImageFormat::Png, 300)).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let decoder = png::Decoder::new(png.as_slice());
+        let info = decoder.read_info().unwrap().info().clone();
+        // A version 1 symbol is 21 modules, 29 with the quiet zone: 10 px each.
+        assert_eq!((info.width, info.height), (290, 290));
+
+        let svg = render_qr(&qr_b64(), &options(QrImageFormat::Svg, 300)).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"width="300""#));
+        assert!(svg.contains(r#"viewBox="0 0 29 29""#));
+    }
+
+    #[test]
+    fn rejects_bad_options_and_payloads() {
+        assert!(QrImageOptions::new(QrImageFormat::Png, 32, QrErrorCorrection::Low).is_err());
+        assert!("gif".parse::<QrImageFormat>().is_err());
+        assert!("X".parse::<QrErrorCorrection>().is_err());
+        assert_eq!(
+            "q".parse::<QrErrorCorrection>().unwrap(),
+            QrErrorCorrection::Quartile
+        );
+        let truncated = general_purpose::STANDARD.encode([6, 4, 1]);
+        assert!(render_qr(&truncated, &options(QrImageFormat::Svg, 128)).is_err());
+    }
+}
@@ -1,17 +1,19 @@
 use actix_web::web::Data;
 use anyhow::Context;
 use fastxml::{
-    parse,
+    XmlDocument, parse,
     schema::{CompiledSchema, XmlSchemaValidationContext},
 };
 use tracing::instrument;
 
+/// Validates the invoice against the XSD and returns the parsed document for
+/// the checks that follow.
 #[instrument(skip(schema, body))]
-pub fn validate_schema(schema: Data<CompiledSchema>, body: &str) -> anyhow::Result<()> {
+pub fn validate_schema(schema: Data<CompiledSchema>, body: &str) -> anyhow::Result<XmlDocument> {
     let validator = XmlSchemaValidationContext::from_arc(schema.into_inner());
     let xml_doc = parse(body)?;
     validator
         .validate(&xml_doc)
         .context("XSD validation failed")?;
-    Ok(())
+    Ok(xml_doc)
 }
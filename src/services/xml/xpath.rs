@@ -0,0 +1,83 @@
+use std::ops::Range;
+
+use anyhow::bail;
+use fastxml::{XmlContext, XmlNode};
+
+/// Whether `expression` is true with `node` as the context node.
+pub fn holds_at(context: &XmlContext, expression: &str, node: &XmlNode) -> anyhow::Result<bool> {
+    // Comparisons are only parsed inside a predicate, so the expression holds
+    // when it keeps the context node. A balanced expression cannot close that
+    // predicate and go on to select other nodes.
+    if !is_balanced(expression) {
+        bail!("unbalanced brackets or quotes in {expression:?}");
+    }
+    let predicate = format!("self::*[{expression}]");
+    Ok(context.evaluate_from(&predicate, node)?.to_boolean())
+}
+
+/// Whether every bracket and parenthesis outside string literals is closed in
+/// order and every literal is terminated.
+fn is_balanced(expression: &str) -> bool {
+    let mut open = Vec::new();
+    let mut in_literal = None;
+    for c in expression.chars() {
+        if let Some(quote) = in_literal {
+            if c == quote {
+                in_literal = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => in_literal = Some(c),
+            '[' => open.push(']'),
+            '(' => open.push(')'),
+            ']' | ')' if open.pop() != Some(c) => return false,
+            _ => {}
+        }
+    }
+    open.is_empty() && in_literal.is_none()
+}
+
+/// Namespace prefixes of the names in an XPath, skipping string literals and
+/// axes such as `self::`.
+pub fn prefixes(xpath: &str) -> Vec<&str> {
+    prefix_ranges(xpath)
+        .into_iter()
+        .map(|range| &xpath[range])
+        .collect()
+}
+
+/// Byte ranges of the prefixes `prefixes` returns.
+pub fn prefix_ranges(xpath: &str) -> Vec<Range<usize>> {
+    let name_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let mut in_literal = None;
+    let mut start = None;
+    let mut found = Vec::new();
+    let bytes = xpath.as_bytes();
+    for (index, c) in xpath.char_indices() {
+        if let Some(quote) = in_literal {
+            if c == quote {
+                in_literal = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => {
+                in_literal = Some(c);
+                start = None;
+            }
+            ':' => {
+                let axis =
+                    bytes.get(index + 1) == Some(&b':') || index > 0 && bytes[index - 1] == b':';
+                if let Some(begin) = start.take().filter(|_| !axis) {
+                    found.push(begin..index);
+                }
+            }
+            c if name_char(c) => {
+                start.get_or_insert(index);
+            }
+            _ => start = None,
+        }
+    }
+    found
+}
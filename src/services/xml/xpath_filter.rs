@@ -0,0 +1,294 @@
+use std::{collections::HashSet, ops::Range};
+
+use anyhow::{Context, anyhow, bail};
+use fastxml::XmlNode;
+use quick_xml::{Reader, events::Event};
+
+use crate::services::xml::xpath::{holds_at, prefix_ranges};
+
+/// An XPath filter transform (`REC-xpath-19991116`): a boolean XPath 1.0
+/// expression evaluated with each node of the referenced document as the
+/// context node, keeping the nodes for which it is true.
+///
+/// UBL signature profiles write `not(//ancestor-or-self::ext:UBLExtensions)`
+/// and mean the ancestors of the context node, not of every node in the
+/// document; like the signers that produce them, a `//` directly before
+/// `ancestor-or-self::` is read relative to the context node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPathFilter {
+    /// The expression as written in the `ds:XPath` element.
+    source: String,
+    expression: String,
+    /// `(prefix, URI)` of each prefix the expression uses, from the
+    /// namespaces in scope on the `ds:XPath` element.
+    namespaces: Vec<(String, String)>,
+}
+
+impl XPathFilter {
+    /// Resolves the prefixes of `expression` with `namespace_of`; prefixes it
+    /// does not know are rejected rather than left to the evaluator.
+    pub fn new<'a, F>(expression: &str, namespace_of: F) -> anyhow::Result<Self>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let source = expression.trim().to_owned();
+        let expression = source.replace("//ancestor-or-self::", "ancestor-or-self::");
+        let mut namespaces: Vec<(String, String)> = Vec::new();
+        for range in prefix_ranges(&expression) {
+            let prefix = &expression[range];
+            if namespaces.iter().any(|(known, _)| known == prefix) {
+                continue;
+            }
+            let uri = namespace_of(prefix).with_context(|| {
+                format!("undeclared namespace prefix in XPath filter: {prefix}")
+            })?;
+            namespaces.push((prefix.to_owned(), uri.to_owned()));
+        }
+        Ok(Self {
+            source,
+            expression,
+            namespaces,
+        })
+    }
+
+    /// The expression as written in the `ds:XPath` element.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// `(prefix, URI)` of each prefix the expression uses.
+    pub fn namespaces(&self) -> &[(String, String)] {
+        &self.namespaces
+    }
+
+    /// The expression with its prefixes renamed to ones no element in the
+    /// document uses, and the `(prefix, URI)` pairs to register: the
+    /// evaluator also matches names by their literal prefix, so `ext:` would
+    /// otherwise select an `ext:` element of any namespace.
+    fn renamed(&self, used: &HashSet<String>) -> (String, Vec<(String, String)>) {
+        let fresh = |index: usize| {
+            (0..)
+                .map(|suffix| format!("f{index}_{suffix}"))
+                .find(|prefix| !used.contains(prefix))
+                .expect("unbounded prefix candidates")
+        };
+        let renamed: Vec<(String, String)> = self
+            .namespaces
+            .iter()
+            .enumerate()
+            .map(|(index, (_, uri))| (fresh(index), uri.clone()))
+            .collect();
+        let mut expression = String::with_capacity(self.expression.len());
+        let mut copied = 0;
+        for range in prefix_ranges(&self.expression) {
+            let prefix = &self.expression[range.clone()];
+            let index = self
+                .namespaces
+                .iter()
+                .position(|(known, _)| known == prefix)
+                .expect("prefixes are resolved in new");
+            expression.push_str(&self.expression[copied..range.start]);
+            expression.push_str(&renamed[index].0);
+            copied = range.end;
+        }
+        expression.push_str(&self.expression[copied..]);
+        (expression, renamed)
+    }
+}
+
+/// Evaluates `filters` over `xml` and serializes the nodes every filter
+/// keeps: the source text with each excluded element cut out, without the
+/// XML declaration.
+///
+/// Filters are evaluated on elements; their attributes, namespaces and text
+/// go with them. Keeping an element inside an excluded one cannot be
+/// expressed by cutting text and is rejected.
+pub fn apply_filters(xml: &[u8], filters: &[XPathFilter]) -> anyhow::Result<Vec<u8>> {
+    let text = std::str::from_utf8(xml).context("Invoice XML is not valid UTF-8")?;
+    let excluded = excluded_elements(text, filters)?;
+    let (declaration_end, ranges) = element_ranges(text)?;
+    if ranges.len() != excluded.len() {
+        bail!("XPath filter could not map the invoice elements");
+    }
+
+    let mut output = Vec::with_capacity(xml.len());
+    let mut start = declaration_end;
+    for (range, _) in ranges
+        .into_iter()
+        .zip(excluded)
+        .filter(|(_, excluded)| *excluded)
+    {
+        if range.start < start {
+            continue;
+        }
+        output.extend_from_slice(&xml[start..range.start]);
+        start = range.end;
+    }
+    output.extend_from_slice(&xml[start..]);
+    Ok(output)
+}
+
+/// Whether each element, in document order, is removed by some filter.
+fn excluded_elements(text: &str, filters: &[XPathFilter]) -> anyhow::Result<Vec<bool>> {
+    let document = fastxml::parse(text).context("failed to parse invoice for XPath filters")?;
+    let mut elements = Vec::new();
+    let mut parents = Vec::new();
+    let mut pending = vec![(document.get_root_element()?, None)];
+    while let Some((element, parent)) = pending.pop() {
+        let index = elements.len();
+        pending.extend(
+            element
+                .get_child_elements()
+                .into_iter()
+                .rev()
+                .map(|child| (child, Some(index))),
+        );
+        elements.push(element);
+        parents.push(parent);
+    }
+    let used: HashSet<String> = elements.iter().filter_map(XmlNode::get_prefix).collect();
+
+    let mut excluded = vec![false; elements.len()];
+    for filter in filters {
+        let (expression, namespaces) = filter.renamed(&used);
+        let mut context = fastxml::create_context(&document)?;
+        for (prefix, uri) in &namespaces {
+            context.register_namespace(prefix, uri)?;
+        }
+        for (element, excluded) in elements.iter().zip(excluded.iter_mut()) {
+            if !holds_at(&context, &expression, element)
+                .map_err(|e| anyhow!("invalid XPath filter expression: {e}"))?
+            {
+                *excluded = true;
+            }
+        }
+    }
+
+    if parents
+        .iter()
+        .zip(&excluded)
+        .any(|(parent, is_excluded)| !is_excluded && parent.is_some_and(|parent| excluded[parent]))
+    {
+        bail!("unsupported XPath filter: it keeps an element inside an excluded one");
+    }
+    Ok(excluded)
+}
+
+/// End of the XML declaration and the byte range of each element, in
+/// document order.
+fn element_ranges(text: &str) -> anyhow::Result<(usize, Vec<Range<usize>>)> {
+    let mut reader = Reader::from_str(text);
+    let mut declaration_end = 0;
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut open = Vec::new();
+    loop {
+        let start = reader.buffer_position() as usize;
+        let event = reader
+            .read_event()
+            .map_err(|e| anyhow!("invoice XML error: {e}"))?;
+        let end = reader.buffer_position() as usize;
+        match event {
+            Event::Decl(_) => declaration_end = end,
+            Event::Start(_) => {
+                open.push(ranges.len());
+                ranges.push(start..start);
+            }
+            Event::End(_) => {
+                let index = open.pop().context("unbalanced invoice XML")?;
+                ranges[index].end = end;
+            }
+            Event::Empty(_) => ranges.push(start..end),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((declaration_end, ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+    const CBC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+    const EXT: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonExtensionComponents-2";
+
+    fn ubl(prefix: &str) -> Option<&'static str> {
+        match prefix {
+            "cac" => Some(CAC),
+            "cbc" => Some(CBC),
+            "ext" => Some(EXT),
+            _ => None,
+        }
+    }
+
+    fn filter(xml: &str, expressions: &[&str]) -> anyhow::Result<String> {
+        let filters = expressions
+            .iter()
+            .map(|expression| XPathFilter::new(expression, ubl))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(String::from_utf8(apply_filters(xml.as_bytes(), &filters)?)?)
+    }
+
+    #[test]
+    fn removes_matching_subtrees_by_namespace() {
+        let xml = format!(
+            r#"<?xml version="1.0"?>
+<Invoice xmlns:x="{EXT}" xmlns:ext="urn:other" xmlns:cbc="{CBC}"><x:UBLExtensions><x:UBLExtension/></x:UBLExtensions><cbc:ID>S1</cbc:ID><ext:UBLExtensions/></Invoice>"#
+        );
+        assert_eq!(
+            filter(&xml, &["not(//ancestor-or-self::ext:UBLExtensions)"]).unwrap(),
+            format!(
+                r#"
+<Invoice xmlns:x="{EXT}" xmlns:ext="urn:other" xmlns:cbc="{CBC}"><cbc:ID>S1</cbc:ID><ext:UBLExtensions/></Invoice>"#
+            )
+        );
+    }
+
+    #[test]
+    fn predicates_are_evaluated_as_xpath() {
+        let xml = format!(
+            r#"<Invoice xmlns:cac="{CAC}" xmlns:cbc="{CBC}"><cac:AdditionalDocumentReference><cbc:ID>PIH</cbc:ID></cac:AdditionalDocumentReference><cac:AdditionalDocumentReference><cbc:ID> QR </cbc:ID></cac:AdditionalDocumentReference><cac:Signature/></Invoice>"#
+        );
+        let filtered = filter(
+            &xml,
+            &[
+                "not(//ancestor-or-self::cac:AdditionalDocumentReference[normalize-space(cbc:ID)='QR'])",
+                "not(ancestor-or-self::cac:Signature)",
+            ],
+        )
+        .unwrap();
+        assert!(filtered.contains("<cbc:ID>PIH</cbc:ID>"));
+        assert!(!filtered.contains("QR"));
+        assert!(!filtered.contains("Signature"));
+    }
+
+    #[test]
+    fn undeclared_prefixes_and_kept_descendants_are_rejected() {
+        let err = XPathFilter::new("not(//ancestor-or-self::sig:Signature)", ubl)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("undeclared namespace prefix"));
+
+        let xml = format!(
+            r#"<Invoice xmlns:cbc="{CBC}"><cbc:Note><cbc:ID>1</cbc:ID></cbc:Note></Invoice>"#
+        );
+        let err = filter(&xml, &["not(self::cbc:Note)"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("keeps an element inside an excluded one"));
+    }
+
+    #[test]
+    fn expressions_cannot_close_the_predicate() {
+        let xml = format!(r#"<Invoice xmlns:cbc="{CBC}"><cbc:ID>S1</cbc:ID></Invoice>"#);
+        for expression in ["1] | //cbc:ID[1", "not(self::cbc:ID)]", "cbc:ID = 'S1"] {
+            let err = filter(&xml, &[expression]).unwrap_err().to_string();
+            assert!(
+                err.contains("unbalanced brackets or quotes"),
+                "{expression}: {err}"
+            );
+        }
+        assert!(filter(&xml, &["not(self::cbc:Note[. = ']'])"]).is_ok());
+    }
+}